@@ -13,7 +13,7 @@ fn create_test_image(size: u32) -> Image {
         }
     }
     
-    Image::from_rgb(img)
+    Image::from_rgb(img).unwrap()
 }
 
 fn benchmark_unsharp_mask(c: &mut Criterion) {
@@ -27,8 +27,7 @@ fn benchmark_unsharp_mask(c: &mut Criterion) {
             size,
             |b, _| {
                 b.iter(|| {
-                    let img_clone = img.clone();
-                    black_box(img_clone.unsharp_mask(1.0, 1.0, 0).unwrap())
+                    black_box(img.unsharp_mask_ref(1.0, 1.0, 0).unwrap())
                 });
             },
         );
@@ -48,8 +47,7 @@ fn benchmark_high_pass_sharpen(c: &mut Criterion) {
             size,
             |b, _| {
                 b.iter(|| {
-                    let img_clone = img.clone();
-                    black_box(img_clone.high_pass_sharpen(0.5).unwrap())
+                    black_box(img.high_pass_sharpen_ref(0.5).unwrap())
                 });
             },
         );
@@ -69,8 +67,7 @@ fn benchmark_edge_enhancement(c: &mut Criterion) {
             size,
             |b, _| {
                 b.iter(|| {
-                    let img_clone = img.clone();
-                    black_box(img_clone.enhance_edges(1.0, EdgeMethod::Sobel).unwrap())
+                    black_box(img.enhance_edges_ref(1.0, EdgeMethod::Sobel).unwrap())
                 });
             },
         );
@@ -80,8 +77,7 @@ fn benchmark_edge_enhancement(c: &mut Criterion) {
             size,
             |b, _| {
                 b.iter(|| {
-                    let img_clone = img.clone();
-                    black_box(img_clone.enhance_edges(1.0, EdgeMethod::Prewitt).unwrap())
+                    black_box(img.enhance_edges_ref(1.0, EdgeMethod::Prewitt).unwrap())
                 });
             },
         );
@@ -101,8 +97,7 @@ fn benchmark_clarity(c: &mut Criterion) {
             size,
             |b, _| {
                 b.iter(|| {
-                    let img_clone = img.clone();
-                    black_box(img_clone.clarity(1.0, 2.0).unwrap())
+                    black_box(img.clarity_ref(1.0, 2.0).unwrap())
                 });
             },
         );
@@ -116,30 +111,17 @@ fn benchmark_builder_pattern(c: &mut Criterion) {
     
     let img = create_test_image(512);
     
+    let single = img.clone().sharpen().unsharp_mask(1.0, 1.0, 0);
     group.bench_function("single_operation", |b| {
-        b.iter(|| {
-            let img_clone = img.clone();
-            black_box(
-                img_clone.sharpen()
-                    .unsharp_mask(1.0, 1.0, 0)
-                    .apply()
-                    .unwrap()
-            )
-        });
+        b.iter(|| black_box(single.apply_ref(&img).unwrap()));
     });
     
+    let multiple = img.clone().sharpen()
+        .unsharp_mask(1.0, 1.0, 0)
+        .high_pass(0.3)
+        .clarity(0.5, 2.0);
     group.bench_function("multiple_operations", |b| {
-        b.iter(|| {
-            let img_clone = img.clone();
-            black_box(
-                img_clone.sharpen()
-                    .unsharp_mask(1.0, 1.0, 0)
-                    .high_pass(0.3)
-                    .clarity(0.5, 2.0)
-                    .apply()
-                    .unwrap()
-            )
-        });
+        b.iter(|| black_box(multiple.apply_ref(&img).unwrap()));
     });
     
     group.finish();
@@ -150,25 +132,19 @@ fn benchmark_presets(c: &mut Criterion) {
     
     let img = create_test_image(512);
     
+    let subtle = SharpeningPresets::subtle(img.clone());
     group.bench_function("subtle", |b| {
-        b.iter(|| {
-            let img_clone = img.clone();
-            black_box(SharpeningPresets::subtle(img_clone).apply().unwrap())
-        });
+        b.iter(|| black_box(subtle.apply_ref(&img).unwrap()));
     });
     
+    let moderate = SharpeningPresets::moderate(img.clone());
     group.bench_function("moderate", |b| {
-        b.iter(|| {
-            let img_clone = img.clone();
-            black_box(SharpeningPresets::moderate(img_clone).apply().unwrap())
-        });
+        b.iter(|| black_box(moderate.apply_ref(&img).unwrap()));
     });
     
+    let strong = SharpeningPresets::strong(img.clone());
     group.bench_function("strong", |b| {
-        b.iter(|| {
-            let img_clone = img.clone();
-            black_box(SharpeningPresets::strong(img_clone).apply().unwrap())
-        });
+        b.iter(|| black_box(strong.apply_ref(&img).unwrap()));
     });
     
     group.finish();
@@ -13,35 +13,35 @@ fn main() -> Result<(), Box<dyn Error>> {
     
     // Test unsharp mask
     let start = Instant::now();
-    let unsharp = image.clone().unsharp_mask(1.0, 1.0, 0)?;
+    let unsharp = image.unsharp_mask_ref(1.0, 1.0, 0)?;
     let duration = start.elapsed();
     unsharp.save("examples/output/compare_unsharp.jpg")?;
     println!("Unsharp mask: {:?}", duration);
     
     // Test high-pass sharpening
     let start = Instant::now();
-    let highpass = image.clone().high_pass_sharpen(0.5)?;
+    let highpass = image.high_pass_sharpen_ref(0.5)?;
     let duration = start.elapsed();
     highpass.save("examples/output/compare_highpass.jpg")?;
     println!("High-pass: {:?}", duration);
     
     // Test edge enhancement (Sobel)
     let start = Instant::now();
-    let edges_sobel = image.clone().enhance_edges(1.0, EdgeMethod::Sobel)?;
+    let edges_sobel = image.enhance_edges_ref(1.0, EdgeMethod::Sobel)?;
     let duration = start.elapsed();
     edges_sobel.save("examples/output/compare_edges_sobel.jpg")?;
     println!("Edge enhancement (Sobel): {:?}", duration);
     
     // Test edge enhancement (Prewitt)
     let start = Instant::now();
-    let edges_prewitt = image.clone().enhance_edges(1.0, EdgeMethod::Prewitt)?;
+    let edges_prewitt = image.enhance_edges_ref(1.0, EdgeMethod::Prewitt)?;
     let duration = start.elapsed();
     edges_prewitt.save("examples/output/compare_edges_prewitt.jpg")?;
     println!("Edge enhancement (Prewitt): {:?}", duration);
     
     // Test clarity
     let start = Instant::now();
-    let clarity = image.clone().clarity(1.0, 2.0)?;
+    let clarity = image.clarity_ref(1.0, 2.0)?;
     let duration = start.elapsed();
     clarity.save("examples/output/compare_clarity.jpg")?;
     println!("Clarity: {:?}", duration);
@@ -0,0 +1,32 @@
+//! Example using a native file dialog to choose the image to sharpen
+//!
+//! Run with: cargo run --example pick_file --features dialog
+
+use sharpy::Image;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Some(input) = rfd::FileDialog::new()
+        .add_filter("Images", &["jpg", "jpeg", "png", "tif", "tiff", "bmp", "webp"])
+        .pick_file()
+    else {
+        println!("No file selected");
+        return Ok(());
+    };
+    
+    let image = Image::load(&input)?;
+    let sharpened = image.unsharp_mask(1.0, 1.0, 0)?;
+    
+    let Some(output) = rfd::FileDialog::new()
+        .set_file_name("sharpened.jpg")
+        .save_file()
+    else {
+        println!("No output selected");
+        return Ok(());
+    };
+    
+    sharpened.save(&output)?;
+    println!("Saved sharpened image to {}", output.display());
+    
+    Ok(())
+}
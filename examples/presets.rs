@@ -3,12 +3,14 @@
 use sharpy::{Image, SharpeningPresets};
 use std::error::Error;
 
+type PresetFn = fn(Image) -> sharpy::Result<Image>;
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Load an image
     let image = Image::load("tests/fixtures/lens.jpg")?;
     
     // Try different presets
-    let presets: &[(&str, fn(Image) -> sharpy::Result<Image>)] = &[
+    let presets: &[(&str, PresetFn)] = &[
         ("subtle", |img| SharpeningPresets::subtle(img).apply()),
         ("moderate", |img| SharpeningPresets::moderate(img).apply()),
         ("strong", |img| SharpeningPresets::strong(img).apply()),
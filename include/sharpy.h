@@ -0,0 +1,47 @@
+/* C interface to the Sharpy image sharpening library.
+ *
+ * Build the shared library (libsharpy.so, libsharpy.dylib or sharpy.dll
+ * under target/release) with:
+ *   cargo build --lib --release --no-default-features --features parallel,ffi
+ *
+ * Functions returning int return SHARPY_OK (0) on success or a negative
+ * error code; sharpy_last_error() describes the last failure on the
+ * calling thread. Functions returning a handle return NULL on failure.
+ */
+#ifndef SHARPY_H
+#define SHARPY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SHARPY_OK 0
+#define SHARPY_ERR_ARGUMENT (-1)
+#define SHARPY_ERR_FAILED (-2)
+#define SHARPY_ERR_PANIC (-3)
+
+typedef struct SharpyImage SharpyImage;
+
+const char *sharpy_last_error(void);
+
+SharpyImage *sharpy_load(const char *path);
+SharpyImage *sharpy_from_rgb(const uint8_t *data, size_t len, uint32_t width, uint32_t height);
+SharpyImage *sharpy_from_rgba(const uint8_t *data, size_t len, uint32_t width, uint32_t height);
+void sharpy_free(SharpyImage *image);
+
+int sharpy_dimensions(SharpyImage *image, uint32_t *width, uint32_t *height);
+int sharpy_unsharp(SharpyImage *image, float radius, float amount, uint8_t threshold);
+int sharpy_pipeline_run(SharpyImage *image, const char *pipeline);
+
+int sharpy_save(SharpyImage *image, const char *path);
+int sharpy_copy_rgb(SharpyImage *image, uint8_t *out, size_t len);
+int sharpy_copy_rgba(SharpyImage *image, uint8_t *out, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SHARPY_H */
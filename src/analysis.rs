@@ -0,0 +1,327 @@
+//! No-reference estimates of blur and noise, for choosing sharpening
+//! parameters before processing.
+
+use crate::pixel::{Buffer, SharpenPixel, unit_scale};
+use crate::utils::Luminance;
+use crate::{Operation, Pipeline};
+use crate::par::*;
+
+/// Width of the averaging filter used to re-blur the image.
+const REBLUR_TAPS: usize = 9;
+
+/// Blur and noise estimates returned by [`Image::analyze`](crate::Image::analyze).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+    /// Perceptual blur (0.0 = crisp, 1.0 = very soft), from how little
+    /// detail is lost when the image is blurred further
+    pub blur: f64,
+    /// Variance of the Laplacian of luminance; higher means more fine
+    /// detail. Only comparable between images of similar content and size
+    pub sharpness: f64,
+    /// Estimated standard deviation of noise, in 0-255 levels
+    pub noise: f64,
+}
+
+/// Where a result will be viewed, for [`Analysis::suggest_for`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Displays, viewed at 100%
+    #[default]
+    Screen,
+    /// Paper, where ink spread softens fine detail
+    Print,
+}
+
+impl std::str::FromStr for OutputTarget {
+    type Err = crate::ImageError;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "screen" => Ok(OutputTarget::Screen),
+            "print" => Ok(OutputTarget::Print),
+            _ => Err(crate::ImageError::Parse(format!(
+                "unknown output target '{}' (use 'screen' or 'print')", s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputTarget::Screen => write!(f, "screen"),
+            OutputTarget::Print => write!(f, "print"),
+        }
+    }
+}
+
+impl Analysis {
+    /// Suggests sharpening for this image: softer images get a larger
+    /// radius and amount, noisier ones a higher threshold and less amount
+    /// so grain isn't amplified, and soft, clean images some clarity.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let image = Image::load("photo.jpg")?;
+    /// let pipeline = image.analyze().suggest();
+    /// println!("{}", pipeline); // e.g. unsharp:1.5:1.1:1
+    /// pipeline.apply(image)?.save("photo_sharp.jpg")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn suggest(&self) -> Pipeline {
+        self.suggest_for(OutputTarget::Screen)
+    }
+
+    /// Like [`Analysis::suggest`], for results viewed on `target`. Prints
+    /// get a larger radius and amount to survive ink spread; screen results
+    /// get the `suggest` parameters.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::{Analysis, OutputTarget};
+    ///
+    /// let analysis = Analysis { blur: 0.4, sharpness: 150.0, noise: 1.0 };
+    /// assert_eq!(analysis.suggest_for(OutputTarget::Screen), analysis.suggest());
+    /// println!("{}", analysis.suggest_for(OutputTarget::Print)); // unsharp:2.3:1.2:2
+    /// ```
+    pub fn suggest_for(&self, target: OutputTarget) -> Pipeline {
+        let blur = self.blur as f32;
+        let noise = self.noise as f32;
+        let round = |value: f32| (value * 10.0).round() / 10.0;
+        let (radius_scale, amount_scale) = match target {
+            OutputTarget::Screen => (1.0, 1.0),
+            OutputTarget::Print => (1.5, 1.4),
+        };
+
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Operation::UnsharpMask {
+            radius: round(((0.6 + 2.4 * blur) * radius_scale).clamp(0.5, 3.0 * radius_scale)),
+            amount: round(((0.5 + 1.5 * blur) / (1.0 + noise / 4.0) * amount_scale).clamp(0.3, 2.0 * amount_scale)),
+            threshold: (noise * 2.0).round().clamp(0.0, 20.0) as u8,
+        });
+        if blur > 0.45 && noise < 2.0 {
+            pipeline.push(Operation::Clarity { strength: 0.2, radius: 3.0 });
+        }
+        pipeline
+    }
+
+    /// Suggests the built-in preset closest to [`Analysis::suggest`].
+    pub fn suggest_preset(&self) -> &'static str {
+        if self.noise > 3.0 {
+            "edge-aware"
+        } else if self.blur < 0.25 {
+            "subtle"
+        } else if self.blur > 0.5 {
+            "strong"
+        } else {
+            "moderate"
+        }
+    }
+}
+
+/// Analyzes `img` in 8-bit units, so estimates and the parameters
+/// suggested from them don't depend on the bit depth.
+pub(crate) fn analyze<P: SharpenPixel>(img: &Buffer<P>, luminance: Luminance) -> Analysis {
+    let (width, height) = img.dimensions();
+    let scale = unit_scale::<P>();
+    let luma: Vec<f32> = img.pixels().map(|pixel| luminance.of(pixel) / scale).collect();
+    let plane = Plane { values: &luma, width: width as usize, height: height as usize };
+
+    Analysis {
+        blur: blur(&plane),
+        sharpness: laplacian_variance(&plane),
+        noise: noise(&plane),
+    }
+}
+
+struct Plane<'a> {
+    values: &'a [f32],
+    width: usize,
+    height: usize,
+}
+
+impl Plane<'_> {
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.values[y * self.width + x]
+    }
+}
+
+/// Blur metric of Crété-Roffet et al. ("The Blur Effect", 2007): compares
+/// neighbor differences before and after a strong averaging blur, taking
+/// the blurrier of the horizontal and vertical directions.
+fn blur(plane: &Plane) -> f64 {
+    let horizontal = directional_blur(plane, |x, y| (x, y), plane.width, plane.height);
+    let vertical = directional_blur(plane, |x, y| (y, x), plane.height, plane.width);
+    horizontal.max(vertical)
+}
+
+/// `coords(i, line)` maps position `i` along a line to plane coordinates.
+fn directional_blur(plane: &Plane, coords: impl Fn(usize, usize) -> (usize, usize) + Sync, len: usize, lines: usize) -> f64 {
+    if len < 2 {
+        return 0.0;
+    }
+
+    let sums: Vec<(f64, f64)> = (0..lines).into_par_iter()
+        .map(|line| {
+            let value = |i: usize| {
+                let (x, y) = coords(i, line);
+                plane.at(x, y)
+            };
+            let half = REBLUR_TAPS / 2;
+            let blurred: Vec<f32> = (0..len)
+                .map(|i| {
+                    let sum: f32 = (0..REBLUR_TAPS).map(|k| value((i + k).saturating_sub(half).min(len - 1))).sum();
+                    sum / REBLUR_TAPS as f32
+                })
+                .collect();
+
+            let mut original = 0.0f64;
+            let mut retained = 0.0f64;
+            for i in 1..len {
+                let d_original = (value(i) - value(i - 1)).abs();
+                let d_blurred = (blurred[i] - blurred[i - 1]).abs();
+                original += d_original as f64;
+                retained += (d_original - d_blurred).max(0.0) as f64;
+            }
+            (original, retained)
+        })
+        .collect();
+    let (original, retained) = sums.iter().fold((0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    if original == 0.0 {
+        // Flat images have no detail to lose
+        return 1.0;
+    }
+    (original - retained) / original
+}
+
+fn laplacian_variance(plane: &Plane) -> f64 {
+    let responses = interior(plane, |x, y| {
+        plane.at(x - 1, y) + plane.at(x + 1, y) + plane.at(x, y - 1) + plane.at(x, y + 1) - 4.0 * plane.at(x, y)
+    });
+    if responses.is_empty() {
+        return 0.0;
+    }
+    let n = responses.len() as f64;
+    let mean = responses.iter().map(|&r| r as f64).sum::<f64>() / n;
+    responses.iter().map(|&r| (r as f64 - mean).powi(2)).sum::<f64>() / n
+}
+
+/// Estimated noise standard deviation of a luminance plane in 8-bit units.
+pub(crate) fn noise_level(luma: &[f32], width: u32, height: u32) -> f64 {
+    noise(&Plane { values: luma, width: width as usize, height: height as usize })
+}
+
+/// Noise estimate after Immerkær ("Fast Noise Variance Estimation", 1996):
+/// a mask that cancels smooth image structure leaves mostly noise. The
+/// median response is used instead of the mean so edges, which also pass
+/// the mask, don't count as noise unless they cover most of the image.
+fn noise(plane: &Plane) -> f64 {
+    let mut responses = interior(plane, |x, y| {
+        let corners = plane.at(x - 1, y - 1) + plane.at(x + 1, y - 1) + plane.at(x - 1, y + 1) + plane.at(x + 1, y + 1);
+        let sides = plane.at(x, y - 1) + plane.at(x - 1, y) + plane.at(x + 1, y) + plane.at(x, y + 1);
+        (corners - 2.0 * sides + 4.0 * plane.at(x, y)).abs()
+    });
+    if responses.is_empty() {
+        return 0.0;
+    }
+    let middle = responses.len() / 2;
+    let (_, median, _) = responses.select_nth_unstable_by(middle, f32::total_cmp);
+    // The mask has weight norm 6; for Gaussian noise the median absolute
+    // response is 0.6745 standard deviations
+    *median as f64 / (0.6745 * 6.0)
+}
+
+/// Evaluates `f` at every pixel with all four neighbors inside the plane.
+fn interior(plane: &Plane, f: impl Fn(usize, usize) -> f32 + Sync) -> Vec<f32> {
+    if plane.width < 3 || plane.height < 3 {
+        return Vec::new();
+    }
+    (1..plane.height - 1).into_par_iter()
+        .flat_map(|y| (1..plane.width - 1).map(|x| f(x, y)).collect::<Vec<_>>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::utils::gaussian_blur;
+    use crate::Image;
+    use image::{Rgb, RgbImage};
+
+    fn checkerboard() -> Image {
+        let img = RgbImage::from_fn(96, 96, |x, y| {
+            let value = if (x / 6 + y / 6) % 2 == 0 { 50 } else { 200 };
+            Rgb([value, value, value])
+        });
+        Image::from_rgb(img).unwrap()
+    }
+
+    /// Deterministic pseudo-random offsets in -amplitude..=amplitude.
+    fn add_noise(image: &Image, amplitude: i32) -> Image {
+        let mut state = 12345u32;
+        let noisy = RgbImage::from_fn(96, 96, |x, y| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let offset = (state >> 16) as i32 % (2 * amplitude + 1) - amplitude;
+            let value = image.as_rgb().get_pixel(x, y)[0] as i32 + offset;
+            let value = value.clamp(0, 255) as u8;
+            Rgb([value, value, value])
+        });
+        Image::from_rgb(noisy).unwrap()
+    }
+
+    #[test]
+    fn test_blur_increases_with_softness() {
+        let sharp = checkerboard();
+        let blurred = gaussian_blur(sharp.as_rgb(), 2.5, &Context::default());
+        let blurred = Image::from_rgb(blurred).unwrap();
+
+        let sharp_analysis = sharp.analyze();
+        let blurred_analysis = blurred.analyze();
+        assert!(sharp_analysis.blur < blurred_analysis.blur);
+        assert!(sharp_analysis.sharpness > blurred_analysis.sharpness);
+    }
+
+    #[test]
+    fn test_noise_estimate() {
+        let clean = checkerboard().analyze();
+        let noisy = add_noise(&checkerboard(), 10).analyze();
+        assert!(clean.noise < 1.5, "{}", clean.noise);
+        // Uniform noise in -10..=10 has a standard deviation of about 6
+        assert!((4.0..8.0).contains(&noisy.noise), "{}", noisy.noise);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let crisp = Analysis { blur: 0.1, sharpness: 500.0, noise: 0.5 };
+        let soft = Analysis { blur: 0.6, sharpness: 20.0, noise: 0.5 };
+        let grainy = Analysis { blur: 0.4, sharpness: 300.0, noise: 6.0 };
+
+        assert_eq!(crisp.suggest().to_string(), "unsharp:0.8:0.6:1");
+        assert_eq!(soft.suggest().to_string(), "unsharp:2:1.2:1,clarity:0.2:3");
+        assert_eq!(grainy.suggest().to_string(), "unsharp:1.6:0.4:12");
+        assert_eq!(
+            [crisp, soft, grainy].map(|analysis| analysis.suggest_preset()),
+            ["subtle", "strong", "edge-aware"]
+        );
+        for analysis in [crisp, soft, grainy] {
+            assert!(analysis.suggest().operations().iter().all(|op| op.validate().is_ok()));
+            assert_eq!(analysis.suggest_for(OutputTarget::Screen), analysis.suggest());
+            assert!(analysis.suggest_for(OutputTarget::Print).operations().iter().all(|op| op.validate().is_ok()));
+        }
+        assert_eq!(soft.suggest_for(OutputTarget::Print).to_string(), "unsharp:3.1:1.7:1,clarity:0.2:3");
+    }
+
+    #[test]
+    fn test_flat_and_tiny_images() {
+        let flat = Image::from_rgb(RgbImage::from_pixel(16, 16, Rgb([90, 90, 90]))).unwrap().analyze();
+        assert_eq!((flat.blur, flat.sharpness, flat.noise), (1.0, 0.0, 0.0));
+        let tiny = Image::from_rgb(RgbImage::new(1, 1)).unwrap().analyze();
+        assert_eq!(tiny.noise, 0.0);
+    }
+}
@@ -0,0 +1,386 @@
+//! Animated GIF, APNG and WebP images.
+//!
+//! [`Image::load`] decodes only the first frame of an animation.
+//! [`AnimatedImage`] decodes every frame, sharpens each with a [`Pipeline`]
+//! and writes GIF or APNG with the original frame delays and loop count.
+//! Animated WebP can be read but not written.
+//!
+//! The gif, png and image-webp crates read what `image` leaves out (loop
+//! counts and APNG output); they're the versions `image` itself builds on,
+//! so no codec is compiled twice.
+
+use crate::{Image, ImageError, Limits, Pipeline, Result};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Delay, DynamicImage, ImageDecoder, ImageFormat, Rgba};
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+/// Quantization speed for GIF frames (1 best to 30 fastest), trading a
+/// little palette quality for encoding time on long animations.
+const GIF_SPEED: i32 = 10;
+
+/// How many times an animation plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCount {
+    /// Loops forever
+    Infinite,
+    /// Plays this many times in total (at least once)
+    Plays(u16),
+}
+
+/// One frame of an animation: the full canvas and how long it shows.
+#[derive(Clone)]
+pub struct AnimationFrame {
+    /// The whole canvas as this frame shows it
+    pub image: Image<Rgba<u8>>,
+    /// How long the frame shows before the next one
+    pub delay: Duration,
+}
+
+/// Every frame of an animated image, with its timing.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{AnimatedImage, Pipeline};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let animation = AnimatedImage::load("banner.gif")?;
+/// let pipeline = Pipeline::parse("unsharp:0.8:0.6:2")?;
+/// animation.apply_parallel(&pipeline)?.save("banner_sharp.gif")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AnimatedImage {
+    frames: Vec<AnimationFrame>,
+    loop_count: LoopCount,
+}
+
+impl AnimatedImage {
+    /// Creates an animation from frames, which must be non-empty and all of
+    /// the same size.
+    pub fn new(frames: Vec<AnimationFrame>, loop_count: LoopCount) -> Result<Self> {
+        let Some(first) = frames.first() else {
+            return Err(ImageError::InvalidParameter {
+                param: "frames".to_string(),
+                value: "0".to_string(),
+            });
+        };
+        let size = first.image.dimensions();
+        if let Some(frame) = frames.iter().find(|frame| frame.image.dimensions() != size) {
+            let (width, height) = frame.image.dimensions();
+            return Err(ImageError::InvalidDimensions { width, height });
+        }
+        Ok(Self { frames, loop_count })
+    }
+
+    /// Loads an animated GIF, PNG or WebP file. Still images load as a
+    /// single frame.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let load = || Self::from_bytes(&std::fs::read(path)?);
+        load().map_err(|e| e.in_file(path))
+    }
+
+    /// Decodes an animation from memory with the default [`Limits`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_limits(bytes, &Limits::default())
+    }
+
+    /// Decodes an animation from memory, rejecting it if the canvas exceeds
+    /// `limits` or all frames together exceed `limits.max_decoded_bytes`.
+    pub fn from_bytes_with_limits(bytes: &[u8], limits: &Limits) -> Result<Self> {
+        let format = image::guess_format(bytes)?;
+        let (frames, loop_count) = match format {
+            ImageFormat::Gif => {
+                let decoder = checked(GifDecoder::new(Cursor::new(bytes))?, limits)?;
+                (decoder.into_frames(), gif_loop_count(bytes)?)
+            }
+            ImageFormat::Png if PngDecoder::new(Cursor::new(bytes))?.is_apng()? => {
+                let decoder = checked(PngDecoder::new(Cursor::new(bytes))?, limits)?;
+                (decoder.apng()?.into_frames(), png_loop_count(bytes)?)
+            }
+            ImageFormat::WebP => {
+                let decoder = checked(WebPDecoder::new(Cursor::new(bytes))?, limits)?;
+                if !decoder.has_animation() {
+                    return Self::still(bytes, limits);
+                }
+                (decoder.into_frames(), webp_loop_count(bytes)?)
+            }
+            _ => return Self::still(bytes, limits),
+        };
+
+        let mut decoded = Vec::new();
+        let mut total_bytes = 0u64;
+        for frame in frames {
+            let frame = frame?;
+            let delay = Duration::from(frame.delay());
+            let buffer = frame.into_buffer();
+            total_bytes += buffer.as_raw().len() as u64;
+            if total_bytes > limits.max_decoded_bytes {
+                return Err(ImageError::LimitExceeded {
+                    limit: "decoded_bytes".to_string(),
+                    value: total_bytes,
+                    max: limits.max_decoded_bytes,
+                });
+            }
+            decoded.push(AnimationFrame { image: Image::from_buffer_with_limits(buffer, limits)?, delay });
+        }
+        Self::new(decoded, loop_count)
+    }
+
+    fn still(bytes: &[u8], limits: &Limits) -> Result<Self> {
+        let decoder = image::ImageReader::new(Cursor::new(bytes)).with_guessed_format()?.into_decoder()?;
+        let decoder = checked(decoder, limits)?;
+        let buffer = DynamicImage::from_decoder(decoder)?.into_rgba8();
+        let frame = AnimationFrame { image: Image::from_buffer_with_limits(buffer, limits)?, delay: Duration::ZERO };
+        Self::new(vec![frame], LoopCount::Plays(1))
+    }
+
+    /// Returns the frames in display order.
+    pub fn frames(&self) -> &[AnimationFrame] {
+        &self.frames
+    }
+
+    /// Returns the frames, consuming the animation.
+    pub fn into_frames(self) -> Vec<AnimationFrame> {
+        self.frames
+    }
+
+    /// Returns how many times the animation plays. [`LoopCount::Infinite`],
+    /// stored in files as a loop count of 0, means it loops forever.
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
+    }
+
+    /// Returns the canvas size shared by all frames.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].image.dimensions()
+    }
+
+    /// Applies `pipeline` to each frame in turn, keeping delays and the
+    /// loop count.
+    pub fn apply(self, pipeline: &Pipeline) -> Result<Self> {
+        let frames = self.frames.into_iter()
+            .map(|frame| Ok(AnimationFrame { image: pipeline.apply(frame.image)?, delay: frame.delay }))
+            .collect::<Result<_>>()?;
+        Ok(Self { frames, ..self })
+    }
+
+    /// Like [`AnimatedImage::apply`], processing several frames at once
+    /// with [`Pipeline::run_all`]. Suits animations of many small frames.
+    pub fn apply_parallel(self, pipeline: &Pipeline) -> Result<Self> {
+        let (images, delays): (Vec<_>, Vec<_>) = self.frames.into_iter()
+            .map(|frame| (frame.image, frame.delay))
+            .unzip();
+        let frames = pipeline.run_all(images).into_iter()
+            .zip(delays)
+            .map(|(image, delay)| Ok(AnimationFrame { image: image?, delay }))
+            .collect::<Result<_>>()?;
+        Ok(Self { frames, ..self })
+    }
+
+    /// Saves as GIF or APNG, chosen by the file extension. Animated WebP
+    /// can't be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let save = || -> Result<()> {
+            let format = ImageFormat::from_path(path)?;
+            std::fs::write(path, self.to_bytes(format)?)?;
+            Ok(())
+        };
+        save().map_err(|e| e.in_file(path))
+    }
+
+    /// Encodes as GIF or APNG ([`ImageFormat::Png`]).
+    ///
+    /// GIF frames are reduced to a 256-color palette each. Animated WebP
+    /// can be read but not written.
+    pub fn to_bytes(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        match format {
+            ImageFormat::Gif => self.encode_gif(),
+            ImageFormat::Png => self.encode_apng(),
+            other => Err(ImageError::Encode(format!(
+                "animations can't be saved as {:?}; use GIF or PNG (APNG)", other
+            ))),
+        }
+    }
+
+    fn encode_gif(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+            // GIF counts repeats after the first play; without the
+            // extension an animation plays once
+            match self.loop_count {
+                LoopCount::Infinite => encoder.set_repeat(Repeat::Infinite)?,
+                LoopCount::Plays(plays) if plays > 1 => encoder.set_repeat(Repeat::Finite(plays - 1))?,
+                LoopCount::Plays(_) => {}
+            }
+            encoder.encode_frames(self.frames.iter().map(|frame| {
+                let delay = Delay::from_saturating_duration(frame.delay);
+                image::Frame::from_parts(frame.image.as_buffer().clone(), 0, 0, delay)
+            }))?;
+        }
+        Ok(bytes)
+    }
+
+    fn encode_apng(&self) -> Result<Vec<u8>> {
+        let encode_error = |e: png::EncodingError| ImageError::Encode(e.to_string());
+        let (width, height) = self.dimensions();
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let animated = self.frames.len() > 1;
+        if animated {
+            let plays = match self.loop_count {
+                LoopCount::Infinite => 0,
+                LoopCount::Plays(plays) => u32::from(plays.max(1)),
+            };
+            encoder.set_animated(self.frames.len() as u32, plays).map_err(encode_error)?;
+        }
+
+        let mut writer = encoder.write_header().map_err(encode_error)?;
+        for frame in &self.frames {
+            if animated {
+                let (numerator, denominator) = apng_delay(frame.delay);
+                writer.set_frame_delay(numerator, denominator).map_err(encode_error)?;
+            }
+            writer.write_image_data(frame.image.as_buffer().as_raw()).map_err(encode_error)?;
+        }
+        writer.finish().map_err(encode_error)?;
+        Ok(bytes)
+    }
+}
+
+/// Checks a decoder's header against `limits` and bounds its allocations.
+fn checked<D: ImageDecoder>(mut decoder: D, limits: &Limits) -> Result<D> {
+    limits.check_decoder(&decoder)?;
+    decoder.set_limits(limits.to_image_limits())?;
+    Ok(decoder)
+}
+
+fn gif_loop_count(bytes: &[u8]) -> Result<LoopCount> {
+    let decoder = gif::DecodeOptions::new().read_info(Cursor::new(bytes))
+        .map_err(|e| ImageError::Parse(e.to_string()))?;
+    // Without the looping extension the animation plays once
+    Ok(match decoder.repeat() {
+        gif::Repeat::Infinite => LoopCount::Infinite,
+        gif::Repeat::Finite(repeats) => LoopCount::Plays(repeats.saturating_add(1)),
+    })
+}
+
+fn png_loop_count(bytes: &[u8]) -> Result<LoopCount> {
+    let reader = png::Decoder::new(Cursor::new(bytes)).read_info()
+        .map_err(|e| ImageError::Parse(e.to_string()))?;
+    Ok(match reader.info().animation_control.map(|control| control.num_plays) {
+        Some(0) => LoopCount::Infinite,
+        Some(plays) => LoopCount::Plays(u16::try_from(plays).unwrap_or(u16::MAX)),
+        None => LoopCount::Plays(1),
+    })
+}
+
+fn webp_loop_count(bytes: &[u8]) -> Result<LoopCount> {
+    let decoder = image_webp::WebPDecoder::new(Cursor::new(bytes))
+        .map_err(|e| ImageError::Parse(e.to_string()))?;
+    Ok(match decoder.loop_count() {
+        image_webp::LoopCount::Forever => LoopCount::Infinite,
+        image_webp::LoopCount::Times(plays) => LoopCount::Plays(plays.get()),
+    })
+}
+
+/// A frame delay as the seconds fraction APNG stores, in milliseconds when
+/// it fits.
+fn apng_delay(delay: Duration) -> (u16, u16) {
+    match u16::try_from(delay.as_millis()) {
+        Ok(millis) => (millis, 1000),
+        Err(_) => (u16::try_from(delay.as_secs()).unwrap_or(u16::MAX), 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn create_test_animation(loop_count: LoopCount) -> AnimatedImage {
+        let frames = (0..3u32)
+            .map(|i| AnimationFrame {
+                image: Image::from_buffer(RgbaImage::from_fn(24, 16, |x, y| {
+                    let value = if (x + y + i * 4) % 8 < 4 { 40 } else { 200 };
+                    Rgba([value, value, value, 255])
+                })).unwrap(),
+                delay: Duration::from_millis(100 + u64::from(i) * 50),
+            })
+            .collect();
+        AnimatedImage::new(frames, loop_count).unwrap()
+    }
+
+    fn delays(animation: &AnimatedImage) -> Vec<Duration> {
+        animation.frames().iter().map(|frame| frame.delay).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ImageFormat::Gif, ImageFormat::Png] {
+            for loop_count in [LoopCount::Infinite, LoopCount::Plays(1), LoopCount::Plays(3)] {
+                let animation = create_test_animation(loop_count);
+                let decoded = AnimatedImage::from_bytes(&animation.to_bytes(format).unwrap()).unwrap();
+                assert_eq!(decoded.frames().len(), 3, "{:?}", format);
+                assert_eq!(decoded.loop_count(), loop_count, "{:?}", format);
+                assert_eq!(delays(&decoded), delays(&animation), "{:?}", format);
+                assert_eq!(decoded.dimensions(), (24, 16));
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_keeps_timing() {
+        let pipeline = Pipeline::parse("unsharp:1:1.5:0").unwrap();
+        let animation = create_test_animation(LoopCount::Plays(2));
+        let sequential = animation.clone().apply(&pipeline).unwrap();
+        let parallel = animation.clone().apply_parallel(&pipeline).unwrap();
+
+        assert_eq!(delays(&sequential), delays(&animation));
+        assert_eq!(sequential.loop_count(), LoopCount::Plays(2));
+        for ((original, sequential), parallel) in animation.frames().iter().zip(sequential.frames()).zip(parallel.frames()) {
+            assert_ne!(sequential.image.as_buffer(), original.image.as_buffer());
+            assert_eq!(sequential.image.as_buffer(), parallel.image.as_buffer());
+        }
+    }
+
+    #[test]
+    fn test_still_image_is_one_frame() {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(8, 8)).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        let animation = AnimatedImage::from_bytes(&bytes).unwrap();
+        assert_eq!(animation.frames().len(), 1);
+        assert_eq!(animation.loop_count(), LoopCount::Plays(1));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(AnimatedImage::new(Vec::new(), LoopCount::Infinite).is_err());
+
+        let mut frames = create_test_animation(LoopCount::Infinite).into_frames();
+        frames.push(AnimationFrame { image: Image::from_buffer(RgbaImage::new(8, 8)).unwrap(), delay: Duration::ZERO });
+        assert!(matches!(
+            AnimatedImage::new(frames, LoopCount::Infinite),
+            Err(ImageError::InvalidDimensions { width: 8, height: 8 })
+        ));
+
+        let animation = create_test_animation(LoopCount::Infinite);
+        assert!(matches!(animation.to_bytes(ImageFormat::WebP), Err(ImageError::Encode(_))));
+
+        let bytes = animation.to_bytes(ImageFormat::Gif).unwrap();
+        let limits = Limits::default().max_decoded_bytes(24 * 16 * 4 * 2);
+        assert!(matches!(
+            AnimatedImage::from_bytes_with_limits(&bytes, &limits),
+            Err(ImageError::LimitExceeded { .. })
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+//! Progress records that let an interrupted `sharpy batch` resume.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Minimum time between writes, so large batches don't spend their time
+/// rewriting the file. An interruption loses at most this much progress.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    /// The operations the files were processed with
+    operations: String,
+    /// Inputs whose outputs have been written
+    completed: BTreeSet<String>,
+}
+
+/// The completed inputs of a batch, kept in a JSON file.
+pub struct Checkpoint {
+    path: PathBuf,
+    state: Mutex<(State, Instant)>,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint at `path`, or starts an empty one if the file
+    /// doesn't exist. Fails if it was written for other operations, since
+    /// its files would need processing again.
+    pub fn open(path: &Path, operations: &str) -> Result<Self> {
+        let state = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read checkpoint: {}", path.display()))?;
+            let state: State = serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid checkpoint: {}", path.display()))?;
+            if state.operations != operations {
+                anyhow::bail!(
+                    "Checkpoint {} was written for operations '{}'; delete it to start over with '{}'",
+                    path.display(), state.operations, operations
+                );
+            }
+            state
+        } else {
+            State { operations: operations.to_string(), ..State::default() }
+        };
+
+        Ok(Self { path: path.to_path_buf(), state: Mutex::new((state, Instant::now())) })
+    }
+
+    pub fn is_completed(&self, input: &Path) -> bool {
+        self.lock().0.completed.contains(&*input.to_string_lossy())
+    }
+
+    /// Records `input` as completed, writing the file if it hasn't been
+    /// written recently.
+    pub fn complete(&self, input: &Path) -> Result<()> {
+        let mut guard = self.lock();
+        guard.0.completed.insert(input.to_string_lossy().into_owned());
+        if guard.1.elapsed() >= SAVE_INTERVAL {
+            self.write(&guard.0)?;
+            guard.1 = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Writes every completion recorded so far.
+    pub fn save(&self) -> Result<()> {
+        self.write(&self.lock().0)
+    }
+
+    fn write(&self, state: &State) -> Result<()> {
+        // Write then rename so an interruption never leaves a truncated file
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, serde_json::to_string_pretty(state)?)
+            .and_then(|_| std::fs::rename(&temp, &self.path))
+            .with_context(|| format!("Failed to write checkpoint: {}", self.path.display()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, (State, Instant)> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let path = std::env::temp_dir().join(format!("sharpy_checkpoint_{}.json", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let (done, pending) = (Path::new("in/a.jpg"), Path::new("in/b.jpg"));
+
+        let checkpoint = Checkpoint::open(&path, "unsharp:1:1:0").unwrap();
+        assert!(!checkpoint.is_completed(done));
+        checkpoint.complete(done).unwrap();
+        assert!(checkpoint.is_completed(done));
+        checkpoint.save().unwrap();
+
+        // A later run picks up where this one stopped, for the same operations only
+        let resumed = Checkpoint::open(&path, "unsharp:1:1:0").unwrap();
+        assert!(resumed.is_completed(done) && !resumed.is_completed(pending));
+        let error = Checkpoint::open(&path, "unsharp:2:1:0").err().unwrap();
+        assert!(error.to_string().contains("delete it to start over"), "{}", error);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(Checkpoint::open(&path, "unsharp:1:1:0").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
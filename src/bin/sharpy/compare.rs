@@ -0,0 +1,75 @@
+//! `sharpy compare`: before/after images for judging settings without an
+//! external viewer.
+
+use anyhow::Result;
+use image::{imageops, Rgb, RgbImage};
+use sharpy::Image;
+use std::path::Path;
+
+/// Width of the line separating the original from the processed image.
+const DIVIDER_WIDTH: u32 = 2;
+const DIVIDER_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// Original left of the split position, processed right of it
+    Wipe,
+    /// Original and processed images next to each other in full
+    SideBySide,
+}
+
+pub fn render(
+    cli: &crate::Cli,
+    input: &Path,
+    output: &Path,
+    recipe: &crate::Recipe,
+    layout: Layout,
+    position: f32,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&position) {
+        anyhow::bail!("--position must be between 0 and 1, got {}", position);
+    }
+
+    crate::process_single_image(cli, input, output, |original| {
+        let processed = recipe.pipeline.operations().iter().fold(original.clone().sharpen(), |builder, operation| {
+            let builder = builder.operation(operation.clone());
+            match recipe.chroma_clamp {
+                Some(tolerance) => builder.chroma_clamp(tolerance),
+                None => builder,
+            }
+        });
+        let processed = processed.protect_stars(recipe.protect_stars).apply()?;
+        compose(&original, &processed, layout, position)
+    })
+}
+
+fn compose(original: &Image, processed: &Image, layout: Layout, position: f32) -> sharpy::Result<Image> {
+    let (before, after) = (original.as_rgb(), processed.as_rgb());
+    let (width, height) = before.dimensions();
+
+    let composed = match layout {
+        Layout::Wipe => {
+            let split = (width as f32 * position).round() as u32;
+            let divider = split.saturating_sub(DIVIDER_WIDTH / 2)..split + DIVIDER_WIDTH / 2;
+            RgbImage::from_fn(width, height, |x, y| {
+                if divider.contains(&x) && split > 0 && split < width {
+                    DIVIDER_COLOR
+                } else if x < split {
+                    *before.get_pixel(x, y)
+                } else {
+                    *after.get_pixel(x, y)
+                }
+            })
+        }
+        Layout::SideBySide => {
+            let mut composed = RgbImage::from_pixel(width * 2 + DIVIDER_WIDTH, height, DIVIDER_COLOR);
+            imageops::replace(&mut composed, before, 0, 0);
+            imageops::replace(&mut composed, after, (width + DIVIDER_WIDTH) as i64, 0);
+            composed
+        }
+    };
+
+    Ok(Image::from_rgb(composed)?
+        .with_icc_profile(original.icc_profile().map(<[u8]>::to_vec))
+        .with_color_type(original.color_type()))
+}
@@ -0,0 +1,66 @@
+//! `sharpy completions` and `sharpy manpage`: shell completion scripts and
+//! man pages generated from the command definitions, for packagers and
+//! users.
+
+use anyhow::{Context, Result};
+use clap::builder::PossibleValuesParser;
+use clap::{Command, CommandFactory};
+use clap_complete::Shell;
+use sharpy::{EdgeMethod, Operation, PresetRegistry};
+use std::path::Path;
+
+/// Prints the completion script for `shell`.
+pub fn completions(shell: Shell) {
+    let mut command = with_value_hints(crate::Cli::command());
+    clap_complete::generate(shell, &mut command, "sharpy", &mut std::io::stdout());
+}
+
+/// Prints the man page, or writes one page per subcommand to `dir`.
+pub fn manpage(dir: Option<&Path>) -> Result<()> {
+    let command = crate::Cli::command();
+    match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+            clap_mangen::generate_to(command, dir)
+                .with_context(|| format!("Failed to write man pages to {}", dir.display()))
+        }
+        None => Ok(clap_mangen::Man::new(command).render(&mut std::io::stdout())?),
+    }
+}
+
+/// Offers the built-in preset names for `--preset` and an example of each
+/// operation for `--operations`. Only completion sees these lists; the
+/// arguments still accept presets from files and any parameters.
+fn with_value_hints(mut command: Command) -> Command {
+    let presets: Vec<String> = PresetRegistry::builtin().iter().map(|preset| preset.name.clone()).collect();
+    // The defaults of the single-operation subcommands
+    let operations = [
+        Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 },
+        Operation::RelativeUnsharp { radius: 1.0, amount: 1.0, threshold: 5.0 },
+        Operation::HighPassSharpen { strength: 0.5 },
+        Operation::EnhanceEdges { strength: 1.0, method: EdgeMethod::Sobel },
+        Operation::Clarity { strength: 1.0, radius: 2.0 },
+        Operation::AdaptiveUnsharp { min_radius: 1.0, max_radius: 4.0, amount: 1.0, threshold: 0 },
+        Operation::TextSharpen { stroke_width: 1.5, strength: 1.5 },
+        Operation::Descreen { period: None },
+        Operation::Median { radius: 1 },
+        Operation::Denoise { strength: 1.0, patch_radius: 1, search_radius: 7 },
+        Operation::SmartEnhance { level: 1.0 },
+        Operation::FrequencySeparation { radius: 2.0, smoothing: 0.4, amount: 0.8 },
+    ].map(|operation| operation.to_string());
+
+    let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    for name in names {
+        command = command.mut_subcommand(name, |mut sub| {
+            for (id, values) in [("preset", &presets[..]), ("operations", &operations[..])] {
+                if sub.get_arguments().any(|arg| arg.get_id() == id) {
+                    let values = values.to_vec();
+                    sub = sub.mut_arg(id, |arg| arg.value_parser(PossibleValuesParser::new(values)));
+                }
+            }
+            sub
+        });
+    }
+    command
+}
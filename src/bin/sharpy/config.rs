@@ -0,0 +1,88 @@
+//! User defaults read from `~/.config/sharpy/config.toml`.
+//!
+//! ```toml
+//! quality = 92                    # JPEG quality
+//! preset = "portrait"             # when no --preset or --operations is given
+//! jobs = 4                        # sharpy batch --jobs
+//! name_template = "{name}_web.{ext}"
+//! ```
+//!
+//! Every setting is optional, and command-line flags take precedence.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub quality: Option<u8>,
+    pub preset: Option<String>,
+    pub jobs: Option<NonZeroUsize>,
+    pub name_template: Option<String>,
+}
+
+impl Config {
+    /// Loads `path`, or the default location if `None`. A missing file at the
+    /// default location means no defaults; an explicitly given one is an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        if !explicit && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
+        if let Some(quality) = config.quality.filter(|quality| !(1..=100).contains(quality)) {
+            anyhow::bail!("Invalid config file: {}: quality {} is outside 1-100", path.display(), quality);
+        }
+        if let Some(template) = &config.name_template {
+            check_template(template)
+                .with_context(|| format!("Invalid config file: {}", path.display()))?;
+        }
+        Ok(config)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/sharpy/config.toml`, falling back to `~/.config` (or
+/// `%APPDATA%` on Windows).
+fn default_path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = var("XDG_CONFIG_HOME")
+        .or_else(|| var("HOME").map(|home| home.join(".config")))
+        .or_else(|| var("APPDATA"))?;
+    Some(base.join("sharpy").join("config.toml"))
+}
+
+/// Fails if `template` uses placeholders other than `{name}` and `{ext}`.
+pub fn check_template(template: &str) -> Result<()> {
+    let rest = template.replace("{name}", "").replace("{ext}", "");
+    if rest.contains(['{', '}']) {
+        anyhow::bail!("name template '{}' may only use the placeholders {{name}} and {{ext}}", template);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_template() {
+        for template in ["{name}_web.{ext}", "{name}.jpg", "sharp/{name}-{name}.{ext}", "fixed.png"] {
+            assert!(check_template(template).is_ok(), "{}", template);
+        }
+        for template in ["{stem}.{ext}", "{name}_{n}.{ext}", "{name.{ext}", "{name}}.jpg", "{}"] {
+            assert!(check_template(template).is_err(), "{}", template);
+        }
+    }
+}
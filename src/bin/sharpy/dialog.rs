@@ -0,0 +1,70 @@
+//! Native file dialogs used by `--pick`.
+//!
+//! Only functional when the CLI is built with the `dialog` feature; otherwise
+//! every entry point reports how to enable it.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "dialog")]
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "bmp", "webp"];
+
+/// Resolves input and output paths, asking the user for any that are missing.
+///
+/// Paths given on the command line always win; the dialog only fills gaps.
+pub fn resolve_paths(input: Option<&Path>, output: Option<&Path>) -> Result<(PathBuf, PathBuf)> {
+    let input = match input {
+        Some(path) => path.to_path_buf(),
+        None => pick_input()?,
+    };
+
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => pick_output(&input)?,
+    };
+
+    Ok((input, output))
+}
+
+#[cfg(feature = "dialog")]
+fn pick_input() -> Result<PathBuf> {
+    rfd::FileDialog::new()
+        .set_title("Select image to sharpen")
+        .add_filter("Images", IMAGE_EXTENSIONS)
+        .pick_file()
+        .ok_or_else(|| anyhow::anyhow!("No input file selected"))
+}
+
+#[cfg(feature = "dialog")]
+fn pick_output(input: &Path) -> Result<PathBuf> {
+    let mut dialog = rfd::FileDialog::new()
+        .set_title("Save sharpened image as")
+        .add_filter("Images", IMAGE_EXTENSIONS)
+        .set_file_name(default_output_name(input));
+
+    if let Some(dir) = input.parent() {
+        dialog = dialog.set_directory(dir);
+    }
+
+    dialog
+        .save_file()
+        .ok_or_else(|| anyhow::anyhow!("No output file selected"))
+}
+
+#[cfg(not(feature = "dialog"))]
+fn pick_input() -> Result<PathBuf> {
+    anyhow::bail!("--pick requires sharpy to be built with the `dialog` feature")
+}
+
+#[cfg(not(feature = "dialog"))]
+fn pick_output(_input: &Path) -> Result<PathBuf> {
+    anyhow::bail!("--pick requires sharpy to be built with the `dialog` feature")
+}
+
+/// Suggests `name_sharp.ext` next to the input as the default save name.
+#[cfg_attr(not(feature = "dialog"), allow(dead_code))]
+fn default_output_name(input: &Path) -> String {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let extension = input.extension().and_then(|s| s.to_str()).unwrap_or("jpg");
+    format!("{}_sharp.{}", stem, extension)
+}
@@ -0,0 +1,175 @@
+//! Exit codes and `--errors json`, so scripts wrapping sharpy can tell
+//! kinds of failure apart.
+//!
+//! | Code | Kind                 | Meaning                                          |
+//! |------|----------------------|--------------------------------------------------|
+//! | 0    |                      | Success                                          |
+//! | 1    | `other`              | Any other error, e.g. an output that can't be written |
+//! | 2    | `usage`              | Invalid arguments or operation strings           |
+//! | 3    | `input`              | An input couldn't be read or decoded             |
+//! | 4    | `invalid_parameters` | Operation parameters out of range                |
+//! | 5    | `partial_batch`      | Some files of a batch failed                     |
+//! | 6    | `reference_mismatch` | Batch outputs differ from their references       |
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// `Error: message` on stderr
+    #[default]
+    Text,
+    /// One JSON object on stderr with the kind, exit code and message
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Other,
+    Usage,
+    Input,
+    InvalidParameters,
+    PartialBatch,
+    ReferenceMismatch,
+}
+
+impl Kind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Kind::Other => 1,
+            Kind::Usage => 2,
+            Kind::Input => 3,
+            Kind::InvalidParameters => 4,
+            Kind::PartialBatch => 5,
+            Kind::ReferenceMismatch => 6,
+        }
+    }
+
+    /// The kind of `error`: the outermost [`Failure`] in its chain, else
+    /// derived from the library error it wraps.
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(failure) = error.downcast_ref::<Failure>() {
+            return failure.kind;
+        }
+        match error.downcast_ref::<sharpy::ImageError>().map(sharpy::ImageError::root) {
+            Some(sharpy::ImageError::Validation(_) | sharpy::ImageError::InvalidParameter { .. }) => Kind::InvalidParameters,
+            Some(sharpy::ImageError::Parse(_)) => Kind::Usage,
+            _ => Kind::Other,
+        }
+    }
+}
+
+/// An error message of a known [`Kind`], as an error or as context for
+/// errors the library doesn't classify.
+#[derive(Debug)]
+pub struct Failure {
+    kind: Kind,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(kind: Kind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// `error` and its causes on one line, leaving out causes whose text an
+/// outer error already includes, and leading parts of a cause (such as a
+/// file path) that the outer errors end with.
+pub fn message(error: &anyhow::Error) -> String {
+    let mut message = String::new();
+    for cause in error.chain().map(ToString::to_string) {
+        if message.contains(&cause) {
+            continue;
+        }
+        let overlap = cause.match_indices(": ").map(|(i, _)| i).filter(|&i| message.ends_with(&cause[..i])).last();
+        let cause = overlap.map_or(cause.as_str(), |i| &cause[i + 2..]);
+        if !message.is_empty() {
+            message.push_str(": ");
+        }
+        message.push_str(cause);
+    }
+    message
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    kind: Kind,
+    exit_code: u8,
+    message: &'a str,
+}
+
+/// Prints `message` to stderr in `format`.
+pub fn report(format: ErrorFormat, kind: Kind, message: &str) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {}", message),
+        ErrorFormat::Json => {
+            let report = Report { kind, exit_code: kind.exit_code(), message };
+            eprintln!("{}", serde_json::to_string(&report).unwrap_or_default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sharpy::ImageError;
+    use std::path::PathBuf;
+
+    fn io_error() -> ImageError {
+        ImageError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "not found"))
+    }
+
+    #[test]
+    fn test_message_drops_repeated_causes() {
+        // The path ending the context isn't repeated by the file error
+        let file = ImageError::File { path: PathBuf::from("in/a.png"), source: Box::new(io_error()) };
+        let error = anyhow::Error::new(file).context("Failed to load image: in/a.png");
+        assert_eq!(message(&error), "Failed to load image: in/a.png: IO error: not found");
+
+        // Causes an outer error already includes are left out
+        let error = anyhow::Error::new(io_error()).context("Failed to load image: IO error: not found");
+        assert_eq!(message(&error), "Failed to load image: IO error: not found");
+
+        // Unrelated causes are kept in order
+        let error = anyhow::Error::new(ImageError::Parse("bogus".to_string())).context("Failed to read pipeline");
+        assert_eq!(message(&error), "Failed to read pipeline: Parse error: bogus");
+
+        // Only a leading part that the message ends with is dropped
+        let file = ImageError::File { path: PathBuf::from("b.png"), source: Box::new(io_error()) };
+        let error = anyhow::Error::new(file).context("Failed to load image: in/a.png");
+        assert_eq!(message(&error), "Failed to load image: in/a.png: b.png: IO error: not found");
+    }
+
+    #[test]
+    fn test_kind() {
+        let codes: Vec<u8> = [Kind::Other, Kind::Usage, Kind::Input, Kind::InvalidParameters, Kind::PartialBatch, Kind::ReferenceMismatch]
+            .into_iter()
+            .map(Kind::exit_code)
+            .collect();
+        assert_eq!(codes, [1, 2, 3, 4, 5, 6]);
+
+        // The outermost failure decides, whatever it wraps
+        let error = anyhow::Error::new(io_error()).context(Failure::new(Kind::Input, "Failed to read stdin"));
+        assert_eq!(Kind::of(&error), Kind::Input);
+        let error = anyhow::Error::new(Failure::new(Kind::Usage, "bad flag")).context(Failure::new(Kind::PartialBatch, "2 files failed"));
+        assert_eq!(Kind::of(&error), Kind::PartialBatch);
+
+        // Otherwise the library error underneath any operation names it
+        let invalid = ImageError::InvalidParameter { param: "radius".to_string(), value: "-1".to_string() };
+        let error = anyhow::Error::new(ImageError::Operation { operation: "Unsharp Mask", index: Some(0), source: Box::new(invalid) });
+        assert_eq!(Kind::of(&error), Kind::InvalidParameters);
+        assert_eq!(Kind::of(&anyhow::Error::new(ImageError::Parse("bogus".to_string()))), Kind::Usage);
+        assert_eq!(Kind::of(&anyhow::Error::new(io_error()).context("Failed to save image")), Kind::Other);
+        assert_eq!(Kind::of(&anyhow::anyhow!("no inputs")), Kind::Other);
+    }
+}
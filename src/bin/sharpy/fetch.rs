@@ -0,0 +1,47 @@
+//! Downloads for http(s) URL inputs.
+//!
+//! Only functional when the CLI is built with the `http` feature; otherwise
+//! URL inputs report how to enable it.
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+/// Whether an input argument is an http(s) URL rather than a path.
+pub fn is_url(input: &Path) -> bool {
+    input.to_str().is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// The last segment of the URL's path, without query or fragment, for
+/// naming outputs.
+pub fn file_name(url: &str) -> &str {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    url[..end].rsplit('/').next().unwrap_or_default()
+}
+
+/// Fetches `url`, failing if the body exceeds `limit` bytes or the whole
+/// request takes longer than `timeout`.
+#[cfg(feature = "http")]
+pub fn download(url: &str, limit: u64, timeout: Duration) -> Result<Vec<u8>> {
+    use crate::errors::{Failure, Kind};
+    use anyhow::Context;
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into();
+    let mut response = agent.get(url).call()
+        .with_context(|| Failure::new(Kind::Input, format!("Failed to fetch: {}", url)))?;
+    response.body_mut().with_config().limit(limit).read_to_vec().map_err(|e| match e {
+        ureq::Error::BodyExceedsLimit(_) => {
+            let message = format!("{} exceeds the download limit of {} bytes (see --max-download)", url, limit);
+            anyhow::Error::new(Failure::new(Kind::Input, message))
+        }
+        e => anyhow::Error::new(e).context(Failure::new(Kind::Input, format!("Failed to download: {}", url))),
+    })
+}
+
+#[cfg(not(feature = "http"))]
+pub fn download(_url: &str, _limit: u64, _timeout: Duration) -> Result<Vec<u8>> {
+    anyhow::bail!("URL inputs require sharpy to be built with the `http` feature")
+}
@@ -0,0 +1,140 @@
+//! `sharpy info` and `sharpy analyze`: what users need to know before
+//! choosing sharpening parameters.
+
+use anyhow::{Context, Result};
+use sharpy::Image;
+use std::path::Path;
+
+/// EXIF fields shown, with their labels.
+const EXIF_FIELDS: [(&str, exif::Tag); 8] = [
+    ("Make", exif::Tag::Make),
+    ("Model", exif::Tag::Model),
+    ("Lens", exif::Tag::LensModel),
+    ("Exposure", exif::Tag::ExposureTime),
+    ("Aperture", exif::Tag::FNumber),
+    ("ISO", exif::Tag::PhotographicSensitivity),
+    ("Focal length", exif::Tag::FocalLength),
+    ("Taken", exif::Tag::DateTimeOriginal),
+];
+
+pub fn print_info(input: &Path) -> Result<()> {
+    let file_size = std::fs::metadata(input)
+        .with_context(|| format!("Failed to read: {}", input.display()))?
+        .len();
+    let image = Image::load(input)
+        .with_context(|| format!("Failed to load image: {}", input.display()))?;
+    let (width, height) = image.dimensions();
+
+    print_field("File", &format!("{} ({})", input.display(), format_bytes(file_size)));
+    if let Ok(format) = image::ImageFormat::from_path(input) {
+        print_field("Format", &format!("{:?}", format));
+    }
+    print_field("Dimensions", &format!("{}x{} ({:.1} MP)", width, height, width as f64 * height as f64 / 1e6));
+    print_field("Color type", &format!("{:?}", image.color_type()));
+    print_field("ICC profile", &match image.icc_profile() {
+        Some(profile) => format_bytes(profile.len() as u64),
+        None => "none (sRGB assumed)".to_string(),
+    });
+
+    for (label, value) in read_exif(input) {
+        print_field(label, &value);
+    }
+
+    let analysis = image.analyze();
+    print_field("Blur", &format!("{:.2} (0 = crisp, 1 = very soft)", analysis.blur));
+    print_field("Sharpness", &format!("{:.1} (Laplacian variance)", analysis.sharpness));
+    print_field("Noise", &format!("{:.2} levels", analysis.noise));
+    print_field("Luminance", &summarize_histogram(&image.histogram()));
+    Ok(())
+}
+
+pub fn analyze(cli: &crate::Cli, input: &Path, output: Option<&Path>) -> Result<()> {
+    let image = Image::load(input)
+        .with_context(|| format!("Failed to load image: {}", input.display()))?;
+    let analysis = image.analyze();
+    let pipeline = analysis.suggest();
+
+    if !cli.quiet {
+        print_field("Blur", &format!("{:.2} (0 = crisp, 1 = very soft)", analysis.blur));
+        print_field("Noise", &format!("{:.2} levels", analysis.noise));
+        print_field("Operations", &pipeline.to_string());
+        print_field("Preset", analysis.suggest_preset());
+    } else {
+        println!("{}", pipeline);
+    }
+
+    if let Some(output) = output {
+        if output.exists() && !cli.overwrite {
+            anyhow::bail!("Output file already exists: {}. Use --overwrite to replace.", output.display());
+        }
+        std::fs::write(output, format!("{}\n", pipeline))
+            .with_context(|| format!("Failed to write: {}", output.display()))?;
+        if !cli.quiet {
+            eprintln!("Saved operations to {}", output.display());
+        }
+    }
+    Ok(())
+}
+
+fn print_field(label: &str, value: &str) {
+    println!("{:<13}{}", format!("{}:", label), value);
+}
+
+/// Reads the basic EXIF fields present in the file; none if it has no EXIF.
+fn read_exif(input: &Path) -> Vec<(&'static str, String)> {
+    let Ok(file) = std::fs::File::open(input) else {
+        return Vec::new();
+    };
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file)) else {
+        return Vec::new();
+    };
+
+    EXIF_FIELDS.iter()
+        .filter_map(|&(label, tag)| {
+            let field = exif.get_field(tag, exif::In::PRIMARY)?;
+            let value = field.display_value().with_unit(&exif).to_string();
+            Some((label, value.trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// Mean, median, 1st-99th percentile range and clipping of luminance.
+fn summarize_histogram(histogram: &[u32; 256]) -> String {
+    let total: u64 = histogram.iter().map(|&count| count as u64).sum();
+    if total == 0 {
+        return "empty".to_string();
+    }
+
+    let mean = histogram.iter().enumerate()
+        .map(|(level, &count)| level as f64 * count as f64)
+        .sum::<f64>() / total as f64;
+    let percentile = |fraction: f64| {
+        let target = (total as f64 * fraction).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        histogram.iter()
+            .position(|&count| {
+                seen += count as u64;
+                seen >= target
+            })
+            .unwrap_or(255)
+    };
+    let clipped = |count: u32| count as f64 * 100.0 / total as f64;
+
+    format!(
+        "mean {:.0}, median {}, 1-99% range {}-{}, clipped {:.1}% shadows / {:.1}% highlights",
+        mean,
+        percentile(0.5),
+        percentile(0.01),
+        percentile(0.99),
+        clipped(histogram[0]),
+        clipped(histogram[255]),
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
@@ -0,0 +1,2229 @@
+use clap::{Parser, Subcommand};
+use sharpy::{Image, Comparison, EdgeMethod, Operation, OutputTarget, Preset, PresetRegistry, Migration, Pipeline, ChromaSubsampling, Dither, EncodeOptions, Luminance, PngCompression, Transfer, Rect, SaveOptions, SharpenPixel, SharpeningBuilder, TemporalSharpener, ValidationConfig, ValidationPolicy, Warning};
+use anyhow::{Result, Context};
+use indicatif::{ProgressBar, ProgressStyle};
+use image::GrayImage;
+use std::io::{Read, Write};
+use std::collections::HashSet;
+use std::fs::FileTimes;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use glob::glob;
+use power::PowerGovernor;
+use progress::{Event, ProgressFormat};
+use prompt::{Cancelled, OverwritePrompt};
+use checkpoint::Checkpoint;
+use config::Config;
+use errors::{ErrorFormat, Failure, Kind};
+use info::{analyze, print_info};
+use compare::Layout;
+use teststrip::ParamRange;
+
+mod checkpoint;
+mod compare;
+mod completions;
+mod config;
+mod dialog;
+mod errors;
+mod fetch;
+mod font;
+mod info;
+mod montage;
+mod pipeline_file;
+mod power;
+mod preview;
+mod progress;
+mod prompt;
+mod report;
+mod sequence;
+mod sheet;
+mod teststrip;
+mod watch;
+
+#[derive(Parser)]
+#[command(name = "sharpy")]
+#[command(author, version, about = "High-performance image sharpening tool", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+    
+    /// Enable verbose output
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    
+    /// Suppress all output except errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    
+    /// Progress display: a bar on stderr, or JSON lines on stdout for other programs to read
+    #[arg(long, global = true, value_enum, default_value = "bar")]
+    progress: ProgressFormat,
+    
+    /// Error format on stderr: text, or a JSON object with the kind and exit code
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    errors: ErrorFormat,
+    
+    /// Preview operations without processing
+    #[arg(long, global = true)]
+    dry_run: bool,
+    
+    /// Overwrite existing files without prompting
+    #[arg(long, global = true)]
+    overwrite: bool,
+    
+    /// Draw a before/after preview in the terminal on single-image commands (--preview=kitty, iterm or sixel; default: detect)
+    #[arg(long, global = true, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "auto", value_name = "PROTOCOL")]
+    preview: Option<preview::Protocol>,
+    
+    /// Give outputs the modification time of their input, for photo managers that sort by it
+    #[arg(long, global = true)]
+    preserve_timestamps: bool,
+    
+    /// Replace existing outputs, first copying each to its name plus SUFFIX (e.g. .orig)
+    #[arg(long, global = true, value_name = "SUFFIX", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    backup: Option<String>,
+    
+    /// JPEG chroma subsampling (444, 422 or 420)
+    #[arg(long, global = true, default_value = "444")]
+    chroma_subsampling: ChromaSubsampling,
+    
+    /// JPEG quality (1-100) [default: 75]; also makes WebP output lossy at this quality
+    /// (needs the lossy-webp feature)
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    
+    /// Write WebP output losslessly (the default unless --quality is given)
+    #[arg(long, global = true, conflicts_with = "quality")]
+    lossless: bool,
+    
+    /// PNG compression (fast, default or best)
+    #[arg(long, global = true, default_value = "default")]
+    png_compression: PngCompression,
+    
+    /// Dithering when 16-bit inputs, such as RAW files, are narrowed to 8 bits (none, ordered or blue-noise)
+    #[arg(long, global = true, default_value = "none")]
+    dither: Dither,
+    
+    /// Wrap filters around the image edges so tileable textures stay tileable, failing if the seams get worse
+    #[arg(long, global = true)]
+    tileable: bool,
+    
+    /// Treat inputs as tangent-space normal maps: sharpen red and green, then recompute blue so each pixel stays a unit vector
+    #[arg(long, global = true)]
+    normal_map: bool,
+    
+    /// Suppress color fringes: limit each operation's output colors to those of the original within a pixel, plus TOLERANCE (0-255)
+    #[arg(long, global = true, value_name = "TOLERANCE")]
+    chroma_clamp: Option<f32>,
+    
+    /// Sharpen only edges and texture, leaving flat areas such as skies (and their noise) untouched
+    #[arg(long, global = true)]
+    adaptive: bool,
+    
+    /// Leave stars in astrophotographs unsharpened, so nebulae can be sharpened hard without ringing around them
+    #[arg(long, global = true)]
+    protect_stars: bool,
+    
+    /// Fade sharpening out between this luminance (0-255) and white, so blown highlights don't gain crunchy detail
+    #[arg(long, global = true, value_name = "ABOVE")]
+    protect_highlights: Option<f32>,
+    
+    /// Fade sharpening out between this luminance (0-255) and black, so blocked shadows don't gain crunchy detail
+    #[arg(long, global = true, value_name = "BELOW")]
+    protect_shadows: Option<f32>,
+    
+    /// Scale sharpening from CENTER (0-1) in the middle to EDGE (0-1) in the corners, over the outer FEATHER (0-1] share of the way, e.g. 0.4:1:0.7 for a lens that is softer in the corners
+    #[arg(long, global = true, value_name = "CENTER:EDGE:FEATHER", value_parser = parse_radial)]
+    radial_falloff: Option<(f32, f32, f32)>,
+    
+    /// Remove the halftone screen of scanned prints before sharpening, so it isn't amplified into moiré: auto to detect it, or the print's and scan's LPI@DPI (e.g. 150@600)
+    #[arg(long, global = true, value_name = "SCREEN", value_parser = parse_screen)]
+    descreen: Option<Operation>,
+    
+    /// Remove salt-and-pepper and sensor noise before sharpening with a median filter of this radius in pixels, so the specks aren't amplified
+    #[arg(long, global = true, value_name = "RADIUS")]
+    median: Option<u32>,
+    
+    /// Remove noise before sharpening with non-local means of this strength (1.0 for noise of about 10 levels), optionally with the patch and search radii in pixels (default 1:7)
+    #[arg(long, global = true, value_name = "STRENGTH[:PATCH:SEARCH]", value_parser = parse_denoise)]
+    denoise: Option<Operation>,
+    
+    /// Luminance weights for edge detection, clarity, text sharpening, masks and analysis (bt601, bt709 for HD video stills, bt2020 or equal)
+    #[arg(long, global = true, default_value = "bt601")]
+    luminance: Luminance,
+    
+    /// Sharpen in linear light, decoding with TRANSFER first (srgb, rec709, slog3, logc3, or a gamma such as gamma:2.2)
+    #[arg(long, global = true, value_name = "TRANSFER")]
+    linearize: Option<Transfer>,
+    
+    /// Roll overshoots off smoothly into black and white instead of clipping them, compressing changes within the last KNEE share (0-1] of the way there
+    #[arg(long, global = true, value_name = "KNEE")]
+    soft_clip: Option<f32>,
+    
+    /// Accept parameters beyond the usual ranges, for creative over-sharpening: radius up to 50, clarity radius 100, amount 20 and strength 10
+    #[arg(long, global = true)]
+    extended_ranges: bool,
+    
+    /// Clamp out-of-range parameters to the nearest valid value with a warning, instead of failing
+    #[arg(long, global = true)]
+    clamp: bool,
+    
+    /// Convert images with an embedded ICC profile to sRGB before processing
+    #[arg(long, global = true)]
+    to_srgb: bool,
+    
+    /// Copy EXIF, XMP and the ICC profile to outputs (the default; EXIF and XMP are written to JPEG)
+    #[arg(long, global = true, conflicts_with = "strip_metadata")]
+    keep_metadata: bool,
+    
+    /// Write outputs without EXIF, XMP or ICC profile
+    #[arg(long, global = true)]
+    strip_metadata: bool,
+    
+    /// Output format (e.g. jpg, png, webp, tiff); defaults to the output file extension,
+    /// or to the input format when writing to stdout
+    #[arg(long, global = true, value_parser = parse_format)]
+    format: Option<image::ImageFormat>,
+    
+    /// Input format, for stdin or files whose format can't be detected from their contents
+    #[arg(long, global = true, value_parser = parse_input_format)]
+    input_format: Option<image::ImageFormat>,
+    
+    /// Largest input to download from a URL, in megabytes
+    #[arg(long, global = true, default_value = "100")]
+    max_download: u64,
+    
+    /// Time limit for downloading an input from a URL, in seconds
+    #[arg(long, global = true, default_value = "30")]
+    download_timeout: u64,
+    
+    /// Read defaults from this file instead of ~/.config/sharpy/config.toml
+    #[arg(long = "config", global = true, value_name = "FILE")]
+    config_file: Option<PathBuf>,
+    
+    /// Defaults from the config file, used where flags are absent
+    #[arg(skip)]
+    config: Config,
+    
+    /// Asks about existing outputs when run interactively without
+    /// `--overwrite` or `--quiet`
+    #[arg(skip)]
+    overwrite_prompt: Option<OverwritePrompt>,
+}
+
+/// Output naming when neither flags nor the config file give one.
+const DEFAULT_SUFFIX: &str = "_sharp";
+
+impl Cli {
+    /// Whether existing outputs are replaced without asking (`--overwrite`
+    /// or `--backup`).
+    fn replaces_outputs(&self) -> bool {
+        self.overwrite || self.backup.is_some()
+    }
+    
+    fn save_options(&self) -> SaveOptions {
+        let options = SaveOptions::from(EncodeOptions::default()
+            .jpeg_quality(self.quality.or(self.config.quality).unwrap_or(75))
+            // Only an explicit --quality switches WebP to lossy, not the config file's JPEG quality
+            .webp_quality(self.quality.unwrap_or(75))
+            .webp_lossless(self.lossless || self.quality.is_none())
+            .png_compression(self.png_compression)
+            .chroma_subsampling(self.chroma_subsampling));
+        match self.format {
+            Some(format) => options.format(format),
+            None => options,
+        }
+    }
+    
+    /// `preset`, or else the config file's default preset.
+    fn preset_name<'a>(&'a self, preset: Option<&'a str>) -> Result<&'a str> {
+        preset.or(self.config.preset.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("No preset given. Use --preset or set `preset` in the config file."))
+    }
+    
+    /// Parses `operations`, or expands the config file's default preset if
+    /// none are given.
+    fn recipe(&self, operations: &[String]) -> Result<Recipe> {
+        if operations.is_empty() {
+            let name = self.config.preset.as_deref().ok_or_else(|| {
+                anyhow::anyhow!("No operations given. Use --operations or set `preset` in the config file.")
+            })?;
+            let registry = PresetRegistry::builtin();
+            return Ok(Recipe::from(find_preset(&registry, name)?));
+        }
+        
+        let (pipeline, migrations) = Pipeline::parse_with_migrations(&operations.join(","))
+            .context("Invalid operations")?;
+        report_migrations(self, &migrations);
+        Ok(Recipe::from(pipeline))
+    }
+    
+    /// The output naming template: from `--suffix`, `--name-template`, the
+    /// config file, or else `{name}_sharp.{ext}`.
+    fn name_template(&self, suffix: Option<&str>, template: Option<&str>) -> Result<String> {
+        let template = match (suffix, template.or(self.config.name_template.as_deref())) {
+            (None, Some(template)) => template.to_string(),
+            (suffix, _) => format!("{{name}}{}.{{ext}}", suffix.unwrap_or(DEFAULT_SUFFIX)),
+        };
+        config::check_template(&template)?;
+        Ok(template)
+    }
+}
+
+fn parse_format(s: &str) -> std::result::Result<image::ImageFormat, String> {
+    image::ImageFormat::from_extension(s)
+        .filter(|format| format.writing_enabled())
+        .ok_or_else(|| format!("unsupported output format '{}'", s))
+}
+
+fn parse_input_format(s: &str) -> std::result::Result<image::ImageFormat, String> {
+    image::ImageFormat::from_extension(s)
+        .filter(|format| format.reading_enabled())
+        .ok_or_else(|| format!("unsupported input format '{}'", s))
+}
+
+fn parse_point(s: &str) -> std::result::Result<(u32, u32), String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("expected X,Y pixel coordinates, got '{}'", s))
+}
+
+fn parse_screen(s: &str) -> std::result::Result<Operation, String> {
+    if s.trim().eq_ignore_ascii_case("auto") {
+        return Ok(Operation::Descreen { period: None });
+    }
+    s.split_once('@')
+        .and_then(|(lpi, dpi)| Some((lpi.trim().parse::<f32>().ok()?, dpi.trim().parse::<f32>().ok()?)))
+        .filter(|&(lpi, dpi)| lpi > 0.0 && dpi > 0.0)
+        .map(|(lpi, dpi)| Operation::Descreen { period: Some(dpi / lpi) })
+        .ok_or_else(|| format!("expected auto or LPI@DPI, got '{}'", s))
+}
+
+fn parse_denoise(s: &str) -> std::result::Result<Operation, String> {
+    let text = if s.contains(':') { format!("denoise:{}", s) } else { format!("denoise:{}:1:7", s) };
+    text.parse().map_err(|_| format!("expected STRENGTH or STRENGTH:PATCH:SEARCH, got '{}'", s))
+}
+
+fn parse_radial(s: &str) -> std::result::Result<(f32, f32, f32), String> {
+    let values: Vec<f32> = s.split(':').filter_map(|value| value.trim().parse().ok()).collect();
+    match values[..] {
+        [center, edge, feather] if s.split(':').count() == 3 => Ok((center, edge, feather)),
+        _ => Err(format!("expected CENTER:EDGE:FEATHER, got '{}'", s)),
+    }
+}
+
+/// An unsharp mask threshold: an absolute difference, or with a `%` suffix
+/// a percentage of the local contrast.
+#[derive(Clone, Copy)]
+enum Threshold {
+    Absolute(u8),
+    Relative(f32),
+}
+
+fn parse_threshold(s: &str) -> std::result::Result<Threshold, String> {
+    match s.trim().strip_suffix('%') {
+        Some(percent) => percent.trim().parse().ok()
+            .filter(|percent| (0.0..=100.0).contains(percent))
+            .map(Threshold::Relative),
+        None => s.trim().parse().ok().map(Threshold::Absolute),
+    }
+    .ok_or_else(|| format!("expected 0-255, or a percentage of the local contrast such as 5%, got '{}'", s))
+}
+
+fn parse_region(s: &str) -> std::result::Result<Rect, String> {
+    let values: Vec<u32> = s.split(',')
+        .map(|value| value.trim().parse())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| format!("expected X,Y,WIDTH,HEIGHT in pixels, got '{}'", s))?;
+    match values[..] {
+        [x, y, width, height] => Ok(Rect::new(x, y, width, height)),
+        _ => Err(format!("expected X,Y,WIDTH,HEIGHT in pixels, got '{}'", s)),
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Apply unsharp mask sharpening
+    Unsharp {
+        /// Input image file (- for stdin)
+        #[arg(required_unless_present = "pick")]
+        input: Option<PathBuf>,
+        
+        /// Output image file (- for stdout)
+        #[arg(required_unless_present = "pick")]
+        output: Option<PathBuf>,
+        
+        /// Blur radius (0.5-10.0)
+        #[arg(short, long, default_value = "1.0")]
+        radius: f32,
+        
+        /// Sharpening strength (0.0-5.0)
+        #[arg(short, long, default_value = "1.0")]
+        amount: f32,
+        
+        /// Minimum difference threshold (0-255), or with a % suffix a percentage of the local contrast
+        #[arg(short, long, default_value = "0", value_parser = parse_threshold)]
+        threshold: Threshold,
+        
+        /// Vary the radius per region from --radius for fine texture up to this for soft, large structures
+        #[arg(long, value_name = "RADIUS")]
+        max_radius: Option<f32>,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+        
+        /// Choose missing input/output files with a file dialog (requires the `dialog` feature)
+        #[arg(long)]
+        pick: bool,
+    },
+    
+    /// Apply high-pass sharpening
+    Highpass {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Blend strength (0.0-3.0)
+        #[arg(short, long, default_value = "0.5")]
+        strength: f32,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Enhance edges in the image
+    Edges {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Enhancement strength (0.0-3.0)
+        #[arg(short, long, default_value = "1.0")]
+        strength: f32,
+        
+        /// Edge detection method
+        #[arg(short, long, default_value = "sobel")]
+        method: EdgeMethod,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Apply clarity enhancement
+    Clarity {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Enhancement strength (0.0-3.0)
+        #[arg(short, long, default_value = "1.0")]
+        strength: f32,
+        
+        /// Local area radius (1.0-20.0)
+        #[arg(short, long, default_value = "2.0")]
+        radius: f32,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Sharpen text and line art in scans and screenshots without halos
+    Text {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Typical stroke width in pixels (0.5-10.0)
+        #[arg(short = 'w', long, default_value = "1.5")]
+        stroke_width: f32,
+        
+        /// Sharpening strength (0.0-3.0)
+        #[arg(short, long, default_value = "1.5")]
+        strength: f32,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Apply a sharpening preset
+    Preset {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Preset name (built-in or defined in --preset-file) [default: `preset` in the config file]
+        #[arg(short, long)]
+        preset: Option<String>,
+        
+        /// Load additional presets from a TOML or JSON file
+        #[arg(long)]
+        preset_file: Option<PathBuf>,
+        
+        /// Only process this rectangle (X,Y,WIDTH,HEIGHT in pixels), leaving the rest unchanged
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Sharpen with parameters chosen from an analysis of the image
+    Auto {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// Where the result will be viewed (screen or print); prints get stronger sharpening
+        #[arg(long, default_value = "screen")]
+        target: OutputTarget,
+    },
+    
+    /// Apply the operations described in a pipeline file
+    Run {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        output: PathBuf,
+        
+        /// YAML, TOML or JSON file listing the operations, each with an optional mask
+        #[arg(long)]
+        pipeline_file: PathBuf,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// List available presets and the operations each applies
+    Presets {
+        /// Also list presets defined in a TOML or JSON file
+        #[arg(long)]
+        preset_file: Option<PathBuf>,
+        
+        /// Print the presets as JSON (same structure as a preset file)
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Render every preset applied to a downscaled copy as a labeled contact sheet
+    PresetsSheet {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        #[arg(short, long)]
+        output: PathBuf,
+        
+        /// Also include presets defined in a TOML or JSON file
+        #[arg(long)]
+        preset_file: Option<PathBuf>,
+        
+        /// Longest side of each thumbnail in pixels
+        #[arg(long, default_value = "400")]
+        size: u32,
+        
+        /// Thumbnails per row (default: as square a grid as possible)
+        #[arg(long)]
+        columns: Option<usize>,
+    },
+    
+    /// Show dimensions, metadata, blur, noise and tonal range of an image
+    Info {
+        /// Input image file
+        input: PathBuf,
+    },
+    
+    /// Estimate blur and noise and suggest operations and a preset
+    Analyze {
+        /// Input image file
+        input: PathBuf,
+        
+        /// Write the suggested operations to this file (for `batch --operations`)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Render the original and the processed image in one before/after image
+    Compare {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        #[arg(short, long)]
+        output: PathBuf,
+        
+        #[command(flatten)]
+        recipe: RecipeArgs,
+        
+        /// Wipe: original left of the split, processed right; side-by-side: both in full
+        #[arg(long, value_enum, default_value = "wipe")]
+        layout: Layout,
+        
+        /// Split position for the wipe layout, as a fraction of the width
+        #[arg(long, default_value = "0.5")]
+        position: f32,
+    },
+    
+    /// Render a labeled grid of crops processed over a range of parameter values
+    Teststrip {
+        /// Input image file (- for stdin)
+        input: PathBuf,
+        
+        /// Output image file (- for stdout)
+        #[arg(short, long)]
+        output: PathBuf,
+        
+        /// Parameter range as NAME=START..END:STEPS (e.g. amount=0.4..1.6:5); a second one adds rows
+        #[arg(long = "param", required = true)]
+        params: Vec<ParamRange>,
+        
+        /// Operations to vary; each range sets the first operation with that parameter
+        #[arg(short = 'p', long, value_delimiter = ',', default_value = "unsharp:1:1:0")]
+        operations: Vec<String>,
+        
+        /// Crop size in pixels
+        #[arg(long, default_value = "256")]
+        size: u32,
+        
+        /// Center of the crop as X,Y pixel coordinates (default: image center)
+        #[arg(long, value_parser = parse_point)]
+        at: Option<(u32, u32)>,
+    },
+    
+    /// Process images as they appear in a directory (hot folder)
+    Watch {
+        /// Directory to watch
+        dir: PathBuf,
+        
+        /// Output directory
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        
+        /// Output filename suffix [default: _sharp]
+        #[arg(short, long)]
+        suffix: Option<String>,
+        
+        /// Output filename template with {name} and {ext} placeholders, e.g. "{name}_web.{ext}"
+        #[arg(long, conflicts_with = "suffix")]
+        name_template: Option<String>,
+        
+        /// Operations to apply (format: "operation:param1:param2:...") [default: `preset` in the config file]
+        #[arg(short = 'p', long, value_delimiter = ',')]
+        operations: Vec<String>,
+        
+        /// Move originals here once processed (default: leave them; an existing output marks them done)
+        #[arg(long)]
+        processed_dir: Option<PathBuf>,
+        
+        /// Milliseconds a file must go unchanged before processing, so partial copies aren't read
+        #[arg(long, default_value = "500")]
+        settle_ms: u64,
+        
+        /// Apply the result only where this grayscale image (same size as the input) is bright
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    
+    /// Process multiple files with batch operations
+    Batch(BatchArgs),
+    
+    /// Process a numbered frame sequence in order (e.g. "frames/%06d.png"), smoothing parameters between frames
+    Sequence {
+        /// Input frames with the frame number as %d, %0Nd or #### (e.g. "frames/%06d.png")
+        input: String,
+        
+        /// Output frames, numbered like the input (e.g. "sharp/%06d.png")
+        #[arg(short, long)]
+        output: String,
+        
+        /// First frame number
+        #[arg(long, default_value = "1")]
+        start: u32,
+        
+        /// Last frame number
+        #[arg(long)]
+        end: u32,
+        
+        /// Operations to apply to every frame [default: `preset` in the config file]
+        #[arg(short = 'p', long, value_delimiter = ',', conflicts_with = "auto")]
+        operations: Vec<String>,
+        
+        /// Choose parameters from each frame's analysis, averaged over recent frames, for this target
+        #[arg(long)]
+        auto: Option<OutputTarget>,
+        
+        /// Weight of earlier frames in the averaged parameters and masks (0 to below 1)
+        #[arg(long, default_value = "0.8")]
+        smoothing: f32,
+        
+        /// Grayscale mask for every frame, or a numbered mask per frame (e.g. "masks/%06d.png")
+        #[arg(long)]
+        mask: Option<String>,
+    },
+    
+    /// Print a shell completion script (e.g. `sharpy completions bash > /etc/bash_completion.d/sharpy`)
+    Completions {
+        /// Shell to generate the script for
+        shell: clap_complete::Shell,
+    },
+    
+    /// Print the man page in roff format
+    Manpage {
+        /// Write sharpy.1 and a page per subcommand (sharpy-batch.1, ...) to this directory instead
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// Input patterns (e.g., "*.jpg" or "images/*.png"), directories or http(s) URLs
+    #[arg(required = true)]
+    patterns: Vec<String>,
+    
+    /// Output directory
+    #[arg(short, long, required_unless_present = "in_place")]
+    output_dir: Option<PathBuf>,
+    
+    /// Output filename suffix [default: _sharp]
+    #[arg(short, long)]
+    suffix: Option<String>,
+    
+    /// Output filename template with {name} and {ext} placeholders, e.g. "{name}_web.{ext}"
+    #[arg(long, conflicts_with = "suffix")]
+    name_template: Option<String>,
+    
+    /// Replace each input with its output, keeping the original as a --backup copy
+    #[arg(
+        long,
+        requires = "backup",
+        conflicts_with_all = ["output_dir", "suffix", "name_template", "skip_existing", "rename_on_conflict", "format"]
+    )]
+    in_place: bool,
+    
+    /// Operations to apply (format: "operation:param1:param2:...") [default: `preset` in the config file]
+    #[arg(short = 'p', long, value_delimiter = ',')]
+    operations: Vec<String>,
+    
+    /// Also search subdirectories, mirroring the input tree under the output directory
+    #[arg(short, long)]
+    recursive: bool,
+    
+    /// Apply the result only where this grayscale image (same size as the input) is bright
+    #[arg(long)]
+    mask: Option<PathBuf>,
+    
+    /// Record completed files here and skip them when rerun, to resume an interrupted batch
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    
+    /// Skip files whose output already exists (instead of failing them)
+    #[arg(long, conflicts_with_all = ["overwrite", "rename_on_conflict"])]
+    skip_existing: bool,
+    
+    /// Add a number to outputs whose name is taken, e.g. photo_sharp_1.jpg
+    #[arg(long, conflicts_with = "overwrite")]
+    rename_on_conflict: bool,
+    
+    /// Write per-file results (status, timing, sharpness before and after) to this JSON file
+    #[arg(long)]
+    report: Option<PathBuf>,
+    
+    /// Files to process at once; each holds one image in memory at a time [default: 1]
+    #[arg(short, long, conflicts_with = "power_aware")]
+    jobs: Option<std::num::NonZeroUsize>,
+    
+    /// Use fewer threads while on battery or thermally throttled
+    #[arg(long)]
+    power_aware: bool,
+    
+    #[command(flatten)]
+    reference: ReferenceArgs,
+}
+
+/// The operations to apply: a preset or an operation list.
+#[derive(clap::Args)]
+struct RecipeArgs {
+    /// Preset name (built-in or defined in --preset-file) [default: `preset` in the config file]
+    #[arg(long, conflicts_with = "operations")]
+    preset: Option<String>,
+    
+    /// Operations to apply (format: "operation:param1:param2:...")
+    #[arg(short = 'p', long, value_delimiter = ',')]
+    operations: Option<Vec<String>>,
+    
+    /// Load additional presets from a TOML or JSON file
+    #[arg(long, conflicts_with = "operations")]
+    preset_file: Option<PathBuf>,
+}
+
+impl RecipeArgs {
+    fn recipe(&self, cli: &Cli) -> Result<Recipe> {
+        if let Some(operations) = &self.operations {
+            return cli.recipe(operations);
+        }
+        
+        let name = cli.preset_name(self.preset.as_deref())?;
+        let registry = load_presets(cli, self.preset_file.as_deref())?;
+        Ok(Recipe::from(find_preset(&registry, name)?))
+    }
+}
+
+/// A pipeline to run, and whether the preset it came from leaves stars
+/// unsharpened and clamps chroma.
+struct Recipe {
+    pipeline: Pipeline,
+    protect_stars: bool,
+    chroma_clamp: Option<f32>,
+}
+
+impl From<Pipeline> for Recipe {
+    fn from(pipeline: Pipeline) -> Self {
+        Self { pipeline, protect_stars: false, chroma_clamp: None }
+    }
+}
+
+impl From<&Preset> for Recipe {
+    fn from(preset: &Preset) -> Self {
+        Self {
+            pipeline: preset.operations.iter().cloned().collect(),
+            protect_stars: preset.protect_stars,
+            chroma_clamp: preset.chroma_clamp,
+        }
+    }
+}
+
+/// Regression checks of batch outputs against approved references.
+#[derive(clap::Args)]
+struct ReferenceArgs {
+    /// Directory of approved outputs to compare each result against (same file names)
+    #[arg(long)]
+    reference_dir: Option<PathBuf>,
+    
+    /// Minimum SSIM against the reference
+    #[arg(long, default_value = "0.98", requires = "reference_dir")]
+    min_ssim: f64,
+    
+    /// Maximum mean color difference (CIE76 delta E) from the reference
+    #[arg(long, default_value = "1.0", requires = "reference_dir")]
+    max_delta_e: f64,
+    
+    /// Maximum mean halo overshoot (luminance levels) beyond the reference
+    #[arg(long, default_value = "0.5", requires = "reference_dir")]
+    max_halo: f64,
+}
+
+impl ReferenceArgs {
+    /// Compares a saved output with its reference, describing any failure.
+    fn check(&self, reference_path: &Path, output: &Path) -> std::result::Result<Comparison, String> {
+        if !reference_path.exists() {
+            return Err(format!("no reference at {}", reference_path.display()));
+        }
+        
+        let load = |path: &Path| Image::load(path).map_err(|e| format!("{}: {}", path.display(), e));
+        let comparison = load(output)?.compare(&load(reference_path)?)
+            .map_err(|e| format!("cannot compare with reference: {}", e))?;
+        
+        let mut problems = Vec::new();
+        if comparison.ssim < self.min_ssim {
+            problems.push(format!("SSIM {:.4} < {}", comparison.ssim, self.min_ssim));
+        }
+        if comparison.delta_e > self.max_delta_e {
+            problems.push(format!("delta E {:.3} > {}", comparison.delta_e, self.max_delta_e));
+        }
+        if comparison.halo > self.max_halo {
+            problems.push(format!("halo {:.3} > {}", comparison.halo, self.max_halo));
+        }
+        
+        if problems.is_empty() {
+            Ok(comparison)
+        } else {
+            Err(problems.join(", "))
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => return usage_error(e),
+    };
+    if !cli.replaces_outputs() && !cli.quiet {
+        cli.overwrite_prompt = OverwritePrompt::if_interactive();
+    }
+    let result = Config::load(cli.config_file.as_deref()).and_then(|config| {
+        cli.config = config;
+        run(&cli)
+    });
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let kind = Kind::of(&e);
+            errors::report(cli.errors, kind, &errors::message(&e));
+            ExitCode::from(kind.exit_code())
+        }
+    }
+}
+
+/// Reports an invalid command line. `--errors json` can't be read from the
+/// parsed arguments here, so it's looked for in the raw ones.
+fn usage_error(error: clap::Error) -> ExitCode {
+    use clap::error::ErrorKind;
+    
+    let args: Vec<_> = std::env::args_os().collect();
+    let json = args.windows(2).any(|pair| pair[0] == "--errors" && pair[1] == "json")
+        || args.iter().any(|arg| arg == "--errors=json");
+    let informational = matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp | ErrorKind::DisplayVersion | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+    );
+    if !json || informational {
+        error.exit();
+    }
+    
+    let rendered = error.to_string();
+    let message = rendered.lines().next().unwrap_or_default().trim_start_matches("error: ");
+    errors::report(ErrorFormat::Json, Kind::Usage, message);
+    ExitCode::from(Kind::Usage.exit_code())
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    match &cli.command {
+        Commands::Unsharp { input, output, radius, amount, threshold, max_radius, region, mask, .. } => {
+            let operation = match (*max_radius, *threshold) {
+                (Some(max_radius), Threshold::Absolute(threshold)) => {
+                    Operation::AdaptiveUnsharp { min_radius: *radius, max_radius, amount: *amount, threshold }
+                }
+                (Some(_), Threshold::Relative(_)) => anyhow::bail!("--max-radius takes an absolute --threshold (0-255)"),
+                (None, Threshold::Absolute(threshold)) => Operation::UnsharpMask { radius: *radius, amount: *amount, threshold },
+                (None, Threshold::Relative(threshold)) => Operation::RelativeUnsharp { radius: *radius, amount: *amount, threshold },
+            };
+            let mask = load_mask(mask.as_deref())?;
+            let (input, output) = dialog::resolve_paths(input.as_deref(), output.as_deref())?;
+            process_single_image(cli, &input, &output, |img| {
+                apply_in_area(cli, img, [operation.clone()], *region, mask)
+            })
+        }
+        
+        Commands::Highpass { input, output, strength, region, mask } => {
+            let mask = load_mask(mask.as_deref())?;
+            process_single_image(cli, input, output, |img| {
+                apply_in_area(cli, img, [Operation::HighPassSharpen { strength: *strength }], *region, mask)
+            })
+        }
+        
+        Commands::Edges { input, output, strength, method, region, mask } => {
+            let mask = load_mask(mask.as_deref())?;
+            process_single_image(cli, input, output, |img| {
+                apply_in_area(cli, img, [Operation::EnhanceEdges { strength: *strength, method: *method }], *region, mask)
+            })
+        }
+        
+        Commands::Clarity { input, output, strength, radius, region, mask } => {
+            let mask = load_mask(mask.as_deref())?;
+            process_single_image(cli, input, output, |img| {
+                apply_in_area(cli, img, [Operation::Clarity { strength: *strength, radius: *radius }], *region, mask)
+            })
+        }
+        
+        Commands::Text { input, output, stroke_width, strength, region, mask } => {
+            let mask = load_mask(mask.as_deref())?;
+            process_single_image(cli, input, output, |img| {
+                let operation = Operation::TextSharpen { stroke_width: *stroke_width, strength: *strength };
+                apply_in_area(cli, img, [operation], *region, mask)
+            })
+        }
+        
+        Commands::Preset { input, output, preset, preset_file, region, mask } => {
+            let mask = load_mask(mask.as_deref())?;
+            let registry = load_presets(cli, preset_file.as_deref())?;
+            let preset = find_preset(&registry, cli.preset_name(preset.as_deref())?)?;
+            process_single_image(cli, input, output, |img| {
+                let builder = sharpen_with(cli, img, preset.operations.iter().cloned(), preset.chroma_clamp);
+                apply_builder_in_area(cli, builder.protect_stars(cli.protect_stars || preset.protect_stars), *region, mask)
+            })
+        }
+        
+        Commands::Auto { input, output, target } => {
+            process_single_image(cli, input, output, |img| {
+                let pipeline = img.analyze().suggest_for(*target);
+                if !cli.quiet {
+                    eprintln!("Operations: {}", pipeline);
+                }
+                apply_pipeline(cli, img, &Recipe::from(pipeline), None)
+            })
+        }
+        
+        Commands::Run { input, output, pipeline_file, mask } => {
+            let graph = pipeline_file::load(pipeline_file)?;
+            let mask = load_mask(mask.as_deref())?;
+            process_single_image(cli, input, output, |img| apply_masked(img, mask.as_ref(), |img| graph.apply(img)))
+        }
+        
+        Commands::Presets { preset_file, json } => {
+            let registry = load_presets(cli, preset_file.as_deref())?;
+            list_presets(&registry, *json)
+        }
+        
+        Commands::PresetsSheet { input, output, preset_file, size, columns } => {
+            let registry = load_presets(cli, preset_file.as_deref())?;
+            sheet::render(cli, input, output, &registry, *size, *columns)
+        }
+        
+        Commands::Compare { input, output, recipe, layout, position } => {
+            let recipe = recipe.recipe(cli)?;
+            compare::render(cli, input, output, &recipe, *layout, *position)
+        }
+        
+        Commands::Teststrip { input, output, params, operations, size, at } => {
+            let (pipeline, migrations) = Pipeline::parse_with_migrations(&operations.join(","))
+                .context("Invalid operations")?;
+            report_migrations(cli, &migrations);
+            teststrip::render(cli, input, output, &pipeline, params, *size, *at)
+        }
+        
+        Commands::Info { input } => print_info(input),
+        
+        Commands::Analyze { input, output } => analyze(cli, input, output.as_deref()),
+        
+        Commands::Watch { dir, output_dir, suffix, name_template, operations, processed_dir, settle_ms, mask } => {
+            let recipe = cli.recipe(operations)?;
+            let mask = load_mask(mask.as_deref())?;
+            let options = watch::WatchOptions {
+                output_dir,
+                name_template: &cli.name_template(suffix.as_deref(), name_template.as_deref())?,
+                processed_dir: processed_dir.as_deref(),
+                settle: std::time::Duration::from_millis(*settle_ms),
+                mask: mask.as_ref(),
+            };
+            watch::run(cli, dir, &recipe, &options)
+        }
+        
+        Commands::Batch(args) => process_batch(cli, args),
+        
+        Commands::Sequence { input, output, start, end, operations, auto, smoothing, mask } => {
+            let sharpener = match auto {
+                Some(target) => TemporalSharpener::auto(*target),
+                None => TemporalSharpener::fixed(cli.recipe(operations)?.pipeline),
+            };
+            let options = sequence::SequenceOptions {
+                sharpener: sharpener.smoothing(*smoothing),
+                start: *start,
+                end: *end,
+                mask: mask.as_deref(),
+            };
+            sequence::run(cli, input, output, options)
+        }
+        
+        Commands::Completions { shell } => {
+            completions::completions(*shell);
+            Ok(())
+        }
+        
+        Commands::Manpage { output_dir } => completions::manpage(output_dir.as_deref()),
+    }
+}
+
+/// Starts a builder running `operations` with `--tileable`, `--normal-map`,
+/// `--chroma-clamp` (or else the preset's `chroma_clamp`), `--adaptive`,
+/// `--protect-stars`, `--protect-highlights`, `--protect-shadows`,
+/// `--radial-falloff`, `--luminance`, `--extended-ranges`, `--clamp`,
+/// `--linearize` and `--soft-clip`, after `--descreen`, `--median` and
+/// `--denoise`.
+fn sharpen_with<P: SharpenPixel, I>(cli: &Cli, image: Image<P>, operations: I, chroma_clamp: Option<f32>) -> SharpeningBuilder<P>
+where
+    I: IntoIterator<Item = Operation>,
+{
+    let image = image.with_luminance(cli.luminance).with_validation(validation(cli));
+    let builder = cli.descreen.iter().fold(image.sharpen(), |builder, descreen| builder.operation(descreen.clone()));
+    let builder = cli.median.iter().fold(builder, |builder, &radius| builder.median_filter(radius));
+    let builder = cli.denoise.iter().fold(builder, |builder, denoise| builder.operation(denoise.clone()));
+    let builder = operations.into_iter().fold(builder, |builder, operation| {
+        let builder = builder.operation(operation);
+        match cli.chroma_clamp.or(chroma_clamp) {
+            Some(tolerance) => builder.chroma_clamp(tolerance),
+            None => builder,
+        }
+    });
+    let builder = builder.tileable(cli.tileable).normal_map(cli.normal_map).adaptive(cli.adaptive).protect_stars(cli.protect_stars);
+    let builder = cli.protect_highlights.iter().fold(builder, |builder, &above| builder.protect_highlights(above));
+    let builder = cli.protect_shadows.iter().fold(builder, |builder, &below| builder.protect_shadows(below));
+    let builder = cli.radial_falloff.iter().fold(builder, |builder, &(center, edge, feather)| builder.radial_falloff(center, edge, feather));
+    let builder = if cli.clamp { builder.validation_policy(ValidationPolicy::Clamp) } else { builder };
+    let builder = match cli.soft_clip {
+        Some(knee) => builder.soft_clip(knee),
+        None => builder,
+    };
+    match cli.linearize {
+        Some(transfer) => builder.linearize(transfer),
+        None => builder,
+    }
+}
+
+/// The parameter ranges `--extended-ranges` selects.
+fn validation(cli: &Cli) -> ValidationConfig {
+    if cli.extended_ranges { ValidationConfig::extended() } else { ValidationConfig::default() }
+}
+
+/// Applies `operations`, only within `region` (`--region`) and where `mask`
+/// (`--mask`) is bright if given; see [`sharpen_with`].
+fn apply_in_area<I>(cli: &Cli, image: Image, operations: I, region: Option<Rect>, mask: Option<GrayImage>) -> sharpy::Result<Image>
+where
+    I: IntoIterator<Item = Operation>,
+{
+    apply_builder_in_area(cli, sharpen_with(cli, image, operations, None), region, mask)
+}
+
+/// Applies `builder` only within `region` and where `mask` is bright if given.
+fn apply_builder_in_area(cli: &Cli, builder: SharpeningBuilder, region: Option<Rect>, mask: Option<GrayImage>) -> sharpy::Result<Image> {
+    let builder = match region {
+        Some(rect) => builder.region(rect),
+        None => builder,
+    };
+    match mask {
+        Some(mask) => apply_reporting(cli, builder.mask(mask)),
+        None => apply_reporting(cli, builder),
+    }
+}
+
+/// Applies `recipe` where `mask` is bright if given; see [`sharpen_with`].
+fn apply_pipeline<P: SharpenPixel>(cli: &Cli, image: Image<P>, recipe: &Recipe, mask: Option<&GrayImage>) -> sharpy::Result<Image<P>> {
+    let builder = sharpen_with(cli, image, recipe.pipeline.operations().iter().cloned(), recipe.chroma_clamp)
+        .protect_stars(cli.protect_stars || recipe.protect_stars);
+    match mask {
+        Some(mask) => apply_reporting(cli, builder.mask(mask.clone())),
+        None => apply_reporting(cli, builder),
+    }
+}
+
+/// Applies `builder`, printing its clamps and warnings unless `--quiet`.
+fn apply_reporting<P: SharpenPixel>(cli: &Cli, builder: SharpeningBuilder<P>) -> sharpy::Result<Image<P>> {
+    let (image, warnings) = builder.apply_with_report()?;
+    if !cli.quiet {
+        for warning in warnings {
+            match warning {
+                Warning::Clamped(clamp) => eprintln!("Clamped: {}", clamp),
+                warning => eprintln!("Warning: {}", warning),
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Runs `apply` on `image`, keeping its result only where `mask` is bright,
+/// for processing that doesn't go through a builder.
+fn apply_masked<F>(image: Image, mask: Option<&GrayImage>, apply: F) -> sharpy::Result<Image>
+where
+    F: FnOnce(Image) -> sharpy::Result<Image>,
+{
+    match mask {
+        Some(mask) => image.blend_masked(apply(image.clone())?, mask),
+        None => apply(image),
+    }
+}
+
+/// Loads a `--mask` image as grayscale.
+fn load_mask(path: Option<&Path>) -> Result<Option<GrayImage>> {
+    path.map(|path| {
+        image::open(path)
+            .map(|mask| mask.to_luma8())
+            .with_context(|| format!("Failed to load mask: {}", path.display()))
+    }).transpose()
+}
+
+/// Returns the built-in presets plus any defined in `preset_file`.
+fn load_presets(cli: &Cli, preset_file: Option<&Path>) -> Result<PresetRegistry> {
+    let mut registry = PresetRegistry::builtin();
+    if let Some(path) = preset_file {
+        let loaded = PresetRegistry::load_file(path)
+            .with_context(|| format!("Failed to load presets: {}", path.display()))?;
+        report_migrations(cli, loaded.migrations());
+        registry.extend(loaded);
+    }
+    Ok(registry)
+}
+
+/// Prints each preset with the operations it expands to.
+fn list_presets(registry: &PresetRegistry, json: bool) -> Result<()> {
+    if json {
+        #[derive(serde::Serialize)]
+        struct Listing<'a> {
+            presets: Vec<&'a sharpy::Preset>,
+        }
+        let listing = Listing { presets: registry.iter().collect() };
+        println!("{}", serde_json::to_string_pretty(&listing)?);
+        return Ok(());
+    }
+    
+    for preset in registry.iter() {
+        match &preset.description {
+            Some(description) => println!("{}: {}", preset.name, description),
+            None => println!("{}", preset.name),
+        }
+        for operation in &preset.operations {
+            println!("  {}", operation);
+        }
+        if preset.protect_stars {
+            println!("  (stars protected)");
+        }
+        if let Some(tolerance) = preset.chroma_clamp {
+            println!("  (chroma clamped, tolerance {})", tolerance);
+        }
+    }
+    Ok(())
+}
+
+/// Looks up a preset, listing the available ones if it doesn't exist.
+fn find_preset<'a>(registry: &'a PresetRegistry, name: &str) -> Result<&'a Preset> {
+    registry.get(name).ok_or_else(|| {
+        let available: Vec<_> = registry.iter().map(|p| p.name.as_str()).collect();
+        anyhow::anyhow!("Unknown preset: {}. Available: {}", name, available.join(", "))
+    })
+}
+
+/// Tells the user which saved operations were written in an older syntax.
+fn report_migrations(cli: &Cli, migrations: &[Migration]) {
+    if cli.quiet {
+        return;
+    }
+    for migration in migrations {
+        eprintln!("Migrated: {}", migration);
+    }
+}
+
+/// Loads `input`, converting it to sRGB when `--to-srgb` is set.
+fn load_image(cli: &Cli, input: &Path) -> Result<Image> {
+    read_input(cli, input).map(|(image, _)| image)
+}
+
+/// `-` in place of a path means stdin for input and stdout for output.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Loads `input` (a path, URL or `-`), returning the image and the format it was decoded from.
+fn read_input(cli: &Cli, input: &Path) -> Result<(Image, Option<image::ImageFormat>)> {
+    let bytes = if is_stdio(input) {
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes)
+            .context(Failure::new(Kind::Input, "Failed to read stdin"))?;
+        Some(bytes)
+    } else if fetch::is_url(input) {
+        let url = input.to_string_lossy();
+        let timeout = std::time::Duration::from_secs(cli.download_timeout);
+        Some(fetch::download(&url, cli.max_download * 1024 * 1024, timeout)?)
+    } else if cli.input_format.is_some() {
+        Some(std::fs::read(input)
+            .with_context(|| Failure::new(Kind::Input, format!("Failed to read: {}", input.display())))?)
+    } else {
+        None
+    };
+    
+    let (image, format) = if let Some(bytes) = &bytes {
+        let format = match cli.input_format {
+            Some(format) => format,
+            None => image::guess_format(bytes)
+                .context(Failure::new(Kind::Input, "Cannot detect the input format; use --input-format"))?,
+        };
+        (Image::from_bytes_with_format(bytes, format), Some(format))
+    } else {
+        (load_file(input, cli.dither), image::ImageFormat::from_path(input).ok())
+    };
+    // Decode 16-bit sources again at full precision so --dither narrows them
+    let image = match image {
+        Ok(image) if cli.dither != Dither::None && is_deep(image.color_type()) => match (&bytes, format) {
+            (Some(bytes), Some(format)) => Image::from_bytes_rgb16_with_format(bytes, format),
+            _ => Image::load_rgb16(input),
+        }.map(|image| image.quantize(cli.dither)),
+        image => image,
+    };
+    let mut image = image
+        .with_context(|| Failure::new(Kind::Input, format!("Failed to load image: {}", input.display())))?
+        .with_luminance(cli.luminance)
+        .with_validation(validation(cli));
+    
+    if cli.to_srgb {
+        image = image.to_srgb()
+            .with_context(|| format!("Failed to convert to sRGB: {}", input.display()))?;
+    }
+    if cli.strip_metadata {
+        image = image.strip_metadata();
+    }
+    Ok((image, format))
+}
+
+/// Loads an image file. RAW files are developed at 16 bits and then
+/// narrowed to RGB8 with `dither`; only batch and watch sharpen them at
+/// 16 bits.
+#[cfg_attr(not(feature = "raw"), allow(unused_variables))]
+fn load_file(input: &Path, dither: Dither) -> sharpy::Result<Image> {
+    #[cfg(feature = "raw")]
+    if sharpy::is_raw_path(input) {
+        return Ok(Image::load_raw(input)?.quantize(dither));
+    }
+    Image::load(input)
+}
+
+/// Whether `color` has more than 8 bits per channel.
+fn is_deep(color: image::ColorType) -> bool {
+    color.bytes_per_pixel() > color.channel_count()
+}
+
+/// Copies an existing `output` to its name plus the `--backup` suffix before
+/// it is replaced. An existing backup is never replaced, so the first
+/// original survives repeated runs.
+fn back_up(cli: &Cli, output: &Path) -> Result<()> {
+    let Some(suffix) = &cli.backup else {
+        return Ok(());
+    };
+    if is_stdio(output) || !output.exists() {
+        return Ok(());
+    }
+    
+    let mut backup = output.as_os_str().to_os_string();
+    backup.push(suffix);
+    let backup = PathBuf::from(backup);
+    if backup.exists() {
+        anyhow::bail!("Backup already exists: {}. Move it away to replace {}.", backup.display(), output.display());
+    }
+    std::fs::copy(output, &backup)
+        .with_context(|| format!("Failed to back up {} to {}", output.display(), backup.display()))?;
+    Ok(())
+}
+
+/// The times to give the output of `input` with `--preserve-timestamps`.
+/// Read before processing, since an in-place output replaces its input.
+fn input_times(cli: &Cli, input: &Path) -> Result<Option<FileTimes>> {
+    if !cli.preserve_timestamps || is_stdio(input) || fetch::is_url(input) {
+        return Ok(None);
+    }
+    let metadata = std::fs::metadata(input)
+        .with_context(|| format!("Failed to read timestamps: {}", input.display()))?;
+    let mut times = FileTimes::new().set_modified(metadata.modified()?);
+    // Not every filesystem records access times
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    Ok(Some(times))
+}
+
+fn set_times(output: &Path, times: Option<FileTimes>) -> Result<()> {
+    match times {
+        Some(times) if !is_stdio(output) => std::fs::File::options()
+            .write(true)
+            .open(output)
+            .and_then(|file| file.set_times(times))
+            .with_context(|| format!("Failed to set timestamps: {}", output.display())),
+        _ => Ok(()),
+    }
+}
+
+/// Saves to `output`, or writes to stdout in the `--format`, the input's
+/// format or PNG.
+fn write_output(cli: &Cli, image: &Image, output: &Path, input_format: Option<image::ImageFormat>) -> Result<()> {
+    if !is_stdio(output) {
+        return image.save_with(output, &cli.save_options())
+            .with_context(|| format!("Failed to save image: {}", output.display()));
+    }
+    
+    let format = cli.format
+        .or(input_format.filter(|format| format.writing_enabled()))
+        .unwrap_or(image::ImageFormat::Png);
+    let bytes = image.to_bytes(format, &cli.save_options().encode)
+        .context("Failed to encode image")?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&bytes).and_then(|_| stdout.flush())
+        .context("Failed to write to stdout")
+}
+
+fn process_single_image<F>(cli: &Cli, input: &Path, output: &Path, operation: F) -> Result<()>
+where
+    F: FnOnce(Image) -> sharpy::Result<Image>,
+{
+    if cli.progress != ProgressFormat::Json {
+        return run_single_image(cli, input, output, operation);
+    }
+    if is_stdio(output) {
+        anyhow::bail!("--progress json writes to stdout, so the output must be a file");
+    }
+    
+    progress::emit(cli, Event::started(input, output));
+    let started = Instant::now();
+    let result = run_single_image(cli, input, output, operation);
+    match &result {
+        Ok(()) => progress::emit(cli, Event::done(input, output, "processed", started.elapsed().as_millis())),
+        Err(e) => progress::emit(cli, Event::error(input, output, errors::message(e))),
+    }
+    result
+}
+
+fn run_single_image<F>(cli: &Cli, input: &Path, output: &Path, operation: F) -> Result<()>
+where
+    F: FnOnce(Image) -> sharpy::Result<Image>,
+{
+    if !cli.quiet {
+        eprintln!("Processing: {} -> {}", input.display(), output.display());
+    }
+    
+    // Check if output exists and handle overwrite
+    if !is_stdio(output) && output.exists() && !cli.replaces_outputs() && !cli.dry_run {
+        let Some(prompt) = &cli.overwrite_prompt else {
+            anyhow::bail!("Output file already exists: {}. Use --overwrite to replace.", output.display());
+        };
+        if !prompt.confirm(output)? {
+            eprintln!("Skipped: {}", output.display());
+            return Ok(());
+        }
+    }
+    
+    if cli.dry_run {
+        if !cli.quiet {
+            eprintln!("Dry run: Would process {} -> {}", input.display(), output.display());
+        }
+        return Ok(());
+    }
+    
+    // Load image
+    let times = input_times(cli, input)?;
+    let (image, input_format) = read_input(cli, input)?;
+    
+    if cli.verbose {
+        let (width, height) = image.dimensions();
+        eprintln!("Loaded image: {}x{}", width, height);
+    }
+    
+    let before = cli.preview.map(|_| preview::thumbnail(&image));
+    
+    // Apply operation
+    let result = operation(image).context("Processing failed")?;
+    
+    // Save result
+    back_up(cli, output)?;
+    write_output(cli, &result, output, input_format)?;
+    set_times(output, times)?;
+    
+    if !cli.quiet {
+        eprintln!("Successfully saved: {}", output.display());
+    }
+    if let (Some(protocol), Some(before)) = (cli.preview, before) {
+        preview::show(protocol, before, &result)?;
+    }
+    
+    Ok(())
+}
+
+fn process_batch(cli: &Cli, args: &BatchArgs) -> Result<()> {
+    let BatchArgs {
+        patterns, output_dir, suffix, name_template, in_place, operations, recursive, mask, checkpoint,
+        skip_existing, rename_on_conflict, report, jobs, power_aware, reference,
+    } = args;
+    let recipe = cli.recipe(operations)?;
+    let mask = load_mask(mask.as_deref())?;
+    let name_template = cli.name_template(suffix.as_deref(), name_template.as_deref())?;
+    // The config file's jobs don't apply to power-aware batches, which
+    // manage their own parallelism
+    let jobs = if *power_aware { 1 } else { jobs.or(cli.config.jobs).map_or(1, |jobs| jobs.get()) };
+    
+    let mut governor = if *power_aware { Some(PowerGovernor::new()?) } else { None };
+    
+    if *in_place && patterns.iter().any(|pattern| fetch::is_url(Path::new(pattern))) {
+        anyhow::bail!("--in-place can't replace URL inputs; use --output-dir");
+    }
+    // Clap misses the conflict when --format comes before the subcommand
+    if *in_place && cli.format.is_some() {
+        anyhow::bail!("--in-place keeps each file's format; --format can't be used with it");
+    }
+    
+    // Create output directory
+    if let (Some(output_dir), false) = (output_dir, cli.dry_run) {
+        std::fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+    }
+    
+    // Find matching files, with the subdirectory each goes to
+    let mut files = Vec::new();
+    for pattern in patterns {
+        files.extend(expand_source(pattern, *recursive)?);
+    }
+    
+    if files.is_empty() {
+        anyhow::bail!("No files match pattern: {}", patterns.join(" "));
+    }
+    
+    // Resume after the files an earlier run completed
+    let checkpoint = checkpoint.as_deref()
+        .map(|path| Checkpoint::open(path, &recipe.pipeline.to_string()))
+        .transpose()?;
+    if let Some(checkpoint) = &checkpoint {
+        let found = files.len();
+        files.retain(|(path, _)| !checkpoint.is_completed(path));
+        if !cli.quiet && files.len() < found {
+            eprintln!("Resuming: {} of {} files already completed", found - files.len(), found);
+        }
+    }
+    
+    if !cli.quiet {
+        eprintln!("Found {} files to process", files.len());
+    }
+    
+    // Setup progress bar
+    let pb = if !cli.quiet && cli.progress == ProgressFormat::Bar {
+        let pb = ProgressBar::new(files.len() as u64);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")?
+                .progress_chars("#>-")
+        );
+        Some(pb)
+    } else {
+        None
+    };
+    
+    // Outputs already taken by earlier files of this batch, so two inputs
+    // with the same name don't write to one output
+    let claimed = Mutex::new(HashSet::new());
+    let policy = if *skip_existing {
+        ConflictPolicy::Skip
+    } else if *rename_on_conflict {
+        ConflictPolicy::Rename
+    } else if cli.replaces_outputs() || *in_place {
+        ConflictPolicy::Overwrite
+    } else if let Some(prompt) = &cli.overwrite_prompt {
+        ConflictPolicy::Ask(prompt)
+    } else {
+        ConflictPolicy::Fail
+    };
+    
+    // Records a file's outcome in the checkpoint and progress display
+    let finished = AtomicUsize::new(0);
+    let finish = |outcome: FileOutcome| -> Result<FileOutcome> {
+        let completed = matches!(outcome.status, FileStatus::Processed | FileStatus::Skipped);
+        if let (Some(checkpoint), true, false) = (&checkpoint, completed, cli.dry_run) {
+            checkpoint.complete(&outcome.input)?;
+        }
+        if let Some(pb) = &pb {
+            pb.inc(1);
+        }
+        let event = match &outcome.status {
+            FileStatus::Failed(error) => Event::error(&outcome.input, &outcome.output, error.clone()),
+            status => Event::done(&outcome.input, &outcome.output, status.name(), outcome.duration.as_millis()),
+        };
+        progress::emit(cli, event);
+        progress::emit(cli, Event::progress(finished.fetch_add(1, Ordering::Relaxed) + 1, files.len()));
+        Ok(outcome)
+    };
+    
+    // Process each file
+    let process_file = |path: &Path, subdir: &Path, governor: Option<&mut PowerGovernor>| -> Result<FileOutcome> {
+        if let Some(pb) = &pb {
+            pb.set_message(format!("Processing: {}", path.file_name().unwrap_or_default().to_string_lossy()));
+        }
+        
+        let output = match output_dir {
+            Some(output_dir) => {
+                let target_dir = output_dir.join(subdir);
+                if !cli.dry_run {
+                    std::fs::create_dir_all(&target_dir)
+                        .with_context(|| format!("Failed to create output directory: {}", target_dir.display()))?;
+                }
+                output_path_for(cli, path, &target_dir, &name_template)?
+            }
+            // --in-place
+            None => path.to_path_buf(),
+        };
+        let started = Instant::now();
+        let mut outcome = FileOutcome {
+            input: path.to_path_buf(),
+            output,
+            status: FileStatus::Skipped,
+            duration: Duration::ZERO,
+            sharpness: None,
+        };
+        progress::emit(cli, Event::started(path, &outcome.output));
+        let result = match claim_output(&claimed, &outcome.output, policy, pb.as_ref()) {
+            Ok(None) => return finish(outcome),
+            // Quitting at the prompt stops the batch
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Ok(Some(output_path)) => {
+                outcome.output = output_path;
+                // Process the file, with parallelism limited by power state if requested
+                let process = || process_single_with_pipeline(cli, path, &outcome.output, &recipe, mask.as_ref(), report.is_some());
+                match governor {
+                    Some(governor) => governor.run(process, |state, threads| {
+                        if !cli.quiet {
+                            let message = format!("Power: {}, using {} thread(s)", state, threads);
+                            match &pb {
+                                Some(pb) => pb.println(message),
+                                None => eprintln!("{}", message),
+                            }
+                        }
+                    })?,
+                    None => process(),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        
+        let output_path = &outcome.output;
+        outcome.status = match result {
+            Ok(sharpness) => {
+                outcome.sharpness = sharpness;
+                match (&reference.reference_dir, cli.dry_run) {
+                    (Some(reference_dir), false) => {
+                        let reference_path = reference_dir.join(subdir).join(output_path.file_name().unwrap_or_default());
+                        match reference.check(&reference_path, output_path) {
+                            Ok(comparison) => {
+                                if cli.verbose {
+                                    eprintln!(
+                                        "{}: SSIM {:.4}, delta E {:.3}, halo {:.3}",
+                                        output_path.display(), comparison.ssim, comparison.delta_e, comparison.halo
+                                    );
+                                }
+                                FileStatus::Processed
+                            }
+                            Err(problem) => FileStatus::ReferenceFailed(problem),
+                        }
+                    }
+                    _ => FileStatus::Processed,
+                }
+            }
+            Err(e) => {
+                if !cli.quiet {
+                    eprintln!("Error processing {}: {}", path.display(), e);
+                }
+                FileStatus::Failed(format!("{}: {}", e, e.root_cause()))
+            }
+        };
+        outcome.duration = started.elapsed();
+        finish(outcome)
+    };
+    
+    let outcomes = if jobs > 1 {
+        run_concurrently(&files, jobs, |(path, subdir)| process_file(path, subdir, None))
+    } else {
+        files.iter()
+            .map(|(path, subdir)| process_file(path, subdir, governor.as_mut()))
+            .collect::<Result<Vec<_>>>()
+    };
+    // Keep the progress made before any error
+    if let (Some(checkpoint), false) = (&checkpoint, cli.dry_run) {
+        checkpoint.save()?;
+    }
+    
+    let outcomes = outcomes?;
+    if let (Some(report), false) = (report, cli.dry_run) {
+        report::write(report, &recipe.pipeline, &outcomes)?;
+    }
+    
+    let mut success_count = 0;
+    let mut skipped_count = 0;
+    let mut error_count = 0;
+    let mut reference_failures = Vec::new();
+    for outcome in &outcomes {
+        match &outcome.status {
+            FileStatus::Processed => success_count += 1,
+            FileStatus::Skipped => skipped_count += 1,
+            FileStatus::ReferenceFailed(problem) => {
+                success_count += 1;
+                reference_failures.push((&outcome.output, problem));
+            }
+            FileStatus::Failed(_) => error_count += 1,
+        }
+    }
+    
+    if let Some(pb) = &pb {
+        pb.finish_with_message(format!("Completed: {} successful, {} errors", success_count, error_count));
+    }
+    
+    if skipped_count > 0 && !cli.quiet {
+        eprintln!("Skipped {} files whose output already exists", skipped_count);
+    }
+    
+    if reference.reference_dir.is_some() && !cli.dry_run {
+        if !cli.quiet || !reference_failures.is_empty() {
+            eprintln!(
+                "Reference comparison: {} passed, {} failed",
+                success_count - reference_failures.len(),
+                reference_failures.len()
+            );
+        }
+        for (path, problem) in &reference_failures {
+            eprintln!("  FAIL {}: {}", path.display(), problem);
+        }
+    }
+    
+    if error_count > 0 {
+        let message = format!("{} files failed to process", error_count);
+        return Err(Failure::new(Kind::PartialBatch, message).into());
+    }
+    
+    if !reference_failures.is_empty() {
+        let message = format!("{} outputs differ from their references", reference_failures.len());
+        return Err(Failure::new(Kind::ReferenceMismatch, message).into());
+    }
+    
+    Ok(())
+}
+
+/// What a batch does with a file whose output name is already taken.
+#[derive(Clone, Copy)]
+enum ConflictPolicy<'a> {
+    /// Fail the file, leaving the existing output alone
+    Fail,
+    Skip,
+    Overwrite,
+    /// Write to the first free name with a number appended
+    Rename,
+    /// Overwrite or skip as the user answers
+    Ask(&'a OverwritePrompt),
+}
+
+/// Claims `output` for one batch file, resolving a conflict with an existing
+/// file or an earlier claim by `policy`. Returns the path to write to, or
+/// `None` if the file should be skipped. `pb` is hidden while asking.
+fn claim_output(
+    claimed: &Mutex<HashSet<PathBuf>>,
+    output: &Path,
+    policy: ConflictPolicy,
+    pb: Option<&ProgressBar>,
+) -> Result<Option<PathBuf>> {
+    let mut claimed = claimed.lock().unwrap_or_else(PoisonError::into_inner);
+    let taken = |path: &Path| claimed.contains(path) || path.exists();
+    
+    let output = match (taken(output), policy) {
+        (false, _) | (true, ConflictPolicy::Overwrite) => output.to_path_buf(),
+        (true, ConflictPolicy::Skip) => return Ok(None),
+        (true, ConflictPolicy::Ask(prompt)) => {
+            let confirm = || prompt.confirm(output);
+            let overwrite = match pb {
+                Some(pb) => pb.suspend(confirm)?,
+                None => confirm()?,
+            };
+            if !overwrite {
+                return Ok(None);
+            }
+            output.to_path_buf()
+        }
+        (true, ConflictPolicy::Fail) => anyhow::bail!(
+            "Output file already exists: {}. Use --overwrite, --skip-existing or --rename-on-conflict.",
+            output.display()
+        ),
+        (true, ConflictPolicy::Rename) => {
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = output.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+            (1..)
+                .map(|n| output.with_file_name(format!("{}_{}{}", stem, n, extension)))
+                .find(|candidate| !taken(candidate))
+                .expect("unbounded range has a free name")
+        }
+    };
+    claimed.insert(output.clone());
+    Ok(Some(output))
+}
+
+/// What became of one batch file.
+struct FileOutcome {
+    input: PathBuf,
+    output: PathBuf,
+    status: FileStatus,
+    /// Time spent loading, processing and saving
+    duration: Duration,
+    /// Sharpness (Laplacian variance) before and after, if measured
+    sharpness: Option<(f64, f64)>,
+}
+
+enum FileStatus {
+    Processed,
+    /// Output already existed (`--skip-existing`, or declined at the prompt)
+    Skipped,
+    /// Saved, but differs from its reference
+    ReferenceFailed(String),
+    /// Failed to process, with the error
+    Failed(String),
+}
+
+impl FileStatus {
+    /// The status as named in reports and progress events.
+    fn name(&self) -> &'static str {
+        match self {
+            FileStatus::Processed => "processed",
+            FileStatus::Skipped => "skipped",
+            FileStatus::ReferenceFailed(_) => "reference_failed",
+            FileStatus::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Runs `work` on every item using `jobs` threads that each take one item
+/// at a time, so at most `jobs` items are in flight. Results are in item
+/// order; no new items are started after an error.
+fn run_concurrently<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else { break };
+                    let result = work(item);
+                    if result.is_err() {
+                        next.store(items.len(), Ordering::Relaxed);
+                    }
+                    results.lock().unwrap_or_else(PoisonError::into_inner).push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Expands a batch source into files, each with the subdirectory of the
+/// output directory it goes to.
+/// 
+/// Directories stand for the images in them. With `recursive`, patterns
+/// also match in subdirectories, and each file keeps its path relative to
+/// the parent of the pattern's fixed leading directories, so
+/// `shoots/day1/a.jpg` from `shoots` or `shoots/*.jpg` goes to
+/// `shoots/day1/`. Otherwise every file goes to the output directory itself.
+fn expand_source(pattern: &str, recursive: bool) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let path = Path::new(pattern);
+    if fetch::is_url(path) {
+        return Ok(vec![(path.to_path_buf(), PathBuf::new())]);
+    }
+    
+    let is_dir = path.is_dir();
+    let (base, pattern) = if is_dir {
+        let dir = glob::Pattern::escape(pattern);
+        (path.to_path_buf(), if recursive { format!("{}/**/*", dir) } else { format!("{}/*", dir) })
+    } else {
+        let is_literal = |component: &std::path::Component| {
+            !component.as_os_str().to_string_lossy().contains(['*', '?', '['])
+        };
+        let base: PathBuf = path.components().take_while(is_literal).collect();
+        let rest = path.strip_prefix(&base).unwrap_or(path);
+        if recursive && !rest.as_os_str().is_empty() {
+            (base.clone(), base.join("**").join(rest).to_string_lossy().into_owned())
+        } else {
+            (base, pattern.to_string())
+        }
+    };
+    let root = base.parent().unwrap_or(Path::new(""));
+    
+    let mut files = Vec::new();
+    for entry in glob(&pattern).map_err(|e| anyhow::anyhow!("Invalid pattern: {}", e))? {
+        let Ok(file) = entry else { continue };
+        // Directory listings include non-images; explicit patterns are taken as given
+        if !file.is_file() || (is_dir && !is_readable(&file)) {
+            continue;
+        }
+        
+        // Only plain names, so `..` or absolute paths can't leave the output directory
+        let subdir = match (recursive, file.parent().and_then(|parent| parent.strip_prefix(root).ok())) {
+            (true, Some(relative)) => relative.components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .collect(),
+            _ => PathBuf::new(),
+        };
+        files.push((file, subdir));
+    }
+    Ok(files)
+}
+
+/// Whether `path` has the extension of a format Sharpy reads.
+fn is_readable(path: &Path) -> bool {
+    #[cfg(feature = "raw")]
+    if sharpy::is_raw_path(path) {
+        return true;
+    }
+    #[cfg(feature = "heic")]
+    if is_heic_path(path) {
+        return true;
+    }
+    image::ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled())
+}
+
+/// Whether `path` has a HEIC or HEIF extension, in any case.
+#[cfg(feature = "heic")]
+fn is_heic_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ["heic", "heif"].contains(&extension.to_lowercase().as_str()))
+}
+
+/// Names the output for `input` in `output_dir` by filling in `template`'s
+/// `{name}` with the input's stem and `{ext}` with the extension of
+/// `--format` or the input (`tif` for RAW files and `jpg` for HEIC, which
+/// can't be written).
+fn output_path_for(cli: &Cli, input: &Path, output_dir: &Path, template: &str) -> Result<PathBuf> {
+    // Outputs for URLs are named after the end of the URL's path
+    let name = if fetch::is_url(input) {
+        PathBuf::from(fetch::file_name(&input.to_string_lossy()))
+    } else {
+        input.to_path_buf()
+    };
+    
+    let stem = name.file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid filename: {}", input.display()))?;
+    
+    let extension = match cli.format {
+        Some(format) => format.extensions_str()[0],
+        #[cfg(feature = "raw")]
+        None if sharpy::is_raw_path(&name) => "tif",
+        #[cfg(feature = "heic")]
+        None if is_heic_path(&name) => "jpg",
+        None => name.extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("jpg"),
+    };
+    
+    Ok(output_dir.join(template.replace("{name}", stem).replace("{ext}", extension)))
+}
+
+/// Processes one file, returning its sharpness before and after if `measure`.
+fn process_single_with_pipeline(
+    cli: &Cli,
+    input: &Path,
+    output: &Path,
+    recipe: &Recipe,
+    mask: Option<&GrayImage>,
+    measure: bool,
+) -> Result<Option<(f64, f64)>> {
+    if cli.dry_run {
+        if cli.verbose {
+            eprintln!("Dry run: Would process {} -> {} with {}", 
+                     input.display(), output.display(), recipe.pipeline);
+        }
+        return Ok(None);
+    }
+    
+    let times = input_times(cli, input)?;
+    #[cfg(feature = "raw")]
+    if sharpy::is_raw_path(input) && !fetch::is_url(input) {
+        let image = Image::load_raw(input)
+            .with_context(|| Failure::new(Kind::Input, format!("Failed to load image: {}", input.display())))?;
+        let sharpness = sharpen_file(cli, image, output, recipe, mask, measure, |image| save_rgb16(cli, image, output))?;
+        set_times(output, times)?;
+        return Ok(sharpness);
+    }
+    let sharpness = if keeps_gray16(cli, input, output) {
+        let image = Image::load_gray16(input)
+            .with_context(|| Failure::new(Kind::Input, format!("Failed to load image: {}", input.display())))?;
+        let image = if cli.strip_metadata { image.strip_metadata() } else { image };
+        sharpen_file(cli, image, output, recipe, mask, measure, |image| image.save_with(output, &cli.save_options()))?
+    } else {
+        let image = load_image(cli, input)?;
+        sharpen_file(cli, image, output, recipe, mask, measure, |image| image.save_with(output, &cli.save_options()))?
+    };
+    set_times(output, times)?;
+    Ok(sharpness)
+}
+
+/// Applies `recipe` to `image` and writes it with `save`, returning its
+/// sharpness before and after if `measure`.
+fn sharpen_file<P: SharpenPixel>(
+    cli: &Cli,
+    image: Image<P>,
+    output: &Path,
+    recipe: &Recipe,
+    mask: Option<&GrayImage>,
+    measure: bool,
+    save: impl FnOnce(&Image<P>) -> sharpy::Result<()>,
+) -> Result<Option<(f64, f64)>> {
+    let before = measure.then(|| image.analyze().sharpness);
+    
+    // Apply each operation in sequence
+    let image = apply_pipeline(cli, image, recipe, mask)
+        .map_err(|e| anyhow::anyhow!("Operation failed: {}", e))?;
+    
+    // Save result
+    back_up(cli, output)?;
+    save(&image).with_context(|| format!("Failed to save image: {}", output.display()))?;
+    
+    Ok(before.map(|before| (before, image.analyze().sharpness)))
+}
+
+/// Saves a developed RAW file, at 16 bits if the output is PNG or TIFF and
+/// as 8-bit RGB with `--dither` otherwise.
+#[cfg(feature = "raw")]
+fn save_rgb16(cli: &Cli, image: &Image<image::Rgb<u16>>, output: &Path) -> sharpy::Result<()> {
+    let options = cli.save_options();
+    let format = match options.format {
+        Some(format) => format,
+        None => image::ImageFormat::from_path(output)?,
+    };
+    match format {
+        image::ImageFormat::Png | image::ImageFormat::Tiff => image.save_with(output, &options),
+        _ => image.quantize(cli.dither).save_with(output, &options),
+    }
+}
+
+/// Whether `input` is a 16-bit grayscale file and `output` a format that
+/// holds it (PNG or TIFF), so it is processed at full precision rather
+/// than as RGB8.
+fn keeps_gray16(cli: &Cli, input: &Path, output: &Path) -> bool {
+    use image::ImageDecoder;
+    
+    if is_stdio(input) || fetch::is_url(input) || cli.input_format.is_some() || cli.to_srgb {
+        return false;
+    }
+    let format = cli.format.or_else(|| image::ImageFormat::from_path(output).ok());
+    if !matches!(format, Some(image::ImageFormat::Png | image::ImageFormat::Tiff)) {
+        return false;
+    }
+    image::ImageReader::open(input).ok()
+        .and_then(|reader| reader.with_guessed_format().ok())
+        .and_then(|reader| reader.into_decoder().ok())
+        .is_some_and(|decoder| decoder.color_type() == image::ColorType::L16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_in_place_keeps_format() {
+        let args = ["sharpy", "batch", "--in-place", "--backup", ".orig", "*.jpg"];
+        assert!(Cli::try_parse_from(args).is_ok());
+        assert!(Cli::try_parse_from([&args[..], &["--format", "png"]].concat()).is_err());
+    }
+    
+    #[test]
+    fn test_preview_flag() {
+        // Without a value the flag leaves the next argument to the command
+        let cli = Cli::try_parse_from(["sharpy", "unsharp", "--preview", "in.png", "out.png"]).unwrap();
+        assert_eq!(cli.preview, Some(preview::Protocol::Auto));
+        assert!(matches!(cli.command, Commands::Unsharp { input: Some(ref input), .. } if input == Path::new("in.png")));
+        
+        let cli = Cli::try_parse_from(["sharpy", "--preview=sixel", "unsharp", "in.png", "out.png"]).unwrap();
+        assert_eq!(cli.preview, Some(preview::Protocol::Sixel));
+        assert!(Cli::try_parse_from(["sharpy", "--preview=bogus", "unsharp", "in.png", "out.png"]).is_err());
+    }
+    
+    #[test]
+    fn test_parse_screen() {
+        assert_eq!(parse_screen("auto"), Ok(Operation::Descreen { period: None }));
+        assert_eq!(parse_screen(" AUTO "), Ok(Operation::Descreen { period: None }));
+        // 150 LPI scanned at 600 DPI: a dot every 4 pixels
+        assert_eq!(parse_screen("150@600"), Ok(Operation::Descreen { period: Some(4.0) }));
+        assert_eq!(parse_screen(" 133 @ 1200 "), Ok(Operation::Descreen { period: Some(1200.0 / 133.0) }));
+        for bad in ["", "150", "150@", "@600", "0@600", "150@-600", "x@600"] {
+            assert!(parse_screen(bad).is_err(), "{}", bad);
+        }
+    }
+    
+    #[test]
+    fn test_dither_on_load() {
+        // Halfway between two 8-bit levels, so rounding alone gives one flat value
+        let path = std::env::temp_dir().join(format!("sharpy_dither_{}.png", std::process::id()));
+        image::ImageBuffer::<image::Rgb<u16>, _>::from_pixel(16, 16, image::Rgb([100 * 257 + 128; 3])).save(&path).unwrap();
+        let levels = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["sharpy"], args, &["unsharp", "in.png", "out.png"]].concat()).unwrap();
+            let image = load_image(&cli, &path).unwrap();
+            let mut levels: Vec<u8> = image.as_buffer().pixels().map(|p| p[0]).collect();
+            levels.sort_unstable();
+            levels.dedup();
+            levels
+        };
+        let plain = levels(&[]);
+        let dithered = levels(&["--dither", "ordered"]);
+        std::fs::remove_file(&path).unwrap();
+        
+        assert_eq!(plain.len(), 1);
+        assert_eq!(dithered, [100, 101]);
+    }
+    
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(parse_region("10,20,300,200"), Ok(Rect::new(10, 20, 300, 200)));
+        assert_eq!(parse_region(" 0, 0 ,1,1 "), Ok(Rect::new(0, 0, 1, 1)));
+        for bad in ["", "10,20,300", "10,20,300,200,5", "10,-20,300,200", "a,b,c,d"] {
+            assert!(parse_region(bad).is_err(), "{}", bad);
+        }
+    }
+    
+    #[test]
+    fn test_parse_threshold() {
+        assert!(matches!(parse_threshold("4"), Ok(Threshold::Absolute(4))));
+        assert!(matches!(parse_threshold(" 20% "), Ok(Threshold::Relative(share)) if share == 20.0));
+        assert!(matches!(parse_threshold("2.5 %"), Ok(Threshold::Relative(share)) if share == 2.5));
+        for bad in ["", "256", "-1", "101%", "-5%", "%", "x"] {
+            assert!(parse_threshold(bad).is_err(), "{}", bad);
+        }
+    }
+    
+    #[test]
+    fn test_parse_denoise() {
+        // A lone strength takes the default patch and search radii
+        assert_eq!(parse_denoise("0.8"), Ok(Operation::Denoise { strength: 0.8, patch_radius: 1, search_radius: 7 }));
+        assert_eq!(parse_denoise("0.5:2:10"), Ok(Operation::Denoise { strength: 0.5, patch_radius: 2, search_radius: 10 }));
+        for bad in ["", "x", "0.5:2", "0.5:2:x"] {
+            assert!(parse_denoise(bad).is_err(), "{}", bad);
+        }
+    }
+    
+    #[test]
+    fn test_parse_radial() {
+        assert_eq!(parse_radial("0.5:1.5:0.8"), Ok((0.5, 1.5, 0.8)));
+        assert_eq!(parse_radial(" 1 : 2 : 0 "), Ok((1.0, 2.0, 0.0)));
+        // A bad value isn't skipped over
+        for bad in ["", "0.5:1.5", "0.5:1.5:0.8:1", "0.5:x:0.8:1", "0.5::1.5:0.8"] {
+            assert!(parse_radial(bad).is_err(), "{}", bad);
+        }
+    }
+    
+    /// An empty directory for one test, removed first if a run left it.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sharpy_{}_{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn test_expand_source() {
+        let dir = scratch_dir("expand");
+        std::fs::create_dir_all(dir.join("shoot/day1")).unwrap();
+        for file in ["shoot/a.png", "shoot/notes.txt", "shoot/day1/b.jpg"] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        let expand = |pattern: PathBuf, recursive| {
+            let mut files = expand_source(&pattern.to_string_lossy(), recursive).unwrap();
+            files.sort();
+            files.into_iter()
+                .map(|(file, subdir)| (file.strip_prefix(&dir).unwrap().to_path_buf(), subdir))
+                .collect::<Vec<_>>()
+        };
+        let entry = |file: &str, subdir: &str| (PathBuf::from(file), PathBuf::from(subdir));
+        
+        // Directories list their images only, keeping subdirectories when recursive
+        assert_eq!(expand(dir.join("shoot"), false), [entry("shoot/a.png", "")]);
+        assert_eq!(expand(dir.join("shoot"), true), [entry("shoot/a.png", "shoot"), entry("shoot/day1/b.jpg", "shoot/day1")]);
+        // Patterns are taken as given, and recursive ones match in subdirectories
+        assert_eq!(expand(dir.join("shoot/*.txt"), false), [entry("shoot/notes.txt", "")]);
+        assert_eq!(expand(dir.join("shoot/*.jpg"), true), [entry("shoot/day1/b.jpg", "shoot/day1")]);
+        assert!(expand(dir.join("shoot/*.jpg"), false).is_empty());
+        // URLs pass through
+        let url = "https://example.com/photo.jpg";
+        assert_eq!(expand_source(url, true).unwrap(), [(PathBuf::from(url), PathBuf::new())]);
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn test_run_concurrently() {
+        let items: Vec<u32> = (0..20).collect();
+        let in_flight = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let squares = run_concurrently(&items, 3, |&item| {
+            most.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(1));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(item * item)
+        }).unwrap();
+        // Results keep item order, with at most `jobs` items at a time
+        assert_eq!(squares, items.iter().map(|item| item * item).collect::<Vec<_>>());
+        assert!(most.load(Ordering::SeqCst) <= 3);
+        
+        // No item starts after an error
+        let started = AtomicUsize::new(0);
+        let result = run_concurrently(&items, 1, |&item| {
+            started.fetch_add(1, Ordering::SeqCst);
+            if item == 4 { anyhow::bail!("item {}", item) } else { Ok(item) }
+        });
+        assert_eq!(result.unwrap_err().to_string(), "item 4");
+        assert_eq!(started.load(Ordering::SeqCst), 5);
+        assert!(run_concurrently(&[] as &[u32], 4, |&item| Ok(item)).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_claim_output() {
+        let dir = scratch_dir("claim");
+        let existing = dir.join("photo.jpg");
+        std::fs::write(&existing, b"").unwrap();
+        let fresh = dir.join("fresh.jpg");
+        let claimed = Mutex::new(HashSet::new());
+        
+        assert!(claim_output(&claimed, &existing, ConflictPolicy::Fail, None).is_err());
+        assert_eq!(claim_output(&claimed, &existing, ConflictPolicy::Skip, None).unwrap(), None);
+        assert_eq!(claim_output(&claimed, &existing, ConflictPolicy::Overwrite, None).unwrap(), Some(existing.clone()));
+        // Names taken on disk or by an earlier claim get the next free number
+        assert_eq!(claim_output(&claimed, &existing, ConflictPolicy::Rename, None).unwrap(), Some(dir.join("photo_1.jpg")));
+        assert_eq!(claim_output(&claimed, &existing, ConflictPolicy::Rename, None).unwrap(), Some(dir.join("photo_2.jpg")));
+        // A name claimed by an earlier file conflicts though nothing is written yet
+        assert_eq!(claim_output(&claimed, &fresh, ConflictPolicy::Fail, None).unwrap(), Some(fresh.clone()));
+        assert!(claim_output(&claimed, &fresh, ConflictPolicy::Fail, None).is_err());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[cfg(feature = "heic")]
+    #[test]
+    fn test_heic_paths() {
+        for path in ["IMG_0001.HEIC", "photo.heic", "burst.Heif"] {
+            assert!(is_heic_path(Path::new(path)) && is_readable(Path::new(path)), "{}", path);
+        }
+        assert!(!is_heic_path(Path::new("photo.jpg")) && !is_heic_path(Path::new("heic")));
+        
+        // HEIC can't be written, so outputs default to JPEG
+        let cli = Cli::try_parse_from(["sharpy", "unsharp", "in.png", "out.png"]).unwrap();
+        let output = output_path_for(&cli, Path::new("IMG_0001.HEIC"), Path::new("out"), "{name}_sharp.{ext}").unwrap();
+        assert_eq!(output, Path::new("out/IMG_0001_sharp.jpg"));
+        let cli = Cli::try_parse_from(["sharpy", "--format", "png", "unsharp", "in.png", "out.png"]).unwrap();
+        let output = output_path_for(&cli, Path::new("IMG_0001.HEIC"), Path::new("out"), "{name}_sharp.{ext}").unwrap();
+        assert_eq!(output, Path::new("out/IMG_0001_sharp.png"));
+    }
+}
+
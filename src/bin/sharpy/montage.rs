@@ -0,0 +1,45 @@
+//! Labeled grids of images, shared by `teststrip` and `presets-sheet`.
+
+use crate::font;
+use image::{imageops, Rgb, RgbImage};
+
+const GAP: u32 = 4;
+const LABEL_PADDING: u32 = 4;
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const TEXT_COLOR: Rgb<u8> = Rgb([230, 230, 230]);
+
+/// An image with the label drawn beneath it.
+pub struct Cell {
+    pub label: String,
+    pub image: RgbImage,
+}
+
+/// Lays out `cells` row by row, `columns` per row, each in a slot as large
+/// as the largest cell.
+pub fn montage(cells: &[Cell], columns: usize) -> RgbImage {
+    let columns = columns.clamp(1, cells.len().max(1));
+    let slot_width = cells.iter().map(|cell| cell.image.width()).max().unwrap_or(0);
+    let slot_height = cells.iter().map(|cell| cell.image.height()).max().unwrap_or(0);
+
+    // Labels are drawn at double size when the longest one fits
+    let widest_label = cells.iter().map(|cell| font::text_width(&cell.label, 1)).max().unwrap_or(0);
+    let scale = if widest_label * 2 + 2 * LABEL_PADDING <= slot_width { 2 } else { 1 };
+    let label_height = font::GLYPH_HEIGHT * scale + 2 * LABEL_PADDING;
+    let (cell_width, cell_height) = (slot_width + GAP, slot_height + label_height + GAP);
+    let rows = cells.len().div_ceil(columns) as u32;
+
+    let mut sheet = RgbImage::from_pixel(
+        columns as u32 * cell_width + GAP,
+        rows * cell_height + GAP,
+        BACKGROUND,
+    );
+    for (i, cell) in cells.iter().enumerate() {
+        let x = GAP + (i % columns) as u32 * cell_width;
+        let y = GAP + (i / columns) as u32 * cell_height;
+        imageops::replace(&mut sheet, &cell.image, x as i64, y as i64);
+
+        let (label_x, label_y) = (x + LABEL_PADDING, y + slot_height + LABEL_PADDING);
+        font::draw_text(&mut sheet, &cell.label, label_x, label_y, scale, TEXT_COLOR);
+    }
+    sheet
+}
@@ -0,0 +1,87 @@
+//! Pipeline files for `sharpy run`: an ordered list of operations, each
+//! optionally limited by a mask, in YAML, TOML or JSON.
+//!
+//! ```yaml
+//! operations:
+//!   - op: unsharp
+//!     radius: 1.2
+//!     amount: 0.8
+//!     threshold: 2
+//!     mask:
+//!       edges: sobel
+//!       blur: 1.5
+//!   - op: clarity
+//!     strength: 0.3
+//!     radius: 5.0
+//! ```
+//!
+//! Operations use the same fields as preset files.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sharpy::{EdgeMethod, Graph, Operation};
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineFile {
+    operations: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    #[serde(flatten)]
+    operation: Operation,
+    mask: Option<Mask>,
+}
+
+/// Limits a step to the edges of its input; elsewhere the input passes
+/// through unchanged.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Mask {
+    edges: EdgeMethod,
+    /// Gaussian radius softening the mask's transitions
+    blur: Option<f32>,
+}
+
+/// Loads a pipeline file as a graph, choosing the syntax by extension.
+pub fn load(path: &Path) -> Result<Graph> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pipeline file: {}", path.display()))?;
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    let file: PipelineFile = match extension.as_deref() {
+        Some("yaml" | "yml") => serde_norway::from_str(&contents).map_err(anyhow::Error::new),
+        Some("toml") => toml::from_str(&contents).map_err(anyhow::Error::new),
+        Some("json") => serde_json::from_str(&contents).map_err(anyhow::Error::new),
+        _ => Err(anyhow::anyhow!("unsupported extension (expected .yaml, .yml, .toml or .json)")),
+    }.with_context(|| format!("Invalid pipeline file: {}", path.display()))?;
+
+    if file.operations.is_empty() {
+        anyhow::bail!("Pipeline file {} contains no operations", path.display());
+    }
+    Ok(build(file.operations))
+}
+
+fn build(steps: Vec<Step>) -> Graph {
+    let mut graph = Graph::new();
+    let mut node = graph.source();
+    for step in steps {
+        let processed = graph.operation(node, step.operation);
+        node = match step.mask {
+            Some(mask) => {
+                let mut edges = graph.edge_mask(node, mask.edges);
+                if let Some(radius) = mask.blur {
+                    edges = graph.blur(edges, radius);
+                }
+                graph.mask_blend(node, processed, edges)
+            }
+            None => processed,
+        };
+    }
+    graph.set_output(node);
+    graph
+}
@@ -0,0 +1,210 @@
+//! Battery and thermal monitoring used by `batch --power-aware`.
+//!
+//! Only functional on Linux when the CLI is built with the `power` feature;
+//! the state is read from sysfs before each file and the batch runs on a
+//! smaller thread pool while on battery or running hot.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// CPU temperature (millidegrees Celsius) treated as thermally throttled.
+#[cfg_attr(not(all(feature = "power", target_os = "linux")), allow(dead_code))]
+const HOT_MILLIDEGREES: i64 = 80_000;
+
+/// Power conditions that limit how hard a batch may run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerState {
+    pub on_battery: bool,
+    pub hot: bool,
+}
+
+impl PowerState {
+    /// Threads to use out of `available`: half on battery, a quarter when hot.
+    fn threads(self, available: usize) -> usize {
+        let divisor = if self.hot { 4 } else if self.on_battery { 2 } else { 1 };
+        (available / divisor).max(1)
+    }
+}
+
+impl std::fmt::Display for PowerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.on_battery, self.hot) {
+            (false, false) => write!(f, "on AC power"),
+            (true, false) => write!(f, "on battery"),
+            (false, true) => write!(f, "running hot"),
+            (true, true) => write!(f, "on battery and running hot"),
+        }
+    }
+}
+
+/// Runs work on a thread pool sized for the current power state.
+pub struct PowerGovernor {
+    available: usize,
+    pools: HashMap<usize, rayon::ThreadPool>,
+    last: Option<PowerState>,
+}
+
+impl PowerGovernor {
+    /// Creates a governor, failing if power state cannot be monitored here.
+    pub fn new() -> Result<Self> {
+        ensure_supported()?;
+        let available = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Ok(Self {
+            available,
+            pools: HashMap::new(),
+            last: None,
+        })
+    }
+
+    /// Samples the power state and runs `work` with matching parallelism.
+    ///
+    /// `on_change` is called with the new state and thread count whenever the
+    /// state differs from the previous sample.
+    pub fn run<T, F>(&mut self, work: F, on_change: impl FnOnce(PowerState, usize)) -> Result<T>
+    where
+        T: Send,
+        F: FnOnce() -> T + Send,
+    {
+        let state = sample();
+        let threads = state.threads(self.available);
+        if self.last != Some(state) {
+            on_change(state, threads);
+            self.last = Some(state);
+        }
+
+        let pool = match self.pools.entry(threads) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(rayon::ThreadPoolBuilder::new().num_threads(threads).build()?)
+            }
+        };
+        Ok(pool.install(work))
+    }
+}
+
+#[cfg(all(feature = "power", target_os = "linux"))]
+fn ensure_supported() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(all(feature = "power", target_os = "linux")))]
+fn ensure_supported() -> Result<()> {
+    anyhow::bail!("--power-aware requires sharpy to be built with the `power` feature on Linux")
+}
+
+#[cfg(all(feature = "power", target_os = "linux"))]
+fn sample() -> PowerState {
+    let sysfs = std::path::Path::new("/sys/class");
+    PowerState {
+        on_battery: linux::on_battery(sysfs),
+        hot: linux::max_temperature(sysfs).is_some_and(|t| t >= HOT_MILLIDEGREES),
+    }
+}
+
+#[cfg(not(all(feature = "power", target_os = "linux")))]
+fn sample() -> PowerState {
+    PowerState { on_battery: false, hot: false }
+}
+
+#[cfg(all(feature = "power", target_os = "linux"))]
+mod linux {
+    use std::fs;
+    use std::path::Path;
+
+    fn read(path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+    }
+
+    /// True when a battery is present and no mains supply is online, read
+    /// from `sysfs` (normally `/sys/class`).
+    pub fn on_battery(sysfs: &Path) -> bool {
+        let Ok(entries) = fs::read_dir(sysfs.join("power_supply")) else {
+            return false;
+        };
+
+        let mut has_battery = false;
+        let mut mains_online = false;
+        let mut discharging = false;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match read(&path.join("type")).as_deref() {
+                Some("Mains") => mains_online |= read(&path.join("online")).as_deref() == Some("1"),
+                Some("Battery") => {
+                    has_battery = true;
+                    discharging |= read(&path.join("status")).as_deref() == Some("Discharging");
+                }
+                _ => {}
+            }
+        }
+        has_battery && (discharging || !mains_online)
+    }
+
+    /// Highest temperature reported by any thermal zone, in millidegrees.
+    pub fn max_temperature(sysfs: &Path) -> Option<i64> {
+        fs::read_dir(sysfs.join("thermal")).ok()?
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("thermal_zone"))
+            .filter_map(|entry| read(&entry.path().join("temp"))?.parse().ok())
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threads() {
+        let state = |on_battery, hot| PowerState { on_battery, hot };
+        assert_eq!(state(false, false).threads(8), 8);
+        assert_eq!(state(true, false).threads(8), 4);
+        assert_eq!(state(false, true).threads(8), 2);
+        assert_eq!(state(true, true).threads(8), 2);
+        // Never fewer than one thread
+        assert_eq!(state(true, true).threads(2), 1);
+        assert_eq!(state(true, true).to_string(), "on battery and running hot");
+    }
+
+    #[cfg(not(all(feature = "power", target_os = "linux")))]
+    #[test]
+    fn test_unsupported() {
+        let error = PowerGovernor::new().err().unwrap();
+        assert!(error.to_string().contains("`power` feature"), "{}", error);
+    }
+
+    #[cfg(all(feature = "power", target_os = "linux"))]
+    #[test]
+    fn test_sysfs() {
+        use std::fs;
+
+        let sysfs = std::env::temp_dir().join(format!("sharpy_power_{}", std::process::id()));
+        fs::remove_dir_all(&sysfs).ok();
+        let write = |path: &str, contents: &str| {
+            let path = sysfs.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+
+        // No power supplies or thermal zones, as on many desktops and VMs
+        assert!(!linux::on_battery(&sysfs));
+        assert_eq!(linux::max_temperature(&sysfs), None);
+
+        write("power_supply/AC/type", "Mains\n");
+        write("power_supply/AC/online", "1\n");
+        write("power_supply/BAT0/type", "Battery\n");
+        write("power_supply/BAT0/status", "Charging\n");
+        assert!(!linux::on_battery(&sysfs));
+        write("power_supply/BAT0/status", "Discharging\n");
+        assert!(linux::on_battery(&sysfs));
+        write("power_supply/BAT0/status", "Full\n");
+        write("power_supply/AC/online", "0\n");
+        assert!(linux::on_battery(&sysfs));
+
+        write("thermal/thermal_zone0/temp", "45000\n");
+        write("thermal/thermal_zone1/temp", "82000\n");
+        write("thermal/cooling_device0/temp", "99000\n");
+        assert_eq!(linux::max_temperature(&sysfs), Some(82_000));
+
+        fs::remove_dir_all(&sysfs).unwrap();
+    }
+}
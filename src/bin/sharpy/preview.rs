@@ -0,0 +1,211 @@
+//! `--preview`: a downscaled before/after pair drawn in the terminal with
+//! the kitty, iTerm2 or sixel graphics protocol, for tuning parameters
+//! over SSH without copying results back.
+
+use crate::montage::{self, Cell};
+use anyhow::Result;
+use base64::Engine;
+use image::{imageops, Rgb, RgbImage};
+use sharpy::Image;
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Write};
+
+/// Longest side of each image in the pair, in pixels.
+const PREVIEW_SIZE: u32 = 400;
+/// Largest escape sequence chunk the kitty protocol accepts.
+const KITTY_CHUNK: usize = 4096;
+/// Sixel palette: a 6x6x6 color cube.
+const LEVELS: u16 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// Detect from the environment, falling back to sixel
+    Auto,
+    /// kitty graphics protocol (kitty, WezTerm, Ghostty)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm)
+    Iterm,
+    /// Sixel (xterm, foot, mlterm, Windows Terminal)
+    Sixel,
+}
+
+impl Protocol {
+    fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if std::env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "ghostty" {
+            Protocol::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" || var("LC_TERMINAL") == "iTerm2" {
+            // LC_TERMINAL is forwarded by most SSH configurations
+            Protocol::Iterm
+        } else {
+            Protocol::Sixel
+        }
+    }
+}
+
+/// A thumbnail of `image` for the "before" side, taken before processing
+/// consumes it.
+pub fn thumbnail(image: &Image) -> RgbImage {
+    shrink(image.as_rgb())
+}
+
+/// Draws `before` next to a thumbnail of `after` on stderr. Does nothing
+/// if stderr isn't a terminal, where escape sequences would be garbage.
+pub fn show(protocol: Protocol, before: RgbImage, after: &Image) -> Result<()> {
+    let mut stderr = std::io::stderr();
+    if !stderr.is_terminal() {
+        return Ok(());
+    }
+
+    let pair = montage::montage(&[
+        Cell { label: "Before".to_string(), image: before },
+        Cell { label: "After".to_string(), image: shrink(after.as_rgb()) },
+    ], 2);
+    let protocol = match protocol {
+        Protocol::Auto => Protocol::detect(),
+        protocol => protocol,
+    };
+    let sequence = match protocol {
+        Protocol::Kitty => kitty(&png(&pair)?),
+        Protocol::Iterm => iterm(&png(&pair)?),
+        Protocol::Auto | Protocol::Sixel => sixel(&pair),
+    };
+    stderr.write_all(sequence.as_bytes())?;
+    writeln!(stderr)?;
+    Ok(())
+}
+
+fn shrink(image: &RgbImage) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let scale = (PREVIEW_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let size = |side: u32| ((side as f32 * scale).round() as u32).max(1);
+    imageops::resize(image, size(width), size(height), imageops::FilterType::Triangle)
+}
+
+fn png(image: &RgbImage) -> Result<Vec<u8>> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+/// PNG data in chunks, each flagged with whether more follow.
+fn kitty(png: &[u8]) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut sequence = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let keys = if i == 0 { "f=100,a=T," } else { "" };
+        // Base64 is ASCII
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        let _ = write!(sequence, "\x1b_G{}m={};{}\x1b\\", keys, more, chunk);
+    }
+    sequence
+}
+
+fn iterm(png: &[u8]) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    format!("\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07", png.len(), data)
+}
+
+/// Sixel data: the image quantized to a color cube, drawn in bands of six
+/// rows with one run-length encoded pass per color used in the band.
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let level = |c: u8| (u16::from(c) * (LEVELS - 1) + 127) / 255;
+    let colors: Vec<u16> = image.pixels()
+        .map(|&Rgb([r, g, b])| (level(r) * LEVELS + level(g)) * LEVELS + level(b))
+        .collect();
+
+    let mut sequence = format!("\x1bPq\"1;1;{};{}", width, height);
+    let percent = |level: u16| level * 100 / (LEVELS - 1);
+    for color in 0..LEVELS.pow(3) {
+        let (r, g, b) = (color / (LEVELS * LEVELS), color / LEVELS % LEVELS, color % LEVELS);
+        let _ = write!(sequence, "#{};2;{};{};{}", color, percent(r), percent(g), percent(b));
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let mut used: Vec<u16> = rows.clone()
+            .flat_map(|y| colors[y * width..(y + 1) * width].iter().copied())
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+
+        for (i, &color) in used.iter().enumerate() {
+            // `$` returns to the start of the band to overlay the next color
+            if i > 0 {
+                sequence.push('$');
+            }
+            let _ = write!(sequence, "#{}", color);
+            let column = |x: usize| {
+                let bits = rows.clone().enumerate()
+                    .filter(|&(_, y)| colors[y * width + x] == color)
+                    .fold(0u8, |bits, (bit, _)| bits | 1 << bit);
+                char::from(63 + bits)
+            };
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let sixel = column(x);
+                match &mut run {
+                    Some((current, count)) if *current == sixel => *count += 1,
+                    _ => {
+                        push_run(&mut sequence, run);
+                        run = Some((sixel, 1));
+                    }
+                }
+            }
+            push_run(&mut sequence, run);
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+fn push_run(sequence: &mut String, run: Option<(char, usize)>) {
+    match run {
+        Some((sixel, count)) if count > 3 => {
+            let _ = write!(sequence, "!{}{}", count, sixel);
+        }
+        Some((sixel, count)) => sequence.extend(std::iter::repeat_n(sixel, count)),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kitty() {
+        let data: Vec<u8> = (0..6000u32).map(|i| (i * 7) as u8).collect();
+        let sequence = kitty(&data);
+        let chunks: Vec<&str> = sequence.split("\x1b\\").filter(|chunk| !chunk.is_empty()).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with("\x1b_Gf=100,a=T,m=1;"));
+        assert!(chunks[1].starts_with("\x1b_Gm=0;"));
+
+        // The chunks carry the data in order
+        let payload: String = chunks.iter().map(|chunk| chunk.split_once(';').unwrap().1).collect();
+        assert!(chunks.iter().all(|chunk| chunk.split_once(';').unwrap().1.len() <= KITTY_CHUNK));
+        assert_eq!(base64::engine::general_purpose::STANDARD.decode(payload).unwrap(), data);
+    }
+
+    #[test]
+    fn test_sixel() {
+        // Red: level 5 of the cube's first axis, color 5 * 36
+        let sequence = sixel(&RgbImage::from_pixel(10, 8, Rgb([255, 0, 0])));
+        assert!(sequence.starts_with("\x1bPq\"1;1;10;8#0;2;0;0;0#1;2;0;0;20"));
+        assert!(sequence.ends_with("\x1b\\"));
+        // A full band of six rows is `~`, runs longer than three are
+        // counted, and the last band has two rows
+        assert!(sequence.contains("#180!10~-#180!10B-\x1b\\"));
+
+        // Each color of a band is drawn in its own pass
+        let image = RgbImage::from_fn(3, 6, |_, y| if y < 3 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) });
+        assert!(sixel(&image).ends_with("#0www$#215FFF-\x1b\\"));
+    }
+}
@@ -0,0 +1,73 @@
+//! `--progress json`: progress as one JSON object per line on stdout, for
+//! GUIs and CI wrappers that draw their own progress display.
+//!
+//! ```text
+//! {"event":"started","input":"a.jpg","output":"out/a_sharp.jpg"}
+//! {"event":"done","input":"a.jpg","output":"out/a_sharp.jpg","status":"processed","duration_ms":412}
+//! {"event":"progress","completed":1,"total":2,"percent":50}
+//! {"event":"started","input":"b.jpg","output":"out/b_sharp.jpg"}
+//! {"event":"error","input":"b.jpg","output":"out/b_sharp.jpg","message":"..."}
+//! {"event":"progress","completed":2,"total":2,"percent":100}
+//! ```
+
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Progress bar and messages on stderr
+    #[default]
+    Bar,
+    /// JSON lines on stdout
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A file is about to be processed
+    Started { input: String, output: String },
+    /// Files of a batch finished so far, whether they succeeded or not
+    Progress { completed: usize, total: usize, percent: u8 },
+    /// A file was processed, skipped, or saved but failed its reference check
+    Done { input: String, output: String, status: &'static str, duration_ms: u128 },
+    /// A file failed
+    Error { input: String, output: String, message: String },
+}
+
+impl Event {
+    pub fn started(input: &Path, output: &Path) -> Self {
+        Event::Started { input: display(input), output: display(output) }
+    }
+
+    pub fn progress(completed: usize, total: usize) -> Self {
+        let percent = (completed * 100).checked_div(total).unwrap_or(100) as u8;
+        Event::Progress { completed, total, percent }
+    }
+
+    pub fn done(input: &Path, output: &Path, status: &'static str, duration_ms: u128) -> Self {
+        Event::Done { input: display(input), output: display(output), status, duration_ms }
+    }
+
+    pub fn error(input: &Path, output: &Path, message: String) -> Self {
+        Event::Error { input: display(input), output: display(output), message }
+    }
+}
+
+/// Writes `event` to stdout if `--progress json` is selected.
+pub fn emit(cli: &crate::Cli, event: Event) {
+    if cli.progress != ProgressFormat::Json {
+        return;
+    }
+    let mut line = serde_json::to_string(&event).unwrap_or_default();
+    line.push('\n');
+    // A single write per event, so lines from parallel jobs don't interleave;
+    // a closed stdout shouldn't fail the processing itself
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(line.as_bytes()).and_then(|_| stdout.flush());
+}
+
+fn display(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,72 @@
+//! Asking before overwriting existing outputs, like `cp -i`, when sharpy
+//! runs in a terminal without `--overwrite` or `--quiet`.
+
+use anyhow::Result;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// Answers that apply beyond the file they were given for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Standing {
+    #[default]
+    Ask,
+    All,
+    Quit,
+}
+
+/// The user quit at an overwrite prompt.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Overwrite questions for one run. Questions from parallel jobs are asked
+/// one at a time.
+#[derive(Debug, Default)]
+pub struct OverwritePrompt {
+    standing: Mutex<Standing>,
+}
+
+impl OverwritePrompt {
+    /// A prompt if both stdin and stderr are terminals, so scripts and
+    /// pipes keep failing instead of waiting for an answer.
+    pub fn if_interactive() -> Option<Self> {
+        (std::io::stdin().is_terminal() && std::io::stderr().is_terminal()).then(Self::default)
+    }
+
+    /// Asks whether to overwrite `output`, unless "all" was answered before.
+    /// Fails with [`Cancelled`] once the user quits.
+    pub fn confirm(&self, output: &Path) -> Result<bool> {
+        let mut standing = self.standing.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match *standing {
+                Standing::All => return Ok(true),
+                Standing::Quit => return Err(Cancelled.into()),
+                Standing::Ask => {}
+            }
+
+            eprint!("Overwrite {}? [y]es/[n]o/[a]ll/[q]uit: ", output.display());
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            // End of input quits, as there is no one left to ask
+            if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+                *standing = Standing::Quit;
+                continue;
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                "a" | "all" => *standing = Standing::All,
+                "q" | "quit" => *standing = Standing::Quit,
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! `sharpy batch --report`: per-file results of a batch as JSON, for
+//! auditing automated runs.
+
+use crate::{FileOutcome, FileStatus};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sharpy::Pipeline;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct Report<'a> {
+    operations: String,
+    summary: Summary,
+    files: Vec<Entry<'a>>,
+}
+
+#[derive(Default, Serialize)]
+struct Summary {
+    processed: usize,
+    skipped: usize,
+    reference_failed: usize,
+    failed: usize,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    input: String,
+    output: String,
+    operations: &'a str,
+    /// One of `processed`, `skipped`, `reference_failed` or `failed`
+    status: &'static str,
+    duration_ms: u128,
+    /// Laplacian variance of the input, when it was processed
+    sharpness_before: Option<f64>,
+    sharpness_after: Option<f64>,
+    error: Option<&'a str>,
+}
+
+/// Writes the outcome of every file in the batch to `path`.
+pub fn write(path: &Path, pipeline: &Pipeline, outcomes: &[FileOutcome]) -> Result<()> {
+    let operations = pipeline.to_string();
+    let mut summary = Summary::default();
+    let files = outcomes.iter().map(|outcome| {
+        let error = match &outcome.status {
+            FileStatus::Processed => {
+                summary.processed += 1;
+                None
+            }
+            FileStatus::Skipped => {
+                summary.skipped += 1;
+                None
+            }
+            FileStatus::ReferenceFailed(problem) => {
+                summary.reference_failed += 1;
+                Some(problem.as_str())
+            }
+            FileStatus::Failed(error) => {
+                summary.failed += 1;
+                Some(error.as_str())
+            }
+        };
+        Entry {
+            input: outcome.input.to_string_lossy().into_owned(),
+            output: outcome.output.to_string_lossy().into_owned(),
+            operations: &operations,
+            status: outcome.status.name(),
+            duration_ms: outcome.duration.as_millis(),
+            sharpness_before: outcome.sharpness.map(|(before, _)| before),
+            sharpness_after: outcome.sharpness.map(|(_, after)| after),
+            error,
+        }
+    }).collect();
+
+    let report = Report { operations: operations.clone(), summary, files };
+    std::fs::write(path, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("Failed to write report: {}", path.display()))
+}
@@ -0,0 +1,231 @@
+//! `sharpy sequence`: numbered frame sequences (`frames/%06d.png`) processed
+//! in order, as exported and read by VFX and encoding tools.
+//!
+//! Frames go through a [`TemporalSharpener`], so `--auto` parameters and
+//! per-frame masks are smoothed from one frame to the next.
+
+use crate::errors::{Failure, Kind};
+use crate::progress::{self, Event, ProgressFormat};
+use crate::{ConflictPolicy, Cli};
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use sharpy::TemporalSharpener;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A path with one frame number placeholder: `%d`, `%0Nd` (zero-padded to
+/// N digits) or a run of `#` (padded to the run's length).
+#[derive(Debug, Clone)]
+pub struct FramePattern {
+    prefix: String,
+    width: usize,
+    suffix: String,
+}
+
+impl FramePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let placeholder = find_printf(pattern).or_else(|| find_hashes(pattern)).ok_or_else(|| {
+            anyhow::anyhow!("No frame number in {}; use %d, %0Nd or #### (e.g. frames/%06d.png)", pattern)
+        })?;
+        let (start, end, width) = placeholder;
+        let (prefix, suffix) = (&pattern[..start], &pattern[end..]);
+        if find_printf(suffix).is_some() || find_hashes(suffix).is_some() {
+            anyhow::bail!("More than one frame number in {}", pattern);
+        }
+        Ok(Self { prefix: prefix.to_string(), width, suffix: suffix.to_string() })
+    }
+
+    pub fn path(&self, frame: u32) -> PathBuf {
+        PathBuf::from(format!("{}{:0width$}{}", self.prefix, frame, self.suffix, width = self.width))
+    }
+}
+
+/// The byte range and padding of the first `%d` or `%0Nd`.
+fn find_printf(pattern: &str) -> Option<(usize, usize, usize)> {
+    pattern.match_indices('%').find_map(|(start, _)| {
+        let rest = &pattern[start + 1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if !rest[digits..].starts_with('d') || (digits > 0 && !rest.starts_with('0')) {
+            return None;
+        }
+        let width = if digits > 0 { rest[..digits].parse().ok()? } else { 0 };
+        Some((start, start + 1 + digits + 1, width))
+    })
+}
+
+/// The byte range and length of the first run of `#`.
+fn find_hashes(pattern: &str) -> Option<(usize, usize, usize)> {
+    let start = pattern.find('#')?;
+    let length = pattern[start..].len() - pattern[start..].trim_start_matches('#').len();
+    Some((start, start + length, length))
+}
+
+/// Where a sequence's parameters and masks come from.
+pub struct SequenceOptions<'a> {
+    pub sharpener: TemporalSharpener,
+    pub start: u32,
+    pub end: u32,
+    /// `--mask`: one mask for every frame, or a pattern of per-frame masks
+    pub mask: Option<&'a str>,
+}
+
+pub fn run(cli: &Cli, input: &str, output: &str, options: SequenceOptions) -> Result<()> {
+    let SequenceOptions { mut sharpener, start, end, mask } = options;
+    if start > end {
+        anyhow::bail!("--start ({}) is after --end ({})", start, end);
+    }
+    let inputs = FramePattern::parse(input)?;
+    let outputs = FramePattern::parse(output)?;
+    if inputs.path(start) == outputs.path(start) {
+        anyhow::bail!("The output pattern must differ from the input pattern");
+    }
+    let masks = mask.map(|mask| match FramePattern::parse(mask) {
+        Ok(pattern) => Masks::PerFrame(pattern),
+        Err(_) => Masks::Single(PathBuf::from(mask)),
+    });
+
+    // A missing frame would break the sequence, so check before starting
+    let frames = start..=end;
+    if let Some(missing) = frames.clone().map(|frame| inputs.path(frame)).find(|path| !path.is_file()) {
+        let message = format!("Missing frame: {}", missing.display());
+        return Err(Failure::new(Kind::Input, message).into());
+    }
+    let total = frames.clone().count();
+    if !cli.quiet {
+        eprintln!("Found {} frames to process", total);
+    }
+
+    if let (Some(parent), false) = (outputs.path(start).parent(), cli.dry_run) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+    }
+
+    let pb = if !cli.quiet && cli.progress == ProgressFormat::Bar {
+        let pb = ProgressBar::new(total as u64);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")?
+                .progress_chars("#>-")
+        );
+        Some(pb)
+    } else {
+        None
+    };
+
+    let policy = if cli.replaces_outputs() {
+        ConflictPolicy::Overwrite
+    } else if let Some(prompt) = &cli.overwrite_prompt {
+        ConflictPolicy::Ask(prompt)
+    } else {
+        ConflictPolicy::Fail
+    };
+    // Fail before the first frame rather than partway through
+    if let ConflictPolicy::Fail = policy {
+        let mut outputs = (start..=end).map(|frame| outputs.path(frame));
+        if let Some(existing) = outputs.find(|path| path.exists()) {
+            anyhow::bail!("Output file already exists: {}. Use --overwrite to replace.", existing.display());
+        }
+    }
+    let claimed = Mutex::new(HashSet::new());
+    let single_mask = match &masks {
+        Some(Masks::Single(path)) => crate::load_mask(Some(path))?,
+        _ => None,
+    };
+
+    for (index, frame) in frames.enumerate() {
+        let (input, output) = (inputs.path(frame), outputs.path(frame));
+        if let Some(pb) = &pb {
+            pb.set_message(format!("Frame {}", frame));
+        }
+        progress::emit(cli, Event::started(&input, &output));
+        let started = Instant::now();
+
+        let result = crate::claim_output(&claimed, &output, policy, pb.as_ref()).and_then(|claimed| {
+            match claimed {
+                Some(_) if cli.dry_run => {
+                    if cli.verbose {
+                        eprintln!("Dry run: Would process {} -> {}", input.display(), output.display());
+                    }
+                    Ok("processed")
+                }
+                Some(_) => {
+                    let mask = match &masks {
+                        Some(Masks::PerFrame(pattern)) => crate::load_mask(Some(&pattern.path(frame)))?,
+                        _ => None,
+                    };
+                    process_frame(cli, &mut sharpener, &input, &output, mask.as_ref().or(single_mask.as_ref()))?;
+                    Ok("processed")
+                }
+                None => Ok("skipped"),
+            }
+        });
+
+        match result {
+            Ok(status) => progress::emit(cli, Event::done(&input, &output, status, started.elapsed().as_millis())),
+            Err(e) => {
+                progress::emit(cli, Event::error(&input, &output, crate::errors::message(&e)));
+                if let Some(pb) = &pb {
+                    pb.abandon_with_message(format!("Stopped at frame {}", frame));
+                }
+                return Err(e.context(format!("Frame {} failed", frame)));
+            }
+        }
+        if let Some(pb) = &pb {
+            pb.inc(1);
+        }
+        progress::emit(cli, Event::progress(index + 1, total));
+    }
+
+    if let Some(pb) = &pb {
+        pb.finish_with_message(format!("Completed: {} frames", total));
+    }
+    if cli.verbose {
+        if let Some(pipeline) = sharpener.pipeline() {
+            eprintln!("Last frame's operations: {}", pipeline);
+        }
+    }
+    Ok(())
+}
+
+enum Masks {
+    Single(PathBuf),
+    PerFrame(FramePattern),
+}
+
+fn process_frame(
+    cli: &Cli,
+    sharpener: &mut TemporalSharpener,
+    input: &Path,
+    output: &Path,
+    mask: Option<&image::GrayImage>,
+) -> Result<()> {
+    let times = crate::input_times(cli, input)?;
+    let (image, input_format) = crate::read_input(cli, input)?;
+    let result = sharpener.process(image, mask).context("Processing failed")?;
+    crate::back_up(cli, output)?;
+    crate::write_output(cli, &result, output, input_format)?;
+    crate::set_times(output, times)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pattern() {
+        let path = |pattern: &str, frame| FramePattern::parse(pattern).unwrap().path(frame);
+        assert_eq!(path("frames/%06d.png", 42), Path::new("frames/000042.png"));
+        assert_eq!(path("frames/%d.png", 42), Path::new("frames/42.png"));
+        assert_eq!(path("shot_####.exr", 7), Path::new("shot_0007.exr"));
+        // Numbers wider than the padding aren't cut
+        assert_eq!(path("%02d.tif", 1234), Path::new("1234.tif"));
+        // A literal percent sign isn't a placeholder
+        assert_eq!(path("50%_#.png", 3), Path::new("50%_3.png"));
+
+        for bad in ["frames/a.png", "%6d.png", "%06d_%d.png", "%d_##.png"] {
+            assert!(FramePattern::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! `sharpy presets-sheet`: every preset applied to one image, for picking a
+//! preset visually.
+
+use crate::montage::{montage, Cell};
+use anyhow::Result;
+use sharpy::{Image, PresetRegistry};
+use std::path::Path;
+
+pub fn render(
+    cli: &crate::Cli,
+    input: &Path,
+    output: &Path,
+    registry: &PresetRegistry,
+    size: u32,
+    columns: Option<usize>,
+) -> Result<()> {
+    if size == 0 {
+        anyhow::bail!("--size must be at least 1");
+    }
+
+    crate::process_single_image(cli, input, output, |image| {
+        // Previews scale radii with the image, so each cell looks like a
+        // shrunken full-size result
+        let mut cells = vec![Cell {
+            label: "original".to_string(),
+            image: image.clone().sharpen().preview(size)?.into_rgb(),
+        }];
+        for preset in registry.iter() {
+            let preview = preset.builder(image.clone()).preview(size)?;
+            cells.push(Cell { label: preset.name.clone(), image: preview.into_rgb() });
+        }
+
+        let columns = columns.unwrap_or_else(|| (cells.len() as f64).sqrt().ceil() as usize);
+        Ok(Image::from_rgb(montage(&cells, columns))?
+            .with_icc_profile(image.icc_profile().map(<[u8]>::to_vec))
+            .with_color_type(image.color_type()))
+    })
+}
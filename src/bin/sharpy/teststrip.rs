@@ -0,0 +1,227 @@
+//! `sharpy teststrip`: the darkroom test strip for sharpening settings, a
+//! labeled grid of the same crop processed with a range of parameter values.
+
+use crate::montage::{montage, Cell};
+use anyhow::Result;
+use image::imageops;
+use sharpy::{Image, Operation, Pipeline};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Extra pixels processed around each crop so blurs near its edges see the
+/// same neighborhood as in the full image.
+const MARGIN: u32 = 64;
+const MAX_STEPS: usize = 12;
+
+/// A parameter swept across the strip, parsed from `NAME=START..END:STEPS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamRange {
+    name: String,
+    start: f32,
+    end: f32,
+    steps: usize,
+}
+
+impl ParamRange {
+    /// `steps` evenly spaced values from `start` to `end` inclusive.
+    fn values(&self) -> Vec<f32> {
+        if self.steps == 1 {
+            return vec![self.start];
+        }
+        (0..self.steps)
+            .map(|i| self.start + (self.end - self.start) * i as f32 / (self.steps - 1) as f32)
+            .collect()
+    }
+}
+
+impl FromStr for ParamRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let usage = || format!("expected NAME=START..END:STEPS (e.g. amount=0.4..1.6:5), got '{}'", s);
+        let (name, range) = s.split_once('=').ok_or_else(usage)?;
+        let (range, steps) = range.rsplit_once(':').ok_or_else(usage)?;
+        let (start, end) = range.split_once("..").ok_or_else(usage)?;
+
+        let parse = |value: &str| value.trim().parse::<f32>().map_err(|_| usage());
+        let steps = steps.trim().parse::<usize>().map_err(|_| usage())?;
+        if !(1..=MAX_STEPS).contains(&steps) {
+            return Err(format!("steps must be between 1 and {}, got {}", MAX_STEPS, steps));
+        }
+        Ok(ParamRange {
+            name: name.trim().to_lowercase(),
+            start: parse(start)?,
+            end: parse(end)?,
+            steps,
+        })
+    }
+}
+
+/// One cell of the strip: its label and the operations that produce it.
+struct Variant {
+    label: String,
+    pipeline: Pipeline,
+}
+
+/// Renders the strip: columns sweep the first parameter and rows the
+/// second, if given.
+pub fn render(
+    cli: &crate::Cli,
+    input: &Path,
+    output: &Path,
+    base: &Pipeline,
+    params: &[ParamRange],
+    size: u32,
+    center: Option<(u32, u32)>,
+) -> Result<()> {
+    if params.len() > 2 {
+        anyhow::bail!("At most two --param ranges are supported (columns and rows)");
+    }
+    if size == 0 {
+        anyhow::bail!("--size must be at least 1");
+    }
+
+    let columns = params[0].values();
+    let rows = params.get(1).map_or_else(|| vec![None], |row| row.values().into_iter().map(Some).collect());
+    let mut variants = Vec::with_capacity(columns.len() * rows.len());
+    for &row in &rows {
+        for &column in &columns {
+            let mut pipeline = base.clone();
+            let mut labels = Vec::new();
+            for (param, value) in params.iter().zip([Some(column), row]) {
+                let Some(value) = value else { continue };
+                pipeline = set_param(&pipeline, &param.name, value)?;
+                labels.push(format!("{}={}", param.name, format_value(value)));
+            }
+            variants.push(Variant { label: labels.join(" "), pipeline });
+        }
+    }
+
+    crate::process_single_image(cli, input, output, |image| {
+        compose(&image, &variants, columns.len(), size, center)
+    })
+}
+
+/// Sets `name` on the first operation that has it.
+fn set_param(pipeline: &Pipeline, name: &str, value: f32) -> Result<Pipeline> {
+    let mut operations = pipeline.operations().to_vec();
+    let Some((index, operation)) = operations.iter().enumerate()
+        .find_map(|(i, op)| with_param(op, name, value).map(|op| (i, op)))
+    else {
+        anyhow::bail!("No operation in '{}' has a '{}' parameter", pipeline, name);
+    };
+    operations[index] = operation;
+    Ok(operations.into())
+}
+
+fn with_param(operation: &Operation, name: &str, value: f32) -> Option<Operation> {
+    let mut operation = operation.clone();
+    if let (Operation::UnsharpMask { threshold, .. }, "threshold") = (&mut operation, name) {
+        *threshold = value.round().clamp(0.0, 255.0) as u8;
+        return Some(operation);
+    }
+    if let (Operation::Median { radius }, "radius") = (&mut operation, name) {
+        *radius = value.round().max(1.0) as u32;
+        return Some(operation);
+    }
+
+    let field = match (&mut operation, name) {
+        (
+            Operation::UnsharpMask { radius, .. }
+            | Operation::RelativeUnsharp { radius, .. }
+            | Operation::Clarity { radius, .. }
+            | Operation::FrequencySeparation { radius, .. },
+            "radius",
+        ) => radius,
+        (
+            Operation::UnsharpMask { amount, .. }
+            | Operation::RelativeUnsharp { amount, .. }
+            | Operation::FrequencySeparation { amount, .. },
+            "amount",
+        ) => amount,
+        (Operation::FrequencySeparation { smoothing, .. }, "smoothing") => smoothing,
+        (Operation::RelativeUnsharp { threshold, .. }, "threshold") => threshold,
+        (
+            Operation::HighPassSharpen { strength }
+            | Operation::EnhanceEdges { strength, .. }
+            | Operation::Clarity { strength, .. }
+            | Operation::TextSharpen { strength, .. }
+            | Operation::Denoise { strength, .. },
+            "strength",
+        ) => strength,
+        (Operation::SmartEnhance { level }, "level") => level,
+        _ => return None,
+    };
+    *field = value;
+    Some(operation)
+}
+
+/// Formats with at most two decimals, dropping trailing zeros.
+fn format_value(value: f32) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn compose(
+    image: &Image,
+    variants: &[Variant],
+    columns: usize,
+    size: u32,
+    center: Option<(u32, u32)>,
+) -> sharpy::Result<Image> {
+    let (width, height) = image.dimensions();
+    let (crop_width, crop_height) = (size.min(width), size.min(height));
+    let (center_x, center_y) = center.unwrap_or((width / 2, height / 2));
+    let crop_x = center_x.saturating_sub(crop_width / 2).min(width - crop_width);
+    let crop_y = center_y.saturating_sub(crop_height / 2).min(height - crop_height);
+
+    // Process a margin around the crop, then cut it away
+    let padded_x = crop_x.saturating_sub(MARGIN);
+    let padded_y = crop_y.saturating_sub(MARGIN);
+    let padded = image.crop(
+        padded_x,
+        padded_y,
+        (crop_x + crop_width + MARGIN).min(width) - padded_x,
+        (crop_y + crop_height + MARGIN).min(height) - padded_y,
+    )?;
+
+    let cells = variants.iter()
+        .map(|variant| {
+            let processed = variant.pipeline.apply(padded.clone())?;
+            let crop = imageops::crop_imm(
+                processed.as_rgb(),
+                crop_x - padded_x,
+                crop_y - padded_y,
+                crop_width,
+                crop_height,
+            );
+            Ok(Cell { label: variant.label.clone(), image: crop.to_image() })
+        })
+        .collect::<sharpy::Result<Vec<_>>>()?;
+    let strip = montage(&cells, columns);
+
+    Ok(Image::from_rgb(strip)?
+        .with_icc_profile(image.icc_profile().map(<[u8]>::to_vec))
+        .with_color_type(image.color_type()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_range() {
+        let range: ParamRange = " Amount = 0.4..1.6 : 4".parse().unwrap();
+        assert_eq!(range, ParamRange { name: "amount".to_string(), start: 0.4, end: 1.6, steps: 4 });
+        let values = range.values();
+        assert_eq!(values.len(), 4);
+        assert!((values[1] - 0.8).abs() < 1e-6 && values[3] == 1.6);
+        // One step is the start value alone, and ranges may run downwards
+        assert_eq!("radius=2..5:1".parse::<ParamRange>().unwrap().values(), [2.0]);
+        assert_eq!("radius=3..1:3".parse::<ParamRange>().unwrap().values(), [3.0, 2.0, 1.0]);
+
+        for bad in ["amount", "amount=0.4..1.6", "amount=0.4-1.6:5", "amount=x..1:5", "amount=0..1:0", "amount=0..1:13"] {
+            assert!(bad.parse::<ParamRange>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! `sharpy watch`: hot-folder processing of images as they arrive.
+//!
+//! Only functional when the CLI is built with the `watch` feature; otherwise
+//! the command reports how to enable it.
+
+use anyhow::Result;
+use image::GrayImage;
+use std::path::Path;
+use std::time::Duration;
+
+/// Where and how watched files are processed.
+#[cfg_attr(not(feature = "watch"), allow(dead_code))]
+pub struct WatchOptions<'a> {
+    pub output_dir: &'a Path,
+    /// Output filename template, see `crate::output_path_for`
+    pub name_template: &'a str,
+    /// Originals are moved here once processed; left in place when `None`
+    pub processed_dir: Option<&'a Path>,
+    /// How long a file must go without changes before it is processed, so
+    /// files still being copied in aren't read half-written
+    pub settle: Duration,
+    /// `--mask` for every file
+    pub mask: Option<&'a GrayImage>,
+}
+
+/// Processes the images in `dir`, then every image that appears or changes
+/// there, until interrupted.
+#[cfg(feature = "watch")]
+pub fn run(cli: &crate::Cli, dir: &Path, recipe: &crate::Recipe, options: &WatchOptions) -> Result<()> {
+    use anyhow::Context;
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::collections::HashMap;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::Instant;
+
+    let dir = dir.canonicalize()
+        .with_context(|| format!("Cannot watch {}", dir.display()))?;
+    for target in std::iter::once(options.output_dir).chain(options.processed_dir) {
+        std::fs::create_dir_all(target)
+            .with_context(|| format!("Failed to create directory: {}", target.display()))?;
+        if target.canonicalize()? == dir {
+            anyhow::bail!("{} must differ from the watched directory", target.display());
+        }
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Cannot watch {}", dir.display()))?;
+    if !cli.quiet {
+        eprintln!("Watching {} (Ctrl+C to stop)", dir.display());
+    }
+
+    // Files waiting to settle, with the time they last changed. Files
+    // already in the folder are treated as settled.
+    let mut pending: HashMap<_, _> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| is_image(path))
+        .map(|path| (path, Instant::now() - options.settle))
+        .collect();
+
+    loop {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths.into_iter().filter(|path| is_image(path)) {
+                    pending.insert(path, Instant::now());
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => eprintln!("Watch error: {}", e),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let mut settled: Vec<_> = pending.iter()
+            .filter(|(_, changed)| changed.elapsed() >= options.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        for path in settled {
+            pending.remove(&path);
+            // Moved or deleted while settling
+            if path.is_file() {
+                process(cli, &path, recipe, options);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+pub fn run(_cli: &crate::Cli, _dir: &Path, _recipe: &crate::Recipe, _options: &WatchOptions) -> Result<()> {
+    anyhow::bail!("sharpy watch requires sharpy to be built with the `watch` feature")
+}
+
+/// How often settled files are checked for while no events arrive.
+#[cfg(feature = "watch")]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Images in a readable format, skipping hidden and temporary files.
+#[cfg(feature = "watch")]
+fn is_image(path: &Path) -> bool {
+    let hidden = path.file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+    !hidden && path.is_file() && crate::is_readable(path)
+}
+
+/// Processes one file, reporting rather than returning errors so the watch
+/// keeps running.
+#[cfg(feature = "watch")]
+fn process(cli: &crate::Cli, path: &Path, recipe: &crate::Recipe, options: &WatchOptions) {
+    use crate::progress::{self, Event};
+    use std::time::Instant;
+
+    let result = crate::output_path_for(cli, path, options.output_dir, options.name_template).and_then(|output| {
+        // An existing output marks the file as done, e.g. by an earlier run
+        if output.exists() && !cli.overwrite {
+            if cli.verbose {
+                eprintln!("Skipping {}: {} exists", path.display(), output.display());
+            }
+            return Ok(());
+        }
+        progress::emit(cli, Event::started(path, &output));
+        let started = Instant::now();
+        let result = crate::process_single_with_pipeline(cli, path, &output, recipe, options.mask, false)
+            .and_then(|_| match (options.processed_dir, cli.dry_run) {
+                (Some(processed_dir), false) => move_file(path, processed_dir),
+                _ => Ok(()),
+            });
+        match &result {
+            Ok(()) => progress::emit(cli, Event::done(path, &output, "processed", started.elapsed().as_millis())),
+            Err(e) => progress::emit(cli, Event::error(path, &output, crate::errors::message(e))),
+        }
+        result?;
+        if !cli.quiet {
+            eprintln!("Processed {} -> {}", path.display(), output.display());
+        }
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        eprintln!("Error processing {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(feature = "watch")]
+fn move_file(path: &Path, dir: &Path) -> Result<()> {
+    use anyhow::Context;
+
+    let target = dir.join(path.file_name().unwrap_or_default());
+    // Renaming fails across filesystems; copy and delete instead
+    if std::fs::rename(path, &target).is_err() {
+        std::fs::copy(path, &target)
+            .and_then(|_| std::fs::remove_file(path))
+            .with_context(|| format!("Failed to move {} to {}", path.display(), dir.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+//! Blend modes for combining an image with a layer, such as a detail layer
+//! from [`Image::high_pass_layer`] laid back over the image it came from.
+//! 
+//! [`blend`] combines two images and [`Layers`](crate::Layers) a whole
+//! stack; the builder blends each step's result over its input the same
+//! way with
+//! [`SharpeningBuilder::blend_mode`](crate::SharpeningBuilder::blend_mode)
+//! and [`opacity`](crate::SharpeningBuilder::opacity).
+
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample};
+use crate::{Image, ImageError, Result};
+use serde::{Deserialize, Serialize};
+
+/// How a layer combines with the image below it, per color sample.
+/// 
+/// Overlay, soft light and linear light leave the image unchanged where the
+/// layer is mid-gray, lighten it where the layer is lighter and darken it
+/// where it is darker, so they recombine high-pass detail layers. Add
+/// leaves it unchanged where the layer is black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// The layer replaces the image
+    #[default]
+    Normal,
+    /// Multiplies dark and screens light areas of the image by the layer,
+    /// stretching contrast in the midtones
+    Overlay,
+    /// A gentler overlay that never pushes samples to black or white
+    SoftLight,
+    /// Adds twice the layer's difference from mid-gray. At opacity 0.5
+    /// over the blur a high-pass layer was taken from, this adds the
+    /// difference once and rebuilds the image exactly, as long as the
+    /// doubled detail doesn't clip
+    LinearLight,
+    /// Adds the layer (linear dodge)
+    Add,
+}
+
+impl BlendMode {
+    /// Blends samples `base` and `layer`, both scaled to 0-1. The result may
+    /// leave that range; it is clipped before the opacity applies.
+    pub fn apply(self, base: f32, layer: f32) -> f32 {
+        match self {
+            BlendMode::Normal => layer,
+            BlendMode::Overlay => {
+                if base < 0.5 {
+                    2.0 * base * layer
+                } else {
+                    1.0 - 2.0 * (1.0 - base) * (1.0 - layer)
+                }
+            }
+            BlendMode::SoftLight => {
+                // The W3C compositing formula, without Photoshop's seam at
+                // mid-gray
+                if layer <= 0.5 {
+                    base - (1.0 - 2.0 * layer) * base * (1.0 - base)
+                } else {
+                    let lifted = if base <= 0.25 { ((16.0 * base - 12.0) * base + 4.0) * base } else { base.sqrt() };
+                    base + (2.0 * layer - 1.0) * (lifted - base)
+                }
+            }
+            BlendMode::LinearLight => base + 2.0 * layer - 1.0,
+            BlendMode::Add => base + layer,
+        }
+    }
+}
+
+impl std::str::FromStr for BlendMode {
+    type Err = ImageError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().replace(['-', ' '], "_").as_str() {
+            "normal" => Ok(BlendMode::Normal),
+            "overlay" => Ok(BlendMode::Overlay),
+            "soft_light" => Ok(BlendMode::SoftLight),
+            "linear_light" => Ok(BlendMode::LinearLight),
+            "add" => Ok(BlendMode::Add),
+            _ => Err(ImageError::Parse(format!(
+                "unknown blend mode '{}' (use 'normal', 'overlay', 'soft_light', 'linear_light' or 'add')", s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Overlay => "overlay",
+            BlendMode::SoftLight => "soft_light",
+            BlendMode::LinearLight => "linear_light",
+            BlendMode::Add => "add",
+        })
+    }
+}
+
+/// Lays `layer` over `base` in `mode` at `opacity` (0.0-1.0); alpha is
+/// taken from `base`.
+/// 
+/// Fails with [`ImageError::InvalidDimensions`] unless the images have the
+/// same dimensions, and with [`ImageError::InvalidParameter`] for an
+/// opacity out of range.
+/// 
+/// # Example
+/// ```
+/// use sharpy::blend::{self, BlendMode};
+/// # use sharpy::Image;
+/// # let image = Image::from_rgb(image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([(x * 4) as u8; 3]))).unwrap();
+/// 
+/// // Classic high-pass sharpening: the detail layer overlaid on the image
+/// let detail = image.high_pass_layer(3.0).unwrap();
+/// let sharpened = blend::blend(&image, &detail, BlendMode::Overlay, 0.8).unwrap();
+/// ```
+pub fn blend<P: SharpenPixel>(base: &Image<P>, layer: &Image<P>, mode: BlendMode, opacity: f32) -> Result<Image<P>> {
+    if layer.dimensions() != base.dimensions() {
+        let (width, height) = layer.dimensions();
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err(ImageError::InvalidParameter { param: "opacity".to_string(), value: opacity.to_string() });
+    }
+    let mut blended = layer.data.get_ref().clone();
+    blend_images(base.data.get_ref(), &mut blended, mode, opacity);
+    Ok(base.with_pixels(blended))
+}
+
+/// Blends `processed` over `original` in `mode` at `opacity`, writing the
+/// result into `processed` so no further buffer is allocated.
+/// 
+/// Color channels are blended; alpha is taken from `original`.
+pub(crate) fn blend_images<P: SharpenPixel>(original: &Buffer<P>, processed: &mut Buffer<P>, mode: BlendMode, opacity: f32) {
+    let blend_factor = opacity.clamp(0.0, 1.0);
+    let inv_blend = 1.0 - blend_factor;
+    let max = max_value::<P>();
+    
+    // Process rows in parallel
+    processed.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let orig_pixel = original.get_pixel(x, y).channels();
+                let channels = pixel.channels_mut();
+                
+                for c in 0..P::COLOR_CHANNELS {
+                    let (base, layer) = (sample::<P>(orig_pixel[c]), sample::<P>(channels[c]));
+                    let layer = match mode {
+                        BlendMode::Normal => layer,
+                        _ => mode.apply(base / max, layer / max).clamp(0.0, 1.0) * max,
+                    };
+                    channels[c] = to_sample::<P>(base * inv_blend + layer * blend_factor);
+                }
+                channels[P::COLOR_CHANNELS..].copy_from_slice(&orig_pixel[P::COLOR_CHANNELS..]);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    
+    #[test]
+    fn test_blend_modes() {
+        for base in [0.0, 0.1, 0.3, 0.5, 0.8, 1.0] {
+            // Mid-gray detail changes nothing, and lighter detail lightens
+            for mode in [BlendMode::Overlay, BlendMode::SoftLight, BlendMode::LinearLight] {
+                assert!((mode.apply(base, 0.5) - base).abs() < 1e-6, "{} over {}", mode, base);
+                assert!(mode.apply(base, 0.7) >= base && mode.apply(base, 0.3) <= base);
+            }
+            assert_eq!(BlendMode::Add.apply(base, 0.0), base);
+            assert_eq!(BlendMode::Normal.apply(base, 0.25), 0.25);
+        }
+        assert_eq!(BlendMode::Overlay.apply(0.25, 0.75), 0.375);
+        assert!((BlendMode::LinearLight.apply(0.4, 0.6) - 0.6).abs() < 1e-6);
+        for mode in ["normal", "overlay", "soft_light", "linear_light", "add"] {
+            assert_eq!(mode.parse::<BlendMode>().unwrap().to_string(), mode);
+        }
+        assert_eq!("Soft Light".parse::<BlendMode>().unwrap(), BlendMode::SoftLight);
+        assert!("screen".parse::<BlendMode>().is_err());
+    }
+    
+    #[test]
+    fn test_blend() {
+        let base = Image::from_rgb(RgbImage::from_fn(8, 8, |x, _| Rgb([(x * 30) as u8, 100, 200]))).unwrap();
+        let gray = Image::from_rgb(RgbImage::from_pixel(8, 8, Rgb([128; 3]))).unwrap();
+        let light = Image::from_rgb(RgbImage::from_pixel(8, 8, Rgb([178; 3]))).unwrap();
+        
+        // Mid-gray detail leaves the base as it is
+        let unchanged = blend(&base, &gray, BlendMode::LinearLight, 1.0).unwrap();
+        assert!(unchanged.as_rgb().pixels().zip(base.as_rgb().pixels()).all(|(a, b)| a[0].abs_diff(b[0]) <= 1));
+        let lighter = blend(&base, &light, BlendMode::LinearLight, 1.0).unwrap();
+        assert_eq!(lighter.as_rgb().get_pixel(2, 0), &Rgb([161, 201, 255]));
+        let quarter = blend(&base, &light, BlendMode::LinearLight, 0.25).unwrap();
+        assert_eq!(quarter.as_rgb().get_pixel(2, 0), &Rgb([85, 125, 214]));
+        
+        let small = Image::from_rgb(RgbImage::new(4, 8)).unwrap();
+        assert!(matches!(blend(&base, &small, BlendMode::Add, 1.0), Err(ImageError::InvalidDimensions { .. })));
+        assert!(blend(&base, &gray, BlendMode::Add, 1.5).is_err());
+    }
+    
+    #[test]
+    fn test_rebuild_from_high_pass() {
+        // Kept away from black and white so the doubled detail doesn't clip
+        let image = Image::from_rgb(RgbImage::from_fn(32, 32, |x, y| Rgb([100 + ((x * 7 + y * 3) % 40) as u8, 100, 150]))).unwrap();
+        let detail = image.high_pass_layer(2.0).unwrap();
+        let blurred = image.with_pixels(crate::Context::default().gaussian_blur(image.data.get_ref(), 2.0).as_ref().clone());
+        let rebuilt = blend(&blurred, &detail, BlendMode::LinearLight, 0.5).unwrap();
+        let error = rebuilt.as_rgb().pixels().zip(image.as_rgb().pixels())
+            .flat_map(|(a, b)| a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)))
+            .max();
+        assert!(error <= Some(1), "{:?}", error);
+    }
+}
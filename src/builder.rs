@@ -1,6 +1,25 @@
-use crate::{Image, Result, Operation};
-use crate::utils::EdgeMethod;
+use crate::{Image, ImageError, PipelineOp, Rect, Result, Operation, Transfer, ValidationConfig, ValidationPolicy, Warning};
+use crate::pixel::SharpenPixel;
+use crate::utils::{Boundary, EdgeMethod};
+use crate::blend::{BlendMode, blend_images};
 use crate::sharpening;
+use crate::operations::clamp_to;
+use crate::normal;
+use crate::fringe;
+use crate::descreen;
+use crate::denoise;
+use crate::transfer;
+use crate::mask;
+use crate::context::{Context, ProgressEvent, ProgressFn};
+use crate::pool::BufferPool;
+use crate::cache::BlurCache;
+use crate::trace::timed;
+use crate::warning;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Rgb};
 
 /// Builder for configuring and applying sharpening operations.
 /// 
@@ -18,125 +37,1775 @@ use crate::sharpening;
 ///     .apply()
 ///     .unwrap();
 /// ```
-pub struct SharpeningBuilder {
-    image: Image,
-    operations: Vec<Operation>,
+pub struct SharpeningBuilder<P: SharpenPixel = Rgb<u8>> {
+    image: Image<P>,
+    steps: Vec<Step<P>>,
+    progress: Option<Arc<ProgressFn>>,
+    parallelism: Parallelism,
+    region: Option<Rect>,
+    mask: Option<GrayImage>,
+    tileable: bool,
+    normal_map: bool,
+    adaptive: bool,
+    protect_stars: bool,
+    transfer: Option<Transfer>,
+    soft_clip: Option<f32>,
+    highlights: Option<f32>,
+    shadows: Option<f32>,
+    radial: Option<(f32, f32, f32)>,
+    policy: ValidationPolicy,
+    /// A chroma clamp set before any operation, which fails validation
+    stray_chroma_clamp: Option<f32>,
 }
 
+/// How much [`SharpeningBuilder::tileable`] lets the seam score
+/// ([`Image::tiling_seam`]) rise above the input's (or 1.0 if lower).
+const SEAM_TOLERANCE: f64 = 1.25;
 
-impl SharpeningBuilder {
-    pub(crate) fn new(image: Image) -> Self {
+/// Where the builder's operations run their parallel work.
+enum Parallelism {
+    /// Rayon's global pool (or the calling thread without `parallel`)
+    Global,
+    /// A dedicated pool of this many threads, created per apply
+    #[cfg(feature = "parallel")]
+    Threads(usize),
+    /// A pool supplied by the caller
+    #[cfg(feature = "parallel")]
+    Pool(Arc<rayon::ThreadPool>),
+}
+
+/// An out-of-range parameter found by [`SharpeningBuilder::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Position of the offending step in the pipeline
+    pub index: usize,
+    /// Name of the step's operation
+    pub operation: &'static str,
+    /// Parameter name
+    pub param: String,
+    /// Rejected value
+    pub value: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {} ({}): {} = {}", self.index, self.operation, self.param, self.value)
+    }
+}
+
+/// A step queued in a builder, as listed by [`SharpeningBuilder::operations`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum QueuedStep<'a> {
+    /// A built-in operation
+    Operation(&'a Operation),
+    /// An unsharp mask from [`SharpeningBuilder::unsharp_mask_with_amount_map`]
+    AmountMap { radius: f32, threshold: u8 },
+    /// A step from [`SharpeningBuilder::custom`]
+    Custom { name: &'static str },
+}
+
+impl<'a> QueuedStep<'a> {
+    /// Name used in progress events, errors and validation reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            QueuedStep::Operation(operation) => operation.name(),
+            QueuedStep::AmountMap { .. } => sharpening::AMOUNT_MAP_NAME,
+            QueuedStep::Custom { name } => name,
+        }
+    }
+    
+    /// The built-in operation, unless this is an amount map or custom step.
+    pub fn operation(self) -> Option<&'a Operation> {
+        match self {
+            QueuedStep::Operation(operation) => Some(operation),
+            QueuedStep::AmountMap { .. } | QueuedStep::Custom { .. } => None,
+        }
+    }
+}
+
+/// A step taken out of a builder by [`SharpeningBuilder::remove`] or
+/// [`SharpeningBuilder::replace`].
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum RemovedStep<P: SharpenPixel = Rgb<u8>> {
+    /// A built-in operation
+    Operation(Operation),
+    /// An unsharp mask from [`SharpeningBuilder::unsharp_mask_with_amount_map`]
+    AmountMap { radius: f32, threshold: u8, map: Arc<GrayImage> },
+    /// A step from [`SharpeningBuilder::custom`]
+    Custom(Arc<dyn PipelineOp<P>>),
+}
+
+impl<P: SharpenPixel> RemovedStep<P> {
+    /// Name used in progress events, errors and validation reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RemovedStep::Operation(operation) => operation.name(),
+            RemovedStep::AmountMap { .. } => sharpening::AMOUNT_MAP_NAME,
+            RemovedStep::Custom(op) => op.name(),
+        }
+    }
+    
+    /// The built-in operation, unless this is an amount map or custom step.
+    pub fn into_operation(self) -> Option<Operation> {
+        match self {
+            RemovedStep::Operation(operation) => Some(operation),
+            RemovedStep::AmountMap { .. } | RemovedStep::Custom(_) => None,
+        }
+    }
+    
+    fn from_action(action: Action<P>) -> Self {
+        match action {
+            Action::Builtin(operation) => RemovedStep::Operation(operation),
+            Action::AmountMap { radius, threshold, map } => RemovedStep::AmountMap { radius, threshold, map },
+            Action::Custom(op) => RemovedStep::Custom(op),
+            Action::Chain { .. } => unreachable!("chains only exist while processing"),
+        }
+    }
+}
+
+impl<P: SharpenPixel> std::fmt::Debug for RemovedStep<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemovedStep::Operation(operation) => f.debug_tuple("Operation").field(operation).finish(),
+            RemovedStep::AmountMap { radius, threshold, map } => f.debug_struct("AmountMap")
+                .field("radius", radius)
+                .field("threshold", threshold)
+                .field("map", &map.dimensions())
+                .finish(),
+            RemovedStep::Custom(op) => f.debug_tuple("Custom").field(&op.name()).finish(),
+        }
+    }
+}
+
+/// A parameter that [`ValidationPolicy::Clamp`] moves into its valid range,
+/// reported by [`SharpeningBuilder::clamps`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clamp {
+    /// Position of the step in the pipeline
+    pub index: usize,
+    /// Name of the step's operation
+    pub operation: &'static str,
+    /// Parameter name
+    pub param: String,
+    /// Value given
+    pub value: String,
+    /// Value used instead
+    pub clamped: String,
+}
+
+impl std::fmt::Display for Clamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {} ({}): {} = {} clamped to {}", self.index, self.operation, self.param, self.value, self.clamped)
+    }
+}
+
+/// A queued operation, the opacity and mode its result is blended with
+/// and the tolerance its chroma is clamped to, if any.
+#[derive(Clone)]
+struct Step<P: SharpenPixel> {
+    action: Action<P>,
+    opacity: f32,
+    mode: BlendMode,
+    chroma_clamp: Option<f32>,
+}
+
+/// What a step runs.
+#[derive(Clone)]
+enum Action<P: SharpenPixel> {
+    Builtin(Operation),
+    /// Unsharp mask with the amount of each pixel from a map the size of
+    /// the image
+    AmountMap { radius: f32, threshold: u8, map: Arc<GrayImage> },
+    Custom(Arc<dyn PipelineOp<P>>),
+    /// Operations run one after another as one step: a smart enhancement
+    /// resolved for the whole image, see [`SharpeningBuilder::resolve`]
+    Chain { name: &'static str, operations: Vec<Operation> },
+}
+
+impl<P: SharpenPixel> Step<P> {
+    fn new(operation: Operation) -> Self {
+        Self::with_action(Action::Builtin(operation))
+    }
+    
+    fn with_action(action: Action<P>) -> Self {
+        Self { action, opacity: 1.0, mode: BlendMode::Normal, chroma_clamp: None }
+    }
+    
+    fn name(&self) -> &'static str {
+        match &self.action {
+            Action::Builtin(operation) => operation.name(),
+            Action::AmountMap { .. } => sharpening::AMOUNT_MAP_NAME,
+            Action::Custom(op) => op.name(),
+            Action::Chain { name, .. } => name,
+        }
+    }
+    
+    /// The built-in operation, unless this is an amount map or custom step.
+    fn operation(&self) -> Option<&Operation> {
+        match &self.action {
+            Action::Builtin(operation) => Some(operation),
+            Action::AmountMap { .. } | Action::Custom(_) | Action::Chain { .. } => None,
+        }
+    }
+    
+    /// How far an output pixel depends on input pixels around it.
+    fn reach(&self) -> u32 {
+        let reach = match &self.action {
+            Action::Builtin(operation) => operation.reach(),
+            Action::AmountMap { radius, threshold, .. } => amount_map_unsharp(*radius, *threshold).reach(),
+            Action::Custom(op) => op.reach(),
+            Action::Chain { operations, .. } => operations.iter().map(Operation::reach).sum(),
+        };
+        reach + if self.chroma_clamp.is_some() { fringe::RADIUS } else { 0 }
+    }
+    
+    /// Returns a copy with the operation and step settings moved into their
+    /// ranges, and the moved parameters as (name, value, clamped value).
+    /// Custom steps keep their parameters.
+    fn clamped(&self, config: &ValidationConfig) -> (Step<P>, Vec<(&'static str, f32, f32)>) {
+        let (action, mut moved) = match &self.action {
+            Action::Builtin(operation) => {
+                let (operation, moved) = operation.clamped(config);
+                (Action::Builtin(operation), moved)
+            }
+            Action::AmountMap { radius, threshold, map } => {
+                let (operation, moved) = amount_map_unsharp(*radius, *threshold).clamped(config);
+                let Operation::UnsharpMask { radius, threshold, .. } = operation else {
+                    unreachable!("clamping keeps the operation");
+                };
+                (Action::AmountMap { radius, threshold, map: Arc::clone(map) }, moved)
+            }
+            Action::Custom(op) => (Action::Custom(Arc::clone(op)), Vec::new()),
+            Action::Chain { name, operations } => {
+                let (operations, moved): (Vec<_>, Vec<_>) = operations.iter().map(|operation| operation.clamped(config)).unzip();
+                (Action::Chain { name, operations }, moved.concat())
+            }
+        };
+        let opacity = clamp_to(self.opacity, 0.0, 1.0);
+        if opacity != self.opacity {
+            moved.push(("opacity", self.opacity, opacity));
+        }
+        let chroma_clamp = self.chroma_clamp.map(|tolerance| {
+            let clamped = clamp_to(tolerance, 0.0, 255.0);
+            if clamped != tolerance {
+                moved.push(("chroma_clamp", tolerance, clamped));
+            }
+            clamped
+        });
+        (Step { action, opacity, mode: self.mode, chroma_clamp }, moved)
+    }
+    
+    /// Out-of-range step settings as (name, value) pairs.
+    fn violations(&self) -> Vec<(&'static str, String)> {
+        let mut violations = Vec::new();
+        if !(0.0..=1.0).contains(&self.opacity) {
+            violations.push(("opacity", self.opacity.to_string()));
+        }
+        if let Some(tolerance) = self.chroma_clamp.filter(|tolerance| !(0.0..=255.0).contains(tolerance)) {
+            violations.push(("chroma_clamp", tolerance.to_string()));
+        }
+        violations
+    }
+    
+    /// Runs the step over `image`, with `run` processing it as the step's
+    /// action does before the chroma clamp and opacity apply.
+    fn apply<Q, F>(&self, image: &Image<Q>, ctx: &Context, run: F) -> Result<Image<Q>>
+    where
+        Q: SharpenPixel,
+        F: FnOnce(&Image<Q>) -> Result<Image<Q>>,
+    {
+        if let Some((param, value)) = self.violations().into_iter().next() {
+            return Err(ImageError::InvalidParameter { param: param.to_string(), value });
+        }
+        
+        if self.opacity == 0.0 {
+            return Ok(image.clone());
+        }
+        
+        let mut processed = run(image)?;
+        if processed.dimensions() != image.dimensions() {
+            let (width, height) = processed.dimensions();
+            return Err(ImageError::InvalidDimensions { width, height });
+        }
+        if let Some(tolerance) = self.chroma_clamp {
+            let mut clamped = processed.into_buffer();
+            fringe::clamp_chroma(image.data.get_ref(), &mut clamped, tolerance, ctx.luminance());
+            processed = image.with_pixels(clamped);
+        }
+        if self.opacity == 1.0 && self.mode == BlendMode::Normal {
+            return Ok(processed);
+        }
+        
+        // Blend the result over its input like a layer in the step's mode
+        // and opacity, reusing the freshly allocated result buffer
+        let mut blended = processed.into_buffer();
+        blend_images(image.data.get_ref(), &mut blended, self.mode, self.opacity);
+        Ok(image.with_pixels(blended))
+    }
+}
+
+/// The unsharp mask an amount map step checks its radius and threshold
+/// against; every amount in the map is valid.
+fn amount_map_unsharp(radius: f32, threshold: u8) -> Operation {
+    Operation::UnsharpMask { radius, amount: 1.0, threshold }
+}
+
+pub(crate) fn run_operation<P: SharpenPixel>(image: &Image<P>, operation: &Operation, ctx: &Context) -> Result<Image<P>> {
+    let _span = timed!(
+        DEBUG,
+        "operation",
+        name = operation.name(),
+        width = image.data.get_ref().width(),
+        height = image.data.get_ref().height()
+    );
+    match *operation {
+        Operation::UnsharpMask { radius, amount, threshold } => {
+            sharpening::unsharp_mask(image, radius, amount, threshold, ctx)
+        }
+        Operation::RelativeUnsharp { radius, amount, threshold } => {
+            sharpening::relative_unsharp_mask(image, radius, amount, threshold, ctx)
+        }
+        Operation::HighPassSharpen { strength } => {
+            sharpening::high_pass_sharpen(image, strength, ctx)
+        }
+        Operation::EnhanceEdges { strength, method } => {
+            sharpening::enhance_edges(image, strength, method, ctx)
+        }
+        Operation::Clarity { strength, radius } => {
+            sharpening::clarity(image, strength, radius, ctx)
+        }
+        Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold } => {
+            sharpening::adaptive_unsharp_mask(image, min_radius, max_radius, amount, threshold, ctx)
+        }
+        Operation::TextSharpen { stroke_width, strength } => {
+            sharpening::text_sharpen(image, stroke_width, strength, ctx)
+        }
+        Operation::Descreen { period } => descreen::descreen(image, period, ctx),
+        Operation::Median { radius } => denoise::median_filter(image, radius, ctx),
+        Operation::Denoise { strength, patch_radius, search_radius } => {
+            denoise::non_local_means(image, strength, patch_radius, search_radius, ctx)
+        }
+        Operation::SmartEnhance { level } => {
+            let operations = sharpening::smart_operations(image.data.get_ref(), level, ctx.luminance(), &image.validation());
+            run_chain(image, &operations, ctx)
+        }
+        Operation::FrequencySeparation { radius, smoothing, amount } => {
+            sharpening::frequency_separation(image, radius, smoothing, amount, ctx)
+        }
+    }
+}
+
+/// Runs `operations` one after another, recycling the intermediate results.
+fn run_chain<P: SharpenPixel>(image: &Image<P>, operations: &[Operation], ctx: &Context) -> Result<Image<P>> {
+    let mut current = Cow::Borrowed(image);
+    for operation in operations {
+        let next = run_operation(&current, operation, ctx)?;
+        if let Cow::Owned(previous) = std::mem::replace(&mut current, Cow::Owned(next)) {
+            ctx.recycle(previous.into_buffer());
+        }
+    }
+    Ok(current.into_owned())
+}
+
+/// Runs the built-in `steps[range]` over a borrowed image, copying it only
+/// if no step changes it. Progress counts all of `steps`.
+/// 
+/// Temporaries and consumed intermediate results go back to a buffer pool
+/// shared by all steps, so later steps reuse their allocations. Blurs of
+/// the same input at the same radius are computed once.
+fn run_steps<P: SharpenPixel, Q: SharpenPixel>(
+    image: &Image<Q>,
+    steps: &[Step<P>],
+    range: Range<usize>,
+    progress: Option<&ProgressFn>,
+    boundary: Boundary,
+    soft_clip: Option<f32>,
+) -> Result<Image<Q>> {
+    let count = steps.len();
+    let pool = BufferPool::default();
+    let blurs = BlurCache::default();
+    let mut current: Option<Image<Q>> = None;
+    
+    for (index, step) in steps.iter().enumerate().take(range.end).skip(range.start) {
+        let operation = step.name();
+        if let Some(callback) = progress {
+            callback(ProgressEvent::Started { index, count, operation });
+        }
+        
+        if step.opacity != 0.0 {
+            let input = current.as_ref().unwrap_or(image);
+            let ctx = match progress {
+                Some(callback) => Context::with_progress(callback, index, count),
+                None => Context::default(),
+            }.with_pool(&pool).with_blur_cache(&blurs, input.data.get_ref()).with_boundary(boundary)
+                .with_luminance(image.luminance()).with_soft_clip(soft_clip);
+            let next = step.apply(input, &ctx, |image| match &step.action {
+                Action::Builtin(builtin) => run_operation(image, builtin, &ctx),
+                Action::AmountMap { radius, threshold, map } => {
+                    sharpening::unsharp_mask_with_amount_map(image, *radius, map, *threshold, &ctx)
+                }
+                Action::Chain { operations, .. } => run_chain(image, operations, &ctx),
+                Action::Custom(_) => unreachable!("custom steps run in SharpeningBuilder::run"),
+            })
+                .map_err(|e| e.in_operation(operation, Some(index)))?;
+            // No later step reads the input, so its blurs go back to the pool
+            blurs.evict(input.data.get_ref(), Some(&pool));
+            if let Some(previous) = current.replace(next) {
+                ctx.recycle(previous.into_buffer());
+            }
+        }
+        
+        if let Some(callback) = progress {
+            callback(ProgressEvent::Finished { index, count, operation });
+        }
+    }
+    
+    Ok(current.unwrap_or_else(|| image.clone()))
+}
+
+impl<P: SharpenPixel> SharpeningBuilder<P> {
+    pub(crate) fn new(image: Image<P>) -> Self {
         Self {
             image,
-            operations: Vec::new(),
+            steps: Vec::new(),
+            progress: None,
+            parallelism: Parallelism::Global,
+            region: None,
+            mask: None,
+            tileable: false,
+            normal_map: false,
+            adaptive: false,
+            protect_stars: false,
+            transfer: None,
+            soft_clip: None,
+            highlights: None,
+            shadows: None,
+            radial: None,
+            policy: ValidationPolicy::Reject,
+            stray_chroma_clamp: None,
         }
     }
     
     /// Adds unsharp mask operation to the pipeline.
-    pub fn unsharp_mask(mut self, radius: f32, amount: f32, threshold: u8) -> Self {
-        self.operations.push(Operation::UnsharpMask { radius, amount, threshold });
+    pub fn unsharp_mask(self, radius: f32, amount: f32, threshold: u8) -> Self {
+        self.operation(Operation::UnsharpMask { radius, amount, threshold })
+    }
+    
+    /// Adds an unsharp mask whose amount varies per pixel with `amount_map`,
+    /// e.g. a saliency map or a subject matte computed elsewhere: every 51
+    /// levels are an amount of 1, so black leaves a pixel unsharpened and
+    /// white sharpens it with amount 5.
+    /// 
+    /// The map must have the image's dimensions; regions crop it and
+    /// previews scale it along with the image. Unlike [`mask`](Self::mask),
+    /// it weights this step alone, and can sharpen beyond amount 1.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// // Amount 2 on the left half, 0.5 on the right
+    /// let amounts = image::GrayImage::from_fn(640, 480, |x, _| image::Luma([if x < 320 { 102 } else { 26 }]));
+    /// let result = image.sharpen().unsharp_mask_with_amount_map(1.2, amounts, 2).apply().unwrap();
+    /// ```
+    pub fn unsharp_mask_with_amount_map(mut self, radius: f32, amount_map: GrayImage, threshold: u8) -> Self {
+        self.steps.push(Step::with_action(Action::AmountMap { radius, threshold, map: Arc::new(amount_map) }));
         self
     }
     
+    /// Adds an unsharp mask whose `threshold` is a percentage of the local
+    /// contrast (0-100) rather than an absolute difference.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().unsharp_mask_relative(1.2, 1.0, 20.0).apply().unwrap();
+    /// ```
+    pub fn unsharp_mask_relative(self, radius: f32, amount: f32, threshold: f32) -> Self {
+        self.operation(Operation::RelativeUnsharp { radius, amount, threshold })
+    }
+    
     /// Adds high-pass sharpening to the pipeline.
-    pub fn high_pass(mut self, strength: f32) -> Self {
-        self.operations.push(Operation::HighPassSharpen { strength });
-        self
+    pub fn high_pass(self, strength: f32) -> Self {
+        self.operation(Operation::HighPassSharpen { strength })
     }
     
     /// Adds edge enhancement to the pipeline.
-    pub fn edge_enhance(mut self, strength: f32, method: EdgeMethod) -> Self {
-        self.operations.push(Operation::EnhanceEdges { strength, method });
-        self
+    pub fn edge_enhance(self, strength: f32, method: EdgeMethod) -> Self {
+        self.operation(Operation::EnhanceEdges { strength, method })
     }
     
     /// Adds clarity enhancement to the pipeline.
-    pub fn clarity(mut self, strength: f32, radius: f32) -> Self {
-        self.operations.push(Operation::Clarity { strength, radius });
+    pub fn clarity(self, strength: f32, radius: f32) -> Self {
+        self.operation(Operation::Clarity { strength, radius })
+    }
+    
+    /// Adds an unsharp mask whose radius adapts to the local detail scale,
+    /// from `min_radius` for fine texture to `max_radius` for soft, large
+    /// structures.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().adaptive_unsharp(0.7, 4.0, 1.2, 1).apply().unwrap();
+    /// ```
+    pub fn adaptive_unsharp(self, min_radius: f32, max_radius: f32, amount: f32, threshold: u8) -> Self {
+        self.operation(Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold })
+    }
+    
+    /// Adds text sharpening, tuned to strokes about `stroke_width` pixels
+    /// wide, to the pipeline.
+    pub fn text_sharpen(self, stroke_width: f32, strength: f32) -> Self {
+        self.operation(Operation::TextSharpen { stroke_width, strength })
+    }
+    
+    /// Adds removal of the halftone screen of a scanned print, detected
+    /// from the image, to the pipeline. Add it before the sharpening, which
+    /// would otherwise amplify the screen into moiré.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let scan = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = scan.sharpen().descreen().unsharp_mask(1.5, 1.0, 2).apply().unwrap();
+    /// ```
+    pub fn descreen(self) -> Self {
+        self.operation(Operation::Descreen { period: None })
+    }
+    
+    /// Like [`descreen`](Self::descreen), for a print screened at `lpi`
+    /// lines per inch and scanned at `dpi`.
+    pub fn descreen_lpi(self, lpi: f32, dpi: f32) -> Self {
+        self.operation(Operation::Descreen { period: Some(dpi / lpi) })
+    }
+    
+    /// Adds a median filter of `radius` pixels, removing salt-and-pepper and
+    /// sensor noise before the sharpening that follows it amplifies it.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().median_filter(1).unsharp_mask(1.0, 1.2, 0).apply().unwrap();
+    /// ```
+    pub fn median_filter(self, radius: u32) -> Self {
+        self.operation(Operation::Median { radius })
+    }
+    
+    /// Adds non-local means denoising (see [`Image::denoise`]), so the
+    /// sharpening that follows it brings out detail rather than grain.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().denoise(1.0, 1, 7).unsharp_mask(1.0, 1.2, 0).apply().unwrap();
+    /// ```
+    pub fn denoise(self, strength: f32, patch_radius: u32, search_radius: u32) -> Self {
+        self.operation(Operation::Denoise { strength, patch_radius, search_radius })
+    }
+    
+    /// Adds a smart enhancement (see [`Image::smart_enhance`]): denoising
+    /// as needed, then as much sharpening as the remaining noise allows.
+    /// 
+    /// The denoising and sharpening are picked from the builder's whole
+    /// image before anything runs, so a [`region`](Self::region) or
+    /// [`preview`](Self::preview) gets the settings of the full result.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().smart_enhance(1.0).apply().unwrap();
+    /// ```
+    pub fn smart_enhance(self, level: f32) -> Self {
+        self.operation(Operation::SmartEnhance { level })
+    }
+    
+    /// Adds frequency-separation retouching (see
+    /// [`Image::frequency_separation`]): smoothing the tones of skin and
+    /// sharpening the detail of everything else.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().frequency_separation(2.0, 0.4, 0.8).apply().unwrap();
+    /// ```
+    pub fn frequency_separation(self, radius: f32, smoothing: f32, amount: f32) -> Self {
+        self.operation(Operation::FrequencySeparation { radius, smoothing, amount })
+    }
+    
+    /// Adds an arbitrary operation to the pipeline.
+    pub fn operation(mut self, operation: Operation) -> Self {
+        self.steps.push(Step::new(operation));
+        self
+    }
+    
+    /// Adds a step the application defines, such as its own filter, to run
+    /// in order with the built-in operations; see [`PipelineOp`]. A boxed
+    /// `Box<dyn PipelineOp<P>>` works as well.
+    /// 
+    /// [`opacity`](Self::opacity) and [`chroma_clamp`](Self::chroma_clamp)
+    /// apply to it like to built-in operations, and its
+    /// [`validate`](PipelineOp::validate) runs with the builder's.
+    pub fn custom<O: PipelineOp<P> + 'static>(mut self, op: O) -> Self {
+        self.steps.push(Step::with_action(Action::Custom(Arc::new(op))));
+        self
+    }
+    
+    /// Adds several operations to the pipeline, in order.
+    pub fn with_operations<I>(mut self, operations: I) -> Self
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        self.steps.extend(operations.into_iter().map(Step::new));
+        self
+    }
+    
+    /// Sets the opacity (0.0-1.0) of the most recently added operation.
+    /// 
+    /// The operation's result is blended with its input like a layer at the
+    /// given opacity, in its [`blend_mode`](Self::blend_mode), so `0.5`
+    /// applies the step at half strength. Has no effect if no operation has
+    /// been added yet.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen()
+    ///     .unsharp_mask(2.0, 1.5, 0)
+    ///     .opacity(0.6)
+    ///     .clarity(0.5, 3.0)
+    ///     .apply()
+    ///     .unwrap();
+    /// ```
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.opacity = opacity;
+        }
+        self
+    }
+    
+    /// Sets the mode the most recently added operation's result is blended
+    /// over its input with, at its [`opacity`](Self::opacity); see
+    /// [`BlendMode`]. Steps that return a mid-gray detail layer, such as
+    /// [`Image::high_pass_layer`], recombine with the image in overlay, soft
+    /// light or linear light. Has no effect if no operation has been added
+    /// yet.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{BlendMode, Image, PipelineOp, Result};
+    /// 
+    /// struct Detail;
+    /// 
+    /// impl PipelineOp for Detail {
+    ///     fn name(&self) -> &'static str {
+    ///         "Detail"
+    ///     }
+    /// 
+    ///     fn apply(&self, image: Image) -> Result<Image> {
+    ///         image.high_pass_layer(3.0)
+    ///     }
+    /// }
+    /// 
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen().custom(Detail).blend_mode(BlendMode::SoftLight).opacity(0.7).apply().unwrap();
+    /// ```
+    pub fn blend_mode(mut self, mode: BlendMode) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.mode = mode;
+        }
+        self
+    }
+    
+    /// Suppresses the purple and green fringes the most recently added
+    /// operation leaves along high-contrast edges.
+    /// 
+    /// The operation's result keeps its sharpened luminance, but each
+    /// pixel's color is limited to the range of colors in the original
+    /// around it (3x3 pixels), widened by `tolerance` in 8-bit units
+    /// (0-255). Grayscale images are unaffected.
+    /// 
+    /// Called before any operation has been added, it has nothing to clamp,
+    /// and [`validate`](Self::validate) and `apply()` fail with
+    /// [`ImageError::InvalidParameter`].
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen()
+    ///     .unsharp_mask(2.0, 1.5, 0)
+    ///     .chroma_clamp(2.0)
+    ///     .clarity(0.5, 3.0)
+    ///     .apply()
+    ///     .unwrap();
+    /// ```
+    pub fn chroma_clamp(mut self, tolerance: f32) -> Self {
+        match self.steps.last_mut() {
+            Some(step) => step.chroma_clamp = Some(tolerance),
+            None => self.stray_chroma_clamp = Some(tolerance),
+        }
+        self
+    }
+    
+    /// Limits the operations to `threads` worker threads.
+    /// 
+    /// A dedicated pool is created for each apply or preview instead of
+    /// using rayon's global pool, and all of its work runs there, so CPU
+    /// usage stays bounded inside host applications. Zero threads fail
+    /// [`validate`](Self::validate).
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen()
+    ///     .unsharp_mask(1.0, 1.0, 0)
+    ///     .threads(2)
+    ///     .apply()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.parallelism = Parallelism::Threads(threads);
+        self
+    }
+    
+    /// Runs the operations on an existing rayon thread pool.
+    /// 
+    /// Use this to share one pool between Sharpy and the host application.
+    #[cfg(feature = "parallel")]
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.parallelism = Parallelism::Pool(pool);
+        self
+    }
+    
+    /// Sets a callback receiving progress while the pipeline is applied.
+    /// 
+    /// Each operation reports when it starts and finishes, and in between
+    /// the percentage of rows processed. The callback may be invoked from
+    /// worker threads.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::{Image, ProgressEvent};
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let result = image.sharpen()
+    ///     .unsharp_mask(1.0, 1.0, 0)
+    ///     .clarity(0.5, 2.0)
+    ///     .on_progress(|event| {
+    ///         if let ProgressEvent::Progress { index, count, percent } = event {
+    ///             println!("step {}/{}: {}%", index + 1, count, percent);
+    ///         }
+    ///     })
+    ///     .apply()
+    ///     .unwrap();
+    /// ```
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+    
+    /// Checks every queued operation's parameters without processing.
+    /// 
+    /// Unlike `apply()`, which stops at the first problem, this reports
+    /// every violation at once as [`ImageError::Validation`]. The ranges
+    /// are the image's, see [`Image::with_validation`]. An out-of-range
+    /// [`linearize`](Self::linearize) gamma, [`soft_clip`](Self::soft_clip)
+    /// knee, [`protect_highlights`](Self::protect_highlights) or
+    /// [`protect_shadows`](Self::protect_shadows) level,
+    /// [`radial_falloff`](Self::radial_falloff), zero
+    /// [`threads`](Self::threads) or a [`chroma_clamp`](Self::chroma_clamp)
+    /// before any operation fails first.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::{Image, ImageError};
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let builder = image.sharpen().unsharp_mask(50.0, 1.0, 0).clarity(0.5, 30.0);
+    /// match builder.validate() {
+    ///     Err(ImageError::Validation(violations)) => assert_eq!(violations.len(), 2),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        #[cfg(feature = "parallel")]
+        if let Parallelism::Threads(0) = self.parallelism {
+            return Err(ImageError::InvalidParameter { param: "threads".to_string(), value: "0".to_string() });
+        }
+        if let Some(tolerance) = self.stray_chroma_clamp {
+            return Err(ImageError::InvalidParameter {
+                param: "chroma_clamp".to_string(),
+                value: format!("{} before any operation", tolerance),
+            });
+        }
+        if let Some(transfer) = self.transfer {
+            transfer.check()?;
+        }
+        if let Some(knee) = self.soft_clip.filter(|knee| !(*knee > 0.0 && *knee <= 1.0)) {
+            return Err(ImageError::InvalidParameter { param: "soft_clip".to_string(), value: knee.to_string() });
+        }
+        for (param, level) in [("protect_highlights", self.highlights), ("protect_shadows", self.shadows)] {
+            if let Some(level) = level.filter(|level| !(0.0..=255.0).contains(level)) {
+                return Err(ImageError::InvalidParameter { param: param.to_string(), value: level.to_string() });
+            }
+        }
+        if let Some((center, edge, feather)) = self.radial {
+            let amounts = 0.0..=1.0;
+            if !(amounts.contains(&center) && amounts.contains(&edge) && feather > 0.0 && feather <= 1.0) {
+                return Err(ImageError::InvalidParameter {
+                    param: "radial_falloff".to_string(),
+                    value: format!("{}:{}:{}", center, edge, feather),
+                });
+            }
+        }
+        let config = self.image.validation();
+        let mut violations = Vec::new();
+        for (index, step) in self.steps().iter().enumerate() {
+            let operation = step.name();
+            match &step.action {
+                Action::Builtin(builtin) => {
+                    for (param, value) in builtin.violations_with(&config) {
+                        violations.push(Violation { index, operation, param: param.to_string(), value });
+                    }
+                }
+                Action::AmountMap { radius, threshold, .. } => {
+                    for (param, value) in amount_map_unsharp(*radius, *threshold).violations_with(&config) {
+                        violations.push(Violation { index, operation, param: param.to_string(), value });
+                    }
+                }
+                Action::Custom(op) => match op.validate() {
+                    Ok(()) => {}
+                    Err(ImageError::InvalidParameter { param, value }) => {
+                        violations.push(Violation { index, operation, param, value });
+                    }
+                    Err(e) => return Err(e.in_operation(operation, Some(index))),
+                },
+                Action::Chain { operations, .. } => {
+                    for (param, value) in operations.iter().flat_map(|chained| chained.violations_with(&config)) {
+                        violations.push(Violation { index, operation, param: param.to_string(), value });
+                    }
+                }
+            }
+            for (param, value) in step.violations() {
+                violations.push(Violation { index, operation, param: param.to_string(), value });
+            }
+        }
+        
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ImageError::Validation(violations))
+        }
+    }
+    
+    /// Sets what happens to out-of-range parameters: rejecting them with
+    /// [`ImageError::Validation`] (the default), or clamping each to the
+    /// nearest valid value and going on, which suits batch jobs driven by
+    /// user input. [`clamps`](Self::clamps) lists what clamping changes, and
+    /// with the `tracing` feature every clamp is logged as a warning when
+    /// the builder is applied. The builder-wide settings, such as a
+    /// [`linearize`](Self::linearize) gamma or a [`soft_clip`](Self::soft_clip)
+    /// knee, are never clamped.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, ValidationPolicy};
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let builder = image.sharpen().clarity(0.5, 30.0).validation_policy(ValidationPolicy::Clamp);
+    /// assert_eq!(builder.clamps()[0].to_string(), "step 0 (Clarity): radius = 30 clamped to 20");
+    /// let result = builder.apply().unwrap();
+    /// ```
+    pub fn validation_policy(mut self, policy: ValidationPolicy) -> Self {
+        self.policy = policy;
         self
     }
     
+    /// Lists the parameters that [`ValidationPolicy::Clamp`] moves into the
+    /// ranges of the image's [`ValidationConfig`], whichever policy is set.
+    pub fn clamps(&self) -> Vec<Clamp> {
+        let config = self.image.validation();
+        let mut clamps = Vec::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let operation = step.name();
+            for (param, value, clamped) in step.clamped(&config).1 {
+                clamps.push(Clamp {
+                    index,
+                    operation,
+                    param: param.to_string(),
+                    value: value.to_string(),
+                    clamped: clamped.to_string(),
+                });
+            }
+        }
+        clamps
+    }
+    
+    /// The steps to run: the queued ones, clamped under
+    /// [`ValidationPolicy::Clamp`].
+    fn steps(&self) -> Cow<'_, [Step<P>]> {
+        match self.policy {
+            ValidationPolicy::Reject => Cow::Borrowed(&self.steps),
+            ValidationPolicy::Clamp => {
+                let config = self.image.validation();
+                Cow::Owned(self.steps.iter().map(|step| step.clamped(&config).0).collect())
+            }
+        }
+    }
+    
+    /// Validates the steps to run and returns them, logging any clamps.
+    fn checked_steps(&self) -> Result<Cow<'_, [Step<P>]>> {
+        self.validate()?;
+        #[cfg(feature = "tracing")]
+        if self.policy == ValidationPolicy::Clamp {
+            for clamp in self.clamps() {
+                tracing::warn!("{}", clamp);
+            }
+        }
+        Ok(self.steps())
+    }
+    
     /// Applies all configured operations and returns the result.
-    pub fn apply(self) -> Result<Image> {
-        let mut image = self.image;
+    /// 
+    /// Parameters are validated before any processing starts.
+    pub fn apply(self) -> Result<Image<P>> {
+        let steps = self.checked_steps()?;
+        if steps.iter().all(|step| step.opacity == 0.0) {
+            drop(steps);
+            return Ok(self.image);
+        }
+        self.process(&self.image, &steps)
+    }
+    
+    /// Applies the configured operations to `image` without consuming it.
+    /// 
+    /// The builder's own image is left untouched, so one builder can act as
+    /// a reusable recipe for many borrowed images.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let original = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let recipe = original.clone().sharpen().unsharp_mask(1.0, 1.0, 0).clarity(0.3, 2.0);
+    /// let sharpened = recipe.apply_ref(&original).unwrap();
+    /// let again = recipe.apply_ref(&sharpened).unwrap();
+    /// ```
+    pub fn apply_ref(&self, image: &Image<P>) -> Result<Image<P>> {
+        let steps = self.checked_steps()?;
+        self.process(image, &steps)
+    }
+    
+    /// Like [`apply`](Self::apply), but also returns the [`Warning`]s about
+    /// conditions that don't stop processing: heavy clipping, a step reading
+    /// further than the image extends, clamps under
+    /// [`ValidationPolicy::Clamp`], and a source file whose precision or
+    /// alpha the image's pixel type dropped. A [`mask`](Self::mask) of other
+    /// dimensions than the image is scaled to fit and reported, where
+    /// `apply` fails.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Warning};
+    /// # let image = Image::from_rgb(image::RgbImage::new(16, 16)).unwrap();
+    /// let (result, warnings) = image.sharpen().unsharp_mask(8.0, 1.0, 0).apply_with_report().unwrap();
+    /// assert!(matches!(warnings[0], Warning::ReachExceedsImage { index: 0, .. }));
+    /// ```
+    pub fn apply_with_report(mut self) -> Result<(Image<P>, Vec<Warning>)> {
+        let steps = self.checked_steps()?.into_owned();
+        let (width, height) = self.image.dimensions();
+        let mut warnings = Vec::new();
+        if let Some(source) = self.image.source_color.filter(|&source| warning::narrows::<P>(source)) {
+            warnings.push(Warning::SourceNarrowed { source });
+        }
+        if self.policy == ValidationPolicy::Clamp {
+            warnings.extend(self.clamps().into_iter().map(Warning::Clamped));
+        }
+        if let Some(mask) = self.mask.take_if(|mask| mask.dimensions() != (width, height)) {
+            warnings.push(Warning::MaskResized { from: mask.dimensions(), to: (width, height) });
+            self.mask = Some(imageops::resize(&mask, width, height, FilterType::Triangle));
+        }
+        let size = width.min(height);
+        for (index, step) in steps.iter().enumerate() {
+            let reach = step.reach();
+            if step.opacity != 0.0 && reach > size {
+                warnings.push(Warning::ReachExceedsImage { index, operation: step.name(), reach, size });
+            }
+        }
+        
+        let result = self.process(&self.image, &steps)?;
+        let fraction = warning::newly_clipped(self.image.data.get_ref(), result.data.get_ref());
+        if fraction > warning::CLIPPING_THRESHOLD {
+            warnings.push(Warning::Clipping { fraction });
+        }
+        Ok((result, warnings))
+    }
+    
+    /// Limits [`apply`](Self::apply) and [`apply_ref`](Self::apply_ref) to
+    /// `rect`, leaving pixels outside it unchanged.
+    /// 
+    /// Only the rectangle and the margin of surrounding pixels its operations
+    /// read are processed, so spot corrections on large images are fast while
+    /// the rectangle matches processing the whole image. Previews ignore it.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::{Image, Rect};
+    /// # let image = Image::from_rgb(image::RgbImage::new(4000, 3000)).unwrap();
+    /// let spot = image.sharpen().unsharp_mask(1.5, 1.0, 0).region(Rect::new(1200, 800, 300, 200)).apply().unwrap();
+    /// assert_eq!(spot.dimensions(), (4000, 3000));
+    /// ```
+    pub fn region(mut self, rect: Rect) -> Self {
+        self.region = Some(rect);
+        self
+    }
+    
+    /// Applies the result only where `mask` is bright, e.g. a hand-painted
+    /// selection: white takes the processed pixel, black keeps the original
+    /// and grays blend between them.
+    /// 
+    /// The mask must have the image's dimensions, except with
+    /// [`apply_with_report`](Self::apply_with_report), which scales it to
+    /// fit; previews scale it along.
+    /// Combined with [`region`](Self::region), only the rectangle is processed
+    /// and the mask applies within it.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let mask = image::GrayImage::from_fn(640, 480, |x, y| image::Luma([if x > 320 && y < 240 { 255 } else { 0 }]));
+    /// let result = image.sharpen().unsharp_mask(1.0, 1.5, 0).mask(mask).apply().unwrap();
+    /// ```
+    pub fn mask(mut self, mask: GrayImage) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+    
+    /// Keeps tileable textures tileable: kernels read across each edge from
+    /// the opposite one instead of repeating edge pixels, and `apply` fails
+    /// with [`ImageError::Seam`] if the result tiles visibly worse than the
+    /// input, e.g. because a region or mask covers only one side of a seam.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// let bricks = image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([if (x / 16 + y / 8) % 2 == 0 { 90 } else { 160 }; 3]));
+    /// let texture = Image::from_rgb(bricks).unwrap();
+    /// let sharpened = texture.sharpen().unsharp_mask(2.0, 1.0, 0).tileable(true).apply().unwrap();
+    /// assert!(sharpened.tiling_seam() <= 1.0);
+    /// ```
+    pub fn tileable(mut self, tileable: bool) -> Self {
+        self.tileable = tileable;
+        self
+    }
+    
+    /// Treats the image as a tangent-space normal map: red and green (the
+    /// surface slopes) are sharpened, then every changed pixel is made a
+    /// unit vector again with blue recomputed from them, so lighting stays
+    /// correct. Unsharp mask and high-pass, being linear, sharpen the height
+    /// detail the normals describe. Fails for grayscale images.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use sharpy::Image;
+    /// let normals: Image = Image::load("bricks_normal.png").unwrap();
+    /// let sharpened = normals.sharpen().unsharp_mask(1.0, 1.2, 0).normal_map(true).tileable(true).apply().unwrap();
+    /// ```
+    pub fn normal_map(mut self, normal_map: bool) -> Self {
+        self.normal_map = normal_map;
+        self
+    }
+    
+    /// Sharpens only where the image has structure: edges and texture get
+    /// the full result, while flat areas such as skies keep their original
+    /// pixels, so their noise isn't amplified. The weights come from
+    /// [`mask::structure`] of the input and combine with
+    /// [`mask`](Self::mask) if both are set.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(1.2, 1.5, 0).adaptive(true).apply().unwrap();
+    /// ```
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+    
+    /// Leaves the stars of astrophotographs unsharpened, so nebulae and
+    /// galaxies can be sharpened hard without dark rings around the stars
+    /// or clipping their cores.
+    /// 
+    /// Stars are found by [`mask::stars`] in the input, with discs
+    /// enlarged by the reach of the pipeline's operations so the background
+    /// around each star is left alone too. Combines with
+    /// [`mask`](Self::mask) and [`adaptive`](Self::adaptive).
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(2.0, 2.0, 2).protect_stars(true).apply().unwrap();
+    /// ```
+    pub fn protect_stars(mut self, protect_stars: bool) -> Self {
+        self.protect_stars = protect_stars;
+        self
+    }
+    
+    /// Fades sharpening out between luminance `above` (in 8-bit levels) and
+    /// white, so blown highlights such as skies, snow and specular glints
+    /// don't gain crunchy detail. The weights come from [`mask::tones`] of
+    /// the input and combine with [`mask`](Self::mask) and the other
+    /// protections.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(1.2, 1.5, 0).protect_highlights(220.0).apply().unwrap();
+    /// ```
+    pub fn protect_highlights(mut self, above: f32) -> Self {
+        self.highlights = Some(above);
+        self
+    }
+    
+    /// Fades sharpening out between luminance `below` (in 8-bit levels) and
+    /// black, so blocked shadows don't gain crunchy detail and noise. See
+    /// [`protect_highlights`](Self::protect_highlights).
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(1.2, 1.5, 0).protect_shadows(30.0).apply().unwrap();
+    /// ```
+    pub fn protect_shadows(mut self, below: f32) -> Self {
+        self.shadows = Some(below);
+        self
+    }
+    
+    /// Scales the sharpening by `center` (0-1) in the middle of the image
+    /// and by `edge` (0-1) in the corners, changing smoothly between them
+    /// over the outer `feather` (0-1] share of the way, e.g. to sharpen the
+    /// corners of a lens that is softer there more than its center. The
+    /// weights come from [`mask::radial`] and combine with
+    /// [`mask`](Self::mask) and the protections; set the operations' amounts
+    /// for the strongest part of the image.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(1.2, 2.0, 0).radial_falloff(0.4, 1.0, 0.7).apply().unwrap();
+    /// ```
+    pub fn radial_falloff(mut self, center: f32, edge: f32, feather: f32) -> Self {
+        self.radial = Some((center, edge, feather));
+        self
+    }
+    
+    /// Runs the operations in linear light: the image is decoded with
+    /// `transfer` to 16-bit linear values first and encoded back after, so
+    /// overshoots are even on both sides of an edge and log-encoded
+    /// footage doesn't ring. Thresholds then apply to linear values.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Transfer};
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let result = image.sharpen().linearize(Transfer::Gamma(2.2)).unsharp_mask(1.0, 1.0, 0).apply().unwrap();
+    /// ```
+    pub fn linearize(mut self, transfer: Transfer) -> Self {
+        self.transfer = Some(transfer);
+        self
+    }
+    
+    /// Rolls overshoots off smoothly into black and white instead of
+    /// clipping them, so strong sharpening leaves graded specular
+    /// highlights and deep shadows rather than flat clipped specks.
+    /// 
+    /// Each sharpened sample may move freely through the first
+    /// `1 - knee` of the way from its original value to black or white;
+    /// beyond that, the change is compressed so it approaches the limit
+    /// without reaching it. `knee` is in (0, 1]: 0.2 touches only large
+    /// overshoots, 1.0 compresses every change. Applies to the built-in
+    /// operations except high-pass sharpening, whose kernel clips before it
+    /// is blended, and the denoisers, which don't overshoot.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let result = image.sharpen().unsharp_mask(1.5, 2.5, 0).soft_clip(0.3).apply().unwrap();
+    /// ```
+    pub fn soft_clip(mut self, knee: f32) -> Self {
+        self.soft_clip = Some(knee);
+        self
+    }
+    
+    /// The mask results are blended through: `mask`, already sized to
+    /// `image`, weighted by the structure of `image` if adaptive, excluding
+    /// the stars `steps` would ring around if protecting stars, fading out
+    /// in protected highlights and shadows and falling off radially.
+    fn blend_mask<'a>(&self, image: &Image<P>, steps: &[Step<P>], mask: Option<Cow<'a, GrayImage>>) -> Option<Cow<'a, GrayImage>> {
+        let mut weights = Vec::new();
+        if self.adaptive {
+            weights.push(mask::structure_buffer(image.data.get_ref(), image.luminance(), self.boundary()));
+        }
+        if self.protect_stars {
+            let mut starless = mask::stars_buffer(image.data.get_ref(), reach(steps), image.luminance(), self.boundary());
+            starless.pixels_mut().for_each(|weight| weight[0] = 255 - weight[0]);
+            weights.push(starless);
+        }
+        if self.highlights.is_some() || self.shadows.is_some() {
+            weights.push(mask::tones(image, self.shadows.unwrap_or(0.0), self.highlights.unwrap_or(255.0)));
+        }
+        if let Some((center, edge, feather)) = self.radial {
+            let (width, height) = image.dimensions();
+            weights.push(mask::radial(width, height, center, edge, feather));
+        }
+        weights.into_iter().fold(mask, |mask, mut weights| {
+            if let Some(mask) = mask {
+                for (weight, limit) in weights.pixels_mut().zip(mask.pixels()) {
+                    weight[0] = ((weight[0] as u16 * limit[0] as u16 + 127) / 255) as u8;
+                }
+            }
+            Some(Cow::Owned(weights))
+        })
+    }
+    
+    /// How kernels read beyond the image's edges.
+    fn boundary(&self) -> Boundary {
+        if self.tileable { Boundary::Wrap } else { Boundary::Clamp }
+    }
+    
+    /// Applies the pipeline to a downsampled copy for a quick approximation.
+    /// 
+    /// The image is resized so its longer side is at most `max_dimension`
+    /// pixels, and radius-type parameters are scaled by the same factor so
+    /// the preview looks like a shrunken version of the full result. The
+    /// builder is left untouched, so `apply()` can follow once the user is
+    /// happy with the settings.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(2000, 1000)).unwrap();
+    /// let builder = image.sharpen().unsharp_mask(2.0, 1.0, 0).clarity(0.4, 8.0);
+    /// let preview = builder.preview(400).unwrap();
+    /// assert_eq!(preview.dimensions(), (400, 200));
+    /// ```
+    pub fn preview(&self, max_dimension: u32) -> Result<Image<P>> {
+        if max_dimension == 0 {
+            return Err(ImageError::InvalidParameter {
+                param: "max_dimension".to_string(),
+                value: max_dimension.to_string(),
+            });
+        }
+        let steps = self.checked_steps()?;
+        self.check_mask(&self.image, &steps)?;
+        if self.normal_map {
+            normal::check_channels::<P>()?;
+        }
+        self.install(|| self.preview_steps(&steps, max_dimension))
+    }
+    
+    /// Runs `steps` over the image shrunk to fit `max_dimension`.
+    fn preview_steps(&self, steps: &[Step<P>], max_dimension: u32) -> Result<Image<P>> {
+        let steps = self.resolve(&self.image, steps);
         
-        for operation in self.operations {
-            image = match operation {
-                Operation::UnsharpMask { radius, amount, threshold } => {
-                    sharpening::unsharp_mask(image, radius, amount, threshold)?
+        let (width, height) = self.image.dimensions();
+        let longest = width.max(height);
+        let (image, scale) = if longest <= max_dimension {
+            (self.image.clone(), 1.0)
+        } else {
+            let scale = max_dimension as f32 / longest as f32;
+            let preview_width = ((width as f32 * scale).round() as u32).max(1);
+            let preview_height = ((height as f32 * scale).round() as u32).max(1);
+            let small = imageops::resize(
+                self.image.data.get_ref(),
+                preview_width,
+                preview_height,
+                FilterType::Triangle,
+            );
+            (self.image.with_pixels(small), scale)
+        };
+        
+        let scaled: Vec<Step<P>> = steps.iter()
+            .map(|step| match &step.action {
+                Action::Builtin(operation) => Step { action: Action::Builtin(operation.scaled(scale)), ..step.clone() },
+                Action::AmountMap { radius, threshold, map } => {
+                    let (width, height) = image.dimensions();
+                    let map = if map.dimensions() == (width, height) {
+                        Arc::clone(map)
+                    } else {
+                        Arc::new(imageops::resize(&**map, width, height, FilterType::Triangle))
+                    };
+                    Step { action: Action::AmountMap { radius: radius * scale, threshold: *threshold, map }, ..step.clone() }
+                }
+                Action::Chain { name, operations } => {
+                    let operations = operations.iter().map(|operation| operation.scaled(scale)).collect();
+                    Step { action: Action::Chain { name, operations }, ..step.clone() }
+                }
+                Action::Custom(_) => step.clone(),
+            })
+            .collect();
+        let processed = self.run(&image, &scaled)?;
+        let mask = self.mask.as_ref().map(|mask| if scale < 1.0 {
+            let (width, height) = image.dimensions();
+            Cow::Owned(imageops::resize(mask, width, height, FilterType::Triangle))
+        } else {
+            Cow::Borrowed(mask)
+        });
+        let result = match self.blend_mask(&image, &scaled, mask) {
+            Some(mask) => image.blend_masked(processed, &mask)?,
+            None => processed,
+        };
+        Ok(self.renormalize(&image, result))
+    }
+    
+    /// Makes `processed` a valid normal map again if [`normal_map`](Self::normal_map)
+    /// is set.
+    fn renormalize(&self, image: &Image<P>, processed: Image<P>) -> Image<P> {
+        if !self.normal_map {
+            return processed;
+        }
+        let mut buffer = processed.into_buffer();
+        normal::renormalize(image.data.get_ref(), &mut buffer);
+        image.with_pixels(buffer)
+    }
+    
+    /// Runs `steps` over `image` on the configured thread pool, limited to
+    /// the region and mask if set.
+    fn process(&self, image: &Image<P>, steps: &[Step<P>]) -> Result<Image<P>> {
+        self.check_mask(image, steps)?;
+        if self.normal_map {
+            normal::check_channels::<P>()?;
+        }
+        self.install(|| self.process_steps(image, steps))
+    }
+    
+    /// Runs `steps` over `image` on the current thread pool, limited to the
+    /// region and mask if set.
+    fn process_steps(&self, image: &Image<P>, steps: &[Step<P>]) -> Result<Image<P>> {
+        let steps = &*self.resolve(image, steps);
+        let processed = self.run_in_region(image, steps)?;
+        let result = self.renormalize(image, match self.blend_mask(image, steps, self.mask.as_ref().map(Cow::Borrowed)) {
+            Some(mask) => image.blend_masked(processed, &mask)?,
+            None => processed,
+        });
+        if self.tileable {
+            let (before, after) = (image.tiling_seam(), result.tiling_seam());
+            if after > before.max(1.0) * SEAM_TOLERANCE {
+                return Err(ImageError::Seam { before, after });
+            }
+        }
+        Ok(result)
+    }
+    
+    /// Fails if the mask or an amount map of `steps` doesn't match `image`,
+    /// before any processing.
+    fn check_mask(&self, image: &Image<P>, steps: &[Step<P>]) -> Result<()> {
+        let amount_maps = steps.iter().filter_map(|step| match &step.action {
+            Action::AmountMap { map, .. } => Some(&**map),
+            _ => None,
+        });
+        match self.mask.iter().chain(amount_maps).find(|mask| mask.dimensions() != image.dimensions()) {
+            Some(mask) => {
+                let (width, height) = mask.dimensions();
+                Err(ImageError::InvalidDimensions { width, height })
+            }
+            None => Ok(()),
+        }
+    }
+    
+    /// Replaces smart enhancements among `steps` by the operations they run
+    /// on the whole of `image`, and automatic descreening by the screen
+    /// period detected there, so that a region crop or preview downscale
+    /// processes the same way the full image would.
+    fn resolve<'s>(&self, image: &Image<P>, steps: &'s [Step<P>]) -> Cow<'s, [Step<P>]> {
+        let detects = |step: &Step<P>| step.opacity != 0.0 && matches!(
+            step.operation(),
+            Some(Operation::SmartEnhance { .. } | Operation::Descreen { period: None })
+        );
+        if !steps.iter().any(detects) {
+            return Cow::Borrowed(steps);
+        }
+        let config = image.validation();
+        Cow::Owned(steps.iter()
+            .map(|step| match step.operation() {
+                Some(&Operation::SmartEnhance { level }) if detects(step) => {
+                    let operations = sharpening::smart_operations(image.data.get_ref(), level, image.luminance(), &config);
+                    Step { action: Action::Chain { name: step.name(), operations }, ..step.clone() }
                 }
-                Operation::HighPassSharpen { strength } => {
-                    sharpening::high_pass_sharpen(image, strength)?
+                Some(Operation::Descreen { period: None }) if detects(step) => {
+                    // Without a screen there's nothing to remove
+                    let operations = descreen::detect_period(image.data.get_ref(), image.luminance())
+                        .map(|period| Operation::Descreen { period: Some(period) })
+                        .into_iter()
+                        .collect();
+                    Step { action: Action::Chain { name: step.name(), operations }, ..step.clone() }
+                }
+                _ => step.clone(),
+            })
+            .collect())
+    }
+    
+    /// Runs `steps` over `image`, or only its region if one is set.
+    fn run_in_region(&self, image: &Image<P>, steps: &[Step<P>]) -> Result<Image<P>> {
+        let Some(rect) = self.region else {
+            return self.run(image, steps);
+        };
+        let (width, height) = image.dimensions();
+        rect.check_within(width, height)?;
+        if self.tileable {
+            // A crop would wrap around its own edges rather than the image's
+            let processed = self.run(image, steps)?;
+            let mut output = image.data.get_ref().clone();
+            imageops::replace(&mut output, &*processed.view(rect)?, rect.x as i64, rect.y as i64);
+            return Ok(image.with_pixels(output));
+        }
+        let _span = timed!(DEBUG, "region", x = rect.x, y = rect.y, width = rect.width, height = rect.height);
+        
+        // Errors at the crop's edges spread inward by each step's reach
+        let context = rect.expand(reach(steps), width, height);
+        let crop = image.with_pixels(image.view(context)?.to_image());
+        let steps: Vec<Step<P>> = steps.iter()
+            .map(|step| match &step.action {
+                Action::AmountMap { radius, threshold, map } => {
+                    let map = imageops::crop_imm(&**map, context.x, context.y, context.width, context.height).to_image();
+                    Step { action: Action::AmountMap { radius: *radius, threshold: *threshold, map: Arc::new(map) }, ..step.clone() }
                 }
-                Operation::EnhanceEdges { strength, method } => {
-                    sharpening::enhance_edges(image, strength, method)?
+                _ => step.clone(),
+            })
+            .collect();
+        let processed = self.run(&crop, &steps)?;
+        
+        let inner = imageops::crop_imm(
+            processed.data.get_ref(),
+            rect.x - context.x,
+            rect.y - context.y,
+            rect.width,
+            rect.height,
+        );
+        let mut output = image.data.get_ref().clone();
+        imageops::replace(&mut output, &*inner, rect.x as i64, rect.y as i64);
+        Ok(image.with_pixels(output))
+    }
+    
+    /// Runs `steps` over `image`: custom steps one by one, and each run of
+    /// built-in steps between them in linear light if
+    /// [`linearize`](Self::linearize) is set.
+    fn run(&self, image: &Image<P>, steps: &[Step<P>]) -> Result<Image<P>> {
+        let mut current = Cow::Borrowed(image);
+        let mut start = 0;
+        while start < steps.len() {
+            let next = match &steps[start].action {
+                Action::Custom(op) => {
+                    let next = self.run_custom(&current, steps, start, op.as_ref())?;
+                    start += 1;
+                    next
                 }
-                Operation::Clarity { strength, radius } => {
-                    sharpening::clarity(image, strength, radius)?
+                Action::Builtin(_) | Action::AmountMap { .. } | Action::Chain { .. } => {
+                    let end = steps[start..].iter()
+                        .position(|step| matches!(step.action, Action::Custom(_)))
+                        .map_or(steps.len(), |len| start + len);
+                    let next = self.run_builtin(&current, steps, start..end)?;
+                    start = end;
+                    next
                 }
             };
+            current = Cow::Owned(next);
+        }
+        Ok(current.into_owned())
+    }
+    
+    /// Runs the built-in `steps[range]` over `image`, in linear light if
+    /// [`linearize`](Self::linearize) is set.
+    fn run_builtin(&self, image: &Image<P>, steps: &[Step<P>], range: Range<usize>) -> Result<Image<P>> {
+        match self.transfer {
+            Some(transfer) => {
+                let linear = self.run_on(&transfer::to_linear(image, transfer), steps, range)?;
+                Ok(transfer::from_linear(&linear, image, transfer))
+            }
+            None => self.run_on(image, steps, range),
+        }
+    }
+    
+    /// Runs the custom step `steps[index]` over `image`.
+    fn run_custom(&self, image: &Image<P>, steps: &[Step<P>], index: usize, op: &dyn PipelineOp<P>) -> Result<Image<P>> {
+        let (count, operation) = (steps.len(), op.name());
+        let progress = self.progress.as_deref();
+        if let Some(callback) = progress {
+            callback(ProgressEvent::Started { index, count, operation });
+        }
+        let ctx = Context::default().with_boundary(self.boundary())
+            .with_luminance(image.luminance()).with_soft_clip(self.soft_clip);
+        let result = steps[index].apply(image, &ctx, |image| op.apply(image.clone()))
+            .map_err(|e| e.in_operation(operation, Some(index)))?;
+        if let Some(callback) = progress {
+            callback(ProgressEvent::Finished { index, count, operation });
+        }
+        Ok(result)
+    }
+    
+    /// Runs the built-in `steps[range]` over `image`.
+    fn run_on<Q: SharpenPixel>(&self, image: &Image<Q>, steps: &[Step<P>], range: Range<usize>) -> Result<Image<Q>> {
+        run_steps(image, steps, range, self.progress.as_deref(), self.boundary(), self.soft_clip)
+    }
+    
+    /// Runs `work`, a whole apply or preview, on the configured thread pool:
+    /// the global one, the caller's, or one built for it with
+    /// [`threads`](Self::threads).
+    fn install<R, F>(&self, work: F) -> Result<R>
+    where
+        R: Send,
+        F: FnOnce() -> Result<R> + Send,
+    {
+        match &self.parallelism {
+            Parallelism::Global => work(),
+            #[cfg(feature = "parallel")]
+            Parallelism::Pool(pool) => pool.install(work),
+            #[cfg(feature = "parallel")]
+            Parallelism::Threads(threads) => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(*threads)
+                    .build()
+                    .map_err(|e| ImageError::InvalidParameter {
+                        param: "threads".to_string(),
+                        value: e.to_string(),
+                    })?;
+                pool.install(work)
+            }
         }
-        
-        Ok(image)
     }
     
     /// Returns the number of operations in the pipeline.
     pub fn operation_count(&self) -> usize {
-        self.operations.len()
+        self.steps.len()
     }
     
     /// Clears all operations from the pipeline.
     pub fn clear(mut self) -> Self {
-        self.operations.clear();
+        self.steps.clear();
         self
     }
+    
+    /// Returns the queued steps in order, one per index of
+    /// [`insert`](Self::insert), [`remove`](Self::remove) and
+    /// [`replace`](Self::replace).
+    pub fn operations(&self) -> impl ExactSizeIterator<Item = QueuedStep<'_>> + '_ {
+        self.steps.iter().map(|step| match &step.action {
+            Action::Builtin(operation) => QueuedStep::Operation(operation),
+            Action::AmountMap { radius, threshold, .. } => QueuedStep::AmountMap { radius: *radius, threshold: *threshold },
+            Action::Custom(op) => QueuedStep::Custom { name: op.name() },
+            Action::Chain { .. } => unreachable!("chains only exist while processing"),
+        })
+    }
+    
+    /// Inserts an operation at `index`, shifting later operations back.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::{Image, Operation};
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let mut builder = image.sharpen().unsharp_mask(1.0, 1.0, 0).clarity(0.3, 2.0);
+    /// builder.insert(1, Operation::HighPassSharpen { strength: 0.4 }).unwrap();
+    /// builder.move_operation(2, 0).unwrap();
+    /// 
+    /// let names: Vec<_> = builder.operations().map(|step| step.name()).collect();
+    /// assert_eq!(names, ["Clarity", "Unsharp Mask", "High-Pass Sharpen"]);
+    /// ```
+    pub fn insert(&mut self, index: usize, operation: Operation) -> Result<()> {
+        check_index(index, self.steps.len() + 1)?;
+        self.steps.insert(index, Step::new(operation));
+        Ok(())
+    }
+    
+    /// Removes and returns the step at `index`, whether a built-in
+    /// operation, a [`custom`](Self::custom) step or an
+    /// [amount map](Self::unsharp_mask_with_amount_map).
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::{Image, Operation};
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let mut builder = image.sharpen().unsharp_mask(1.0, 1.0, 0).clarity(0.3, 2.0);
+    /// let removed = builder.remove(0).unwrap();
+    /// assert_eq!(removed.into_operation(), Some(Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 }));
+    /// ```
+    pub fn remove(&mut self, index: usize) -> Result<RemovedStep<P>> {
+        check_index(index, self.steps.len())?;
+        Ok(RemovedStep::from_action(self.steps.remove(index).action))
+    }
+    
+    /// Replaces the step at `index` with `operation`, keeping its opacity,
+    /// blend mode and chroma clamp, and returns the previous step.
+    pub fn replace(&mut self, index: usize, operation: Operation) -> Result<RemovedStep<P>> {
+        check_index(index, self.steps.len())?;
+        let previous = std::mem::replace(&mut self.steps[index].action, Action::Builtin(operation));
+        Ok(RemovedStep::from_action(previous))
+    }
+    
+    /// Moves the operation at `from` to position `to`, along with its opacity.
+    pub fn move_operation(&mut self, from: usize, to: usize) -> Result<()> {
+        check_index(from, self.steps.len())?;
+        check_index(to, self.steps.len())?;
+        let step = self.steps.remove(from);
+        self.steps.insert(to, step);
+        Ok(())
+    }
 }
 
+/// How far an output pixel of `steps` depends on input pixels around it.
+fn reach<P: SharpenPixel>(steps: &[Step<P>]) -> u32 {
+    steps.iter()
+        .filter(|step| step.opacity != 0.0)
+        .map(Step::reach)
+        .sum()
+}
+
+fn check_index(index: usize, len: usize) -> Result<()> {
+    if index >= len {
+        return Err(ImageError::InvalidParameter {
+            param: "index".to_string(),
+            value: index.to_string(),
+        });
+    }
+    Ok(())
+}
+
+const SUBTLE: &[Operation] = &[
+    Operation::UnsharpMask { radius: 0.8, amount: 0.6, threshold: 2 },
+];
+
+const MODERATE: &[Operation] = &[
+    Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 3 },
+    Operation::Clarity { strength: 0.3, radius: 2.0 },
+];
+
+const STRONG: &[Operation] = &[
+    Operation::UnsharpMask { radius: 1.5, amount: 1.5, threshold: 2 },
+    Operation::HighPassSharpen { strength: 0.3 },
+    Operation::Clarity { strength: 0.5, radius: 3.0 },
+];
+
+const EDGE_AWARE: &[Operation] = &[
+    Operation::EnhanceEdges { strength: 0.8, method: EdgeMethod::Sobel },
+    Operation::UnsharpMask { radius: 0.5, amount: 0.8, threshold: 5 },
+];
+
+const PORTRAIT: &[Operation] = &[
+    Operation::UnsharpMask { radius: 1.2, amount: 0.7, threshold: 10 },
+    Operation::Clarity { strength: 0.2, radius: 5.0 },
+];
+
+const LANDSCAPE: &[Operation] = &[
+    Operation::UnsharpMask { radius: 1.0, amount: 1.2, threshold: 1 },
+    Operation::EnhanceEdges { strength: 0.5, method: EdgeMethod::Sobel },
+    Operation::Clarity { strength: 0.4, radius: 3.0 },
+];
+
+const DOCUMENT: &[Operation] = &[
+    Operation::TextSharpen { stroke_width: 1.5, strength: 1.5 },
+];
+
+const SMART: &[Operation] = &[
+    Operation::SmartEnhance { level: 1.0 },
+];
+
+const PORTRAIT_FS: &[Operation] = &[
+    Operation::FrequencySeparation { radius: 2.0, smoothing: 0.4, amount: 0.8 },
+];
+
+/// Run with stars protected.
+const ASTRO: &[Operation] = &[
+    Operation::UnsharpMask { radius: 2.0, amount: 1.5, threshold: 2 },
+    Operation::Clarity { strength: 0.6, radius: 6.0 },
+];
+
+/// Built-in presets as (name, description, operations, protect stars).
+pub(crate) const BUILTIN_PRESETS: &[(&str, &str, &[Operation], bool)] = &[
+    ("subtle", "Subtle sharpening suitable for most images", SUBTLE, false),
+    ("moderate", "Moderate sharpening for slightly soft images", MODERATE, false),
+    ("strong", "Strong sharpening for very soft images", STRONG, false),
+    ("edge-aware", "Edge-focused sharpening that preserves smooth areas", EDGE_AWARE, false),
+    ("portrait", "Portrait sharpening that avoids over-sharpening skin", PORTRAIT, false),
+    ("landscape", "Landscape sharpening for maximum detail", LANDSCAPE, false),
+    ("document", "Text sharpening for scans and screenshots, without halos around glyphs", DOCUMENT, false),
+    ("astro", "Strong nebula and galaxy sharpening that leaves stars untouched", ASTRO, true),
+    ("smart", "Denoising as needed, then as much sharpening as the remaining noise allows", SMART, false),
+    ("portrait_fs", "Frequency-separation portrait retouching: slightly smoother skin, sharper eyes and hair", PORTRAIT_FS, false),
+];
+
 /// Preset sharpening configurations for common use cases.
 pub struct SharpeningPresets;
 
 impl SharpeningPresets {
     /// Subtle sharpening suitable for most images.
-    pub fn subtle(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .unsharp_mask(0.8, 0.6, 2)
+    pub fn subtle<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(SUBTLE.iter().cloned())
     }
     
     /// Moderate sharpening for slightly soft images.
-    pub fn moderate(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .unsharp_mask(1.0, 1.0, 3)
-            .clarity(0.3, 2.0)
+    pub fn moderate<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(MODERATE.iter().cloned())
     }
     
     /// Strong sharpening for very soft images.
-    pub fn strong(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .unsharp_mask(1.5, 1.5, 2)
-            .high_pass(0.3)
-            .clarity(0.5, 3.0)
+    pub fn strong<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(STRONG.iter().cloned())
     }
     
     /// Edge-focused sharpening that preserves smooth areas.
-    pub fn edge_aware(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .edge_enhance(0.8, EdgeMethod::Sobel)
-            .unsharp_mask(0.5, 0.8, 5)
+    pub fn edge_aware<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(EDGE_AWARE.iter().cloned())
     }
     
     /// Portrait sharpening that avoids over-sharpening skin.
-    pub fn portrait(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .unsharp_mask(1.2, 0.7, 10)
-            .clarity(0.2, 5.0)
+    pub fn portrait<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(PORTRAIT.iter().cloned())
     }
     
     /// Landscape sharpening for maximum detail.
-    pub fn landscape(image: Image) -> SharpeningBuilder {
-        SharpeningBuilder::new(image)
-            .unsharp_mask(1.0, 1.2, 1)
-            .edge_enhance(0.5, EdgeMethod::Sobel)
-            .clarity(0.4, 3.0)
+    pub fn landscape<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(LANDSCAPE.iter().cloned())
+    }
+    
+    /// Text sharpening for scanned documents and screenshots, without halos
+    /// around glyphs.
+    pub fn document<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(DOCUMENT.iter().cloned())
+    }
+    
+    /// Strong sharpening of nebulae and galaxies that leaves stars
+    /// untouched, see [`SharpeningBuilder::protect_stars`].
+    pub fn astro<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(ASTRO.iter().cloned()).protect_stars(true)
+    }
+    
+    /// Denoising as needed, then as much sharpening as the remaining noise
+    /// allows, see [`Image::smart_enhance`].
+    pub fn smart<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(SMART.iter().cloned())
+    }
+    
+    /// Portrait retouching by frequency separation: slightly smoother skin,
+    /// with eyes, lashes and hair sharpened, see
+    /// [`Image::frequency_separation`].
+    pub fn portrait_fs<P: SharpenPixel>(image: Image<P>) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(image).with_operations(PORTRAIT_FS.iter().cloned())
     }
 }
 
@@ -168,6 +1837,518 @@ mod tests {
         assert!(builder.apply().is_ok());
     }
     
+    #[test]
+    fn test_pooled_buffers_match_direct_calls() {
+        let rgb = RgbImage::from_fn(48, 40, |x, y| image::Rgb([(x * 5) as u8, (y * 6) as u8, ((x ^ y) * 4) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let direct = image.unsharp_mask_ref(1.5, 1.0, 0)
+            .and_then(|img| img.enhance_edges(0.5, EdgeMethod::Sobel))
+            .and_then(|img| img.unsharp_mask(0.8, 0.6, 2))
+            .and_then(|img| img.high_pass_sharpen(0.4))
+            .unwrap();
+        let pooled = image.sharpen()
+            .unsharp_mask(1.5, 1.0, 0)
+            .edge_enhance(0.5, EdgeMethod::Sobel)
+            .unsharp_mask(0.8, 0.6, 2)
+            .high_pass(0.4)
+            .apply()
+            .unwrap();
+        assert_eq!(pooled.into_rgb(), direct.into_rgb());
+    }
+    
+    #[test]
+    fn test_builder_opacity() {
+        let mut rgb = RgbImage::new(64, 64);
+        for (x, y, pixel) in rgb.enumerate_pixels_mut() {
+            let value = if (x / 8 + y / 8) % 2 == 0 { 80 } else { 170 };
+            *pixel = image::Rgb([value, value, value]);
+        }
+        let original = Image::from_rgb(rgb).unwrap();
+        
+        let full = original.clone().sharpen().unsharp_mask(2.0, 2.0, 0).apply().unwrap();
+        let half = original.clone().sharpen().unsharp_mask(2.0, 2.0, 0).opacity(0.5).apply().unwrap();
+        let none = original.clone().sharpen().unsharp_mask(2.0, 2.0, 0).opacity(0.0).apply().unwrap();
+        
+        let diff = |a: &Image, b: &Image| -> u64 {
+            a.data.get_ref().pixels().zip(b.data.get_ref().pixels())
+                .map(|(p, q)| (p[0] as i64 - q[0] as i64).unsigned_abs())
+                .sum()
+        };
+        
+        assert_eq!(diff(&none, &original), 0);
+        let half_diff = diff(&half, &original);
+        let full_diff = diff(&full, &original);
+        assert!(half_diff > 0 && half_diff < full_diff);
+    }
+    
+    #[test]
+    fn test_builder_opacity_out_of_range() {
+        let img = create_test_image();
+        assert!(img.sharpen().high_pass(0.5).opacity(1.5).apply().is_err());
+    }
+    
+    #[test]
+    fn test_builder_editing() {
+        let mut builder = create_test_image().sharpen()
+            .unsharp_mask(1.0, 1.0, 0)
+            .high_pass(0.5)
+            .opacity(0.5);
+        
+        builder.insert(0, Operation::Clarity { strength: 0.3, radius: 2.0 }).unwrap();
+        assert_eq!(builder.remove(1).unwrap().name(), "Unsharp Mask");
+        
+        let previous = builder.replace(1, Operation::HighPassSharpen { strength: 0.2 }).unwrap();
+        assert_eq!(previous.into_operation(), Some(Operation::HighPassSharpen { strength: 0.5 }));
+        assert_eq!(builder.steps[1].opacity, 0.5);
+        
+        builder.move_operation(1, 0).unwrap();
+        let operations: Vec<_> = builder.operations().filter_map(QueuedStep::operation).cloned().collect();
+        assert_eq!(operations, vec![
+            Operation::HighPassSharpen { strength: 0.2 },
+            Operation::Clarity { strength: 0.3, radius: 2.0 },
+        ]);
+        assert_eq!(builder.steps[0].opacity, 0.5);
+        assert!(builder.apply().is_ok());
+    }
+    
+    #[test]
+    fn test_builder_editing_out_of_range() {
+        let mut builder = create_test_image().sharpen().high_pass(0.5);
+        assert!(builder.insert(2, Operation::HighPassSharpen { strength: 0.1 }).is_err());
+        assert!(builder.insert(1, Operation::HighPassSharpen { strength: 0.1 }).is_ok());
+        assert!(builder.remove(2).is_err());
+        assert!(builder.replace(5, Operation::HighPassSharpen { strength: 0.1 }).is_err());
+        assert!(builder.move_operation(0, 2).is_err());
+        assert_eq!(builder.operation_count(), 2);
+    }
+    
+    #[test]
+    fn test_validate_reports_all() {
+        let builder = create_test_image().sharpen()
+            .unsharp_mask(1.0, 1.0, 0)
+            .clarity(4.0, 30.0)
+            .high_pass(0.5)
+            .opacity(2.0);
+        
+        match builder.validate() {
+            Err(ImageError::Validation(violations)) => {
+                let found: Vec<_> = violations.iter()
+                    .map(|v| (v.index, v.param.as_str()))
+                    .collect();
+                assert_eq!(found, vec![(1, "strength"), (1, "radius"), (2, "opacity")]);
+            }
+            other => panic!("expected validation error, got {:?}", other),
+        }
+        assert!(builder.apply().is_err());
+        assert!(create_test_image().sharpen().high_pass(0.5).validate().is_ok());
+    }
+    
+    #[test]
+    fn test_validation_policy() {
+        let builder = create_test_image().sharpen()
+            .unsharp_mask(1.0, 7.0, 0)
+            .high_pass(0.5)
+            .clarity(0.3, 2.0)
+            .opacity(1.5);
+        assert!(builder.validate().is_err());
+        let clamps: Vec<_> = builder.clamps().iter().map(|c| (c.index, c.param.clone(), c.clamped.clone())).collect();
+        assert_eq!(clamps, vec![(0, "amount".to_string(), "5".to_string()), (2, "opacity".to_string(), "1".to_string())]);
+        
+        let builder = builder.validation_policy(ValidationPolicy::Clamp);
+        assert!(builder.validate().is_ok());
+        let clamped = builder.apply().unwrap();
+        let expected = create_test_image().sharpen().unsharp_mask(1.0, 5.0, 0).high_pass(0.5).clarity(0.3, 2.0).apply().unwrap();
+        assert!(clamped.into_rgb() == expected.into_rgb());
+        
+        // Clamping doesn't touch a gamma
+        let gamma = create_test_image().sharpen().high_pass(0.5).linearize(Transfer::Gamma(20.0));
+        assert!(gamma.validation_policy(ValidationPolicy::Clamp).apply().is_err());
+        
+        // Unbounded maxima stop at the ceiling instead of sizing a huge kernel
+        let unbounded = create_test_image().with_validation(crate::ValidationConfig::default().max_radius(f32::INFINITY));
+        let builder = unbounded.sharpen().unsharp_mask(1e30, 1.0, 0).validation_policy(ValidationPolicy::Clamp);
+        assert_eq!(builder.clamps()[0].clamped, crate::ValidationConfig::CEILING.max_radius.to_string());
+        assert!(builder.apply().is_ok());
+    }
+    
+    #[test]
+    fn test_validate_extended_ranges() {
+        let image = create_test_image().with_validation(crate::ValidationConfig::extended());
+        let builder = image.sharpen().unsharp_mask(1.0, 12.0, 0).clarity(4.0, 30.0).opacity(2.0);
+        match builder.validate() {
+            Err(ImageError::Validation(violations)) => {
+                assert_eq!(violations.iter().map(|v| v.param.as_str()).collect::<Vec<_>>(), vec!["opacity"]);
+            }
+            other => panic!("expected validation error, got {:?}", other),
+        }
+        let sharpened = builder.opacity(1.0).apply().unwrap();
+        assert_eq!(sharpened.validation(), crate::ValidationConfig::extended());
+    }
+    
+    #[test]
+    fn test_preview() {
+        let builder = create_test_image().sharpen().unsharp_mask(2.0, 1.0, 0).opacity(0.5);
+        
+        let preview = builder.preview(40).unwrap();
+        assert_eq!(preview.dimensions(), (40, 40));
+        
+        // Already small enough: same as applying at full size
+        let full = builder.preview(500).unwrap();
+        assert_eq!(full.dimensions(), (100, 100));
+        assert_eq!(builder.operation_count(), 1);
+        
+        assert!(builder.preview(0).is_err());
+        assert!(create_test_image().sharpen().high_pass(5.0).preview(40).is_err());
+    }
+    
+    #[test]
+    fn test_apply_ref_matches_apply() {
+        let original = create_test_image();
+        let builder = original.clone().sharpen().unsharp_mask(1.0, 1.0, 0).high_pass(0.3).opacity(0.5);
+        
+        let by_ref = builder.apply_ref(&original).unwrap().into_rgb();
+        let by_value = builder.apply().unwrap().into_rgb();
+        assert_eq!(by_ref, by_value);
+        assert_eq!(original.dimensions(), (100, 100));
+    }
+    
+    #[test]
+    fn test_region_matches_full_image() {
+        let rgb = RgbImage::from_fn(96, 80, |x, y| image::Rgb([(x * 5) as u8, (y * 6) as u8, ((x ^ y) * 4) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let builder = image.clone().sharpen().unsharp_mask(2.0, 1.0, 0).edge_enhance(0.5, EdgeMethod::Sobel).clarity(0.5, 3.0);
+        let full = builder.apply_ref(&image).unwrap().into_rgb();
+        
+        let rect = Rect::new(30, 20, 24, 16);
+        let spot = builder.region(rect).apply().unwrap().into_rgb();
+        for (x, y, pixel) in spot.enumerate_pixels() {
+            let inside = (30..54).contains(&x) && (20..36).contains(&y);
+            let expected = if inside { full.get_pixel(x, y) } else { image.as_rgb().get_pixel(x, y) };
+            assert_eq!(pixel, expected, "({}, {})", x, y);
+        }
+        
+        let outside = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).region(Rect::new(90, 0, 10, 10));
+        assert!(outside.apply().is_err());
+    }
+    
+    #[test]
+    fn test_amount_map() {
+        let rgb = RgbImage::from_fn(96, 80, |x, y| image::Rgb([(x * 5) as u8, (y * 6) as u8, ((x ^ y) * 4) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let amounts = GrayImage::from_fn(96, 80, |x, y| image::Luma([((x + y) * 2) as u8]));
+        let builder = image.clone().sharpen().clarity(0.3, 2.0).unsharp_mask_with_amount_map(1.5, amounts.clone(), 0);
+        let full = builder.apply_ref(&image).unwrap();
+        assert!(full.as_rgb() != image.as_rgb());
+        
+        // Regions crop the map along with the image
+        let rect = Rect::new(30, 20, 24, 16);
+        let spot = builder.region(rect).apply().unwrap().into_rgb();
+        for (x, y, pixel) in spot.enumerate_pixels() {
+            let inside = (30..54).contains(&x) && (20..36).contains(&y);
+            let expected = if inside { full.as_rgb().get_pixel(x, y) } else { image.as_rgb().get_pixel(x, y) };
+            assert_eq!(pixel, expected, "({}, {})", x, y);
+        }
+        
+        // Previews scale it
+        let preview = image.clone().sharpen().unsharp_mask_with_amount_map(1.5, amounts.clone(), 0).preview(48).unwrap();
+        assert_eq!(preview.dimensions(), (48, 40));
+        
+        let mut builder = image.clone().sharpen().unsharp_mask_with_amount_map(20.0, amounts, 0);
+        let Err(ImageError::Validation(violations)) = builder.validate() else { panic!("radius 20 accepted") };
+        assert_eq!((violations[0].operation, violations[0].param.as_str()), ("Amount Map Unsharp", "radius"));
+        assert!(matches!(builder.remove(0).unwrap(), RemovedStep::AmountMap { radius, .. } if radius == 20.0));
+        assert_eq!(builder.operation_count(), 0);
+        let mismatched = image.sharpen().unsharp_mask_with_amount_map(1.0, GrayImage::new(10, 10), 0);
+        assert!(matches!(mismatched.apply(), Err(ImageError::InvalidDimensions { width: 10, height: 10 })));
+    }
+    
+    #[test]
+    fn test_mask() {
+        let rgb = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 3) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let builder = image.clone().sharpen().unsharp_mask(1.5, 2.0, 0);
+        let full = builder.apply_ref(&image).unwrap().into_rgb();
+        
+        // Left half processed, right half untouched
+        let mask = GrayImage::from_fn(64, 48, |x, _| image::Luma([if x < 32 { 255 } else { 0 }]));
+        let masked = builder.mask(mask).apply().unwrap().into_rgb();
+        for (x, y, pixel) in masked.enumerate_pixels() {
+            let expected = if x < 32 { full.get_pixel(x, y) } else { image.as_rgb().get_pixel(x, y) };
+            assert_eq!(pixel, expected, "({}, {})", x, y);
+        }
+        
+        let mismatched = image.sharpen().unsharp_mask(1.0, 1.0, 0).mask(GrayImage::new(32, 48));
+        assert!(mismatched.apply().is_err());
+    }
+    
+    /// Custom step darkening every sample by `amount`.
+    struct Darken {
+        amount: u8,
+    }
+    
+    impl PipelineOp for Darken {
+        fn name(&self) -> &'static str {
+            "Darken"
+        }
+        
+        fn apply(&self, image: Image) -> Result<Image> {
+            let mut rgb = image.into_rgb();
+            rgb.pixels_mut().flat_map(|pixel| pixel.0.iter_mut()).for_each(|value| *value = value.saturating_sub(self.amount));
+            Image::from_rgb(rgb)
+        }
+        
+        fn validate(&self) -> Result<()> {
+            if self.amount > 128 {
+                return Err(ImageError::InvalidParameter { param: "amount".to_string(), value: self.amount.to_string() });
+            }
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_custom_steps() {
+        let rgb = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 3 + 20) as u8, (y * 4 + 20) as u8, ((x ^ y) * 3 + 20) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let darken = Darken { amount: 20 };
+        let expected = darken.apply(image.clone().unsharp_mask(1.0, 1.0, 0).unwrap()).unwrap().clarity(0.3, 2.0).unwrap().into_rgb();
+        let result = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).custom(Darken { amount: 20 }).clarity(0.3, 2.0).apply().unwrap();
+        assert!(result.into_rgb() == expected);
+        
+        // Boxed steps, and operations passed as steps, run the same way
+        let boxed: Box<dyn PipelineOp> = Box::new(Darken { amount: 20 });
+        let result = image.clone().sharpen().custom(Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 }).custom(boxed).clarity(0.3, 2.0).apply().unwrap();
+        assert!(result.into_rgb() == expected);
+        
+        // Opacity applies to custom steps (no sample darkens below zero here)
+        let half = image.clone().sharpen().custom(Darken { amount: 20 }).opacity(0.5).apply().unwrap();
+        assert!(half.into_rgb() == Darken { amount: 10 }.apply(image.clone()).unwrap().into_rgb());
+        
+        let builder = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).custom(Darken { amount: 200 });
+        match builder.validate() {
+            Err(ImageError::Validation(violations)) => assert_eq!(violations[0].to_string(), "step 1 (Darken): amount = 200"),
+            _ => panic!("expected validation error"),
+        }
+        
+        let mut builder = image.clone().sharpen().custom(Darken { amount: 20 }).high_pass(0.5);
+        let steps: Vec<_> = builder.operations().collect();
+        assert_eq!(steps, [QueuedStep::Custom { name: "Darken" }, QueuedStep::Operation(&Operation::HighPassSharpen { strength: 0.5 })]);
+        // Custom steps can be taken out like any other
+        let mut replaced = image.clone().sharpen().custom(Darken { amount: 20 }).high_pass(0.5);
+        let previous = replaced.replace(0, Operation::HighPassSharpen { strength: 0.1 }).unwrap();
+        assert!(matches!(previous, RemovedStep::Custom(ref op) if op.name() == "Darken"));
+        assert_eq!(replaced.operations().next(), Some(QueuedStep::Operation(&Operation::HighPassSharpen { strength: 0.1 })));
+        let removed = builder.remove(0).unwrap();
+        assert_eq!(removed.name(), "Darken");
+        assert_eq!(removed.into_operation(), None);
+        assert_eq!(builder.operations().collect::<Vec<_>>(), [QueuedStep::Operation(&Operation::HighPassSharpen { strength: 0.5 })]);
+        assert!(builder.apply().unwrap().into_rgb() == image.clone().high_pass_sharpen(0.5).unwrap().into_rgb());
+        
+        struct Crop;
+        impl PipelineOp for Crop {
+            fn name(&self) -> &'static str {
+                "Crop"
+            }
+            fn apply(&self, image: Image) -> Result<Image> {
+                Image::from_rgb(imageops::crop_imm(&image.into_rgb(), 0, 0, 10, 10).to_image())
+            }
+        }
+        let error = image.sharpen().custom(Crop).apply().err().unwrap();
+        assert_eq!(error.operation(), Some("Crop"));
+    }
+    
+    #[test]
+    fn test_blend_mode() {
+        struct Detail;
+        impl PipelineOp for Detail {
+            fn name(&self) -> &'static str {
+                "Detail"
+            }
+            fn apply(&self, image: Image) -> Result<Image> {
+                image.high_pass_layer(2.0)
+            }
+        }
+        let rgb = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 3 + 20) as u8, (y * 4 + 20) as u8, if (x / 4 + y / 4) % 2 == 0 { 90 } else { 150 }]));
+        let image = Image::from_rgb(rgb).unwrap();
+        
+        // A detail layer in linear light adds twice the detail: unsharp
+        // masking with amount 2
+        let expected = image.clone().unsharp_mask(2.0, 2.0, 0).unwrap().into_rgb();
+        let result = image.clone().sharpen().custom(Detail).blend_mode(BlendMode::LinearLight).apply().unwrap().into_rgb();
+        for (a, b) in result.pixels().zip(expected.pixels()) {
+            assert!((0..3).all(|c| a[c].abs_diff(b[c]) <= 2), "{:?} vs {:?}", a, b);
+        }
+        
+        // The mode blends at the step's opacity
+        let layer = image.high_pass_layer(2.0).unwrap();
+        let expected = crate::blend::blend(&image, &layer, BlendMode::Overlay, 0.6).unwrap().into_rgb();
+        let result = image.sharpen().custom(Detail).blend_mode(BlendMode::Overlay).opacity(0.6).apply().unwrap();
+        assert!(result.into_rgb() == expected);
+    }
+    
+    #[test]
+    fn test_apply_with_report() {
+        let rgb = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 3) as u8]));
+        let image = Image::from_rgb(rgb).unwrap();
+        let (_, warnings) = image.clone().sharpen().unsharp_mask(1.0, 0.5, 0).apply_with_report().unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        
+        // A half-size mask is scaled up like a full-size one of the same shape
+        let half = GrayImage::from_fn(32, 24, |x, _| image::Luma([if x < 16 { 255 } else { 0 }]));
+        let full = imageops::resize(&half, 64, 48, FilterType::Triangle);
+        let builder = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0);
+        let expected = builder.apply_ref(&image).unwrap();
+        let (masked, warnings) = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).mask(half).apply_with_report().unwrap();
+        assert_eq!(warnings, vec![Warning::MaskResized { from: (32, 24), to: (64, 48) }]);
+        let expected = image.blend_masked(expected, &full).unwrap();
+        assert!(masked.into_rgb() == expected.into_rgb());
+        
+        let (_, warnings) = image.clone().sharpen()
+            .unsharp_mask(1.0, 5.0, 0)
+            .clarity(0.5, 30.0)
+            .validation_policy(ValidationPolicy::Clamp)
+            .apply_with_report()
+            .unwrap();
+        assert!(matches!(&warnings[0], Warning::Clamped(clamp) if clamp.param == "radius"));
+        assert!(matches!(warnings[1], Warning::Clipping { fraction } if fraction > 0.01));
+        assert_eq!(warnings.len(), 2);
+        
+        let small = Image::from_rgb(RgbImage::from_pixel(30, 20, image::Rgb([90, 120, 150]))).unwrap();
+        let (_, warnings) = small.sharpen().unsharp_mask(8.0, 0.5, 0).opacity(0.5).apply_with_report().unwrap();
+        assert_eq!(warnings, vec![Warning::ReachExceedsImage { index: 0, operation: "Unsharp Mask", reach: 24, size: 20 }]);
+    }
+    
+    #[test]
+    fn test_tileable() {
+        let texture = RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([if (x / 6 + y / 4) % 2 == 0 { 70 } else { 180 }, (x * 5) as u8 % 64, 90])
+        });
+        // Shifting the texture by part of a tile must shift the result alike
+        let shift = |image: &RgbImage| RgbImage::from_fn(48, 32, |x, y| *image.get_pixel((x + 20) % 48, (y + 7) % 32));
+        let sharpen = |image: RgbImage| {
+            Image::from_rgb(image).unwrap().sharpen()
+                .unsharp_mask(2.0, 1.0, 0)
+                .edge_enhance(0.5, EdgeMethod::Sobel)
+                .clarity(0.5, 3.0)
+                .tileable(true)
+                .apply()
+                .unwrap()
+                .into_rgb()
+        };
+        assert_eq!(sharpen(shift(&texture)), shift(&sharpen(texture.clone())));
+        // The structure weights wrap around too, seeing the edge between
+        // the halves that meet across the sides
+        let halves = RgbImage::from_fn(48, 32, |x, _| image::Rgb([if x < 24 { 70 } else { 180 }; 3]));
+        let adaptive = |image: RgbImage| {
+            Image::from_rgb(image).unwrap().sharpen().unsharp_mask(2.0, 1.0, 0).adaptive(true).tileable(true).apply().unwrap().into_rgb()
+        };
+        assert_eq!(adaptive(shift(&halves)), shift(&adaptive(halves)));
+        
+        // Sharpening only the left edge breaks the seam with the right edge
+        let region = Image::from_rgb(texture).unwrap().sharpen()
+            .unsharp_mask(2.0, 3.0, 0)
+            .region(Rect::new(0, 0, 4, 32))
+            .tileable(true);
+        assert!(matches!(region.apply(), Err(ImageError::Seam { .. })));
+    }
+    
+    #[test]
+    fn test_soft_clip() {
+        // Bright specular spots of different levels on a midtone ramp
+        let scene = RgbImage::from_fn(48, 32, |x, y| {
+            let spot = (x % 12 == 6 && y % 8 == 4).then_some(150 + (x / 12) as u8 * 20);
+            let value = spot.unwrap_or(60 + x as u8);
+            image::Rgb([value, value, value])
+        });
+        let sharpen = |knee: Option<f32>| {
+            let builder = Image::from_rgb(scene.clone()).unwrap().sharpen().unsharp_mask(1.0, 2.0, 0);
+            match knee {
+                Some(knee) => builder.soft_clip(knee),
+                None => builder,
+            }
+            .apply()
+            .unwrap()
+            .into_rgb()
+        };
+        let (hard, soft) = (sharpen(None), sharpen(Some(0.4)));
+        let spots: Vec<(u8, u8)> = (0..4).map(|i| (hard.get_pixel(i * 12 + 6, 4)[0], soft.get_pixel(i * 12 + 6, 4)[0])).collect();
+        // Hard clipping flattens every spot to white; soft clipping keeps
+        // them apart, below white
+        assert!(spots.iter().all(|&(hard, _)| hard == 255));
+        assert!(spots.windows(2).all(|pair| pair[0].1 < pair[1].1) && spots[3].1 < 255);
+        // Changes well within the headroom are left alone
+        assert_eq!(soft.get_pixel(20, 20), hard.get_pixel(20, 20));
+        
+        let invalid = Image::from_rgb(scene).unwrap().sharpen().unsharp_mask(1.0, 1.0, 0).soft_clip(0.0);
+        assert!(matches!(invalid.apply(), Err(ImageError::InvalidParameter { ref param, .. }) if param == "soft_clip"));
+    }
+    
+    #[test]
+    fn test_progress_events() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        
+        create_test_image().sharpen()
+            .unsharp_mask(1.0, 1.0, 0)
+            .clarity(0.5, 2.0)
+            .on_progress(move |event| sink.lock().unwrap().push(event))
+            .apply()
+            .unwrap();
+        
+        let events = events.lock().unwrap();
+        assert_eq!(events.first(), Some(&ProgressEvent::Started { index: 0, count: 2, operation: "Unsharp Mask" }));
+        assert_eq!(events.last(), Some(&ProgressEvent::Finished { index: 1, count: 2, operation: "Clarity" }));
+        for index in 0..2 {
+            let percents: Vec<u8> = events.iter()
+                .filter_map(|event| match *event {
+                    ProgressEvent::Progress { index: i, percent, .. } if i == index => Some(percent),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(percents.iter().max(), Some(&100));
+        }
+    }
+    
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_thread_pool() {
+        let original = create_test_image();
+        let expected = original.clone().sharpen().unsharp_mask(1.0, 1.0, 0).apply().unwrap().into_rgb();
+        
+        let limited = original.clone().sharpen().unsharp_mask(1.0, 1.0, 0).threads(1).apply().unwrap();
+        assert_eq!(limited.into_rgb(), expected);
+        
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let pooled = original.clone().sharpen()
+            .unsharp_mask(1.0, 1.0, 0)
+            .on_progress(|_| assert!(rayon::current_num_threads() == 2))
+            .thread_pool(pool)
+            .apply()
+            .unwrap();
+        assert_eq!(pooled.into_rgb(), expected);
+        
+        // One pool runs the whole apply, custom and built-in steps alike
+        let threads = Arc::new(std::sync::Mutex::new(std::collections::HashSet::new()));
+        let seen = Arc::clone(&threads);
+        original.clone().sharpen()
+            .custom(Darken { amount: 1 })
+            .unsharp_mask(1.0, 1.0, 0)
+            .custom(Darken { amount: 1 })
+            .on_progress(move |_| {
+                assert_eq!(rayon::current_num_threads(), 1);
+                seen.lock().unwrap().insert(std::thread::current().id());
+            })
+            .threads(1)
+            .apply()
+            .unwrap();
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert!(!threads.contains(&std::thread::current().id()));
+        
+        let builder = original.sharpen().high_pass(0.5).threads(0);
+        assert!(matches!(builder.validate(), Err(ImageError::InvalidParameter { param, .. }) if param == "threads"));
+        assert!(builder.apply().is_err());
+    }
+    
     #[test]
     fn test_presets() {
         let img = create_test_image();
@@ -176,6 +2357,9 @@ mod tests {
         assert!(SharpeningPresets::strong(img.clone()).apply().is_ok());
         assert!(SharpeningPresets::edge_aware(img.clone()).apply().is_ok());
         assert!(SharpeningPresets::portrait(img.clone()).apply().is_ok());
-        assert!(SharpeningPresets::landscape(img).apply().is_ok());
+        assert!(SharpeningPresets::landscape(img.clone()).apply().is_ok());
+        assert!(SharpeningPresets::document(img.clone()).apply().is_ok());
+        assert!(SharpeningPresets::astro(img.clone()).apply().is_ok());
+        assert!(SharpeningPresets::portrait_fs(img).apply().is_ok());
     }
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+//! Blur results shared by the operations of one pipeline run.
+//!
+//! Operations and graph nodes that blur the same source at the same radius
+//! get the first result instead of recomputing it.
+
+use crate::pixel::{Buffer, SharpenPixel};
+use crate::pool::BufferPool;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+/// Blur algorithms whose results are cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum BlurKind {
+    Gaussian,
+}
+
+/// Identifies a buffer by its samples' address and dimensions, for as long
+/// as the buffer lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Source {
+    address: usize,
+    width: u32,
+    height: u32,
+}
+
+impl Source {
+    pub(crate) fn of<P: SharpenPixel>(buffer: &Buffer<P>) -> Self {
+        Self { address: buffer.as_ptr() as usize, width: buffer.width(), height: buffer.height() }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    source: Source,
+    radius: u32,
+    kind: BlurKind,
+    pixel: TypeId,
+}
+
+/// A blur, computed by the first thread to ask for it.
+type Entry = Arc<OnceLock<Arc<dyn Any + Send + Sync>>>;
+
+/// Cache of blurred images keyed by source buffer, radius and algorithm.
+/// 
+/// Entries are keyed by the address of the source, so its owner must
+/// [`evict`](BlurCache::evict) them before the source is dropped or
+/// recycled and another buffer can take its place.
+#[derive(Default)]
+pub(crate) struct BlurCache {
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl BlurCache {
+    /// Returns the cached blur of `source`, computing it with `blur` on a
+    /// miss. Threads asking for the same blur meanwhile wait for it
+    /// instead of computing it again.
+    pub(crate) fn get_or_insert<P: SharpenPixel>(
+        &self,
+        source: Source,
+        radius: f32,
+        kind: BlurKind,
+        blur: impl FnOnce() -> Buffer<P>,
+    ) -> Arc<Buffer<P>> {
+        let key = Key { source, radius: radius.to_bits(), kind, pixel: TypeId::of::<P>() };
+        let entry = Arc::clone(self.lock().entry(key).or_default());
+        // Blur outside the map's lock so other keys keep using the cache
+        let blurred = entry.get_or_init(|| Arc::new(blur()));
+        Arc::clone(blurred).downcast().expect("cache entries match their key's pixel type")
+    }
+
+    /// Drops the blurs of `source`, returning those no longer in use to
+    /// `pool` if given.
+    pub(crate) fn evict<P: SharpenPixel>(&self, source: &Buffer<P>, pool: Option<&BufferPool>) {
+        let source = Source::of(source);
+        let evicted: Vec<Entry> = {
+            let mut entries = self.lock();
+            let keys: Vec<Key> = entries.keys().filter(|key| key.source == source).copied().collect();
+            keys.iter().filter_map(|key| entries.remove(key)).collect()
+        };
+
+        let Some(pool) = pool else { return };
+        for entry in evicted {
+            let blurred = Arc::try_unwrap(entry).ok()
+                .and_then(OnceLock::into_inner)
+                .and_then(|blurred| blurred.downcast::<Buffer<P>>().ok())
+                .and_then(|blurred| Arc::try_unwrap(blurred).ok());
+            if let Some(blurred) = blurred {
+                pool.recycle(blurred);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Key, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+
+    #[test]
+    fn test_blur_computed_once_per_key() {
+        let cache = BlurCache::default();
+        let (a, b) = (Buffer::<Rgb<u8>>::new(4, 4), Buffer::<Rgb<u8>>::new(4, 4));
+        let calls = Cell::new(0);
+        let blur = || {
+            calls.set(calls.get() + 1);
+            Buffer::<Rgb<u8>>::new(4, 4)
+        };
+
+        let first = cache.get_or_insert(Source::of(&a), 1.5, BlurKind::Gaussian, blur);
+        let second = cache.get_or_insert(Source::of(&a), 1.5, BlurKind::Gaussian, blur);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(calls.get(), 1);
+
+        cache.get_or_insert(Source::of(&a), 2.0, BlurKind::Gaussian, blur);
+        cache.get_or_insert(Source::of(&b), 1.5, BlurKind::Gaussian, blur);
+        cache.get_or_insert(Source::of(&a), 1.5, BlurKind::Gaussian, || Buffer::<Luma<u8>>::new(4, 4));
+        assert_eq!(calls.get(), 3);
+
+        cache.evict(&a, None);
+        cache.get_or_insert(Source::of(&a), 1.5, BlurKind::Gaussian, blur);
+        cache.get_or_insert(Source::of(&b), 1.5, BlurKind::Gaussian, blur);
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn test_concurrent_requests_blur_once() {
+        let cache = BlurCache::default();
+        let source = Buffer::<Rgb<u8>>::new(4, 4);
+        let calls = AtomicUsize::new(0);
+        let barrier = Barrier::new(4);
+
+        let results: Vec<Arc<Buffer<Rgb<u8>>>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|_| scope.spawn(|| {
+                barrier.wait();
+                cache.get_or_insert(Source::of(&source), 1.5, BlurKind::Gaussian, || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    Buffer::<Rgb<u8>>::new(4, 4)
+                })
+            })).collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        });
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|result| Arc::ptr_eq(result, &results[0])));
+    }
+
+    #[test]
+    fn test_evicted_blurs_recycled() {
+        let cache = BlurCache::default();
+        let pool = BufferPool::default();
+        let source = Buffer::<Rgb<u8>>::new(4, 4);
+        let address = cache.get_or_insert(Source::of(&source), 1.5, BlurKind::Gaussian, || Buffer::<Rgb<u8>>::new(4, 4)).as_ptr();
+
+        cache.evict(&source, Some(&pool));
+        assert_eq!(pool.take::<Rgb<u8>>(4, 4).as_ptr(), address);
+
+        // Blurs still in use stay with their users
+        let held = cache.get_or_insert(Source::of(&source), 1.5, BlurKind::Gaussian, || Buffer::<Rgb<u8>>::new(4, 4));
+        cache.evict(&source, Some(&pool));
+        assert_ne!(pool.take::<Rgb<u8>>(4, 4).as_ptr(), held.as_ptr());
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-call processing state shared by the sharpening kernels.
+
+use crate::cache::{BlurCache, BlurKind, Source};
+use crate::pixel::{Buffer, SharpenPixel, max_value, to_sample};
+use crate::pool::BufferPool;
+use crate::utils::{gaussian_blur, soft_clip, Boundary, Luminance};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Progress reported while a builder applies its operations.
+///
+/// `index` is the position of the operation in the pipeline and `count` the
+/// number of operations. Events may arrive from worker threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// An operation is about to run
+    Started { index: usize, count: usize, operation: &'static str },
+    /// Percentage (0-100) of the running operation's rows processed
+    Progress { index: usize, count: usize, percent: u8 },
+    /// An operation has completed
+    Finished { index: usize, count: usize, operation: &'static str },
+}
+
+/// Callback receiving [`ProgressEvent`]s.
+pub(crate) type ProgressFn = dyn Fn(ProgressEvent) + Send + Sync;
+
+/// Execution context handed to each sharpening kernel.
+///
+/// The default context does nothing and allocates buffers directly, so
+/// direct `Image` calls pay no cost.
+#[derive(Default)]
+pub(crate) struct Context<'a> {
+    progress: Option<RowProgress<'a>>,
+    pool: Option<&'a BufferPool>,
+    /// Blur cache and the input image, whose blurs it shares
+    blurs: Option<(&'a BlurCache, Source)>,
+    boundary: Boundary,
+    luminance: Luminance,
+    /// Knee of the soft clip applied to sharpened samples, if any
+    soft_clip: Option<f32>,
+}
+
+struct RowProgress<'a> {
+    callback: &'a ProgressFn,
+    index: usize,
+    count: usize,
+    total: AtomicUsize,
+    done: AtomicUsize,
+    percent: AtomicU8,
+}
+
+impl<'a> Context<'a> {
+    /// Creates a context reporting row progress for operation `index` of `count`.
+    pub(crate) fn with_progress(callback: &'a ProgressFn, index: usize, count: usize) -> Self {
+        Self {
+            progress: Some(RowProgress {
+                callback,
+                index,
+                count,
+                total: AtomicUsize::new(0),
+                done: AtomicUsize::new(0),
+                percent: AtomicU8::new(0),
+            }),
+            pool: None,
+            blurs: None,
+            boundary: Boundary::default(),
+            luminance: Luminance::default(),
+            soft_clip: None,
+        }
+    }
+
+    /// Draws scratch and output buffers from `pool`.
+    pub(crate) fn with_pool(mut self, pool: &'a BufferPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Shares blurs of `input` through `cache`. The caller evicts them
+    /// before `input` goes away.
+    pub(crate) fn with_blur_cache<P: SharpenPixel>(mut self, cache: &'a BlurCache, input: &Buffer<P>) -> Self {
+        self.blurs = Some((cache, Source::of(input)));
+        self
+    }
+
+    /// Sets how kernels read beyond the image's edges.
+    pub(crate) fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub(crate) fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    /// Sets the weights kernels use for luminance.
+    pub(crate) fn with_luminance(mut self, luminance: Luminance) -> Self {
+        self.luminance = luminance;
+        self
+    }
+
+    pub(crate) fn luminance(&self) -> Luminance {
+        self.luminance
+    }
+
+    /// Rolls sharpened samples off towards black and white within `knee`
+    /// of their headroom instead of clipping them, see [`Context::store`].
+    pub(crate) fn with_soft_clip(mut self, knee: Option<f32>) -> Self {
+        self.soft_clip = knee;
+        self
+    }
+
+    /// Converts `value`, sharpened from the sample `original`, back to a
+    /// sample: clipped, or soft-clipped if a knee is set.
+    pub(crate) fn store<P: SharpenPixel>(&self, original: f32, value: f32) -> P::Subpixel {
+        match self.soft_clip {
+            Some(knee) => to_sample::<P>(soft_clip(original, value, knee, max_value::<P>())),
+            None => to_sample::<P>(value),
+        }
+    }
+
+    /// Gaussian blur of `img`, reusing an earlier blur at the same radius
+    /// when `img` is the input of a cache.
+    pub(crate) fn gaussian_blur<P: SharpenPixel>(&self, img: &Buffer<P>, radius: f32) -> Arc<Buffer<P>> {
+        match self.blurs {
+            Some((cache, source)) if source == Source::of(img) => {
+                cache.get_or_insert(source, radius, BlurKind::Gaussian, || gaussian_blur(img, radius, self))
+            }
+            _ => Arc::new(gaussian_blur(img, radius, self)),
+        }
+    }
+
+    /// Recycles a blur from [`Context::gaussian_blur`] unless it is cached.
+    pub(crate) fn release<P: SharpenPixel>(&self, blurred: Arc<Buffer<P>>) {
+        if let Ok(blurred) = Arc::try_unwrap(blurred) {
+            self.recycle(blurred);
+        }
+    }
+
+    /// Returns a zeroed buffer, from the pool if there is one.
+    pub(crate) fn buffer<P: SharpenPixel>(&self, width: u32, height: u32) -> Buffer<P> {
+        match self.pool {
+            Some(pool) => pool.take(width, height),
+            None => Buffer::<P>::new(width, height),
+        }
+    }
+
+    /// Hands a consumed temporary back for reuse.
+    pub(crate) fn recycle<P: SharpenPixel>(&self, buffer: Buffer<P>) {
+        if let Some(pool) = self.pool {
+            pool.recycle(buffer);
+        }
+    }
+
+    /// Declares how many rows (across all passes) the operation will process.
+    pub(crate) fn start(&self, total_rows: usize) {
+        if let Some(progress) = &self.progress {
+            progress.total.store(total_rows.max(1), Ordering::Relaxed);
+        }
+    }
+
+    /// Records `rows` more rows as processed, reporting whole-percent steps.
+    pub(crate) fn advance(&self, rows: usize) {
+        let Some(progress) = &self.progress else {
+            return;
+        };
+
+        let total = progress.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return;
+        }
+        let done = progress.done.fetch_add(rows, Ordering::Relaxed) + rows;
+        let percent = (done.min(total) * 100 / total) as u8;
+
+        // Only the thread that raises the percentage reports it, so each
+        // value is reported at most once
+        if progress.percent.fetch_max(percent, Ordering::Relaxed) < percent {
+            (progress.callback)(ProgressEvent::Progress {
+                index: progress.index,
+                count: progress.count,
+                percent,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_progress_reports_each_percent_once() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let callback = move |event: ProgressEvent| sink.lock().unwrap().push(event);
+        let ctx = Context::with_progress(&callback, 1, 3);
+
+        ctx.start(200);
+        for _ in 0..200 {
+            ctx.advance(1);
+        }
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 100);
+        assert_eq!(events.last(), Some(&ProgressEvent::Progress { index: 1, count: 3, percent: 100 }));
+    }
+}
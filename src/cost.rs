@@ -0,0 +1,251 @@
+//! Cost and memory estimates for operations, so schedulers can decide
+//! whether to process a job inline, queue it or split it up before
+//! running it.
+//!
+//! Estimates follow the kernels' loops and buffers and are meant for
+//! comparing jobs, not for predicting wall-clock time.
+
+use crate::pixel::SharpenPixel;
+use crate::{descreen, sharpening};
+use crate::{Operation, Pipeline};
+use image::Rgb;
+
+/// Estimated work and memory of running an operation or pipeline once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Floating-point operations over all pixels and color channels
+    pub flops: u64,
+    /// Largest number of bytes held at once by the input, output and
+    /// temporary buffers
+    pub peak_bytes: u64,
+    /// Full passes over the image
+    pub passes: u32,
+}
+
+/// Multiply-adds per sample of a 3x3 convolution.
+const KERNEL_3X3: u64 = 9 * 2;
+/// Flops of a luminance (three multiply-adds).
+const LUMINANCE: u64 = 5;
+
+impl Operation {
+    /// Estimates running this operation on an 8-bit RGB image of the given
+    /// size.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::Operation;
+    ///
+    /// let small = Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 };
+    /// let large = Operation::UnsharpMask { radius: 5.0, amount: 1.0, threshold: 0 };
+    /// let (cost, larger_cost) = (small.estimate(1920, 1080), large.estimate(1920, 1080));
+    /// assert_eq!(cost.passes, 3);
+    /// assert_eq!(cost.peak_bytes, larger_cost.peak_bytes);
+    /// assert!(larger_cost.flops > cost.flops);
+    /// ```
+    pub fn estimate(&self, width: u32, height: u32) -> CostEstimate {
+        self.estimate_for::<Rgb<u8>>(width, height)
+    }
+
+    /// Estimates running this operation on an image with pixels of type `P`.
+    pub(crate) fn estimate_for<P: SharpenPixel>(&self, width: u32, height: u32) -> CostEstimate {
+        let pixels = u64::from(width) * u64::from(height);
+        let samples = pixels * P::COLOR_CHANNELS as u64;
+        let image_bytes = pixels * P::CHANNEL_COUNT as u64 * std::mem::size_of::<P::Subpixel>() as u64;
+
+        // Copies alive at the peak include the input and the output, plus
+        // any f32 planes
+        let (flops, copies, passes, planes) = match *self {
+            Operation::UnsharpMask { radius, .. } => {
+                // See `utils::gaussian_blur`: two separable passes, then the
+                // combine pass; the blur's intermediate pass is alive with it
+                let kernel = ((radius * 6.0).ceil() as u64) | 1;
+                (samples * (2 * kernel * 2 + 4), 4, 3, 0)
+            }
+            Operation::RelativeUnsharp { radius, .. } => {
+                // The blur as for an unsharp mask, then a separable minimum
+                // and maximum over the radius per channel; each channel's
+                // contrast is an f32 plane, and the last takes four more
+                // while it's computed
+                let kernel = ((radius * 6.0).ceil() as u64) | 1;
+                let window = 2 * radius.ceil() as u64 + 1;
+                (samples * (2 * kernel * 2 + 2 * window * 2 + 4), 4, 4, P::COLOR_CHANNELS as u64 + 4)
+            }
+            // The convolution, then the blend with the original
+            Operation::HighPassSharpen { .. } => (samples * (KERNEL_3X3 + 3), 2, 2, 0),
+            // Two gradient convolutions, their magnitude, then the enhancement
+            Operation::EnhanceEdges { .. } => {
+                (samples * (2 * KERNEL_3X3 + 4) + pixels * (3 * LUMINANCE + 4), 4, 4, 0)
+            }
+            Operation::Clarity { radius, .. } => {
+                // The luminance of every pixel in the window, per pixel
+                let side = ((radius * 2.0).round() as u64) / 2 * 2 + 1;
+                (pixels * (side * side * (LUMINANCE + 1) + LUMINANCE) + samples * 2, 2, 1, 0)
+            }
+            Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold } => {
+                let radii = sharpening::scale_radii(min_radius, max_radius);
+                if radii.len() == 1 {
+                    return Operation::UnsharpMask { radius: min_radius, amount, threshold }.estimate_for::<P>(width, height);
+                }
+                // Every blur stays alive for the combine pass, which mixes
+                // all but the coarsest; the luminance of each blur and the
+                // pooled energy of each band between them are f32 planes
+                let bands = radii.len() as u64 - 1;
+                let blurs: u64 = radii.iter().map(|&radius| 2 * (((radius * 6.0).ceil() as u64) | 1) * 2).sum();
+                let pool = 2 * (2 * sharpening::scale_region_reach(max_radius) as u64 + 1) * 2;
+                let flops = samples * (blurs + bands * 2 + 4)
+                    + pixels * (radii.len() as u64 * LUMINANCE + bands * (3 + pool + 3));
+                (flops, radii.len() as u64 + 3, 2 * radii.len() as u32 + 2, radii.len() as u64 + bands)
+            }
+            Operation::TextSharpen { stroke_width, .. } => {
+                // The blur as for an unsharp mask, the luminance of the
+                // image and the blur, then a separable minimum and maximum
+                // over the stroke window; the luminance and both halves of
+                // the range are f32 planes, doubled during the range passes
+                let kernel = ((stroke_width * 6.0).ceil() as u64) | 1;
+                let window = 2 * stroke_width.ceil() as u64 + 1;
+                (samples * (2 * kernel * 2 + 2) + pixels * (2 * LUMINANCE + 2 * window * 2 + 8), 4, 4, 5)
+            }
+            // A weighted sum over the disc, bounded by its square, per
+            // sample; a detected period is assumed the largest, and
+            // detection itself doesn't grow with the image
+            Operation::Descreen { period } => {
+                let side = 2 * u64::from(descreen::reach(period)) + 1;
+                (samples * side * side * 2, 2, 1, 0)
+            }
+            // Gathering the window, then selecting its median in about
+            // linear time, per sample
+            Operation::Median { radius } => {
+                let side = 2 * u64::from(radius) + 1;
+                (samples * side * side * 3, 2, 1, 0)
+            }
+            // Per offset in the search window: the squared differences down
+            // a patch column and the running sum along the row, per sample,
+            // then the weight and the weighted sum; the image is copied to
+            // an f32 plane
+            Operation::Denoise { patch_radius, search_radius, .. } => {
+                let offsets = (2 * u64::from(search_radius) + 1).pow(2) - 1;
+                let column = 2 * u64::from(patch_radius) + 1;
+                (offsets * (samples * (column * 3 + 2) + pixels * 4), 2, 1, P::COLOR_CHANNELS as u64)
+            }
+            // The skin mask (chroma, structure and their blurs, about a
+            // structure mask's work on four planes), the tones blurred twice
+            // in sequence, then the combine pass; the tones and their blur
+            // are alive with the output, along with the mask's planes
+            Operation::FrequencySeparation { radius, .. } => {
+                let blur = |radius: f32| 2 * (((radius * 6.0).ceil() as u64) | 1) * 2;
+                let sigma = |sigma: f32| 2 * (2 * (sigma * 3.0).ceil() as u64 + 1) * 2;
+                let skin = pixels * (2 * LUMINANCE + 12 + 3 * sigma(2.0) + 2 * sigma(1.0) + 16);
+                let flops = samples * (blur(radius) + blur(radius * sharpening::SKIN_BLUR_FACTOR) + 6) + skin;
+                (flops, 5, 6, 4)
+            }
+            // Assuming the image needs denoising and the largest unsharp
+            // mask, plus the two analyses: the luminance, a 9-tap re-blur in
+            // either direction and the noise and Laplacian masks
+            Operation::SmartEnhance { .. } => {
+                let denoise = Operation::Denoise {
+                    strength: 1.0,
+                    patch_radius: sharpening::SMART_PATCH_RADIUS,
+                    search_radius: sharpening::SMART_SEARCH_RADIUS,
+                }
+                .estimate_for::<P>(width, height);
+                let sharpen = Operation::UnsharpMask { radius: sharpening::SMART_MAX_RADIUS, amount: 1.0, threshold: 0 }
+                    .estimate_for::<P>(width, height);
+                let analysis = 2 * pixels * (LUMINANCE + 2 * 9 * 2 + 2 * KERNEL_3X3);
+                return CostEstimate {
+                    flops: denoise.flops + sharpen.flops + analysis,
+                    peak_bytes: denoise.peak_bytes.max(sharpen.peak_bytes + image_bytes),
+                    passes: denoise.passes + sharpen.passes + 2,
+                };
+            }
+        };
+        let plane_bytes = pixels * std::mem::size_of::<f32>() as u64 * planes;
+        CostEstimate { flops, peak_bytes: image_bytes * copies + plane_bytes, passes }
+    }
+}
+
+impl Pipeline {
+    /// Estimates [`Pipeline::apply`] on an 8-bit RGB image of the given
+    /// size: work and passes add up, and the peak is the largest of any
+    /// operation as each releases its input.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::Pipeline;
+    ///
+    /// let pipeline = Pipeline::parse("unsharp:1:1:0,clarity:0.5:2").unwrap();
+    /// let cost = pipeline.estimate(4000, 3000);
+    /// assert_eq!(cost.passes, 4);
+    /// // Four 8-bit RGB copies at the unsharp mask's peak
+    /// assert_eq!(cost.peak_bytes, 4 * 4000 * 3000 * 3);
+    /// ```
+    pub fn estimate(&self, width: u32, height: u32) -> CostEstimate {
+        self.estimate_for::<Rgb<u8>>(width, height)
+    }
+
+    /// Estimates [`Pipeline::apply`] on an image with pixels of type `P`.
+    pub(crate) fn estimate_for<P: SharpenPixel>(&self, width: u32, height: u32) -> CostEstimate {
+        self.operations().iter()
+            .map(|operation| operation.estimate_for::<P>(width, height))
+            .fold(CostEstimate::default(), |total, cost| CostEstimate {
+                flops: total.flops + cost.flops,
+                peak_bytes: total.peak_bytes.max(cost.peak_bytes),
+                passes: total.passes + cost.passes,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeMethod;
+    use image::{LumaA, Rgba};
+
+    #[test]
+    fn test_scales_with_size() {
+        let operations = [
+            Operation::UnsharpMask { radius: 2.0, amount: 1.0, threshold: 0 },
+            Operation::RelativeUnsharp { radius: 2.0, amount: 1.0, threshold: 5.0 },
+            Operation::HighPassSharpen { strength: 0.5 },
+            Operation::EnhanceEdges { strength: 1.0, method: EdgeMethod::Sobel },
+            Operation::Clarity { strength: 1.0, radius: 2.0 },
+            Operation::AdaptiveUnsharp { min_radius: 0.5, max_radius: 3.0, amount: 1.0, threshold: 0 },
+            Operation::TextSharpen { stroke_width: 1.5, strength: 1.0 },
+            Operation::Descreen { period: Some(4.0) },
+            Operation::Median { radius: 2 },
+            Operation::Denoise { strength: 1.0, patch_radius: 1, search_radius: 5 },
+            Operation::SmartEnhance { level: 1.0 },
+            Operation::FrequencySeparation { radius: 2.0, smoothing: 0.4, amount: 0.8 },
+        ];
+        for operation in operations {
+            let small = operation.estimate(100, 100);
+            let large = operation.estimate(200, 200);
+            assert_eq!(large.flops, small.flops * 4, "{}", operation);
+            assert_eq!(large.peak_bytes, small.peak_bytes * 4, "{}", operation);
+            assert_eq!(large.passes, small.passes, "{}", operation);
+            assert!(small.peak_bytes >= 2 * 100 * 100 * 3, "{}", operation);
+        }
+    }
+
+    #[test]
+    fn test_pixel_types() {
+        let operation = Operation::HighPassSharpen { strength: 0.5 };
+        let rgb = operation.estimate(10, 10);
+        let rgba16 = operation.estimate_for::<Rgba<u16>>(10, 10);
+        let gray = operation.estimate_for::<LumaA<u8>>(10, 10);
+        // Alpha takes memory but no work
+        assert_eq!(rgba16.peak_bytes, rgb.peak_bytes / 3 * 8);
+        assert_eq!(rgba16.flops, rgb.flops);
+        assert_eq!(gray.flops, rgb.flops / 3);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let pipeline = Pipeline::parse("highpass:0.5,unsharp:1:1:0").unwrap();
+        let total = pipeline.estimate(64, 64);
+        let parts: Vec<CostEstimate> = pipeline.operations().iter().map(|op| op.estimate(64, 64)).collect();
+        assert_eq!(total.flops, parts[0].flops + parts[1].flops);
+        assert_eq!(total.passes, 5);
+        assert_eq!(total.peak_bytes, parts[1].peak_bytes);
+        assert_eq!(Pipeline::new().estimate(64, 64), CostEstimate::default());
+    }
+}
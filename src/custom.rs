@@ -0,0 +1,115 @@
+//! Steps supplied by the application, run by builders alongside the
+//! built-in operations.
+
+use crate::{Image, Operation, Pipeline, Result, SharpenPixel};
+use image::Rgb;
+
+/// A processing step the application defines, e.g. its own denoiser or
+/// color grade, for [`SharpeningBuilder::custom`](crate::SharpeningBuilder::custom).
+///
+/// Custom steps take part in the builder like built-in ones: they run in
+/// order with them, report progress, get the step's opacity and chroma
+/// clamp, and are limited to the region and mask. They always see the
+/// image in its own pixel format and transfer, also when the builder
+/// [linearizes](crate::SharpeningBuilder::linearize) its built-in steps,
+/// and must keep its dimensions.
+///
+/// [`Operation`] and [`Pipeline`] implement this trait too, so either can
+/// be passed where a step is expected.
+///
+/// # Example
+/// ```
+/// use sharpy::{Image, PipelineOp, Result};
+///
+/// struct Invert;
+///
+/// impl PipelineOp for Invert {
+///     fn name(&self) -> &'static str {
+///         "Invert"
+///     }
+///
+///     fn apply(&self, image: Image) -> Result<Image> {
+///         let mut rgb = image.into_rgb();
+///         image::imageops::invert(&mut rgb);
+///         Image::from_rgb(rgb)
+///     }
+/// }
+///
+/// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+/// let result = image.sharpen().unsharp_mask(1.0, 1.0, 0).custom(Invert).clarity(0.3, 2.0).apply().unwrap();
+/// ```
+pub trait PipelineOp<P: SharpenPixel = Rgb<u8>>: Send + Sync {
+    /// Name used in progress events, errors and validation reports
+    fn name(&self) -> &'static str;
+
+    /// Processes `image`, returning a result of the same dimensions.
+    fn apply(&self, image: Image<P>) -> Result<Image<P>>;
+
+    /// Checks the step's parameters before any processing starts; an
+    /// [`ImageError::InvalidParameter`](crate::ImageError::InvalidParameter)
+    /// is reported like a built-in operation's. Accepts everything by default.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// How far an output pixel depends on input pixels around it, which
+    /// regions process around their rectangle. Zero by default, for steps
+    /// that work pixel by pixel.
+    fn reach(&self) -> u32 {
+        0
+    }
+}
+
+impl<P: SharpenPixel, O: PipelineOp<P> + ?Sized> PipelineOp<P> for Box<O> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn apply(&self, image: Image<P>) -> Result<Image<P>> {
+        (**self).apply(image)
+    }
+
+    fn validate(&self) -> Result<()> {
+        (**self).validate()
+    }
+
+    fn reach(&self) -> u32 {
+        (**self).reach()
+    }
+}
+
+impl<P: SharpenPixel> PipelineOp<P> for Operation {
+    fn name(&self) -> &'static str {
+        Operation::name(self)
+    }
+
+    fn apply(&self, image: Image<P>) -> Result<Image<P>> {
+        Operation::apply(self, image)
+    }
+
+    fn validate(&self) -> Result<()> {
+        Operation::validate(self)
+    }
+
+    fn reach(&self) -> u32 {
+        Operation::reach(self)
+    }
+}
+
+impl<P: SharpenPixel> PipelineOp<P> for Pipeline {
+    fn name(&self) -> &'static str {
+        "Pipeline"
+    }
+
+    fn apply(&self, image: Image<P>) -> Result<Image<P>> {
+        Pipeline::apply(self, image)
+    }
+
+    fn validate(&self) -> Result<()> {
+        Pipeline::validate::<P>(self)
+    }
+
+    fn reach(&self) -> u32 {
+        Pipeline::reach::<P>(self)
+    }
+}
@@ -0,0 +1,297 @@
+//! 16-bit files end to end: grayscale as produced by microscopes and
+//! astronomy cameras, and RGB as scanned or developed from camera RAW files.
+//!
+//! [`Image::load`] converts every file to RGB8, which throws away the low
+//! byte of 16-bit data. `Image<Luma<u16>>` and `Image<Rgb<u16>>` load,
+//! sharpen and save the full precision instead; thresholds,
+//! [`Image::analyze`] and [`Image::compare`] take and report 8-bit units,
+//! so values carry over from 8-bit workflows.
+
+use crate::encode::{self, Pixels};
+use crate::dither::{self, Dither};
+use crate::{EncodeOptions, Image, ImageError, Limits, Result, SaveOptions, SharpenPixel};
+use image::{DynamicImage, ImageFormat, Luma, Rgb};
+use std::io::Write;
+use std::path::Path;
+
+impl Image<Luma<u16>> {
+    /// Loads an image file as 16-bit grayscale, keeping any embedded ICC
+    /// profile and metadata.
+    ///
+    /// 16-bit grayscale files (e.g. TIFF or PNG) keep their exact values;
+    /// 8-bit files are scaled to the 16-bit range and color files are
+    /// converted to luminance.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    ///
+    /// # fn main() -> sharpy::Result<()> {
+    /// let image = Image::load_gray16("cells.tif")?;
+    /// // The threshold is in 8-bit units: 2 skips differences below 514
+    /// let sharpened = image.unsharp_mask(1.5, 1.0, 2)?;
+    /// sharpened.save("cells_sharp.tif")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_gray16<Q: AsRef<Path>>(path: Q) -> Result<Self> {
+        Self::load_gray16_with_limits(path, &Limits::default())
+    }
+
+    /// Like [`Image::load_gray16`], rejecting files whose header exceeds
+    /// `limits`.
+    pub fn load_gray16_with_limits<Q: AsRef<Path>>(path: Q, limits: &Limits) -> Result<Self> {
+        Self::decode_gray16(image::ImageReader::open(path)?, limits)
+    }
+
+    /// Decodes an in-memory image as 16-bit grayscale; see
+    /// [`Image::load_gray16`].
+    pub fn from_bytes_gray16(bytes: &[u8]) -> Result<Self> {
+        let reader = image::ImageReader::new(std::io::Cursor::new(bytes)).with_guessed_format()?;
+        Self::decode_gray16(reader, &Limits::default())
+    }
+
+    fn decode_gray16<R: std::io::BufRead + std::io::Seek>(
+        reader: image::ImageReader<R>,
+        limits: &Limits,
+    ) -> Result<Self> {
+        let (img, icc_profile, metadata) = crate::decode(reader, limits)?;
+        Ok(Self::from_buffer_unchecked(img.into_luma16())
+            .with_icc_profile(icc_profile)
+            .with_metadata(metadata))
+    }
+
+    /// Saves the image as 16-bit grayscale in the format of the file
+    /// extension, with default encoder settings; see
+    /// [`save_with`](Image::<Luma<u16>>::save_with).
+    pub fn save<Q: AsRef<Path>>(self, path: Q) -> Result<()> {
+        self.save_with(path, &SaveOptions::default())
+    }
+
+    /// Saves the image as 16-bit grayscale, choosing the format from
+    /// `options.format` or the file extension.
+    ///
+    /// PNG and TIFF hold 16-bit grayscale; other formats fail with
+    /// [`ImageError::Encode`] rather than dropping precision. Convert with
+    /// [`Image::quantize`](Image::<Luma<u16>>::quantize) first to write
+    /// 8-bit output deliberately.
+    pub fn save_with<Q: AsRef<Path>>(&self, path: Q, options: &SaveOptions) -> Result<()> {
+        save(self, DynamicImage::ImageLuma16(self.as_buffer().clone()), path.as_ref(), options)
+    }
+
+    /// Encodes the image as 16-bit grayscale into an in-memory buffer; see
+    /// [`Image::save_with`](Image::<Luma<u16>>::save_with) for the formats.
+    pub fn to_bytes(&self, format: ImageFormat, options: &EncodeOptions) -> Result<Vec<u8>> {
+        to_bytes(self, DynamicImage::ImageLuma16(self.as_buffer().clone()), format, options)
+    }
+
+    /// Converts to 8-bit grayscale for formats without 16-bit support,
+    /// keeping the ICC profile and metadata. [`Dither::Ordered`] or
+    /// [`Dither::BlueNoise`] hide the banding that rounding leaves in
+    /// smooth gradients.
+    pub fn quantize(&self, dither: Dither) -> Image<Luma<u8>> {
+        quantize(self, dither)
+    }
+}
+
+impl Image<Rgb<u16>> {
+    /// Loads an image file as 16-bit RGB, keeping any embedded ICC profile
+    /// and metadata.
+    ///
+    /// 16-bit color files (e.g. TIFF or PNG) keep their exact values;
+    /// 8-bit files are scaled to the 16-bit range, grayscale is expanded
+    /// to color and alpha is dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    ///
+    /// # fn main() -> sharpy::Result<()> {
+    /// let image = Image::load_rgb16("scan_48bit.tif")?;
+    /// image.unsharp_mask(1.0, 1.0, 1)?.save("scan_sharp.tif")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_rgb16<Q: AsRef<Path>>(path: Q) -> Result<Self> {
+        Self::load_rgb16_with_limits(path, &Limits::default())
+    }
+
+    /// Like [`Image::load_rgb16`], rejecting files whose header exceeds
+    /// `limits`.
+    pub fn load_rgb16_with_limits<Q: AsRef<Path>>(path: Q, limits: &Limits) -> Result<Self> {
+        Self::decode_rgb16(image::ImageReader::open(path)?, limits)
+    }
+
+    /// Decodes an in-memory image as 16-bit RGB; see
+    /// [`Image::load_rgb16`].
+    pub fn from_bytes_rgb16(bytes: &[u8]) -> Result<Self> {
+        let reader = image::ImageReader::new(std::io::Cursor::new(bytes)).with_guessed_format()?;
+        Self::decode_rgb16(reader, &Limits::default())
+    }
+
+    /// Like [`Image::from_bytes_rgb16`], decoding `bytes` as `format`
+    /// rather than guessing it.
+    pub fn from_bytes_rgb16_with_format(bytes: &[u8], format: ImageFormat) -> Result<Self> {
+        let reader = image::ImageReader::with_format(std::io::Cursor::new(bytes), format);
+        Self::decode_rgb16(reader, &Limits::default())
+    }
+
+    fn decode_rgb16<R: std::io::BufRead + std::io::Seek>(
+        reader: image::ImageReader<R>,
+        limits: &Limits,
+    ) -> Result<Self> {
+        let (img, icc_profile, metadata) = crate::decode(reader, limits)?;
+        Ok(Self::from_buffer_unchecked(img.into_rgb16())
+            .with_icc_profile(icc_profile)
+            .with_metadata(metadata))
+    }
+
+    /// Saves the image as 16-bit RGB in the format of the file extension,
+    /// with default encoder settings; see
+    /// [`save_with`](Image::<Rgb<u16>>::save_with).
+    pub fn save<Q: AsRef<Path>>(self, path: Q) -> Result<()> {
+        self.save_with(path, &SaveOptions::default())
+    }
+
+    /// Saves the image as 16-bit RGB, choosing the format from
+    /// `options.format` or the file extension.
+    ///
+    /// PNG and TIFF hold 16-bit RGB; other formats fail with
+    /// [`ImageError::Encode`] rather than dropping precision.
+    pub fn save_with<Q: AsRef<Path>>(&self, path: Q, options: &SaveOptions) -> Result<()> {
+        save(self, DynamicImage::ImageRgb16(self.as_buffer().clone()), path.as_ref(), options)
+    }
+
+    /// Encodes the image as 16-bit RGB into an in-memory buffer; see
+    /// [`Image::save_with`](Image::<Rgb<u16>>::save_with) for the formats.
+    pub fn to_bytes(&self, format: ImageFormat, options: &EncodeOptions) -> Result<Vec<u8>> {
+        to_bytes(self, DynamicImage::ImageRgb16(self.as_buffer().clone()), format, options)
+    }
+
+    /// Converts to an 8-bit RGB [`Image`], keeping the ICC profile and
+    /// metadata; see [`Image::quantize`](Image::<Luma<u16>>::quantize).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use image::{ImageBuffer, Rgb};
+    /// use sharpy::{Dither, Image};
+    ///
+    /// # fn main() -> sharpy::Result<()> {
+    /// // A smooth sky gradient
+    /// let sky = ImageBuffer::from_fn(640, 480, |_, y| Rgb([20000, 30000 + y as u16 * 20, 50000]));
+    /// let image = Image::from_buffer(sky)?.unsharp_mask(0.6, 0.8, 1)?;
+    /// image.quantize(Dither::BlueNoise).save("sky.jpg")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn quantize(&self, dither: Dither) -> Image {
+        quantize(self, dither)
+    }
+}
+
+fn quantize<P, Q>(image: &Image<P>, dither: Dither) -> Image<Q>
+where
+    P: SharpenPixel + image::Pixel<Subpixel = u16>,
+    Q: SharpenPixel + image::Pixel<Subpixel = u8>,
+{
+    Image::from_buffer_unchecked(dither::quantize(image.as_buffer(), dither))
+        .with_icc_profile(image.icc_profile().map(<[u8]>::to_vec))
+        .with_metadata(image.metadata().clone())
+        .with_luminance(image.luminance())
+}
+
+/// Writes `img`, the pixels of `image`, to `path` with its profile and
+/// metadata.
+fn save<P: SharpenPixel>(image: &Image<P>, img: DynamicImage, path: &Path, options: &SaveOptions) -> Result<()> {
+    let save = || -> Result<()> {
+        let format = match options.format {
+            Some(format) => format,
+            None => ImageFormat::from_path(path)?,
+        };
+        check_format(format, &img)?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        encode::write(Pixels::from(&img), &mut file, format, &options.encode, image.icc_profile(), image.metadata())?;
+        file.flush()?;
+        Ok(())
+    };
+    save().map_err(|e| e.in_file(path))
+}
+
+fn to_bytes<P: SharpenPixel>(
+    image: &Image<P>,
+    img: DynamicImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>> {
+    check_format(format, &img)?;
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    encode::write(Pixels::from(&img), &mut cursor, format, options, image.icc_profile(), image.metadata())?;
+    Ok(cursor.into_inner())
+}
+
+/// Fails for formats that can't hold 16-bit pixels, before any file is
+/// created.
+fn check_format(format: ImageFormat, img: &DynamicImage) -> Result<()> {
+    match format {
+        ImageFormat::Png | ImageFormat::Tiff => Ok(()),
+        other => Err(ImageError::Encode(format!("{:?} cannot encode {:?} pixels", other, img.color()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    /// Faint structure that only differs in the low byte, as in dark
+    /// microscopy frames.
+    fn create_test_image() -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        ImageBuffer::from_fn(64, 64, |x, y| Luma([1000 + if (x / 4 + y / 4) % 2 == 0 { 0 } else { 120 }]))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = Image::from_buffer(create_test_image()).unwrap();
+        let tiff = image.to_bytes(ImageFormat::Tiff, &EncodeOptions::default()).unwrap();
+        let loaded = Image::from_bytes_gray16(&tiff).unwrap();
+        assert_eq!(loaded.as_buffer(), image.as_buffer());
+
+        let sharpened = loaded.clone().unsharp_mask(1.0, 1.0, 0).unwrap();
+        assert_ne!(sharpened.as_buffer(), loaded.as_buffer());
+        // The 120-level steps are below half an 8-bit level, so a threshold
+        // of 1 (257 levels) leaves them alone
+        let thresholded = loaded.clone().unsharp_mask(1.0, 1.0, 1).unwrap();
+        assert_eq!(thresholded.as_buffer(), loaded.as_buffer());
+
+        let png = sharpened.to_bytes(ImageFormat::Png, &EncodeOptions::default()).unwrap();
+        assert_eq!(Image::from_bytes_gray16(&png).unwrap().as_buffer(), sharpened.as_buffer());
+        assert!(matches!(sharpened.to_bytes(ImageFormat::Jpeg, &EncodeOptions::default()), Err(ImageError::Encode(_))));
+
+        let color = Image::from_buffer(ImageBuffer::from_pixel(8, 8, Rgb([1000u16, 2001, 40003]))).unwrap();
+        let tiff = color.to_bytes(ImageFormat::Tiff, &EncodeOptions::default()).unwrap();
+        assert_eq!(Image::from_bytes_rgb16(&tiff).unwrap().as_buffer(), color.as_buffer());
+    }
+
+    #[test]
+    fn test_narrowed_source_reported() {
+        let color = Image::from_buffer(ImageBuffer::from_pixel(8, 8, Rgb([1000u16, 2001, 40003]))).unwrap();
+        let png = color.to_bytes(ImageFormat::Png, &EncodeOptions::default()).unwrap();
+
+        let (_, warnings) = Image::from_bytes(&png).unwrap().sharpen().unsharp_mask(1.0, 1.0, 0).apply_with_report().unwrap();
+        assert_eq!(warnings, [crate::Warning::SourceNarrowed { source: image::ColorType::Rgb16 }]);
+        assert_eq!(warnings[0].to_string(), "decoded from Rgb16 pixels, losing its precision");
+
+        let (_, warnings) = Image::from_bytes_rgb16(&png).unwrap().sharpen().unsharp_mask(1.0, 1.0, 0).apply_with_report().unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_analysis_in_8_bit_units() {
+        let deep = ImageBuffer::from_fn(64, 64, |x, y| Luma([if (x / 2 + y / 2) % 2 == 0 { 60u16 * 257 } else { 190 * 257 }]));
+        let shallow = ImageBuffer::from_fn(64, 64, |x, y| Luma([if (x / 2 + y / 2) % 2 == 0 { 60u8 } else { 190 }]));
+        let deep = Image::from_buffer(deep).unwrap().analyze();
+        let shallow = Image::from_buffer(shallow).unwrap().analyze();
+        assert!((deep.blur - shallow.blur).abs() < 1e-3, "{:?} vs {:?}", deep, shallow);
+        assert!((deep.noise - shallow.noise).abs() < 0.1, "{:?} vs {:?}", deep, shallow);
+    }
+}
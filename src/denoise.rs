@@ -0,0 +1,200 @@
+//! Noise removal to run before sharpening, which would otherwise amplify
+//! the noise along with the detail.
+
+use crate::context::Context;
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, sample, to_sample, unit_scale};
+use crate::{Image, Result};
+
+/// Largest patch radius for [`non_local_means`]: larger patches match
+/// fewer neighbors without telling noise from detail any better.
+pub(crate) const MAX_PATCH_RADIUS: u32 = 3;
+/// Filtering level, in 8-bit sample units, of non-local means at strength 1.
+const STRENGTH_UNIT: f32 = 10.0;
+
+/// Replaces each color sample with the median of the same channel over the
+/// `(2 * radius + 1)` square around it; alpha is kept.
+///
+/// Unlike a blur, the median drops isolated outliers, such as
+/// salt-and-pepper noise and hot pixels, entirely and keeps edges sharp,
+/// so sharpening afterwards has no specks to amplify.
+pub fn median_filter<P: SharpenPixel>(image: &Image<P>, radius: u32, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let extent = radius as i32;
+    let boundary = ctx.boundary();
+
+    ctx.start(height as usize);
+    let mut buffer: Buffer<P> = ctx.buffer(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        let mut window = Vec::with_capacity((2 * radius as usize + 1).pow(2));
+        for (x, _, pixel) in row {
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            for (c, value) in pixel.iter_mut().enumerate() {
+                if c >= P::COLOR_CHANNELS {
+                    *value = orig_pixel[c];
+                    continue;
+                }
+                window.clear();
+                for dy in -extent..=extent {
+                    let sy = boundary.index(y as i32 + dy, height);
+                    for dx in -extent..=extent {
+                        window.push(original.get_pixel(boundary.index(x as i32 + dx, width), sy).channels()[c]);
+                    }
+                }
+                let middle = window.len() / 2;
+                *value = *window.select_nth_unstable_by(middle, |a, b| a.partial_cmp(b).unwrap()).1;
+            }
+        }
+        ctx.advance(1);
+    });
+    Ok(image.with_pixels(buffer))
+}
+
+/// Replaces each color sample with an average of the pixels within
+/// `search_radius` whose surrounding patches, of `patch_radius`, look like
+/// its own; alpha is kept.
+///
+/// Noise doesn't repeat, so averaging similar patches cancels it, while
+/// detail and edges recur along their own direction and are kept. A
+/// neighbor's weight falls off with the mean squared difference between
+/// the patches, compared to `strength` times 10 levels (8-bit) squared;
+/// the pixel itself counts as much as its best match, so that it doesn't
+/// outweigh the others.
+///
+/// Each row's patch distances for an offset are summed down columns first,
+/// then along the row with a running sum, so the cost grows with the patch
+/// side rather than its area.
+pub fn non_local_means<P: SharpenPixel>(
+    image: &Image<P>,
+    strength: f32,
+    patch_radius: u32,
+    search_radius: u32,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let channels = P::COLOR_CHANNELS;
+    let boundary = ctx.boundary();
+    let plane: Vec<f32> = original.pixels()
+        .flat_map(|pixel| pixel.channels()[..channels].iter().map(|&value| sample::<P>(value)).collect::<Vec<_>>())
+        .collect();
+    let at = |x: i32, y: i32| {
+        let index = (boundary.index(y, height) as usize * width as usize + boundary.index(x, width) as usize) * channels;
+        &plane[index..index + channels]
+    };
+
+    let filtering = strength * STRENGTH_UNIT * unit_scale::<P>();
+    let scale = 1.0 / (filtering * filtering * ((2 * patch_radius + 1).pow(2) as usize * channels) as f32);
+    let (patch, search) = (patch_radius as i32, search_radius as i32);
+    let offsets: Vec<(i32, i32)> = (-search..=search)
+        .flat_map(|dy| (-search..=search).map(move |dx| (dx, dy)))
+        .filter(|&offset| offset != (0, 0))
+        .collect();
+
+    ctx.start(height as usize);
+    let mut buffer: Buffer<P> = ctx.buffer(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        let y = y as i32;
+        let columns = width as usize + 2 * patch as usize;
+        let mut sums = vec![0.0f32; width as usize * channels];
+        let mut totals = vec![0.0f32; width as usize];
+        let mut best = vec![0.0f32; width as usize];
+        let mut distances = vec![0.0f32; columns];
+        for &(dx, dy) in &offsets {
+            // Squared differences summed down each patch column, including
+            // the columns beyond either end of the row
+            for (column, distance) in distances.iter_mut().enumerate() {
+                let x = column as i32 - patch;
+                *distance = (-patch..=patch)
+                    .map(|py| {
+                        at(x, y + py).iter().zip(at(x + dx, y + dy + py))
+                            .map(|(a, b)| (a - b) * (a - b))
+                            .sum::<f32>()
+                    })
+                    .sum();
+            }
+            let mut distance: f32 = distances[..2 * patch as usize].iter().sum();
+            for x in 0..width as usize {
+                distance += distances[x + 2 * patch as usize];
+                let weight = (-distance.max(0.0) * scale).exp();
+                distance -= distances[x];
+                for (sum, value) in sums[x * channels..(x + 1) * channels].iter_mut().zip(at(x as i32 + dx, y + dy)) {
+                    *sum += weight * value;
+                }
+                totals[x] += weight;
+                best[x] = best[x].max(weight);
+            }
+        }
+
+        for (x, _, pixel) in row {
+            let own = at(x as i32, y);
+            let weight = if best[x as usize] > 0.0 { best[x as usize] } else { 1.0 };
+            let total = totals[x as usize] + weight;
+            let orig_pixel = original.get_pixel(x, y as u32).channels();
+            let pixel = pixel.channels_mut();
+            for (c, value) in pixel.iter_mut().enumerate() {
+                *value = if c < channels {
+                    to_sample::<P>((sums[x as usize * channels + c] + weight * own[c]) / total)
+                } else {
+                    orig_pixel[c]
+                };
+            }
+        }
+        ctx.advance(1);
+    });
+    Ok(image.with_pixels(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_median_filter() {
+        // A vertical edge sprinkled with salt and pepper
+        let noisy = RgbImage::from_fn(32, 24, |x, y| {
+            let value = if x < 16 { 60 } else { 190 };
+            match (x * 7 + y * 13) % 23 {
+                0 => Rgb([255, 255, 255]),
+                1 => Rgb([0, 0, 0]),
+                _ => Rgb([value, value, value]),
+            }
+        });
+        let image = Image::from_rgb(noisy).unwrap();
+        let filtered = median_filter(&image, 1, &Context::default()).unwrap().into_rgb();
+        for (x, y, pixel) in filtered.enumerate_pixels() {
+            let expected = if x < 16 { 60 } else { 190 };
+            assert_eq!(pixel[0], expected, "({}, {})", x, y);
+        }
+
+        let flat = Image::from_rgb(RgbImage::from_pixel(8, 8, Rgb([10, 20, 30]))).unwrap();
+        assert!(median_filter(&flat, 2, &Context::default()).unwrap().into_rgb() == flat.into_rgb());
+    }
+
+    #[test]
+    fn test_non_local_means() {
+        // A vertical edge under pseudo-random noise of about ±12 levels
+        let noise = |x: u32, y: u32| ((x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761) >> 27) as i32 - 16;
+        let clean = |x: u32| if x < 20 { 70 } else { 180 };
+        let noisy = RgbImage::from_fn(40, 30, |x, y| {
+            let value = (clean(x) + noise(x, y) * 3 / 4).clamp(0, 255) as u8;
+            Rgb([value, value, value])
+        });
+        let error = |image: &RgbImage| {
+            image.enumerate_pixels().map(|(x, _, pixel)| (pixel[0] as i32 - clean(x)).pow(2)).sum::<i32>() as f32 / 1200.0
+        };
+        let image = Image::from_rgb(noisy.clone()).unwrap();
+        let denoised = non_local_means(&image, 1.5, 1, 5, &Context::default()).unwrap().into_rgb();
+        assert!(error(&denoised) < error(&noisy) / 4.0, "{} vs {}", error(&denoised), error(&noisy));
+        // The edge isn't blurred across
+        for y in 0..30 {
+            assert!(denoised.get_pixel(18, y)[0] < 100 && denoised.get_pixel(21, y)[0] > 150);
+        }
+
+        let flat = Image::from_rgb(RgbImage::from_pixel(8, 8, Rgb([10, 20, 30]))).unwrap();
+        assert!(non_local_means(&flat, 1.0, 1, 3, &Context::default()).unwrap().into_rgb() == flat.into_rgb());
+    }
+}
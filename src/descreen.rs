@@ -0,0 +1,274 @@
+//! Removal of the halftone screen from scans of printed material.
+//!
+//! Print renders tones as a grid of dots, so a scan carries a strong
+//! periodic pattern at the screen's frequency. Sharpening amplifies it into
+//! moiré. [`descreen`] averages each pixel over a disc whose first spectral
+//! null falls on the screen frequency, which removes the pattern at every
+//! screen angle (the inks of a color print are screened at different
+//! angles, but the same frequency) before any sharpening runs.
+
+use crate::context::Context;
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, sample, to_sample};
+use crate::utils::{blur_plane, Boundary, Luminance};
+use crate::{Image, Result};
+
+/// Smallest screen period, in pixels, that can be detected or removed.
+pub(crate) const MIN_PERIOD: f32 = 2.0;
+/// Largest screen period, in pixels: a 40 lpi newspaper screen at 640 dpi.
+pub(crate) const MAX_PERIOD: f32 = 16.0;
+/// Disc diameter, relative to the period, that puts the disc's first
+/// spectral null (the first zero of its jinc response) on the screen
+/// frequency.
+const NULL_DIAMETER: f32 = 1.22;
+/// Side of the central square whose autocorrelation detects the screen.
+const SAMPLE_SIZE: u32 = 256;
+/// Smallest side the sampled square may have: a few periods of the
+/// coarsest screen, below which noise and gradients pass for one.
+const MIN_SAMPLE_SIZE: u32 = 3 * MAX_PERIOD as u32;
+/// How much more a lag must correlate than the lag halfway to it, relative
+/// to the correlation at no lag, for the image to count as screened.
+const MIN_CONTRAST: f32 = 0.5;
+/// Subdivisions of a pixel in the search for the dot spacing.
+const SUBSTEPS: i32 = 4;
+
+/// How far, in pixels, descreening with `period` (or the largest period if
+/// detected) reads around each pixel.
+pub(crate) fn reach(period: Option<f32>) -> u32 {
+    (NULL_DIAMETER * period.unwrap_or(MAX_PERIOD) / 2.0).ceil() as u32
+}
+
+/// Returns the period, in pixels, of the halftone screen in `img`, if it
+/// has one.
+///
+/// The central square's luminance, less its low frequencies, is correlated
+/// with shifted copies of itself. A screen makes it correlate strongly at
+/// shifts by one dot spacing and weakly halfway there, between the dots;
+/// photographic detail correlates less the further it is shifted.
+pub(crate) fn detect_period<P: SharpenPixel>(img: &Buffer<P>, luminance: Luminance) -> Option<f32> {
+    let (width, height) = img.dimensions();
+    let (size_x, size_y) = (width.min(SAMPLE_SIZE), height.min(SAMPLE_SIZE));
+    if size_x < MIN_SAMPLE_SIZE || size_y < MIN_SAMPLE_SIZE {
+        return None;
+    }
+    let (left, top) = ((width - size_x) / 2, (height - size_y) / 2);
+    let luma: Vec<f32> = (0..size_y)
+        .flat_map(|y| (0..size_x).map(move |x| (x, y)))
+        .map(|(x, y)| luminance.of(img.get_pixel(left + x, top + y)))
+        .collect();
+    let background = blur_plane(&luma, size_x, size_y, MAX_PERIOD, Boundary::Clamp);
+    let detail: Vec<f32> = luma.iter().zip(&background).map(|(value, base)| value - base).collect();
+    let variance = detail.iter().map(|value| value * value).sum::<f32>() / detail.len() as f32;
+    if variance <= 0.0 {
+        return None;
+    }
+
+    let limit = MAX_PERIOD.ceil() as i32;
+    let side = 2 * limit + 1;
+    let correlation = |dx: i32, dy: i32| -> f32 {
+        let (columns, rows) = (dx.unsigned_abs(), dy.unsigned_abs());
+        if columns >= size_x || rows >= size_y {
+            return 0.0;
+        }
+        let sum: f32 = (0..size_y - rows)
+            .map(|y| {
+                (0..size_x - columns)
+                    .map(|x| {
+                        let (x0, x1) = if dx >= 0 { (x, x + columns) } else { (x + columns, x) };
+                        detail[(y * size_x + x0) as usize] * detail[((y + rows) * size_x + x1) as usize]
+                    })
+                    .sum::<f32>()
+            })
+            .sum();
+        sum / ((size_x - columns) * (size_y - rows)) as f32 / variance
+    };
+    // Every lag in one half-plane; the other mirrors it
+    let correlations: Vec<f32> = (0..side * (limit + 1))
+        .into_par_iter()
+        .map(|index| correlation(index % side - limit, index / side))
+        .collect();
+    let at = |dx: i32, dy: i32| {
+        let (dx, dy) = if dy < 0 { (-dx, -dy) } else { (dx, dy) };
+        correlations[(dy * side + dx + limit) as usize]
+    };
+    // Halfway lags fall between pixels
+    let between = |dx: f32, dy: f32| {
+        let (x0, y0) = (dx.floor(), dy.floor());
+        let (fx, fy) = (dx - x0, dy - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        (at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx) * (1.0 - fy)
+            + (at(x0, y0 + 1) * (1.0 - fx) + at(x0 + 1, y0 + 1) * fx) * fy
+    };
+    // Dot spacings of angled screens fall between pixels too, so lags are
+    // searched on a finer grid
+    let steps = limit * SUBSTEPS;
+    let lags: Vec<(f32, f32)> = (0..=steps)
+        .flat_map(|y| (-steps..=steps).map(move |x| (x, y)))
+        .filter(|&(x, y)| y > 0 || x > 0)
+        .map(|(x, y)| (x as f32 / SUBSTEPS as f32, y as f32 / SUBSTEPS as f32))
+        .filter(|&(dx, dy)| dx.abs() < limit as f32 && dy < limit as f32)
+        .filter(|&(dx, dy)| (MIN_PERIOD..=MAX_PERIOD).contains(&dx.hypot(dy)))
+        .collect();
+    let scores: Vec<f32> = lags.iter().map(|&(dx, dy)| between(dx, dy) - between(dx / 2.0, dy / 2.0)).collect();
+
+    // The nearest strong lag points at the neighboring dot, though blur can
+    // leave it well short of the lags spanning two dots. Its score is skewed
+    // outward, so the correlation peak near it gives the spacing.
+    let best = scores.iter().copied().fold(f32::MIN, f32::max);
+    if best < MIN_CONTRAST {
+        return None;
+    }
+    let (nx, ny) = lags.iter().zip(&scores)
+        .filter(|&(_, &score)| score >= 0.3 * best)
+        .map(|(&lag, _)| lag)
+        .min_by(|a, b| a.0.hypot(a.1).total_cmp(&b.0.hypot(b.1)))?;
+    let (x, y) = (-limit + 1..limit)
+        .flat_map(|x| (0..limit).map(move |y| (x, y)))
+        .filter(|&(x, y)| (x as f32 - nx).hypot(y as f32 - ny) <= 0.25 * nx.hypot(ny) + 1.0)
+        .max_by(|a, b| at(a.0, a.1).total_cmp(&at(b.0, b.1)))?;
+    // Fit a parabola through the peak and its neighbors along each axis
+    let offset = |before: f32, peak: f32, after: f32| {
+        let curvature = before - 2.0 * peak + after;
+        if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 }
+    };
+    let dx = x as f32 + offset(at(x - 1, y), at(x, y), at(x + 1, y));
+    let dy = y as f32 + offset(at(x, y - 1), at(x, y), at(x, y + 1));
+    Some(dx.hypot(dy)).filter(|period| (MIN_PERIOD..=MAX_PERIOD).contains(period))
+}
+
+/// Removes a halftone screen with the given `period` in pixels, or the
+/// detected one if `None`. Images without a detectable screen are returned
+/// unchanged.
+pub(crate) fn descreen<P: SharpenPixel>(image: &Image<P>, period: Option<f32>, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let Some(period) = period.or_else(|| detect_period(original, ctx.luminance())) else {
+        return Ok(image.clone());
+    };
+
+    // Disc taps with antialiased edges
+    let radius = NULL_DIAMETER * period / 2.0;
+    let extent = reach(Some(period)) as i32;
+    let taps: Vec<(i32, i32, f32)> = (-extent..=extent)
+        .flat_map(|dy| (-extent..=extent).map(move |dx| (dx, dy)))
+        .filter_map(|(dx, dy)| {
+            let weight = (radius + 0.5 - ((dx * dx + dy * dy) as f32).sqrt()).clamp(0.0, 1.0);
+            (weight > 0.0).then_some((dx, dy, weight))
+        })
+        .collect();
+    let total: f32 = taps.iter().map(|tap| tap.2).sum();
+    let boundary = ctx.boundary();
+
+    ctx.start(height as usize);
+    let mut buffer: Buffer<P> = ctx.buffer(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let mut sums = [0.0f32; 4];
+            for &(dx, dy, weight) in &taps {
+                let neighbor = original.get_pixel(boundary.index(x as i32 + dx, width), boundary.index(y as i32 + dy, height));
+                for (sum, &value) in sums.iter_mut().zip(&neighbor.channels()[..P::COLOR_CHANNELS]) {
+                    *sum += weight * sample::<P>(value);
+                }
+            }
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            for (i, value) in pixel.iter_mut().enumerate() {
+                *value = if i < P::COLOR_CHANNELS { to_sample::<P>(sums[i] / total) } else { orig_pixel[i] };
+            }
+        }
+        ctx.advance(1);
+    });
+    Ok(image.with_pixels(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Continuous tone: a horizontal ramp with a bright disc.
+    fn tone(x: u32, y: u32) -> f32 {
+        let disc = if (x as f32 - 80.0).powi(2) + (y as f32 - 64.0).powi(2) < 900.0 { 0.3 } else { 0.0 };
+        (0.2 + 0.5 * x as f32 / 160.0 + disc).min(0.95)
+    }
+
+    /// `tone` printed with round dots on a 45-degree screen of `period`
+    /// pixels, then scanned with a little blur.
+    fn halftone(period: f32) -> RgbImage {
+        let (cos, sin) = (std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2);
+        let sharp = RgbImage::from_fn(160, 128, |x, y| {
+            let (u, v) = ((x as f32 * cos + y as f32 * sin) / period, (y as f32 * cos - x as f32 * sin) / period);
+            let (du, dv) = (u - u.round(), v - v.round());
+            // Dots grow with darkness; their area covers 1 - tone
+            let dot = ((1.0 - tone(x, y)) / std::f32::consts::PI).sqrt();
+            let value = if (du * du + dv * dv).sqrt() < dot { 20 } else { 240 };
+            Rgb([value, value, value])
+        });
+        image::imageops::blur(&sharp, 0.6)
+    }
+
+    #[test]
+    fn test_detect_period() {
+        for period in [3.5, 4.0, 5.0, 6.0, 9.0, 12.0] {
+            let detected = detect_period(&halftone(period), Luminance::default()).unwrap();
+            assert!((detected - period).abs() < period * 0.05, "{} detected as {}", period, detected);
+        }
+        // Continuous tone with noise has no screen
+        let photo = RgbImage::from_fn(160, 128, |x, y| {
+            let grain = ((x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761) >> 28) as f32;
+            let value = (tone(x, y) * 255.0 + grain - 8.0).clamp(0.0, 255.0) as u8;
+            Rgb([value, value, value])
+        });
+        assert_eq!(detect_period(&photo, Luminance::default()), None);
+    }
+
+    #[test]
+    fn test_small_images_have_no_screen() {
+        for size in [4, 8, 12, 16, 32] {
+            let noise = RgbImage::from_fn(size, size, |x, y| {
+                let value = ((x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761) >> 24) as u8;
+                Rgb([value, value, value])
+            });
+            let gradient = RgbImage::from_fn(size, size, |x, y| {
+                let value = (255 * (x + y) / (2 * size - 2)) as u8;
+                Rgb([value, value, value])
+            });
+            assert_eq!(detect_period(&noise, Luminance::default()), None, "{0}x{0} noise", size);
+            assert_eq!(detect_period(&gradient, Luminance::default()), None, "{0}x{0} gradient", size);
+        }
+    }
+
+    #[test]
+    fn test_descreen() {
+        let scan = Image::from_rgb(halftone(6.0)).unwrap();
+        let ctx = Context::default();
+        // Deviation from the printed tone over a patch away from the disc
+        let error = |img: &RgbImage| {
+            let squares: Vec<f32> = (20..40)
+                .flat_map(|y| (100..120).map(move |x| (x, y)))
+                .map(|(x, y)| (img.get_pixel(x, y)[0] as f32 - (240.0 - 220.0 * (1.0 - tone(x, y)))).powi(2))
+                .collect();
+            (squares.iter().sum::<f32>() / squares.len() as f32).sqrt()
+        };
+        let original = scan.clone().into_rgb();
+        let descreened = descreen(&scan, None, &ctx).unwrap().into_rgb();
+        assert!(error(&original) > 50.0, "{}", error(&original));
+        assert!(error(&descreened) < 8.0, "{}", error(&descreened));
+        // Tones survive: the disc stays brighter than its surroundings
+        assert!(descreened.get_pixel(80, 64)[0] > descreened.get_pixel(80, 110)[0] + 40);
+
+        let flat = Image::from_rgb(RgbImage::from_pixel(32, 32, Rgb([90, 90, 90]))).unwrap();
+        assert_eq!(descreen(&flat, None, &ctx).unwrap().into_rgb(), flat.into_rgb());
+    }
+
+    #[test]
+    fn test_detected_on_whole_image() {
+        // The screen is detected on the whole scan, not the region's crop
+        let scan = Image::from_rgb(halftone(6.0)).unwrap();
+        let rect = crate::Rect::new(100, 20, 12, 12);
+        let full = scan.clone().sharpen().descreen().apply().unwrap().into_rgb();
+        let region = scan.sharpen().descreen().region(rect).apply().unwrap().into_rgb();
+        let crop = |img: &RgbImage| image::imageops::crop_imm(img, rect.x, rect.y, rect.width, rect.height).to_image();
+        assert_eq!(crop(&region), crop(&full));
+    }
+}
@@ -0,0 +1,248 @@
+//! Dithered quantization from 16 to 8 bits per sample.
+//!
+//! Rounding a smooth 16-bit gradient to 8 bits leaves flat bands one level
+//! apart, and sharpening or clarity makes their edges stand out. Dithering
+//! shifts each pixel's rounding threshold by a fixed pattern, so the bands
+//! break up into fine texture. Samples that are exact 8-bit levels are
+//! never changed.
+
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel};
+use crate::{ImageError, Result};
+use image::Pixel;
+use std::sync::OnceLock;
+
+/// Dithering applied when 16-bit samples are quantized to 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Plain rounding
+    #[default]
+    None,
+    /// 8x8 Bayer matrix; cheap, with a visible cross-hatch
+    Ordered,
+    /// 64x64 blue-noise mask; fine grain without a visible pattern
+    BlueNoise,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "ordered" | "bayer" => Ok(Dither::Ordered),
+            "blue-noise" | "bluenoise" => Ok(Dither::BlueNoise),
+            _ => Err(ImageError::InvalidParameter {
+                param: "dither".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Dither {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dither::None => write!(f, "none"),
+            Dither::Ordered => write!(f, "ordered"),
+            Dither::BlueNoise => write!(f, "blue-noise"),
+        }
+    }
+}
+
+/// Side length of the blue-noise mask, which tiles the image.
+const NOISE_SIZE: usize = 64;
+/// Spread of the Gaussian that measures how crowded a pixel is.
+const NOISE_SIGMA: f32 = 1.5;
+
+impl Dither {
+    /// Offset in (-0.5, 0.5) added to the pixel at (x, y) before rounding.
+    fn offset(self, x: u32, y: u32) -> f32 {
+        let (rank, levels) = match self {
+            Dither::None => return 0.0,
+            Dither::Ordered => (bayer_rank(x % 8, y % 8), 64),
+            Dither::BlueNoise => {
+                let index = (y as usize % NOISE_SIZE) * NOISE_SIZE + x as usize % NOISE_SIZE;
+                (blue_noise()[index] as u32, NOISE_SIZE * NOISE_SIZE)
+            }
+        };
+        (rank as f32 + 0.5) / levels as f32 - 0.5
+    }
+}
+
+/// Position of (x, y) in the 8x8 Bayer threshold order (0-63).
+fn bayer_rank(x: u32, y: u32) -> u32 {
+    // Each bit of the coordinates picks a cell of the 2x2 pattern; the
+    // finest bit gives the most significant base-4 digit
+    (0..3).fold(0, |rank, bit| {
+        let cell = [[0, 2], [3, 1]][((y >> bit) & 1) as usize][((x >> bit) & 1) as usize];
+        rank | cell << (2 * (2 - bit))
+    })
+}
+
+/// Threshold order of the blue-noise mask, computed on first use.
+fn blue_noise() -> &'static [u16] {
+    static RANKS: OnceLock<Vec<u16>> = OnceLock::new();
+    RANKS.get_or_init(void_and_cluster)
+}
+
+/// Binary pattern on the wrapped-around noise tile, with each pixel's
+/// Gaussian-weighted crowding by the set pixels.
+#[derive(Clone)]
+struct Pattern {
+    set: Vec<bool>,
+    energy: Vec<f32>,
+}
+
+impl Pattern {
+    fn toggle(&mut self, index: usize, kernel: &[f32]) {
+        self.set[index] = !self.set[index];
+        let sign = if self.set[index] { 1.0 } else { -1.0 };
+        let (x, y) = (index % NOISE_SIZE, index / NOISE_SIZE);
+        for (other, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (other % NOISE_SIZE + NOISE_SIZE - x) % NOISE_SIZE;
+            let dy = (other / NOISE_SIZE + NOISE_SIZE - y) % NOISE_SIZE;
+            *energy += sign * kernel[dy * NOISE_SIZE + dx];
+        }
+    }
+
+    /// The most crowded set pixel.
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    /// The least crowded unset pixel.
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (index, &energy) in self.energy.iter().enumerate() {
+            if self.set[index] == set && best.is_none_or(|(_, value)| better(energy, value)) {
+                best = Some((index, energy));
+            }
+        }
+        best.map_or(0, |(index, _)| index)
+    }
+}
+
+/// Ranks the pixels of the noise tile with Ulichney's void-and-cluster
+/// method, so the pixels below any threshold are spread evenly.
+fn void_and_cluster() -> Vec<u16> {
+    let count = NOISE_SIZE * NOISE_SIZE;
+    let kernel: Vec<f32> = (0..count).map(|index| {
+        let (dx, dy) = (index % NOISE_SIZE, index / NOISE_SIZE);
+        let (dx, dy) = (dx.min(NOISE_SIZE - dx) as f32, dy.min(NOISE_SIZE - dy) as f32);
+        (-(dx * dx + dy * dy) / (2.0 * NOISE_SIGMA * NOISE_SIGMA)).exp()
+    }).collect();
+
+    // Start from a tenth of the pixels, chosen by a fixed generator
+    let mut pattern = Pattern { set: vec![false; count], energy: vec![0.0; count] };
+    let mut state: u32 = 1;
+    let mut initial = 0;
+    while initial < count / 10 {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let index = (state >> 8) as usize % count;
+        if !pattern.set[index] {
+            pattern.toggle(index, &kernel);
+            initial += 1;
+        }
+    }
+    // Even it out: move the tightest cluster into the largest void until
+    // that would put it back where it was
+    for _ in 0..count {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster, &kernel);
+        let void = pattern.largest_void();
+        pattern.toggle(void, &kernel);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+    let mut removing = pattern.clone();
+    for rank in (0..initial).rev() {
+        let cluster = removing.tightest_cluster();
+        removing.toggle(cluster, &kernel);
+        ranks[cluster] = rank as u16;
+    }
+    for rank in initial..count {
+        let void = pattern.largest_void();
+        pattern.toggle(void, &kernel);
+        ranks[void] = rank as u16;
+    }
+    ranks
+}
+
+/// Quantizes 16-bit pixels to 8 bits. The color channels of a pixel share
+/// one dither offset, so grays stay neutral; alpha is rounded plainly.
+pub(crate) fn quantize<P, Q>(img: &Buffer<P>, dither: Dither) -> Buffer<Q>
+where
+    P: SharpenPixel + Pixel<Subpixel = u16>,
+    Q: SharpenPixel + Pixel<Subpixel = u8>,
+{
+    let (width, height) = img.dimensions();
+    let mut quantized = Buffer::<Q>::new(width, height);
+    quantized.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let offset = dither.offset(x, y);
+                let source = img.get_pixel(x, y).channels();
+                for (channel, (value, &sample)) in pixel.channels_mut().iter_mut().zip(source).enumerate() {
+                    let level = sample as f32 / 257.0;
+                    let level = if channel < P::COLOR_CHANNELS { level + offset } else { level };
+                    *value = level.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        });
+    quantized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    #[test]
+    fn test_patterns_cover_every_level() {
+        let mut bayer: Vec<u32> = (0..64).map(|index| bayer_rank(index % 8, index / 8)).collect();
+        bayer.sort_unstable();
+        assert_eq!(bayer, (0..64).collect::<Vec<_>>());
+        assert_eq!([bayer_rank(0, 0), bayer_rank(1, 0), bayer_rank(0, 1), bayer_rank(1, 1)], [0, 32, 48, 16]);
+
+        let mut noise = blue_noise().to_vec();
+        noise.sort_unstable();
+        assert_eq!(noise, (0..4096).collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn test_quantize() {
+        // A gradient spanning two 8-bit levels
+        let gradient: Buffer<Luma<u16>> = ImageBuffer::from_fn(256, 64, |x, _| Luma([100 * 257 + x as u16 * 2]));
+        // Mean of a 64x64 block, in 8-bit units
+        let mean = |samples: Vec<f32>| samples.iter().sum::<f32>() / samples.len() as f32;
+        let block = |img: &Buffer<Luma<u8>>, left: u32| mean(img.enumerate_pixels()
+            .filter(|(x, _, _)| (left..left + 64).contains(x))
+            .map(|(_, _, p)| p[0] as f32)
+            .collect());
+        let expected = |left: u32| mean((left..left + 64).map(|x| gradient.get_pixel(x, 0)[0] as f32 / 257.0).collect());
+
+        let rounded = quantize::<_, Luma<u8>>(&gradient, Dither::None);
+        assert!(rounded.pixels().all(|p| (100..=102).contains(&p[0])));
+        assert!((block(&rounded, 0) - expected(0)).abs() > 0.1);
+        for dither in [Dither::Ordered, Dither::BlueNoise] {
+            let dithered = quantize::<_, Luma<u8>>(&gradient, dither);
+            // Blocks average to their 16-bit value instead of stepping
+            for left in [0, 64, 128, 192] {
+                assert!((block(&dithered, left) - expected(left)).abs() < 0.05, "{}: {} vs {}", dither, block(&dithered, left), expected(left));
+            }
+            // Exact levels stay untouched
+            assert!((0..64).all(|y| dithered.get_pixel(0, y)[0] == 100));
+        }
+        assert_eq!("blue-noise".parse::<Dither>().unwrap(), Dither::BlueNoise);
+        assert!("dots".parse::<Dither>().is_err());
+    }
+}
@@ -0,0 +1,545 @@
+//! Encoder settings used when saving images.
+
+use crate::metadata::{EXIF_HEADER, XMP_HEADER};
+use crate::{ImageError, Metadata, Result};
+use image::{ColorType, DynamicImage, ImageFormat, RgbImage};
+use std::io::{Seek, Write};
+
+/// Chroma subsampling used for JPEG output.
+///
+/// Sharpening adds fine color detail at edges; 4:2:0 averages chroma over
+/// 2x2 blocks and smears part of that detail back out on re-encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChromaSubsampling {
+    /// Full-resolution chroma (4:4:4)
+    #[default]
+    Yuv444,
+    /// Half horizontal chroma resolution (4:2:2)
+    Yuv422,
+    /// Half horizontal and vertical chroma resolution (4:2:0)
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    fn sampling_factor(self) -> jpeg_encoder::SamplingFactor {
+        match self {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+impl std::str::FromStr for ChromaSubsampling {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.replace(':', "").as_str() {
+            "444" => Ok(ChromaSubsampling::Yuv444),
+            "422" => Ok(ChromaSubsampling::Yuv422),
+            "420" => Ok(ChromaSubsampling::Yuv420),
+            _ => Err(ImageError::InvalidParameter {
+                param: "chroma_subsampling".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChromaSubsampling::Yuv444 => write!(f, "4:4:4"),
+            ChromaSubsampling::Yuv422 => write!(f, "4:2:2"),
+            ChromaSubsampling::Yuv420 => write!(f, "4:2:0"),
+        }
+    }
+}
+
+/// PNG compression level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngCompression {
+    /// Fast, minimal compression
+    Fast,
+    /// Balanced speed and size
+    #[default]
+    Default,
+    /// Smallest files, slowest to encode
+    Best,
+}
+
+impl PngCompression {
+    pub(crate) fn compression_type(self) -> image::codecs::png::CompressionType {
+        match self {
+            PngCompression::Fast => image::codecs::png::CompressionType::Fast,
+            PngCompression::Default => image::codecs::png::CompressionType::Default,
+            PngCompression::Best => image::codecs::png::CompressionType::Best,
+        }
+    }
+}
+
+impl std::str::FromStr for PngCompression {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "fast" => Ok(PngCompression::Fast),
+            "default" => Ok(PngCompression::Default),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(ImageError::InvalidParameter {
+                param: "png_compression".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for PngCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngCompression::Fast => write!(f, "fast"),
+            PngCompression::Default => write!(f, "default"),
+            PngCompression::Best => write!(f, "best"),
+        }
+    }
+}
+
+/// Options controlling how images are encoded on save.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{ChromaSubsampling, EncodeOptions, Image};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let options = EncodeOptions::default()
+///     .jpeg_quality(92)
+///     .chroma_subsampling(ChromaSubsampling::Yuv444);
+///
+/// let image = Image::load("photo.jpg")?.unsharp_mask(1.0, 1.0, 0)?;
+/// image.save_with_options("photo_sharp.jpg", &options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodeOptions {
+    /// JPEG quality (1-100)
+    pub jpeg_quality: u8,
+    /// JPEG chroma subsampling
+    pub chroma_subsampling: ChromaSubsampling,
+    /// PNG compression level
+    pub png_compression: PngCompression,
+    /// WebP quality (0-100) for lossy WebP
+    pub webp_quality: u8,
+    /// Whether WebP is written losslessly, ignoring `webp_quality`
+    pub webp_lossless: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 75,
+            chroma_subsampling: ChromaSubsampling::Yuv444,
+            png_compression: PngCompression::Default,
+            webp_quality: 75,
+            webp_lossless: true,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Sets the JPEG quality (1-100).
+    pub fn jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = quality;
+        self
+    }
+
+    /// Sets the JPEG chroma subsampling.
+    pub fn chroma_subsampling(mut self, subsampling: ChromaSubsampling) -> Self {
+        self.chroma_subsampling = subsampling;
+        self
+    }
+
+    /// Sets the PNG compression level.
+    pub fn png_compression(mut self, compression: PngCompression) -> Self {
+        self.png_compression = compression;
+        self
+    }
+
+    /// Sets the quality (0-100) of lossy WebP.
+    pub fn webp_quality(mut self, quality: u8) -> Self {
+        self.webp_quality = quality;
+        self
+    }
+
+    /// Sets whether WebP is lossless (the default) or lossy at
+    /// `webp_quality`. Lossy WebP needs the `lossy-webp` feature.
+    pub fn webp_lossless(mut self, lossless: bool) -> Self {
+        self.webp_lossless = lossless;
+        self
+    }
+}
+
+/// Output format plus [`EncodeOptions`] for [`Image::save_with`](crate::Image::save_with).
+/// 
+/// # Example
+/// ```no_run
+/// use sharpy::{EncodeOptions, Image, PngCompression, SaveOptions};
+/// use image::ImageFormat;
+/// 
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let image = Image::load("photo.png")?.unsharp_mask(1.0, 1.0, 0)?;
+/// 
+/// // Format from the extension, smallest PNG
+/// let smallest = EncodeOptions::default().png_compression(PngCompression::Best);
+/// image.save_with("photo_sharp.png", &SaveOptions::from(smallest))?;
+/// 
+/// // Explicit format, whatever the file is called
+/// let options = SaveOptions::default().format(ImageFormat::Jpeg).encode(EncodeOptions::default().jpeg_quality(90));
+/// image.save_with("upload.bin", &options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SaveOptions {
+    /// Output format; detected from the file extension when `None`
+    pub format: Option<image::ImageFormat>,
+    /// Encoder settings
+    pub encode: EncodeOptions,
+}
+
+impl SaveOptions {
+    /// Sets the output format instead of using the file extension.
+    pub fn format(mut self, format: image::ImageFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Sets the encoder settings.
+    pub fn encode(mut self, encode: EncodeOptions) -> Self {
+        self.encode = encode;
+        self
+    }
+}
+
+impl From<EncodeOptions> for SaveOptions {
+    fn from(encode: EncodeOptions) -> Self {
+        Self { format: None, encode }
+    }
+}
+
+/// Borrowed pixels handed to an encoder.
+#[derive(Clone, Copy)]
+pub(crate) struct Pixels<'a> {
+    pub bytes: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub color: ColorType,
+}
+
+impl<'a> From<&'a RgbImage> for Pixels<'a> {
+    fn from(img: &'a RgbImage) -> Self {
+        Self { bytes: img.as_raw(), width: img.width(), height: img.height(), color: ColorType::Rgb8 }
+    }
+}
+
+impl<'a> From<&'a DynamicImage> for Pixels<'a> {
+    fn from(img: &'a DynamicImage) -> Self {
+        Self { bytes: img.as_bytes(), width: img.width(), height: img.height(), color: img.color() }
+    }
+}
+
+/// Pixel format written for `format` given the source's: kept where the
+/// encoder supports it, 8-bit gray for grayscale JPEG and RGB8 otherwise.
+pub(crate) fn output_color(format: ImageFormat, source: ColorType) -> ColorType {
+    use ColorType::*;
+    match (format, source) {
+        (ImageFormat::Png, L8 | La8 | Rgb8 | Rgba8 | L16 | La16 | Rgb16 | Rgba16) => source,
+        (ImageFormat::Tiff, L8 | Rgb8 | Rgba8 | L16 | Rgb16 | Rgba16) => source,
+        (ImageFormat::Jpeg, L8 | La8 | L16 | La16) => L8,
+        _ => Rgb8,
+    }
+}
+
+/// Encodes `img` in `format` into `writer`, with the profile and metadata
+/// where the format supports them.
+pub(crate) fn write<W: Write + Seek>(
+    img: Pixels<'_>,
+    writer: &mut W,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    icc_profile: Option<&[u8]>,
+    metadata: &Metadata,
+) -> Result<()> {
+    match format {
+        ImageFormat::Jpeg => write_jpeg(img, writer, options, icc_profile, metadata),
+        ImageFormat::Png => {
+            let encoder = image::codecs::png::PngEncoder::new_with_quality(
+                writer,
+                options.png_compression.compression_type(),
+                image::codecs::png::FilterType::Adaptive,
+            );
+            write_with_profile(img, encoder, icc_profile)
+        }
+        ImageFormat::WebP if options.webp_lossless => {
+            let encoder = image::codecs::webp::WebPEncoder::new_lossless(writer);
+            write_with_profile(img, encoder, icc_profile)
+        }
+        ImageFormat::WebP => write_lossy_webp(img, writer, options.webp_quality, icc_profile),
+        _ => {
+            image::write_buffer_with_format(writer, img.bytes, img.width, img.height, img.color, format)?;
+            Ok(())
+        }
+    }
+}
+
+/// Largest payload of a JPEG APP segment.
+const MAX_APP_SEGMENT: usize = 65533;
+
+/// Encodes 8-bit RGB or grayscale pixels as JPEG into `writer`.
+pub(crate) fn write_jpeg<W: Write>(
+    img: Pixels<'_>,
+    writer: W,
+    options: &EncodeOptions,
+    icc_profile: Option<&[u8]>,
+    metadata: &Metadata,
+) -> Result<()> {
+    if !(1..=100).contains(&options.jpeg_quality) {
+        return Err(ImageError::InvalidParameter {
+            param: "jpeg_quality".to_string(),
+            value: options.jpeg_quality.to_string(),
+        });
+    }
+
+    let color = match img.color {
+        ColorType::Rgb8 => jpeg_encoder::ColorType::Rgb,
+        ColorType::L8 => jpeg_encoder::ColorType::Luma,
+        other => return Err(ImageError::Encode(format!("JPEG cannot encode {:?} pixels", other))),
+    };
+    let (width, height) = (img.width, img.height);
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+
+    let mut encoder = jpeg_encoder::Encoder::new(writer, options.jpeg_quality);
+    encoder.set_sampling_factor(options.chroma_subsampling.sampling_factor());
+    if let Some(profile) = icc_profile {
+        encoder.add_icc_profile(profile).map_err(|e| ImageError::Encode(e.to_string()))?;
+    }
+    let segments = [(EXIF_HEADER, &metadata.exif), (XMP_HEADER, &metadata.xmp)];
+    for (header, data) in segments {
+        let segment = data.as_ref().map(|data| [header, data].concat());
+        // Larger segments would need splitting (e.g. extended XMP); drop them
+        if let Some(segment) = segment.filter(|segment| segment.len() <= MAX_APP_SEGMENT) {
+            encoder.add_app_segment(1, segment).map_err(|e| ImageError::Encode(e.to_string()))?;
+        }
+    }
+    encoder
+        .encode(img.bytes, width as u16, height as u16, color)
+        .map_err(|e| ImageError::Encode(e.to_string()))
+}
+
+/// Encodes 8-bit RGB(A) pixels as lossy WebP into `writer`.
+#[cfg(feature = "lossy-webp")]
+fn write_lossy_webp<W: Write>(img: Pixels<'_>, mut writer: W, quality: u8, icc_profile: Option<&[u8]>) -> Result<()> {
+    if quality > 100 {
+        return Err(ImageError::InvalidParameter {
+            param: "webp_quality".to_string(),
+            value: quality.to_string(),
+        });
+    }
+    let layout = match img.color {
+        ColorType::Rgb8 => webp::PixelLayout::Rgb,
+        ColorType::Rgba8 => webp::PixelLayout::Rgba,
+        other => return Err(ImageError::Encode(format!("WebP cannot encode {:?} pixels", other))),
+    };
+    if img.width == 0 || img.height == 0 {
+        return Err(ImageError::InvalidDimensions { width: img.width, height: img.height });
+    }
+    let encoded = webp::Encoder::new(img.bytes, layout, img.width, img.height)
+        .encode_simple(false, quality as f32)
+        .map_err(|e| ImageError::Encode(format!("{:?}", e)))?;
+    match icc_profile {
+        Some(profile) => writer.write_all(&with_webp_profile(&encoded, profile, img.width, img.height)?)?,
+        None => writer.write_all(&encoded)?,
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "lossy-webp"))]
+fn write_lossy_webp<W: Write>(_: Pixels<'_>, _: W, _: u8, _: Option<&[u8]>) -> Result<()> {
+    Err(ImageError::Encode("lossy WebP needs the `lossy-webp` feature".to_string()))
+}
+
+/// Adds an `ICCP` chunk to a WebP file from libwebp, as it doesn't embed
+/// profiles itself.
+///
+/// A simple-format file (one `VP8 ` or `VP8L` chunk) is rewrapped in the
+/// extended format; an extended one (`VP8X` first, as libwebp writes for
+/// images with alpha) gets the profile flag set and the chunk inserted
+/// after `VP8X`, where the container spec requires it.
+#[cfg(feature = "lossy-webp")]
+fn with_webp_profile(webp: &[u8], profile: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    /// `VP8X` flag marking an ICC profile
+    const ICC_FLAG: u8 = 0x20;
+    /// `VP8X` flag marking alpha
+    const ALPHA_FLAG: u8 = 0x10;
+
+    let malformed = || ImageError::Encode("libwebp wrote a malformed WebP file".to_string());
+    if webp.len() < 12 || &webp[..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        return Err(malformed());
+    }
+
+    let mut chunks = Vec::new();
+    let mut rest = &webp[12..];
+    while !rest.is_empty() {
+        let size = rest.get(4..8).ok_or_else(malformed)?;
+        let size = u32::from_le_bytes(size.try_into().unwrap()) as usize;
+        let data = rest.get(8..8 + size).ok_or_else(malformed)?;
+        chunks.push((&rest[..4], data));
+        // Chunks are padded to an even size
+        rest = rest.get(8 + size + size % 2..).unwrap_or_default();
+    }
+
+    let chunk = |fourcc: &[u8], data: &[u8]| {
+        let mut chunk = [fourcc, &(data.len() as u32).to_le_bytes(), data].concat();
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    };
+    let (vp8x, images) = match chunks.split_first() {
+        Some(((b"VP8X", data), images)) if data.len() >= 10 => {
+            let mut vp8x = data.to_vec();
+            vp8x[0] |= ICC_FLAG;
+            (vp8x, images)
+        }
+        Some(((fourcc @ (b"VP8 " | b"VP8L"), data), _)) => {
+            // A lossless bitstream records alpha in bit 28 of its header
+            let alpha = *fourcc == b"VP8L" && data.get(1..5).is_some_and(|header| header[3] & 0x10 != 0);
+            let canvas = |size: u32| (size - 1).to_le_bytes()[..3].to_vec();
+            let flags = if alpha { ICC_FLAG | ALPHA_FLAG } else { ICC_FLAG };
+            ([&[flags, 0, 0, 0][..], &canvas(width), &canvas(height)].concat(), &chunks[..])
+        }
+        _ => return Err(malformed()),
+    };
+
+    let mut body = [&b"WEBP"[..], &chunk(b"VP8X", &vp8x), &chunk(b"ICCP", profile)].concat();
+    for &(fourcc, data) in images.iter().filter(|(fourcc, _)| *fourcc != b"ICCP") {
+        body.extend(chunk(fourcc, data));
+    }
+    Ok([&b"RIFF"[..], &(body.len() as u32).to_le_bytes(), &body].concat())
+}
+
+/// Encodes `img` with `encoder`, embedding `icc_profile` when given.
+pub(crate) fn write_with_profile(
+    img: Pixels<'_>,
+    mut encoder: impl image::ImageEncoder,
+    icc_profile: Option<&[u8]>,
+) -> Result<()> {
+    if let Some(profile) = icc_profile {
+        encoder.set_icc_profile(profile.to_vec())
+            .map_err(|e| ImageError::Encode(e.to_string()))?;
+    }
+    encoder.write_image(img.bytes, img.width, img.height, img.color.into())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// Returns the sampling byte of the first (luma) component in the SOF header.
+    fn luma_sampling(jpeg: &[u8]) -> u8 {
+        let sof = jpeg.windows(2)
+            .position(|w| w[0] == 0xFF && (w[1] == 0xC0 || w[1] == 0xC2))
+            .expect("SOF marker");
+        // marker(2) length(2) precision(1) height(2) width(2) count(1) id(1) sampling(1)
+        jpeg[sof + 11]
+    }
+
+    fn test_image() -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, y| Rgb([(x * 8) as u8, (y * 8) as u8, 128]))
+    }
+
+    #[test]
+    fn test_chroma_subsampling_written() {
+        let img = test_image();
+
+        let mut full = Vec::new();
+        write_jpeg(Pixels::from(&img), &mut full, &EncodeOptions::default(), None, &Metadata::default()).unwrap();
+        assert_eq!(luma_sampling(&full), 0x11);
+
+        let mut sub = Vec::new();
+        let options = EncodeOptions::default().chroma_subsampling(ChromaSubsampling::Yuv420);
+        write_jpeg(Pixels::from(&img), &mut sub, &options, None, &Metadata::default()).unwrap();
+        assert_eq!(luma_sampling(&sub), 0x22);
+
+        let decoded = image::load_from_memory(&sub).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (32, 32));
+    }
+
+    #[test]
+    #[cfg(feature = "lossy-webp")]
+    fn test_lossy_webp() {
+        use image::{GenericImageView, ImageDecoder};
+
+        let img = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, ((x ^ y) * 4) as u8, (y * 4) as u8]));
+        let encode = |options: &EncodeOptions, icc_profile: Option<&[u8]>| {
+            let mut cursor = std::io::Cursor::new(Vec::new());
+            write(Pixels::from(&img), &mut cursor, ImageFormat::WebP, options, icc_profile, &Metadata::default()).unwrap();
+            cursor.into_inner()
+        };
+        let lossless = encode(&EncodeOptions::default(), None);
+        let low = encode(&EncodeOptions::default().webp_lossless(false).webp_quality(20), None);
+        let high = encode(&EncodeOptions::default().webp_lossless(false).webp_quality(95), None);
+        assert!(low.len() < high.len() && high.len() < lossless.len(), "{} {} {}", low.len(), high.len(), lossless.len());
+        assert_eq!(image::load_from_memory(&lossless).unwrap().to_rgb8(), img);
+        assert_eq!(image::load_from_memory(&low).unwrap().dimensions(), (64, 64));
+
+        let profile = b"not really a profile";
+        let tagged = encode(&EncodeOptions::default().webp_lossless(false), Some(profile));
+        let mut decoder = image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(&tagged)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_deref(), Some(&profile[..]));
+        assert_eq!(image::load_from_memory(&tagged).unwrap().dimensions(), (64, 64));
+
+        // libwebp writes alpha in the extended format already
+        let rgba = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, 128, (y * 4) as u8, if x < 32 { 255 } else { 0 }])
+        }));
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        let options = EncodeOptions::default().webp_lossless(false);
+        write(Pixels::from(&rgba), &mut cursor, ImageFormat::WebP, &options, Some(profile), &Metadata::default()).unwrap();
+        let tagged = cursor.into_inner();
+        assert_eq!(&tagged[12..16], b"VP8X");
+        assert_eq!(&tagged[30..34], b"ICCP");
+        let mut decoder = image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(&tagged)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_deref(), Some(&profile[..]));
+        let decoded = image::load_from_memory(&tagged).unwrap().to_rgba8();
+        assert_eq!((decoded.get_pixel(0, 0)[3], decoded.get_pixel(63, 0)[3]), (255, 0));
+        assert!(with_webp_profile(&tagged[..20], profile, 64, 64).is_err());
+
+        let options = EncodeOptions::default().webp_lossless(false).webp_quality(101);
+        assert!(write(Pixels::from(&img), &mut std::io::Cursor::new(Vec::new()), ImageFormat::WebP, &options, None, &Metadata::default()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_quality() {
+        let options = EncodeOptions::default().jpeg_quality(0);
+        assert!(write_jpeg(Pixels::from(&test_image()), Vec::new(), &options, None, &Metadata::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_png_compression() {
+        assert_eq!("Best".parse::<PngCompression>().unwrap(), PngCompression::Best);
+        assert_eq!(PngCompression::Fast.to_string(), "fast");
+        assert!("max".parse::<PngCompression>().is_err());
+    }
+
+    #[test]
+    fn test_parse_subsampling() {
+        assert_eq!("4:2:0".parse::<ChromaSubsampling>().unwrap(), ChromaSubsampling::Yuv420);
+        assert_eq!("444".parse::<ChromaSubsampling>().unwrap(), ChromaSubsampling::Yuv444);
+        assert!("411".parse::<ChromaSubsampling>().is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! C interface for using Sharpy from C, C++, C# and other languages.
+//!
+//! Images are opaque `SharpyImage` handles created by `sharpy_load`,
+//! `sharpy_from_rgb` or `sharpy_from_rgba` and released with `sharpy_free`.
+//! Functions returning `int` return `SHARPY_OK` (0) on success or a negative
+//! error code; functions returning a handle return null on failure. The
+//! message for the last failure on the calling thread is available from
+//! `sharpy_last_error`. See `include/sharpy.h` for the C declarations.
+
+use crate::{Image, Pipeline};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Opaque image handle.
+pub struct SharpyImage(Image);
+
+/// Success.
+pub const SHARPY_OK: c_int = 0;
+/// A required pointer was null, or a string was not valid UTF-8.
+pub const SHARPY_ERR_ARGUMENT: c_int = -1;
+/// The operation failed; see `sharpy_last_error`.
+pub const SHARPY_ERR_FAILED: c_int = -2;
+/// Sharpy panicked; the handle should not be used further.
+pub const SHARPY_ERR_PANIC: c_int = -3;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, converting errors and panics into error codes.
+fn guard<F>(f: F) -> c_int
+where
+    F: FnOnce() -> Result<(), c_int>,
+{
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => SHARPY_OK,
+        Ok(Err(code)) => code,
+        Err(_) => {
+            set_last_error("internal panic");
+            SHARPY_ERR_PANIC
+        }
+    }
+}
+
+/// Runs `f`, converting errors and panics into a null handle.
+fn guard_new<F>(f: F) -> *mut SharpyImage
+where
+    F: FnOnce() -> Result<Image, c_int>,
+{
+    let mut image = None;
+    guard(|| {
+        image = Some(f()?);
+        Ok(())
+    });
+    image.map_or(ptr::null_mut(), |image| Box::into_raw(Box::new(SharpyImage(image))))
+}
+
+fn failed(error: crate::ImageError) -> c_int {
+    set_last_error(error.to_string());
+    SHARPY_ERR_FAILED
+}
+
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str, c_int> {
+    if value.is_null() {
+        set_last_error(format!("{} is null", name));
+        return Err(SHARPY_ERR_ARGUMENT);
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| {
+        set_last_error(format!("{} is not valid UTF-8", name));
+        SHARPY_ERR_ARGUMENT
+    })
+}
+
+unsafe fn image_arg<'a>(image: *mut SharpyImage) -> Result<&'a mut Image, c_int> {
+    match image.as_mut() {
+        Some(handle) => Ok(&mut handle.0),
+        None => {
+            set_last_error("image is null");
+            Err(SHARPY_ERR_ARGUMENT)
+        }
+    }
+}
+
+unsafe fn bytes_arg<'a>(data: *const u8, len: usize) -> Result<&'a [u8], c_int> {
+    if data.is_null() {
+        set_last_error("data is null");
+        return Err(SHARPY_ERR_ARGUMENT);
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+/// Returns the message for the last error on this thread, or null.
+///
+/// The string stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn sharpy_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Loads an image file.
+///
+/// # Safety
+/// `path` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_load(path: *const c_char) -> *mut SharpyImage {
+    guard_new(|| Image::load(str_arg(path, "path")?).map_err(failed))
+}
+
+/// Creates an image from `len` bytes of tightly packed RGB pixels.
+///
+/// # Safety
+/// `data` must be null or point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_from_rgb(
+    data: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+) -> *mut SharpyImage {
+    guard_new(|| Image::from_raw_ref(width, height, bytes_arg(data, len)?).map_err(failed))
+}
+
+/// Creates an image from `len` bytes of tightly packed RGBA pixels; alpha is
+/// discarded.
+///
+/// # Safety
+/// `data` must be null or point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_from_rgba(
+    data: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+) -> *mut SharpyImage {
+    guard_new(|| Image::from_rgba_bytes(width, height, bytes_arg(data, len)?).map_err(failed))
+}
+
+/// Releases an image. Passing null is a no-op.
+///
+/// # Safety
+/// `image` must be null or a handle returned by Sharpy that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_free(image: *mut SharpyImage) {
+    if !image.is_null() {
+        drop(Box::from_raw(image));
+    }
+}
+
+/// Writes the image dimensions to `width` and `height`.
+///
+/// # Safety
+/// `image` must be a valid handle; `width` and `height` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_dimensions(
+    image: *mut SharpyImage,
+    width: *mut u32,
+    height: *mut u32,
+) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        if width.is_null() || height.is_null() {
+            set_last_error("width or height is null");
+            return Err(SHARPY_ERR_ARGUMENT);
+        }
+        let (w, h) = image.dimensions();
+        *width = w;
+        *height = h;
+        Ok(())
+    })
+}
+
+/// Applies an unsharp mask in place.
+///
+/// # Safety
+/// `image` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_unsharp(
+    image: *mut SharpyImage,
+    radius: f32,
+    amount: f32,
+    threshold: u8,
+) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        *image = image.unsharp_mask_ref(radius, amount, threshold).map_err(failed)?;
+        Ok(())
+    })
+}
+
+/// Applies a pipeline in the CLI syntax (e.g. `"unsharp:1:1:0,clarity:0.5:2"`)
+/// in place.
+///
+/// # Safety
+/// `image` must be a valid handle and `pipeline` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_pipeline_run(image: *mut SharpyImage, pipeline: *const c_char) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        let pipeline = Pipeline::parse(str_arg(pipeline, "pipeline")?).map_err(failed)?;
+        *image = pipeline.apply(image.clone()).map_err(failed)?;
+        Ok(())
+    })
+}
+
+/// Saves the image; the format is chosen from the file extension.
+///
+/// # Safety
+/// `image` must be a valid handle and `path` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_save(image: *mut SharpyImage, path: *const c_char) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        image.save_with_options(str_arg(path, "path")?, &Default::default()).map_err(failed)
+    })
+}
+
+/// Copies the pixels as RGB into `out`, which must hold width * height * 3 bytes.
+///
+/// # Safety
+/// `image` must be a valid handle and `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_copy_rgb(image: *mut SharpyImage, out: *mut u8, len: usize) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        copy_out(image.data.get_ref().as_raw(), out, len)
+    })
+}
+
+/// Copies the pixels as RGBA (opaque alpha) into `out`, which must hold
+/// width * height * 4 bytes.
+///
+/// # Safety
+/// `image` must be a valid handle and `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sharpy_copy_rgba(image: *mut SharpyImage, out: *mut u8, len: usize) -> c_int {
+    guard(|| {
+        let image = image_arg(image)?;
+        copy_out(&image.to_rgba_bytes(), out, len)
+    })
+}
+
+unsafe fn copy_out(bytes: &[u8], out: *mut u8, len: usize) -> Result<(), c_int> {
+    if out.is_null() || len != bytes.len() {
+        set_last_error(format!("output buffer must hold {} bytes", bytes.len()));
+        return Err(SHARPY_ERR_ARGUMENT);
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), out, len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_round_trip() {
+        let pixels: Vec<u8> = (0..16 * 16 * 3).map(|i| (i % 251) as u8).collect();
+        unsafe {
+            let image = sharpy_from_rgb(pixels.as_ptr(), pixels.len(), 16, 16);
+            assert!(!image.is_null());
+
+            let (mut width, mut height) = (0, 0);
+            assert_eq!(sharpy_dimensions(image, &mut width, &mut height), SHARPY_OK);
+            assert_eq!((width, height), (16, 16));
+
+            assert_eq!(sharpy_unsharp(image, 1.0, 1.0, 0), SHARPY_OK);
+            let pipeline = CString::new("highpass:0.3,clarity:0.5:2").unwrap();
+            assert_eq!(sharpy_pipeline_run(image, pipeline.as_ptr()), SHARPY_OK);
+
+            let mut out = vec![0u8; 16 * 16 * 4];
+            assert_eq!(sharpy_copy_rgba(image, out.as_mut_ptr(), out.len()), SHARPY_OK);
+            assert!(out.chunks(4).all(|p| p[3] == 255));
+            sharpy_free(image);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            assert!(sharpy_from_rgb(ptr::null(), 0, 1, 1).is_null());
+            assert_eq!(sharpy_unsharp(ptr::null_mut(), 1.0, 1.0, 0), SHARPY_ERR_ARGUMENT);
+
+            let pixels = [0u8; 12];
+            let image = sharpy_from_rgb(pixels.as_ptr(), pixels.len(), 2, 2);
+            assert_eq!(sharpy_unsharp(image, 50.0, 1.0, 0), SHARPY_ERR_FAILED);
+            let message = CStr::from_ptr(sharpy_last_error()).to_str().unwrap();
+            assert!(message.contains("radius"));
+            sharpy_free(image);
+        }
+    }
+}
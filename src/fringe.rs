@@ -0,0 +1,124 @@
+//! Suppression of the color fringes that sharpening RGB channels separately
+//! leaves along high-contrast edges.
+//!
+//! Each channel overshoots by a different amount, so a neutral-looking edge
+//! gains purple or green halos. [`clamp_chroma`] keeps the sharpened
+//! luminance but limits each pixel's color (its blue and red differences
+//! from luminance) to the range found in the original around it, so
+//! sharpening can't add colors that weren't there.
+
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample, unit_scale};
+use crate::utils::Luminance;
+
+/// How far, in pixels, the original is searched for colors a pixel may keep.
+pub(crate) const RADIUS: u32 = 1;
+
+/// Blue and red differences from luminance.
+fn chroma<P: SharpenPixel>(pixel: &P, luminance: Luminance) -> (f32, f32) {
+    let luma = luminance.of(pixel);
+    let channels = pixel.channels();
+    (sample::<P>(channels[2]) - luma, sample::<P>(channels[0]) - luma)
+}
+
+/// Clamps the chroma of every pixel of `processed` to the range within
+/// [`RADIUS`] of it in `original`, widened by `tolerance` (in 8-bit units),
+/// keeping its `luminance`. Grayscale images are left unchanged.
+pub(crate) fn clamp_chroma<P: SharpenPixel>(original: &Buffer<P>, processed: &mut Buffer<P>, tolerance: f32, luminance: Luminance) {
+    if P::COLOR_CHANNELS < 3 {
+        return;
+    }
+    let (width, height) = original.dimensions();
+    let tolerance = tolerance * unit_scale::<P>();
+    let max = max_value::<P>();
+    let planes: Vec<(f32, f32)> = original.pixels().map(|pixel| chroma(pixel, luminance)).collect();
+    let [kr, kg, kb] = luminance.weights();
+
+    processed.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            let rows = y.saturating_sub(RADIUS)..=(y + RADIUS).min(height - 1);
+            for (x, _, pixel) in row {
+                let columns = x.saturating_sub(RADIUS)..=(x + RADIUS).min(width - 1);
+                let (mut low, mut high) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+                for ny in rows.clone() {
+                    for nx in columns.clone() {
+                        let (cb, cr) = planes[(ny * width + nx) as usize];
+                        low = (low.0.min(cb), low.1.min(cr));
+                        high = (high.0.max(cb), high.1.max(cr));
+                    }
+                }
+                let (cb, cr) = chroma(pixel, luminance);
+                let clamped_cb = cb.clamp(low.0 - tolerance, high.0 + tolerance);
+                let clamped_cr = cr.clamp(low.1 - tolerance, high.1 + tolerance);
+                if clamped_cb == cb && clamped_cr == cr {
+                    continue;
+                }
+                // Rebuild RGB around the sharpened luminance, desaturating
+                // rather than shifting hue where a channel would clip
+                let luma = luminance.of(pixel).clamp(0.0, max);
+                let offsets = [clamped_cr, -(kr * clamped_cr + kb * clamped_cb) / kg, clamped_cb];
+                let fit = offsets.iter().fold(1.0f32, |fit, &offset| match offset {
+                    offset if offset > 0.0 => fit.min((max - luma) / offset),
+                    offset if offset < 0.0 => fit.min(-luma / offset),
+                    _ => fit,
+                });
+                for (value, offset) in pixel.channels_mut().iter_mut().zip(offsets) {
+                    *value = to_sample::<P>((luma + fit * offset).clamp(0.0, max));
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Image, ImageError};
+    use image::{Rgb, RgbImage};
+
+    /// Largest chroma excursion of `img` beyond the range of `original`
+    /// around each pixel.
+    fn worst_fringe(original: &RgbImage, img: &RgbImage) -> f32 {
+        let (width, height) = original.dimensions();
+        let mut worst: f32 = 0.0;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let (cb, cr) = chroma(pixel, Luminance::default());
+            let neighbors: Vec<(f32, f32)> = (y.saturating_sub(1)..=(y + 1).min(height - 1))
+                .flat_map(|ny| (x.saturating_sub(1)..=(x + 1).min(width - 1)).map(move |nx| (nx, ny)))
+                .map(|(nx, ny)| chroma(original.get_pixel(nx, ny), Luminance::default()))
+                .collect();
+            let beyond = |value: f32, range: (f32, f32)| (range.0 - value).max(value - range.1).max(0.0);
+            let cb_range = neighbors.iter().fold((f32::MAX, f32::MIN), |(lo, hi), c| (lo.min(c.0), hi.max(c.0)));
+            let cr_range = neighbors.iter().fold((f32::MAX, f32::MIN), |(lo, hi), c| (lo.min(c.1), hi.max(c.1)));
+            worst = worst.max(beyond(cb, cb_range)).max(beyond(cr, cr_range));
+        }
+        worst
+    }
+
+    #[test]
+    fn test_chroma_clamp() {
+        // Warm highlights against a cool shadow
+        let original = RgbImage::from_fn(48, 32, |x, _| if x < 24 { Rgb([180, 150, 120]) } else { Rgb([70, 80, 110]) });
+        let image = Image::from_rgb(original.clone()).unwrap();
+        let plain = image.clone().sharpen().unsharp_mask(2.0, 1.0, 0).apply().unwrap().into_rgb();
+        let clamped = image.clone().sharpen().unsharp_mask(2.0, 1.0, 0).chroma_clamp(0.0).apply().unwrap().into_rgb();
+
+        assert!(worst_fringe(&original, &plain) > 10.0, "{}", worst_fringe(&original, &plain));
+        // Within 8-bit rounding
+        assert!(worst_fringe(&original, &clamped) < 2.0, "{}", worst_fringe(&original, &clamped));
+        // Luminance is still sharpened
+        for (a, b) in plain.pixels().zip(clamped.pixels()) {
+            assert!((Luminance::default().of(a) - Luminance::default().of(b)).abs() < 1.5);
+        }
+        assert_ne!(clamped, original);
+
+        let gray = Image::from_buffer(image::GrayImage::from_fn(8, 8, |x, _| image::Luma([x as u8 * 30]))).unwrap();
+        let expected = gray.clone().sharpen().high_pass(0.5).apply().unwrap();
+        assert_eq!(gray.sharpen().high_pass(0.5).chroma_clamp(2.0).apply().unwrap().into_buffer(), expected.into_buffer());
+
+        // Before any operation there is nothing to clamp
+        let stray = image.sharpen().chroma_clamp(2.0).unsharp_mask(2.0, 1.0, 0);
+        assert!(matches!(stray.validate(), Err(ImageError::InvalidParameter { param, .. }) if param == "chroma_clamp"));
+        assert!(stray.apply().is_err());
+    }
+}
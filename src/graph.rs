@@ -0,0 +1,551 @@
+//! Processing graphs with parallel branches and merge nodes.
+//!
+//! A [`Graph`] generalizes the linear pipeline: each node takes the output of
+//! earlier nodes, so branches that share an input are evaluated in parallel
+//! and then combined by a merge node.
+
+use crate::context::Context;
+use crate::builder::run_operation;
+use crate::cache::BlurCache;
+use crate::utils::{apply_edge_detection, Boundary, EdgeMethod, Luminance};
+use crate::{Image, ImageData, ImageError, Migration, Operation, Pipeline, Result};
+use crate::mask::structure_buffer;
+use image::{DynamicImage, Rgb, RgbImage};
+use crate::par::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Largest radius accepted by blur nodes.
+const MAX_BLUR_RADIUS: f32 = 100.0;
+
+/// Identifies a node within a [`Graph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// How a merge node combines its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Merge {
+    /// Blends from the first input to the second, weighted per pixel by the
+    /// luminance of the third (mask) input, with the source image's
+    /// [`Luminance`] weights
+    MaskBlend,
+    /// Per-channel maximum of all inputs
+    Max,
+    /// Per-channel average of all inputs
+    Average,
+    /// Blends from the first input to the second at a fixed opacity (0.0-1.0)
+    Blend { opacity: f32 },
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Source,
+    Operation { input: NodeId, operation: Operation },
+    EdgeMask { input: NodeId, method: EdgeMethod },
+    StructureMask { input: NodeId },
+    Blur { input: NodeId, radius: f32 },
+    Merge { inputs: Vec<NodeId>, merge: Merge },
+}
+
+impl Node {
+    fn inputs(&self) -> &[NodeId] {
+        match self {
+            Node::Source => &[],
+            Node::Operation { input, .. }
+            | Node::EdgeMask { input, .. }
+            | Node::StructureMask { input }
+            | Node::Blur { input, .. } => std::slice::from_ref(input),
+            Node::Merge { inputs, .. } => inputs,
+        }
+    }
+}
+
+/// A directed acyclic graph of image operations.
+///
+/// Nodes can only reference nodes created before them, so every graph is
+/// acyclic by construction. Nodes whose inputs are ready are evaluated in
+/// parallel, and intermediate results are shared rather than copied.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{EdgeMethod, Graph, Image, Operation};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut graph = Graph::new();
+/// let src = graph.source();
+///
+/// // One branch sharpens, the other builds an edge mask
+/// let sharp = graph.operation(src, Operation::UnsharpMask { radius: 2.0, amount: 1.5, threshold: 0 });
+/// let mask = graph.edge_mask(src, EdgeMethod::Sobel);
+///
+/// // Only apply the sharpening where edges were found
+/// let out = graph.mask_blend(src, sharp, mask);
+///
+/// let result = graph.run(Image::load("photo.jpg")?, out)?;
+/// # Ok(())
+/// # }
+/// ```
+/// 
+/// Graphs can also be written as scripts of named buffers, see [`Graph::parse`].
+#[derive(Debug, Clone)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    names: BTreeMap<String, NodeId>,
+    output: Option<NodeId>,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Graph {
+    /// Creates a graph containing only the source node.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::Source],
+            names: BTreeMap::new(),
+            output: None,
+        }
+    }
+
+    /// Parses a graph script of named buffers.
+    /// 
+    /// Each statement assigns the result of a call to a name; statements are
+    /// separated by `;` or newlines and `#` starts a comment. `src` is the
+    /// input image and the buffer named `out` (or else the last one assigned)
+    /// becomes the graph's output.
+    /// 
+    /// ```text
+    /// blur1 = gaussian(2.0)               # input defaults to src
+    /// sharp = unsharp(src, 1.5, 1.2, 0)
+    /// mask  = edge_mask(src, sobel)
+    /// out   = mask_blend(blur1, sharp, mask)
+    /// ```
+    /// 
+    /// Available calls: the operations `unsharp`, `highpass`, `edges`,
+    /// `clarity`, `adaptive_unsharp`, `text`, `descreen`, `median`,
+    /// `denoise`, `smart_enhance` and `frequency_separation` (with their
+    /// usual parameters),
+    /// `gaussian(radius)`, `edge_mask(method)`, `structure_mask()`,
+    /// `blend(a, b, opacity)`, `mask_blend(a, b, mask)`, `max(a, b, ...)`
+    /// and `average(a, b, ...)`.
+    pub fn parse(script: &str) -> Result<Self> {
+        Self::parse_with_migrations(script).map(|(graph, _)| graph)
+    }
+
+    /// Parses a graph script, reporting each migration applied to
+    /// operations saved by older releases; see
+    /// [`Pipeline::parse_with_migrations`].
+    pub fn parse_with_migrations(script: &str) -> Result<(Self, Vec<Migration>)> {
+        crate::script::parse(script)
+    }
+
+    /// Loads a graph script from a file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| ImageError::from(e).in_file(path))?;
+        Self::parse(&contents).map_err(|e| match e {
+            ImageError::Parse(msg) => ImageError::Parse(format!("{}: {}", path.display(), msg)),
+            other => other,
+        })
+    }
+
+    /// Assigns a name to a node, replacing any node previously using it.
+    pub fn set_name(&mut self, name: impl Into<String>, node: NodeId) {
+        self.names.insert(name.into(), node);
+    }
+
+    /// Looks up a named node.
+    pub fn node(&self, name: &str) -> Option<NodeId> {
+        if name == "src" {
+            return Some(self.source());
+        }
+        self.names.get(name).copied()
+    }
+
+    /// Sets the node produced by [`Graph::apply`].
+    pub fn set_output(&mut self, node: NodeId) {
+        self.output = Some(node);
+    }
+
+    /// Returns the node produced by [`Graph::apply`], if set.
+    pub fn output(&self) -> Option<NodeId> {
+        self.output
+    }
+
+    /// Evaluates the graph's output node for `image`.
+    pub fn apply(&self, image: Image) -> Result<Image> {
+        let output = self.output.ok_or_else(|| {
+            ImageError::InvalidParameter {
+                param: "output".to_string(),
+                value: "unset".to_string(),
+            }
+        })?;
+        self.run(image, output)
+    }
+
+    /// Returns the node representing the input image.
+    pub fn source(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Returns the number of nodes, including the source.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the graph only contains the source node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a node applying `operation` to the output of `input`.
+    pub fn operation(&mut self, input: NodeId, operation: Operation) -> NodeId {
+        self.push(Node::Operation { input, operation })
+    }
+
+    /// Adds a chain of nodes applying each operation of `pipeline` in order.
+    ///
+    /// Returns `input` unchanged if the pipeline is empty.
+    pub fn pipeline(&mut self, input: NodeId, pipeline: &Pipeline) -> NodeId {
+        pipeline.operations().iter().fold(input, |node, operation| {
+            self.operation(node, operation.clone())
+        })
+    }
+
+    /// Adds a node producing a grayscale edge-magnitude mask of `input`.
+    pub fn edge_mask(&mut self, input: NodeId, method: EdgeMethod) -> NodeId {
+        self.push(Node::EdgeMask { input, method })
+    }
+
+    /// Adds a node producing a grayscale mask of where `input` has edges or
+    /// texture rather than flat areas, see [`mask::structure`](crate::mask::structure).
+    pub fn structure_mask(&mut self, input: NodeId) -> NodeId {
+        self.push(Node::StructureMask { input })
+    }
+
+    /// Adds a node applying a Gaussian blur to `input`.
+    pub fn blur(&mut self, input: NodeId, radius: f32) -> NodeId {
+        self.push(Node::Blur { input, radius })
+    }
+
+    /// Adds a node blending from `base` to `overlay` at a fixed opacity.
+    pub fn blend(&mut self, base: NodeId, overlay: NodeId, opacity: f32) -> NodeId {
+        self.push(Node::Merge { inputs: vec![base, overlay], merge: Merge::Blend { opacity } })
+    }
+
+    /// Adds a node blending from `base` to `overlay` where `mask` is bright.
+    pub fn mask_blend(&mut self, base: NodeId, overlay: NodeId, mask: NodeId) -> NodeId {
+        self.push(Node::Merge { inputs: vec![base, overlay, mask], merge: Merge::MaskBlend })
+    }
+
+    /// Adds a node taking the per-channel maximum of `inputs`.
+    pub fn max(&mut self, inputs: &[NodeId]) -> NodeId {
+        self.push(Node::Merge { inputs: inputs.to_vec(), merge: Merge::Max })
+    }
+
+    /// Adds a node averaging `inputs`.
+    pub fn average(&mut self, inputs: &[NodeId]) -> NodeId {
+        self.push(Node::Merge { inputs: inputs.to_vec(), merge: Merge::Average })
+    }
+
+    /// Evaluates the graph for `image` and returns the result of `output`.
+    ///
+    /// Only nodes that `output` depends on are evaluated.
+    pub fn run(&self, image: Image, output: NodeId) -> Result<Image> {
+        self.check_node(output)?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            // Inputs must precede their consumer (ids from another graph may not)
+            for &input in node.inputs() {
+                if input.0 >= index {
+                    return Err(ImageError::InvalidParameter {
+                        param: "node".to_string(),
+                        value: input.0.to_string(),
+                    });
+                }
+            }
+            if let Node::Operation { operation, .. } = node {
+                operation.validate_with(&image.validation)?;
+            }
+            if let Node::Blur { radius, .. } = node {
+                if *radius <= 0.0 || *radius > MAX_BLUR_RADIUS {
+                    return Err(ImageError::InvalidParameter {
+                        param: "radius".to_string(),
+                        value: radius.to_string(),
+                    });
+                }
+            }
+            if let Node::Merge { inputs, merge } = node {
+                if let Merge::Blend { opacity } = merge {
+                    if !(0.0..=1.0).contains(opacity) {
+                        return Err(ImageError::InvalidParameter {
+                            param: "opacity".to_string(),
+                            value: opacity.to_string(),
+                        });
+                    }
+                }
+                let expected_ok = match merge {
+                    Merge::MaskBlend => inputs.len() == 3,
+                    Merge::Blend { .. } => inputs.len() == 2,
+                    Merge::Max | Merge::Average => !inputs.is_empty(),
+                };
+                if !expected_ok {
+                    return Err(ImageError::InvalidParameter {
+                        param: "merge inputs".to_string(),
+                        value: inputs.len().to_string(),
+                    });
+                }
+            }
+        }
+
+        // Mark nodes needed for the output and count how often each is consumed
+        let mut needed = vec![false; self.nodes.len()];
+        let mut consumers = vec![0usize; self.nodes.len()];
+        needed[output.0] = true;
+        for index in (0..self.nodes.len()).rev() {
+            if !needed[index] {
+                continue;
+            }
+            for input in self.nodes[index].inputs() {
+                needed[input.0] = true;
+                consumers[input.0] += 1;
+            }
+        }
+
+        // Group needed nodes by depth; nodes at the same depth are independent
+        let mut depth = vec![0usize; self.nodes.len()];
+        let mut levels: Vec<Vec<usize>> = Vec::new();
+        for index in 0..self.nodes.len() {
+            if !needed[index] {
+                continue;
+            }
+            depth[index] = self.nodes[index].inputs().iter()
+                .map(|input| depth[input.0] + 1)
+                .max()
+                .unwrap_or(0);
+            if levels.len() <= depth[index] {
+                levels.resize(depth[index] + 1, Vec::new());
+            }
+            levels[depth[index]].push(index);
+        }
+
+        let (icc_profile, metadata, source_color, luminance, validation) =
+            (image.icc_profile.clone(), image.metadata.clone(), image.source_color, image.luminance, image.validation);
+        let source = Arc::new(image.into_rgb());
+        let mut results: Vec<Option<Arc<RgbImage>>> = vec![None; self.nodes.len()];
+        // Blur nodes and unsharp operations on the same input share blurs
+        let blurs = BlurCache::default();
+
+        for level in levels {
+            let computed: Vec<(usize, Result<Arc<RgbImage>>)> = level.par_iter()
+                .map(|&index| (index, self.evaluate(index, &source, &results, &blurs, luminance)))
+                .collect();
+
+            for (index, result) in computed {
+                results[index] = Some(result?);
+            }
+
+            // Release intermediates once every consumer has been evaluated
+            for &index in &level {
+                for input in self.nodes[index].inputs() {
+                    consumers[input.0] -= 1;
+                    if consumers[input.0] == 0 {
+                        if let Some(result) = &results[input.0] {
+                            blurs.evict(result.as_ref(), None);
+                        }
+                        if input.0 != output.0 {
+                            results[input.0] = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        let result = results[output.0].take().expect("output node evaluated");
+        let rgb = Arc::try_unwrap(result).unwrap_or_else(|shared| (*shared).clone());
+        Ok(Image { data: ImageData::Owned(rgb), icc_profile, metadata, source_color, luminance, validation })
+    }
+
+    fn check_node(&self, node: NodeId) -> Result<()> {
+        if node.0 >= self.nodes.len() {
+            return Err(ImageError::InvalidParameter {
+                param: "node".to_string(),
+                value: node.0.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn evaluate(
+        &self,
+        index: usize,
+        source: &Arc<RgbImage>,
+        results: &[Option<Arc<RgbImage>>],
+        blurs: &BlurCache,
+        luminance: Luminance,
+    ) -> Result<Arc<RgbImage>> {
+        let input = |node: &NodeId| -> Arc<RgbImage> {
+            Arc::clone(results[node.0].as_ref().expect("inputs evaluated before consumers"))
+        };
+
+        match &self.nodes[index] {
+            Node::Source => Ok(Arc::clone(source)),
+            Node::Operation { input: node, operation } => {
+                let image = Image::from_shared(input(node)).with_luminance(luminance);
+                let ctx = Context::default().with_blur_cache(blurs, image.data.get_ref()).with_luminance(luminance);
+                let result = run_operation(&image, operation, &ctx).map_err(|e| e.in_operation(operation.name(), None))?;
+                Ok(Arc::new(result.into_rgb()))
+            }
+            Node::EdgeMask { input: node, method } => {
+                Ok(Arc::new(apply_edge_detection(&input(node), *method, &Context::default().with_luminance(luminance))))
+            }
+            Node::StructureMask { input: node } => {
+                Ok(Arc::new(DynamicImage::ImageLuma8(structure_buffer(&input(node), luminance, Boundary::Clamp)).into_rgb8()))
+            }
+            Node::Blur { input: node, radius } => {
+                let input = input(node);
+                let ctx = Context::default().with_blur_cache(blurs, input.as_ref());
+                Ok(ctx.gaussian_blur(&input, *radius))
+            }
+            Node::Merge { inputs, merge } => {
+                let images: Vec<Arc<RgbImage>> = inputs.iter().map(input).collect();
+                let dimensions = images[0].dimensions();
+                if let Some(mismatch) = images.iter().find(|img| img.dimensions() != dimensions) {
+                    let (width, height) = mismatch.dimensions();
+                    return Err(ImageError::InvalidDimensions { width, height });
+                }
+                Ok(Arc::new(merge_images(&images, *merge, luminance)))
+            }
+        }
+    }
+}
+
+fn merge_images(images: &[Arc<RgbImage>], merge: Merge, luminance: Luminance) -> RgbImage {
+    let (width, height) = images[0].dimensions();
+    let mut result = RgbImage::new(width, height);
+
+    result.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            *pixel = match merge {
+                Merge::MaskBlend => {
+                    let base = images[0].get_pixel(x, y);
+                    let overlay = images[1].get_pixel(x, y);
+                    let weight = luminance.of(images[2].get_pixel(x, y)) / 255.0;
+                    Rgb(std::array::from_fn(|i| {
+                        (base[i] as f32 * (1.0 - weight) + overlay[i] as f32 * weight)
+                            .round()
+                            .clamp(0.0, 255.0) as u8
+                    }))
+                }
+                Merge::Max => Rgb(std::array::from_fn(|i| {
+                    images.iter().map(|img| img.get_pixel(x, y)[i]).max().unwrap_or(0)
+                })),
+                Merge::Average => Rgb(std::array::from_fn(|i| {
+                    let sum: u32 = images.iter().map(|img| img.get_pixel(x, y)[i] as u32).sum();
+                    (sum as f32 / images.len() as f32).round() as u8
+                })),
+                Merge::Blend { opacity } => {
+                    let base = images[0].get_pixel(x, y);
+                    let overlay = images[1].get_pixel(x, y);
+                    Rgb(std::array::from_fn(|i| {
+                        (base[i] as f32 * (1.0 - opacity) + overlay[i] as f32 * opacity)
+                            .round()
+                            .clamp(0.0, 255.0) as u8
+                    }))
+                }
+            };
+        }
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_image() -> Image {
+        let img = RgbImage::from_fn(64, 64, |x, y| {
+            let value = if (x / 8 + y / 8) % 2 == 0 { 60 } else { 190 };
+            Rgb([value, value, value])
+        });
+        Image::from_rgb(img).unwrap()
+    }
+
+    #[test]
+    fn test_source_only() {
+        let graph = Graph::new();
+        let image = create_test_image();
+        let expected = image.clone().into_rgb();
+        let result = graph.run(image, graph.source()).unwrap();
+        assert_eq!(result.into_rgb(), expected);
+    }
+
+    #[test]
+    fn test_linear_matches_pipeline() {
+        let pipeline = Pipeline::parse("unsharp:1:1:0,clarity:0.5:2").unwrap();
+        let mut graph = Graph::new();
+        let out = graph.pipeline(graph.source(), &pipeline);
+
+        let expected = pipeline.apply(create_test_image()).unwrap().into_rgb();
+        let result = graph.run(create_test_image(), out).unwrap().into_rgb();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_merge_nodes() {
+        let mut graph = Graph::new();
+        let src = graph.source();
+        let sharp = graph.operation(src, Operation::HighPassSharpen { strength: 1.0 });
+        let avg = graph.average(&[src, src]);
+        let max = graph.max(&[src, sharp]);
+        let mask = graph.edge_mask(src, EdgeMethod::Sobel);
+        let blended = graph.mask_blend(src, sharp, mask);
+
+        let original = create_test_image().into_rgb();
+        assert_eq!(graph.run(create_test_image(), avg).unwrap().into_rgb(), original);
+
+        let max_result = graph.run(create_test_image(), max).unwrap().into_rgb();
+        assert!(max_result.pixels().zip(original.pixels()).all(|(m, o)| m[0] >= o[0]));
+
+        // Flat interior pixels have no edges, so the mask keeps the original there
+        let blend_result = graph.run(create_test_image(), blended).unwrap().into_rgb();
+        assert_eq!(blend_result.get_pixel(4, 4), original.get_pixel(4, 4));
+    }
+
+    #[test]
+    fn test_shared_blur_matches_separate_runs() {
+        let mut graph = Graph::new();
+        let src = graph.source();
+        let blur = graph.blur(src, 2.0);
+        let sharp = graph.operation(src, Operation::UnsharpMask { radius: 2.0, amount: 1.0, threshold: 0 });
+        let both = graph.blend(blur, sharp, 0.5);
+        let result = graph.run(create_test_image(), both).unwrap().into_rgb();
+
+        let mut separate = Graph::new();
+        let src = separate.source();
+        let blur = separate.blur(src, 2.0);
+        let blurred = separate.run(create_test_image(), blur).unwrap();
+        let sharpened = create_test_image().unsharp_mask(2.0, 1.0, 0).unwrap();
+        let expected = RgbImage::from_fn(64, 64, |x, y| {
+            let a = blurred.as_rgb().get_pixel(x, y);
+            let b = sharpened.as_rgb().get_pixel(x, y);
+            Rgb(std::array::from_fn(|i| (a[i] as f32 * 0.5 + b[i] as f32 * 0.5).round().clamp(0.0, 255.0) as u8))
+        });
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_invalid_node_rejected() {
+        let graph = Graph::new();
+        assert!(graph.run(create_test_image(), NodeId(5)).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Convolution kernels, recognizing the separable ones that can run as
+//! two 1D passes.
+
+use crate::{ImageError, Result};
+
+/// Largest relative error, per weight, that a separable kernel's factors
+/// may leave.
+const SEPARABLE_TOLERANCE: f32 = 1e-5;
+
+/// A 2D convolution kernel for [`Image::convolve`](crate::Image::convolve).
+///
+/// Weights are given row by row and centered on the middle one, so both
+/// sides are odd. They needn't be equal: sizes are width by height, so a
+/// 9x1 kernel blurs along rows only and a 1x9 one along columns, and a 7x3
+/// one can hold a motion blur along a line up to about 18 degrees from
+/// horizontal. Kernels that are the outer product of a column and a row
+/// (Gaussian, box and Sobel kernels, for example) are detected on creation
+/// and run as a horizontal and a vertical pass, reading `width + height`
+/// pixels for each output pixel instead of `width * height`.
+///
+/// # Example
+/// ```
+/// use sharpy::Kernel;
+///
+/// let box_blur = Kernel::new(9, 9, vec![1.0; 81]).unwrap().normalized();
+/// assert!(box_blur.is_separable());
+/// assert_eq!(box_blur.weights()[0], 1.0 / 81.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+    /// Column and row whose outer product is the kernel, if there are any
+    factors: Option<(Vec<f32>, Vec<f32>)>,
+}
+
+impl Kernel {
+    /// Creates a kernel from `width * height` weights in row order.
+    ///
+    /// Fails with [`ImageError::InvalidParameter`] unless both sides are odd,
+    /// the weights fill the kernel exactly and all are finite.
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Result<Self> {
+        let invalid = |value: String| ImageError::InvalidParameter { param: "kernel".to_string(), value };
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(invalid(format!("{}x{} (sides must be odd)", width, height)));
+        }
+        if weights.len() != width * height {
+            return Err(invalid(format!("{} weights for {}x{}", weights.len(), width, height)));
+        }
+        if let Some(weight) = weights.iter().find(|weight| !weight.is_finite()) {
+            return Err(invalid(weight.to_string()));
+        }
+        let factors = factorize(width, height, &weights);
+        Ok(Self { width, height, weights, factors })
+    }
+
+    /// Scales the weights to sum to 1, so flat areas keep their brightness.
+    /// Kernels whose weights sum to zero, like edge detectors, are returned
+    /// unchanged.
+    pub fn normalized(mut self) -> Self {
+        let sum = self.sum();
+        if sum == 0.0 {
+            return self;
+        }
+        self.weights.iter_mut().for_each(|weight| *weight /= sum);
+        if let Some((_, row)) = &mut self.factors {
+            row.iter_mut().for_each(|weight| *weight /= sum);
+        }
+        self
+    }
+
+    /// Number of columns.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The weights in row order.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Sum of the weights.
+    pub fn sum(&self) -> f32 {
+        self.weights.iter().sum()
+    }
+
+    /// Whether the kernel is the outer product of a column and a row.
+    pub fn is_separable(&self) -> bool {
+        self.factors.is_some()
+    }
+
+    /// The column and row whose outer product is the kernel, if it is
+    /// separable.
+    pub fn factors(&self) -> Option<(&[f32], &[f32])> {
+        self.factors.as_ref().map(|(column, row)| (column.as_slice(), row.as_slice()))
+    }
+
+    /// Whether two 1D passes read fewer pixels than one 2D pass, by enough
+    /// to pay for the intermediate buffer.
+    pub(crate) fn runs_separately(&self) -> bool {
+        self.is_separable() && 2 * (self.width + self.height) <= self.width * self.height
+    }
+}
+
+/// Splits a rank-one kernel into a column and a row: the column through its
+/// largest weight, and that weight's row divided by it.
+fn factorize(width: usize, height: usize, weights: &[f32]) -> Option<(Vec<f32>, Vec<f32>)> {
+    let (pivot, largest) = weights.iter()
+        .map(|weight| weight.abs())
+        .enumerate()
+        .fold((0, 0.0), |best, (index, weight)| if weight > best.1 { (index, weight) } else { best });
+    if largest == 0.0 {
+        return Some((vec![0.0; height], vec![0.0; width]));
+    }
+    let (px, py) = (pivot % width, pivot / width);
+    let column: Vec<f32> = (0..height).map(|y| weights[y * width + px]).collect();
+    let row: Vec<f32> = (0..width).map(|x| weights[py * width + x] / weights[pivot]).collect();
+    let exact = weights.iter().enumerate().all(|(index, &weight)| {
+        (column[index / width] * row[index % width] - weight).abs() <= SEPARABLE_TOLERANCE * largest
+    });
+    exact.then_some((column, row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Image;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_separability() {
+        let sobel = Kernel::new(3, 3, vec![-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0]).unwrap();
+        let (column, row) = sobel.factors().unwrap();
+        for (index, &weight) in sobel.weights().iter().enumerate() {
+            assert_eq!(column[index / 3] * row[index % 3], weight);
+        }
+
+        let high_pass = Kernel::new(3, 3, vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0]).unwrap();
+        assert!(!high_pass.is_separable());
+
+        let gaussian: Vec<f32> = (0..25).map(|i| (-(((i % 5) as f32 - 2.0).powi(2) + ((i / 5) as f32 - 2.0).powi(2)) / 2.0).exp()).collect();
+        let gaussian = Kernel::new(5, 5, gaussian).unwrap().normalized();
+        assert!(gaussian.runs_separately());
+        assert!((gaussian.sum() - 1.0).abs() < 1e-5);
+        let (column, row) = gaussian.factors().unwrap();
+        assert!((column.iter().sum::<f32>() * row.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+        assert!(Kernel::new(4, 4, vec![0.0; 16]).is_err());
+        assert!(Kernel::new(3, 3, vec![0.0; 8]).is_err());
+        assert!(Kernel::new(3, 3, vec![f32::NAN; 9]).is_err());
+        assert!(Kernel::new(9, 1, vec![1.0; 9]).unwrap().is_separable());
+    }
+
+    #[test]
+    fn test_convolve() {
+        let rgba = RgbaImage::from_fn(40, 30, |x, y| Rgba([(x * 6) as u8, (y * 8) as u8, ((x ^ y) * 4) as u8, (x + y) as u8]));
+        let image = Image::from_buffer(rgba.clone()).unwrap();
+        let weights: Vec<f32> = (0..81).map(|i| (-(((i % 9) as f32 - 4.0).powi(2) + ((i / 9) as f32 - 4.0).powi(2)) / 8.0).exp()).collect();
+        let separable = Kernel::new(9, 9, weights).unwrap().normalized();
+        assert!(separable.runs_separately());
+        // The same weights, made inseparable by a negligible change
+        let mut weights = separable.weights().to_vec();
+        weights[0] += 1e-3;
+        let full = Kernel::new(9, 9, weights).unwrap();
+        assert!(!full.is_separable());
+
+        let fast = image.convolve(&separable).into_buffer();
+        let slow = image.convolve(&full).into_buffer();
+        for (a, b) in fast.pixels().zip(slow.pixels()) {
+            for c in 0..3 {
+                assert!(a[c].abs_diff(b[c]) <= 1);
+            }
+        }
+        assert!(fast.pixels().zip(rgba.pixels()).all(|(a, b)| a[3] == b[3]));
+    }
+
+    #[test]
+    fn test_rectangular_kernels() {
+        let rgb = image::RgbImage::from_fn(24, 16, |x, y| image::Rgb([(x * 10) as u8, (y * 15) as u8, ((x * y) % 256) as u8]));
+        let image = Image::from_rgb(rgb.clone()).unwrap();
+        let average = |x: u32, y: u32, dx: i32, dy: i32, c: usize| {
+            let taps = (-4..=4).map(|k| {
+                let sx = (x as i32 + k * dx).clamp(0, 23) as u32;
+                let sy = (y as i32 + k * dy).clamp(0, 15) as u32;
+                f32::from(rgb.get_pixel(sx, sy)[c])
+            });
+            (taps.sum::<f32>() / 9.0).round() as u8
+        };
+
+        // 9 wide and 1 high blurs along rows, 1 wide and 9 high along columns
+        let rows = image.convolve(&Kernel::new(9, 1, vec![1.0; 9]).unwrap().normalized()).into_rgb();
+        let columns = image.convolve(&Kernel::new(1, 9, vec![1.0; 9]).unwrap().normalized()).into_rgb();
+        for (x, y, pixel) in rows.enumerate_pixels() {
+            for c in 0..3 {
+                assert!(pixel[c].abs_diff(average(x, y, 1, 0, c)) <= 1);
+                assert!(columns.get_pixel(x, y)[c].abs_diff(average(x, y, 0, 1, c)) <= 1);
+            }
+        }
+
+        // An off-center tap in a 7x3 kernel shifts the image by its offset
+        let mut weights = vec![0.0; 21];
+        weights[2 * 7 + 6] = 1.0;
+        let shifted = image.convolve(&Kernel::new(7, 3, weights).unwrap()).into_rgb();
+        assert_eq!(shifted.get_pixel(5, 5), rgb.get_pixel(8, 6));
+    }
+}
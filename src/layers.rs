@@ -0,0 +1,201 @@
+//! A minimal layer stack for compositing sharpening results, detail layers
+//! and masks without a separate compositing library.
+
+use crate::blend::{BlendMode, blend_images};
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample};
+use crate::utils::blend_masked;
+use crate::{Image, ImageError, Result};
+use image::{GrayImage, Rgb};
+
+/// A base image and layers stacked over it, bottom to top, each with a
+/// [`BlendMode`], an opacity and optionally a mask.
+///
+/// Layers are added with [`layer`](Self::layer); [`mask`](Self::mask)
+/// limits the most recently added one. [`flatten`](Self::flatten) composites
+/// the stack into one image with the base's metadata, leaving the stack
+/// as it was.
+///
+/// # Example
+/// ```
+/// use sharpy::{BlendMode, Image, Layers};
+/// # let image = Image::from_rgb(image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 128]))).unwrap();
+/// # let subject = image::GrayImage::from_fn(64, 64, |x, _| image::Luma([if x < 32 { 255 } else { 0 }]));
+///
+/// let detail = image.high_pass_layer(3.0).unwrap();
+/// let sharpened = image.unsharp_mask_ref(1.0, 1.5, 0).unwrap();
+/// let result = Layers::new(image)
+///     .layer(sharpened, BlendMode::Normal, 0.7)
+///     .layer(detail, BlendMode::SoftLight, 1.0)
+///     .mask(subject)
+///     .flatten()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Layers<P: SharpenPixel = Rgb<u8>> {
+    base: Image<P>,
+    layers: Vec<Layer<P>>,
+}
+
+/// An image in the stack and how it blends with what is below it.
+#[derive(Clone)]
+struct Layer<P: SharpenPixel> {
+    image: Image<P>,
+    mode: BlendMode,
+    opacity: f32,
+    mask: Option<GrayImage>,
+}
+
+impl<P: SharpenPixel> Layers<P> {
+    /// Starts a stack with `base` at the bottom.
+    pub fn new(base: Image<P>) -> Self {
+        Self { base, layers: Vec::new() }
+    }
+
+    /// Adds `image` on top, blended in `mode` at `opacity` (0.0-1.0). It
+    /// must have the base's dimensions.
+    pub fn layer(mut self, image: Image<P>, mode: BlendMode, opacity: f32) -> Self {
+        self.layers.push(Layer { image, mode, opacity, mask: None });
+        self
+    }
+
+    /// Limits the most recently added layer to where `mask` is bright:
+    /// white shows the blended layer, black what is below it. The mask must
+    /// have the base's dimensions. Has no effect if no layer has been added
+    /// yet.
+    pub fn mask(mut self, mask: GrayImage) -> Self {
+        if let Some(layer) = self.layers.last_mut() {
+            layer.mask = Some(mask);
+        }
+        self
+    }
+
+    /// The image at the bottom of the stack.
+    pub fn base(&self) -> &Image<P> {
+        &self.base
+    }
+
+    /// Number of layers over the base.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Composites the layers over the base, bottom to top.
+    ///
+    /// For pixel types with alpha, a layer's alpha limits it like a mask,
+    /// so what is below shows through where it is transparent, and the
+    /// result's alpha is each layer's, scaled by its opacity and mask, laid
+    /// over the alpha below it.
+    ///
+    /// Fails, before compositing anything, with
+    /// [`ImageError::InvalidDimensions`] if a layer or mask doesn't match
+    /// the base, and with [`ImageError::InvalidParameter`] for an opacity
+    /// out of range.
+    pub fn flatten(&self) -> Result<Image<P>> {
+        let dimensions = self.base.dimensions();
+        for layer in &self.layers {
+            let mut sizes = std::iter::once(layer.image.dimensions()).chain(layer.mask.as_ref().map(GrayImage::dimensions));
+            if let Some((width, height)) = sizes.find(|&size| size != dimensions) {
+                return Err(ImageError::InvalidDimensions { width, height });
+            }
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(ImageError::InvalidParameter { param: "opacity".to_string(), value: layer.opacity.to_string() });
+            }
+        }
+
+        let mut current = self.base.data.get_ref().clone();
+        for layer in &self.layers {
+            let mut blended = layer.image.data.get_ref().clone();
+            blend_images(&current, &mut blended, layer.mode, layer.opacity);
+            if P::CHANNEL_COUNT as usize > P::COLOR_CHANNELS {
+                composite_alpha(&current, layer.image.data.get_ref(), &mut blended, layer.opacity, layer.mask.as_ref());
+            } else if let Some(mask) = &layer.mask {
+                blend_masked(&current, &mut blended, mask);
+            }
+            current = blended;
+        }
+        Ok(self.base.with_pixels(current))
+    }
+}
+
+/// Limits `blended`, `layer` blended over `below`, to where the layer is
+/// opaque and its mask bright, and lays the layer's alpha, at `opacity`, over
+/// the alpha below.
+fn composite_alpha<P: SharpenPixel>(below: &Buffer<P>, layer: &Buffer<P>, blended: &mut Buffer<P>, opacity: f32, mask: Option<&GrayImage>) {
+    let max = max_value::<P>();
+    let alpha = P::COLOR_CHANNELS;
+    blended.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let below = below.get_pixel(x, y).channels();
+                let coverage = sample::<P>(layer.get_pixel(x, y).channels()[alpha]) / max
+                    * mask.map_or(1.0, |mask| f32::from(mask.get_pixel(x, y)[0]) / 255.0);
+                let channels = pixel.channels_mut();
+
+                for c in 0..P::COLOR_CHANNELS {
+                    let value = sample::<P>(below[c]) + (sample::<P>(channels[c]) - sample::<P>(below[c])) * coverage;
+                    channels[c] = to_sample::<P>(value);
+                }
+                let below_alpha = sample::<P>(below[alpha]) / max;
+                channels[alpha] = to_sample::<P>((below_alpha + coverage * opacity * (1.0 - below_alpha)) * max);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blend;
+    use image::{Luma, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn test_flatten() {
+        let base = Image::from_rgb(RgbImage::from_fn(16, 8, |x, y| Rgb([(x * 12) as u8, (y * 20) as u8, 90]))).unwrap();
+        let white = Image::from_rgb(RgbImage::from_pixel(16, 8, Rgb([255; 3]))).unwrap();
+        let detail = base.high_pass_layer(1.0).unwrap();
+        assert!(Layers::new(base.clone()).flatten().unwrap().into_rgb() == base.clone().into_rgb());
+
+        // Layers apply bottom to top, like blending one after the other
+        let stack = Layers::new(base.clone())
+            .layer(white.clone(), BlendMode::Normal, 0.25)
+            .layer(detail.clone(), BlendMode::Overlay, 1.0);
+        let lighter = blend::blend(&base, &white, BlendMode::Normal, 0.25).unwrap();
+        let expected = blend::blend(&lighter, &detail, BlendMode::Overlay, 1.0).unwrap();
+        assert_eq!(stack.layer_count(), 2);
+        assert!(stack.flatten().unwrap().into_rgb() == expected.into_rgb());
+
+        // A mask limits its layer to the left half
+        let left = GrayImage::from_fn(16, 8, |x, _| Luma([if x < 8 { 255 } else { 0 }]));
+        let masked = Layers::new(base.clone()).layer(white.clone(), BlendMode::Normal, 1.0).mask(left).flatten().unwrap().into_rgb();
+        for (x, y, pixel) in masked.enumerate_pixels() {
+            let expected = if x < 8 { Rgb([255; 3]) } else { *base.as_rgb().get_pixel(x, y) };
+            assert_eq!(*pixel, expected);
+        }
+
+        let small = Image::from_rgb(RgbImage::new(8, 8)).unwrap();
+        assert!(matches!(Layers::new(base.clone()).layer(small, BlendMode::Add, 1.0).flatten(), Err(ImageError::InvalidDimensions { width: 8, height: 8 })));
+        assert!(Layers::new(base.clone()).layer(white.clone(), BlendMode::Add, 1.0).mask(GrayImage::new(2, 2)).flatten().is_err());
+        assert!(Layers::new(base).layer(white, BlendMode::Add, -0.5).flatten().is_err());
+    }
+
+    #[test]
+    fn test_flatten_alpha() {
+        let base = Image::from_buffer(RgbaImage::from_pixel(16, 8, Rgba([40, 80, 120, 255]))).unwrap();
+        // Opaque white on the left half, transparent on the right
+        let layer = Image::from_buffer(RgbaImage::from_fn(16, 8, |x, _| Rgba([255, 255, 255, if x < 8 { 255 } else { 0 }]))).unwrap();
+        let flat = Layers::new(base.clone()).layer(layer.clone(), BlendMode::Normal, 1.0).flatten().unwrap().into_buffer();
+        for (x, _, pixel) in flat.enumerate_pixels() {
+            let expected = if x < 8 { Rgba([255; 4]) } else { Rgba([40, 80, 120, 255]) };
+            assert_eq!(*pixel, expected);
+        }
+
+        // Over a transparent base the layer's alpha, at its opacity, becomes the result's
+        let clear = Image::from_buffer(RgbaImage::from_pixel(16, 8, Rgba([0; 4]))).unwrap();
+        let top = GrayImage::from_fn(16, 8, |_, y| Luma([if y < 4 { 255 } else { 0 }]));
+        let flat = Layers::new(clear).layer(layer, BlendMode::Normal, 0.5).mask(top).flatten().unwrap().into_buffer();
+        assert_eq!(flat.get_pixel(0, 0)[3], 128);
+        assert_eq!(flat.get_pixel(0, 6)[3], 0);
+        assert_eq!(flat.get_pixel(12, 0)[3], 0);
+    }
+}
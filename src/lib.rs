@@ -44,29 +44,102 @@
 //! 
 //! ## Performance
 //! 
-//! All algorithms use parallel processing via Rayon for optimal performance.
+//! With the default `parallel` feature, all algorithms process in parallel
+//! via Rayon; without it (e.g. on wasm32) they run single-threaded.
 //! The library uses copy-on-write semantics to minimize memory allocations.
 
-use image::{DynamicImage, RgbImage};
+use image::{DynamicImage, GrayImage, ImageDecoder, Rgb, RgbImage};
 use std::sync::Arc;
-use std::path::Path;
-use rayon::prelude::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::par::*;
 use std::sync::atomic::{AtomicU32, Ordering};
-
-// Memory safety constants
-const MAX_IMAGE_PIXELS: usize = 100_000_000; // ~100 megapixels
-const MAX_IMAGE_DIMENSION: u32 = 65536; // Max 65k x 65k
+use context::Context;
 
 mod sharpening;
 mod utils;
 mod builder;
 mod operations;
+mod presets;
+mod encode;
+mod pipeline;
+mod graph;
+mod script;
+mod migrate;
+mod metrics;
+mod context;
+mod limits;
+mod region;
+mod pyramid;
+mod analysis;
+mod pixel;
+mod pool;
+mod metadata;
+mod cache;
+mod cost;
+mod custom;
+mod kernel;
+mod animation;
+mod video;
+mod normal;
+mod fringe;
+mod deep;
+mod dither;
+mod descreen;
+mod denoise;
+mod transfer;
+mod par;
+mod trace;
+mod warning;
+mod layers;
+#[cfg(feature = "async")]
+mod spawn;
+#[cfg(feature = "raw")]
+mod raw;
+pub mod mask;
+pub mod blend;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
-pub use utils::EdgeMethod;
-pub use builder::{SharpeningBuilder, SharpeningPresets};
-pub use operations::Operation;
+pub use utils::{EdgeMethod, Luminance};
+pub use blend::BlendMode;
+pub use layers::Layers;
+pub use builder::{Clamp, QueuedStep, RemovedStep, SharpeningBuilder, SharpeningPresets, Violation};
+pub use operations::{Operation, ValidationConfig, ValidationPolicy};
+pub use presets::{Preset, PresetRegistry};
+pub use encode::{ChromaSubsampling, EncodeOptions, PngCompression, SaveOptions};
+pub use pipeline::Pipeline;
+pub use cost::CostEstimate;
+pub use custom::PipelineOp;
+pub use kernel::Kernel;
+pub use animation::{AnimatedImage, AnimationFrame, LoopCount};
+pub use video::TemporalSharpener;
+pub use graph::{Graph, Merge, NodeId};
+pub use migrate::Migration;
+pub use metrics::Comparison;
+pub use context::ProgressEvent;
+pub use limits::Limits;
+pub use region::Rect;
+pub use pyramid::Pyramid;
+pub use analysis::{Analysis, OutputTarget};
+pub use dither::Dither;
+pub use transfer::Transfer;
+pub use pixel::{Sample, SharpenPixel};
+pub use metadata::Metadata;
+pub use warning::Warning;
+#[cfg(feature = "raw")]
+pub use raw::{RAW_EXTENSIONS, is_raw_path};
+use pixel::Buffer;
 
+/// Errors from loading, processing and saving images.
+/// 
+/// Errors from loading and saving a file come wrapped in
+/// [`ImageError::File`], and errors from a step of a builder or graph in
+/// [`ImageError::Operation`]; [`ImageError::root`] reaches the error
+/// underneath for matching. Methods running a single operation on an
+/// [`Image`] return their errors directly.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum ImageError {
     #[error("Invalid dimensions: {width}x{height}")]
     InvalidDimensions { width: u32, height: u32 },
@@ -79,112 +152,924 @@ pub enum ImageError {
     
     #[error("Image format error: {0}")]
     Format(#[from] image::ImageError),
+    
+    #[error("Preset error: {0}")]
+    Preset(String),
+    
+    #[error("Encoding error: {0}")]
+    Encode(String),
+    
+    #[error("Parse error: {0}")]
+    Parse(String),
+    
+    #[error("Invalid parameters: {}", format_violations(.0))]
+    Validation(Vec<Violation>),
+    
+    #[error("Color profile error: {0}")]
+    ColorProfile(String),
+    
+    #[error("Limit exceeded: {limit} = {value} (max {max})")]
+    LimitExceeded { limit: String, value: u64, max: u64 },
+    
+    #[error("Tiling error: seam score rose from {before:.2} to {after:.2}")]
+    Seam { before: f64, after: f64 },
+    
+    #[error("RAW decoding error: {0}")]
+    Raw(String),
+    
+    #[error("{} failed: {source}", describe_step(operation, *index))]
+    Operation { operation: &'static str, index: Option<usize>, #[source] source: Box<ImageError> },
+    
+    #[error("{}: {source}", path.display())]
+    File { path: PathBuf, #[source] source: Box<ImageError> },
+}
+
+fn format_violations(violations: &[Violation]) -> String {
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+fn describe_step(operation: &str, index: Option<usize>) -> String {
+    match index {
+        Some(index) => format!("step {} ({})", index, operation),
+        None => operation.to_string(),
+    }
+}
+
+impl ImageError {
+    /// The error underneath any [`ImageError::Operation`] and
+    /// [`ImageError::File`] context, for matching on what went wrong.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, ImageError};
+    /// 
+    /// let error = Image::load("missing.png").err().unwrap();
+    /// assert_eq!(error.path(), Some(std::path::Path::new("missing.png")));
+    /// assert!(matches!(error.root(), ImageError::Io(_)));
+    /// ```
+    pub fn root(&self) -> &ImageError {
+        match self {
+            ImageError::Operation { source, .. } | ImageError::File { source, .. } => source.root(),
+            other => other,
+        }
+    }
+    
+    /// The file that failed to load or save, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ImageError::File { path, .. } => Some(path),
+            ImageError::Operation { source, .. } => source.path(),
+            _ => None,
+        }
+    }
+    
+    /// The name of the operation that failed, if any.
+    pub fn operation(&self) -> Option<&'static str> {
+        match self {
+            ImageError::Operation { operation, .. } => Some(operation),
+            ImageError::File { source, .. } => source.operation(),
+            _ => None,
+        }
+    }
+    
+    /// Adds the operation that failed, and its position in a pipeline.
+    pub(crate) fn in_operation(self, operation: &'static str, index: Option<usize>) -> Self {
+        ImageError::Operation { operation, index, source: Box::new(self) }
+    }
+    
+    /// Adds the file that failed to load or save.
+    pub(crate) fn in_file(self, path: &Path) -> Self {
+        ImageError::File { path: path.to_path_buf(), source: Box::new(self) }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ImageError>;
 
 #[derive(Clone)]
-enum ImageData {
-    Owned(RgbImage),
-    Shared(Arc<RgbImage>),
+enum ImageData<P: SharpenPixel> {
+    Owned(Buffer<P>),
+    Shared(Arc<Buffer<P>>),
 }
 
-impl ImageData {
-    fn get_mut(&mut self) -> &mut RgbImage {
+impl<P: SharpenPixel> ImageData<P> {
+    fn get_ref(&self) -> &Buffer<P> {
         match self {
             ImageData::Owned(img) => img,
-            ImageData::Shared(arc_img) => {
-                *self = ImageData::Owned((**arc_img).clone());
-                if let ImageData::Owned(img) = self {
-                    img
-                } else {
-                    unreachable!()
-                }
-            }
+            ImageData::Shared(arc_img) => arc_img,
         }
     }
+}
+
+/// The main image type that provides sharpening operations.
+/// 
+/// This struct uses copy-on-write semantics internally to minimize memory
+/// allocations when cloning images.
+/// 
+/// Images hold RGB8 pixels unless another [`SharpenPixel`] type is chosen:
+/// `Image<Rgba<u8>>` keeps alpha, `Image<Luma<u8>>` is grayscale and
+/// `Image<Rgb<u16>>` keeps 16-bit precision. Sharpening, analysis and
+/// metrics work on all of them; file I/O works on RGB8 and 16-bit
+/// grayscale (see [`Image::load_gray16`]), graphs on RGB8.
+/// 
+/// # Examples
+/// 
+/// ```no_run
+/// use sharpy::Image;
+/// 
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// // Load from file
+/// let image = Image::load("photo.jpg")?;
+/// 
+/// // Create from existing image data
+/// use image::RgbImage;
+/// let rgb_image = RgbImage::new(800, 600);
+/// let image = Image::from_rgb(rgb_image)?;
+/// 
+/// // Keep 16-bit precision
+/// let deep = Image::from_buffer(image::DynamicImage::new_rgb16(800, 600).to_rgb16())?;
+/// let sharpened = deep.unsharp_mask(1.0, 1.0, 0)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Image<P: SharpenPixel = Rgb<u8>> {
+    data: ImageData<P>,
+    icc_profile: Option<Arc<[u8]>>,
+    /// EXIF and XMP of the source, written back when saving
+    metadata: Option<Arc<Metadata>>,
+    /// Pixel format of the decoded source, restored on output
+    source_color: Option<image::ColorType>,
+    /// Channel weights of the luminance operations work on
+    luminance: Luminance,
+    /// Parameter ranges the sharpening methods accept
+    validation: ValidationConfig,
+}
+
+/// Returned by [`Image::metadata`] for images without any.
+static NO_METADATA: Metadata = Metadata { exif: None, xmp: None };
+
+impl<P: SharpenPixel> Image<P> {
+    /// Creates an image from a pixel buffer of any supported type,
+    /// failing with [`ImageError::LimitExceeded`] if it exceeds the default
+    /// [`Limits`].
+    pub fn from_buffer(img: Buffer<P>) -> Result<Self> {
+        Self::from_buffer_with_limits(img, &Limits::default())
+    }
     
-    fn get_ref(&self) -> &RgbImage {
-        match self {
+    /// Like [`Image::from_buffer`], with explicit limits.
+    pub fn from_buffer_with_limits(img: Buffer<P>, limits: &Limits) -> Result<Self> {
+        let (width, height) = img.dimensions();
+        limits.check_dimensions(width, height)?;
+        Ok(Self::from_buffer_unchecked(img))
+    }
+    
+    /// Borrows the pixel buffer without consuming the image.
+    pub fn as_buffer(&self) -> &Buffer<P> {
+        self.data.get_ref()
+    }
+    
+    /// Returns the pixel buffer, copying it only if it is shared.
+    pub fn into_buffer(self) -> Buffer<P> {
+        match self.data {
             ImageData::Owned(img) => img,
-            ImageData::Shared(arc_img) => arc_img,
+            ImageData::Shared(arc_img) => Arc::try_unwrap(arc_img).unwrap_or_else(|shared| (*shared).clone()),
+        }
+    }
+    
+    /// Create from a pixel buffer without validation (for internal use)
+    fn from_buffer_unchecked(img: Buffer<P>) -> Self {
+        Self {
+            data: ImageData::Owned(img),
+            icc_profile: None,
+            metadata: None,
+            source_color: None,
+            luminance: Luminance::default(),
+            validation: ValidationConfig::default(),
+        }
+    }
+    
+    /// Wraps a shared pixel buffer without validation (for internal use)
+    pub(crate) fn from_shared(img: Arc<Buffer<P>>) -> Self {
+        Self {
+            data: ImageData::Shared(img),
+            icc_profile: None,
+            metadata: None,
+            source_color: None,
+            luminance: Luminance::default(),
+            validation: ValidationConfig::default(),
+        }
+    }
+    
+    /// Creates an image with `img`'s pixels and this image's color profile
+    /// and source pixel format, for results derived from this image.
+    pub(crate) fn with_pixels(&self, img: Buffer<P>) -> Self {
+        Self {
+            data: ImageData::Owned(img),
+            icc_profile: self.icc_profile.clone(),
+            metadata: self.metadata.clone(),
+            source_color: self.source_color,
+            luminance: self.luminance,
+            validation: self.validation,
+        }
+    }
+    
+    /// Returns the embedded ICC profile, if the image has one.
+    /// 
+    /// Profiles are read on load, kept through sharpening and re-embedded
+    /// when saving as JPEG, PNG or WebP. Images without a profile are
+    /// treated as sRGB.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+    
+    /// Replaces the embedded ICC profile; `None` removes it.
+    /// 
+    /// The pixels are not converted; use [`Image::to_srgb`] for that.
+    pub fn with_icc_profile(mut self, profile: Option<Vec<u8>>) -> Self {
+        self.icc_profile = profile.map(Arc::from);
+        self
+    }
+    
+    /// Returns the EXIF and XMP metadata read on load, if any.
+    /// 
+    /// Metadata is kept through sharpening and written when saving as JPEG.
+    pub fn metadata(&self) -> &Metadata {
+        self.metadata.as_deref().unwrap_or(&NO_METADATA)
+    }
+    
+    /// Returns the channel weights used for this image's luminance.
+    pub fn luminance(&self) -> Luminance {
+        self.luminance
+    }
+    
+    /// Sets the channel weights of the luminance that edge detection,
+    /// clarity, text sharpening, masks, analysis and [`Image::histogram`]
+    /// work on. The default is BT.601; stills from HD video expect BT.709.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Luminance};
+    /// # let frame = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = frame.with_luminance(Luminance::Bt709).clarity(0.5, 2.0)?;
+    /// assert_eq!(sharpened.luminance(), Luminance::Bt709);
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn with_luminance(mut self, luminance: Luminance) -> Self {
+        self.luminance = luminance;
+        self
+    }
+    
+    /// Returns the parameter ranges this image's sharpening methods accept.
+    pub fn validation(&self) -> ValidationConfig {
+        self.validation
+    }
+    
+    /// Sets the parameter ranges that this image's sharpening methods and
+    /// [`SharpeningBuilder`]s accept, e.g. [`ValidationConfig::extended`]
+    /// for creative over-sharpening. The default is the strict ranges.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, ValidationConfig};
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// assert!(image.clone().clarity(0.5, 40.0).is_err());
+    /// let glow = image.with_validation(ValidationConfig::extended()).clarity(0.5, 40.0)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn with_validation(mut self, validation: ValidationConfig) -> Self {
+        self.validation = validation;
+        self
+    }
+    
+    /// A context for direct calls on this image.
+    fn context(&self) -> Context<'static> {
+        Context::default().with_luminance(self.luminance)
+    }
+    
+    /// Replaces the EXIF and XMP metadata.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = (!metadata.is_empty()).then(|| Arc::new(metadata));
+        self
+    }
+    
+    /// Removes EXIF, XMP and the ICC profile, so saved files carry no
+    /// metadata (e.g. camera details or GPS position) from the source.
+    /// 
+    /// Without a profile, wide-gamut pixels are read as sRGB; convert with
+    /// [`Image::to_srgb`] first to keep their appearance.
+    pub fn strip_metadata(self) -> Self {
+        self.with_metadata(Metadata::default()).with_icc_profile(None)
+    }
+    
+    /// Validate image dimensions against the default limits
+    fn validate_dimensions(width: u32, height: u32) -> Result<()> {
+        Limits::default().check_dimensions(width, height)
+    }
+    
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.data.get_ref().dimensions()
+    }
+    
+    /// Returns the pixel at (`x`, `y`), or `None` if out of bounds.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<&P> {
+        self.data.get_ref().get_pixel_checked(x, y)
+    }
+    
+    /// Iterates over the pixels in row-major order.
+    pub fn pixels(&self) -> image::buffer::Pixels<'_, P> {
+        self.data.get_ref().pixels()
+    }
+    
+    /// Copies a region into a new image, keeping the color profile.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(640, 480)).unwrap();
+    /// // Sharpen only the subject
+    /// let face = image.crop(200, 100, 240, 240).unwrap().unsharp_mask(1.0, 1.5, 0).unwrap();
+    /// assert_eq!(face.dimensions(), (240, 240));
+    /// ```
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Self> {
+        let view = self.view(Rect::new(x, y, width, height))?;
+        Ok(self.with_pixels(view.to_image()))
+    }
+    
+    /// Borrows a region without copying, e.g. for inspecting or comparing
+    /// crops of a result.
+    pub fn view(&self, rect: Rect) -> Result<image::SubImage<&Buffer<P>>> {
+        let (width, height) = self.dimensions();
+        rect.check_within(width, height)?;
+        Ok(image::imageops::crop_imm(self.data.get_ref(), rect.x, rect.y, rect.width, rect.height))
+    }
+    
+    /// Combines this image with `processed`, a result of processing it, so
+    /// the processing only shows where `mask` is bright: white takes the
+    /// processed pixel, black keeps this one and grays blend between them.
+    /// 
+    /// All three must have the same dimensions.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// // Full sharpening at the left edge, fading out towards the right
+    /// let mask = image::GrayImage::from_fn(64, 64, |x, _| image::Luma([255 - (x * 4) as u8]));
+    /// let sharpened = image.unsharp_mask_ref(1.0, 1.0, 0).unwrap();
+    /// let result = image.blend_masked(sharpened, &mask).unwrap();
+    /// ```
+    pub fn blend_masked(&self, processed: Self, mask: &image::GrayImage) -> Result<Self> {
+        for dimensions in [processed.dimensions(), mask.dimensions()] {
+            if dimensions != self.dimensions() {
+                let (width, height) = dimensions;
+                return Err(ImageError::InvalidDimensions { width, height });
+            }
         }
+        let mut blended = processed.into_buffer();
+        utils::blend_masked(self.data.get_ref(), &mut blended, mask);
+        Ok(self.with_pixels(blended))
+    }
+    
+    /// Convolves the color channels with `kernel`, repeating edge pixels
+    /// beyond the image; alpha is kept. Separable kernels run as two 1D
+    /// passes, see [`Kernel`].
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Kernel};
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let box_blur = Kernel::new(15, 15, vec![1.0; 225]).unwrap().normalized();
+    /// let blurred = image.convolve(&box_blur);
+    /// ```
+    pub fn convolve(&self, kernel: &Kernel) -> Self {
+        self.with_pixels(utils::apply_convolution(self.data.get_ref(), kernel, &self.context()))
+    }
+    
+    /// Returns the detail layer of frequency separation, as image editors
+    /// show it: the difference between the image and its Gaussian blur of
+    /// `radius`, on mid-gray. It shows what an unsharp mask of the same
+    /// radius will amplify, and adding it back to the blur (minus mid-gray)
+    /// rebuilds the image, for custom recombination. `radius` is checked
+    /// like an unsharp mask's.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let detail = image.high_pass_layer(2.0).unwrap();
+    /// // Flat areas hold no detail
+    /// assert!(detail.as_rgb().pixels().all(|pixel| pixel.0 == [128; 3]));
+    /// ```
+    pub fn high_pass_layer(&self, radius: f32) -> Result<Self> {
+        Operation::UnsharpMask { radius, amount: 1.0, threshold: 0 }.validate_with(&self.validation)?;
+        sharpening::high_pass_layer(self, radius, &self.context())
+    }
+    
+    /// Estimates blur and noise, e.g. to choose sharpening parameters.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let image = Image::load("photo.jpg")?;
+    /// let analysis = image.analyze();
+    /// // Skip sharpening noisy images, which it would make grainier
+    /// if analysis.blur > 0.4 && analysis.noise < 3.0 {
+    ///     image.unsharp_mask(1.5, 1.0, 2)?.save("photo_sharp.jpg")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn analyze(&self) -> Analysis {
+        analysis::analyze(self.data.get_ref(), self.luminance)
+    }
+    
+    /// Compares this image against an approved reference of the same size.
+    /// SSIM and halos are measured on luminance with this image's
+    /// [`Luminance`] weights.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let output = Image::load("out/photo_sharp.jpg")?;
+    /// let reference = Image::load("approved/photo_sharp.jpg")?;
+    /// let comparison = output.compare(&reference)?;
+    /// assert!(comparison.ssim > 0.98 && comparison.delta_e < 1.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compare(&self, reference: &Self) -> Result<Comparison> {
+        metrics::compare(self.data.get_ref(), reference.data.get_ref(), self.luminance)
+    }
+    
+    /// Measures how visible the seams are when the image is tiled: the mean
+    /// luminance step across the wrap-around edge, relative to the strongest
+    /// mean step across a column (or row) boundary inside the image.
+    /// Luminance uses the image's [`Luminance`] weights.
+    /// 
+    /// Textures that tile seamlessly score at most about 1.0; a seam that
+    /// stands out from everything inside the image scores above it.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// let stripes = image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([if x % 8 < 4 { 40 } else { 200 }; 3]));
+    /// assert!(Image::from_rgb(stripes).unwrap().tiling_seam() <= 1.0);
+    /// let gradient = image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([(x * 4) as u8; 3]));
+    /// assert!(Image::from_rgb(gradient).unwrap().tiling_seam() > 10.0);
+    /// ```
+    pub fn tiling_seam(&self) -> f64 {
+        metrics::tiling_seam(self.data.get_ref(), self.luminance)
+    }
+    
+    pub fn unsharp_mask(self, radius: f32, amount: f32, threshold: u8) -> Result<Self> {
+        self.unsharp_mask_ref(radius, amount, threshold)
+    }
+    
+    /// Applies an unsharp mask whose `threshold` is a percentage of the
+    /// local contrast rather than an absolute difference, so it holds back
+    /// noise and keeps detail consistently in dark and bright regions.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// // Leave differences under 20% of the local contrast alone
+    /// let sharpened = image.unsharp_mask_relative(1.2, 1.0, 20.0).unwrap();
+    /// ```
+    pub fn unsharp_mask_relative(self, radius: f32, amount: f32, threshold: f32) -> Result<Self> {
+        self.unsharp_mask_relative_ref(radius, amount, threshold)
+    }
+    
+    /// Applies an unsharp mask whose amount varies per pixel with
+    /// `amount_map`, e.g. a saliency map or a subject matte: every 51 levels
+    /// are an amount of 1, so black leaves a pixel unsharpened and white
+    /// sharpens it with amount 5. The map must have the image's dimensions.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// // Amount 2 in the middle, fading to nothing toward the sides
+    /// let amounts = image::GrayImage::from_fn(64, 64, |x, _| image::Luma([102 - (x as i32 - 32).unsigned_abs() as u8 * 3]));
+    /// let sharpened = image.unsharp_mask_with_amount_map(1.2, &amounts, 0).unwrap();
+    /// ```
+    pub fn unsharp_mask_with_amount_map(self, radius: f32, amount_map: &GrayImage, threshold: u8) -> Result<Self> {
+        self.unsharp_mask_with_amount_map_ref(radius, amount_map, threshold)
+    }
+    
+    pub fn high_pass_sharpen(self, strength: f32) -> Result<Self> {
+        self.high_pass_sharpen_ref(strength)
+    }
+    
+    pub fn enhance_edges(self, strength: f32, method: EdgeMethod) -> Result<Self> {
+        self.enhance_edges_ref(strength, method)
+    }
+    
+    pub fn clarity(self, strength: f32, radius: f32) -> Result<Self> {
+        self.clarity_ref(strength, radius)
+    }
+    
+    /// Applies an unsharp mask whose radius follows the detail around each
+    /// pixel, between `min_radius` (fine texture) and `max_radius` (soft,
+    /// large structures), instead of one radius for the whole image.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = image.adaptive_unsharp_mask(0.7, 4.0, 1.2, 1).unwrap();
+    /// ```
+    pub fn adaptive_unsharp_mask(self, min_radius: f32, max_radius: f32, amount: f32, threshold: u8) -> Result<Self> {
+        self.adaptive_unsharp_mask_ref(min_radius, max_radius, amount, threshold)
+    }
+    
+    /// Sharpens text and line art, tuned to strokes about `stroke_width`
+    /// pixels wide, without the halos an unsharp mask leaves around glyphs
+    /// and without roughening the paper or background.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let scan = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = scan.text_sharpen(1.5, 1.5).unwrap();
+    /// ```
+    pub fn text_sharpen(self, stroke_width: f32, strength: f32) -> Result<Self> {
+        self.text_sharpen_ref(stroke_width, strength)
+    }
+    
+    /// Removes the halftone screen from a scan of printed material, so
+    /// sharpening afterwards doesn't turn it into moiré. `period` is the
+    /// screen's dot spacing in pixels (the scan's dpi divided by the
+    /// print's lpi); `None` detects it, leaving images without a screen
+    /// unchanged.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let scan = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// // A 150 lpi magazine scanned at 600 dpi
+    /// let sharpened = scan.descreen(Some(600.0 / 150.0))?.unsharp_mask(1.5, 1.0, 2)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn descreen(self, period: Option<f32>) -> Result<Self> {
+        self.descreen_ref(period)
+    }
+    
+    /// Removes salt-and-pepper and sensor noise with a median filter over
+    /// the square of `radius` pixels around each pixel (1-10), so
+    /// sharpening afterwards doesn't amplify it. Edges stay sharp.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = image.median_filter(1)?.unsharp_mask(1.0, 1.0, 0)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn median_filter(self, radius: u32) -> Result<Self> {
+        self.median_filter_ref(radius)
+    }
+    
+    /// Removes noise with non-local means: each pixel becomes an average of
+    /// the pixels within `search_radius` (1-10) whose surrounding patches of
+    /// `patch_radius` (1-3) resemble its own, weighted by how closely.
+    /// `strength` (0.0-3.0) sets how different patches may be and still
+    /// count; 1.0 suits noise of about 10 levels in 8 bits.
+    /// 
+    /// Unlike a blur, it keeps edges and repeated texture, so sharpening
+    /// afterwards brings out detail rather than grain.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = image.denoise(1.0, 1, 7)?.unsharp_mask(1.0, 1.0, 0)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn denoise(self, strength: f32, patch_radius: u32, search_radius: u32) -> Result<Self> {
+        self.denoise_ref(strength, patch_radius, search_radius)
+    }
+    
+    /// Makes the image crisper without making it noisier: estimates the
+    /// noise, removes it with [`Image::denoise`] if there is any to speak
+    /// of, then sharpens with an unsharp mask whose radius follows the
+    /// image's blur and whose amount and threshold follow the noise left.
+    /// `level` (0.0-3.0) scales the amount; 1.0 is what
+    /// [`Analysis::suggest`] would pick.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let enhanced = image.smart_enhance(1.0)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn smart_enhance(self, level: f32) -> Result<Self> {
+        self.smart_enhance_ref(level)
+    }
+    
+    /// Retouches a portrait by frequency separation: splits the image at a
+    /// blur of `radius` into tones and detail, moves the tones of skin
+    /// (see [`mask::skin`]) by `smoothing` (0.0-1.0) towards a wider blur,
+    /// and sharpens the detail everywhere else, such as eyes, lashes and
+    /// hair, by `amount` as an unsharp mask would. Pores and fine skin
+    /// texture are kept.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let retouched = image.frequency_separation(2.0, 0.4, 0.8)?;
+    /// # Ok::<(), sharpy::ImageError>(())
+    /// ```
+    pub fn frequency_separation(self, radius: f32, smoothing: f32, amount: f32) -> Result<Self> {
+        self.frequency_separation_ref(radius, smoothing, amount)
+    }
+    
+    /// Returns the dot spacing, in pixels, of the halftone screen in the
+    /// image, or `None` if it has none. See [`Image::descreen`].
+    pub fn halftone_period(&self) -> Option<f32> {
+        descreen::detect_period(self.data.get_ref(), self.luminance)
+    }
+    
+    /// Like [`Image::unsharp_mask`], but keeps `self` and returns a new image.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let original = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let soft = original.unsharp_mask_ref(1.0, 0.5, 0).unwrap();
+    /// let hard = original.unsharp_mask_ref(1.0, 2.0, 0).unwrap();
+    /// ```
+    pub fn unsharp_mask_ref(&self, radius: f32, amount: f32, threshold: u8) -> Result<Self> {
+        self.run(Operation::UnsharpMask { radius, amount, threshold })
+    }
+    
+    /// Like [`Image::unsharp_mask_relative`], but keeps `self` and returns a new image.
+    pub fn unsharp_mask_relative_ref(&self, radius: f32, amount: f32, threshold: f32) -> Result<Self> {
+        self.run(Operation::RelativeUnsharp { radius, amount, threshold })
+    }
+    
+    /// Like [`Image::unsharp_mask_with_amount_map`], but keeps `self` and returns a new image.
+    pub fn unsharp_mask_with_amount_map_ref(&self, radius: f32, amount_map: &GrayImage, threshold: u8) -> Result<Self> {
+        Operation::UnsharpMask { radius, amount: 1.0, threshold }.validate_with(&self.validation)?;
+        sharpening::unsharp_mask_with_amount_map(self, radius, amount_map, threshold, &self.context())
+    }
+    
+    /// Like [`Image::high_pass_sharpen`], but keeps `self` and returns a new image.
+    pub fn high_pass_sharpen_ref(&self, strength: f32) -> Result<Self> {
+        self.run(Operation::HighPassSharpen { strength })
+    }
+    
+    /// Like [`Image::enhance_edges`], but keeps `self` and returns a new image.
+    pub fn enhance_edges_ref(&self, strength: f32, method: EdgeMethod) -> Result<Self> {
+        self.run(Operation::EnhanceEdges { strength, method })
+    }
+    
+    /// Like [`Image::clarity`], but keeps `self` and returns a new image.
+    pub fn clarity_ref(&self, strength: f32, radius: f32) -> Result<Self> {
+        self.run(Operation::Clarity { strength, radius })
+    }
+    
+    /// Like [`Image::adaptive_unsharp_mask`], but keeps `self` and returns a new image.
+    pub fn adaptive_unsharp_mask_ref(&self, min_radius: f32, max_radius: f32, amount: f32, threshold: u8) -> Result<Self> {
+        self.run(Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold })
+    }
+    
+    /// Like [`Image::text_sharpen`], but keeps `self` and returns a new image.
+    pub fn text_sharpen_ref(&self, stroke_width: f32, strength: f32) -> Result<Self> {
+        self.run(Operation::TextSharpen { stroke_width, strength })
+    }
+    
+    /// Like [`Image::descreen`], but keeps `self` and returns a new image.
+    pub fn descreen_ref(&self, period: Option<f32>) -> Result<Self> {
+        self.run(Operation::Descreen { period })
+    }
+    
+    /// Like [`Image::median_filter`], but keeps `self` and returns a new image.
+    pub fn median_filter_ref(&self, radius: u32) -> Result<Self> {
+        self.run(Operation::Median { radius })
+    }
+    
+    /// Like [`Image::denoise`], but keeps `self` and returns a new image.
+    pub fn denoise_ref(&self, strength: f32, patch_radius: u32, search_radius: u32) -> Result<Self> {
+        self.run(Operation::Denoise { strength, patch_radius, search_radius })
+    }
+    
+    /// Like [`Image::smart_enhance`], but keeps `self` and returns a new image.
+    pub fn smart_enhance_ref(&self, level: f32) -> Result<Self> {
+        self.run(Operation::SmartEnhance { level })
+    }
+    
+    /// Like [`Image::frequency_separation`], but keeps `self` and returns a
+    /// new image.
+    pub fn frequency_separation_ref(&self, radius: f32, smoothing: f32, amount: f32) -> Result<Self> {
+        self.run(Operation::FrequencySeparation { radius, smoothing, amount })
+    }
+    
+    /// Runs `operation` on `self` after checking its parameters.
+    fn run(&self, operation: Operation) -> Result<Self> {
+        operation.validate_with(&self.validation)?;
+        builder::run_operation(self, &operation, &self.context())
+    }
+    
+    /// Creates a sharpening builder for fluent configuration.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
+    /// let sharpened = image.sharpen()
+    ///     .unsharp_mask(1.0, 1.0, 0)
+    ///     .clarity(0.5, 2.0)
+    ///     .apply()
+    ///     .unwrap();
+    /// ```
+    pub fn sharpen(self) -> SharpeningBuilder<P> {
+        SharpeningBuilder::new(self)
     }
 }
 
-/// The main image type that provides sharpening operations.
-/// 
-/// This struct uses copy-on-write semantics internally to minimize memory
-/// allocations when cloning images.
-/// 
-/// # Examples
-/// 
-/// ```no_run
-/// use sharpy::Image;
-/// 
-/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
-/// // Load from file
-/// let image = Image::load("photo.jpg")?;
-/// 
-/// // Create from existing image data
-/// use image::RgbImage;
-/// let rgb_image = RgbImage::new(800, 600);
-/// let image = Image::from_rgb(rgb_image)?;
-/// # Ok(())
-/// # }
-/// ```
-#[derive(Clone)]
-pub struct Image {
-    data: ImageData,
-}
-
 impl Image {
+    /// Creates an image, failing with [`ImageError::LimitExceeded`] if it
+    /// exceeds the default [`Limits`].
     pub fn from_dynamic(img: DynamicImage) -> Result<Self> {
-        Self::validate_dimensions(img.width(), img.height())?;
-        Ok(Self {
-            data: ImageData::Owned(img.to_rgb8()),
-        })
+        Self::from_dynamic_with_limits(img, &Limits::default())
+    }
+    
+    /// Like [`Image::from_dynamic`], with explicit limits.
+    pub fn from_dynamic_with_limits(img: DynamicImage, limits: &Limits) -> Result<Self> {
+        limits.check_dimensions(img.width(), img.height())?;
+        Ok(Self::from_dynamic_unchecked(img))
     }
     
+    /// Creates an image, failing with [`ImageError::LimitExceeded`] if it
+    /// exceeds the default [`Limits`].
     pub fn from_rgb(img: RgbImage) -> Result<Self> {
+        Self::from_rgb_with_limits(img, &Limits::default())
+    }
+    
+    /// Like [`Image::from_rgb`], with explicit limits. Raise them to work
+    /// with legitimately huge images.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Limits};
+    /// 
+    /// let panorama = image::RgbImage::new(70_000, 10);
+    /// assert!(Image::from_rgb(panorama.clone()).is_err());
+    /// 
+    /// let limits = Limits::default().max_dimensions(100_000, 100_000);
+    /// assert!(Image::from_rgb_with_limits(panorama, &limits).is_ok());
+    /// ```
+    pub fn from_rgb_with_limits(img: RgbImage, limits: &Limits) -> Result<Self> {
         let (width, height) = img.dimensions();
+        limits.check_dimensions(width, height)?;
+        Ok(Self::from_buffer_unchecked(img))
+    }
+    
+    /// Creates an image from tightly packed RGBA bytes, such as the pixel
+    /// data of a browser canvas. Alpha is discarded.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::Image;
+    /// 
+    /// let pixels = vec![255u8; 4 * 4 * 4];
+    /// let image = Image::from_rgba_bytes(4, 4, &pixels).unwrap();
+    /// let sharpened = image.unsharp_mask(1.0, 1.0, 0).unwrap();
+    /// assert_eq!(sharpened.to_rgba_bytes(), pixels);
+    /// ```
+    pub fn from_rgba_bytes(width: u32, height: u32, bytes: &[u8]) -> Result<Self> {
+        Self::check_buffer(width, height, bytes.len(), 4)?;
+        let rgb: Vec<u8> = bytes.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+        let img = RgbImage::from_raw(width, height, rgb)
+            .ok_or(ImageError::InvalidDimensions { width, height })?;
+        Ok(Self::from_buffer_unchecked(img))
+    }
+    
+    /// Creates an image that takes ownership of a tightly packed RGB buffer,
+    /// such as a decoded video frame, without copying it.
+    /// 
+    /// # Example
+    /// ```
+    /// use sharpy::Image;
+    /// 
+    /// let frame = vec![128u8; 320 * 240 * 3];
+    /// let image = Image::from_raw(320, 240, frame).unwrap();
+    /// assert_eq!(image.dimensions(), (320, 240));
+    /// ```
+    pub fn from_raw(width: u32, height: u32, bytes: Vec<u8>) -> Result<Self> {
+        Self::check_buffer(width, height, bytes.len(), 3)?;
+        let img = RgbImage::from_raw(width, height, bytes)
+            .ok_or(ImageError::InvalidDimensions { width, height })?;
+        Ok(Self::from_buffer_unchecked(img))
+    }
+    
+    /// Creates an image from a borrowed, tightly packed RGB buffer, copying
+    /// it once into the image.
+    pub fn from_raw_ref(width: u32, height: u32, bytes: &[u8]) -> Result<Self> {
+        Self::from_raw(width, height, bytes.to_vec())
+    }
+    
+    /// Checks limits and that `len` bytes hold exactly one RGB (3 channels)
+    /// or RGBA (4 channels) image.
+    fn check_buffer(width: u32, height: u32, len: usize, channels: usize) -> Result<()> {
         Self::validate_dimensions(width, height)?;
-        Ok(Self {
-            data: ImageData::Owned(img),
-        })
+        if len != width as usize * height as usize * channels {
+            let layout = if channels == 4 { "RGBA" } else { "RGB" };
+            return Err(ImageError::InvalidParameter {
+                param: "bytes".to_string(),
+                value: format!("{} bytes for {}x{} {}", len, width, height, layout),
+            });
+        }
+        Ok(())
+    }
+    
+    /// Returns the pixels as tightly packed RGBA bytes with opaque alpha.
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.data.get_ref().as_raw()
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect()
     }
     
     /// Create from dynamic image without validation (for internal use)
     fn from_dynamic_unchecked(img: DynamicImage) -> Self {
+        let source_color = Some(img.color());
         Self {
-            data: ImageData::Owned(img.to_rgb8()),
+            data: ImageData::Owned(img.into_rgb8()),
+            icc_profile: None,
+            metadata: None,
+            source_color,
+            luminance: Luminance::default(),
+            validation: ValidationConfig::default(),
         }
     }
     
-    /// Create from RGB image without validation (for internal use)
-    fn from_rgb_unchecked(img: RgbImage) -> Self {
-        Self {
-            data: ImageData::Owned(img),
-        }
+    /// Loads an image file, keeping any embedded ICC profile and metadata.
+    /// 
+    /// Files exceeding the default [`Limits`] are rejected with
+    /// [`ImageError::LimitExceeded`] before their pixels are decoded.
+    /// Animations load as their first frame; see [`AnimatedImage`]. Pixels
+    /// are converted to RGB8; see [`Image::color_type`] for what that loses.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_with_limits(path, &Limits::default())
     }
     
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let img = image::open(path)?;
-        Self::from_dynamic(img)
+    /// Loads an image file, rejecting it if its header exceeds `limits`.
+    pub fn load_with_limits<P: AsRef<Path>>(path: P, limits: &Limits) -> Result<Self> {
+        let path = path.as_ref();
+        let load = || Self::decode(image::ImageReader::open(path)?, limits);
+        load().map_err(|e| e.in_file(path))
     }
     
-    /// Validate image dimensions to prevent memory issues
-    fn validate_dimensions(width: u32, height: u32) -> Result<()> {
-        if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
-            return Err(ImageError::InvalidDimensions { width, height });
-        }
+    fn decode<R: std::io::BufRead + std::io::Seek>(
+        reader: image::ImageReader<R>,
+        limits: &Limits,
+    ) -> Result<Self> {
+        let (img, icc_profile, metadata) = decode(reader, limits)?;
+        Ok(Self::from_dynamic_unchecked(img)
+            .with_icc_profile(icc_profile)
+            .with_metadata(metadata))
+    }
+    
+    /// Converts the pixels from the embedded ICC profile to sRGB and drops
+    /// the profile. Images without a profile are returned unchanged.
+    /// 
+    /// Sharpening works on the stored values, so converting wide-gamut
+    /// images (AdobeRGB, Display P3) first makes results consistent with
+    /// sRGB sources.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let image = Image::load("adobe_rgb.jpg")?.to_srgb()?;
+    /// assert!(image.icc_profile().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_srgb(&self) -> Result<Self> {
+        let Some(profile) = &self.icc_profile else {
+            return Ok(self.clone());
+        };
         
-        let total_pixels = width as usize * height as usize;
-        if total_pixels > MAX_IMAGE_PIXELS {
-            return Err(ImageError::InvalidDimensions { width, height });
-        }
+        let input = qcms::Profile::new_from_slice(profile, false)
+            .ok_or_else(|| ImageError::ColorProfile("unsupported or malformed ICC profile".to_string()))?;
+        let mut output = qcms::Profile::new_sRGB();
+        output.precache_output_transform();
+        let transform = qcms::Transform::new(&input, &output, qcms::DataType::RGB8, qcms::Intent::Perceptual)
+            .ok_or_else(|| ImageError::ColorProfile("cannot convert ICC profile to sRGB".to_string()))?;
         
-        Ok(())
+        let mut img = self.data.get_ref().clone();
+        transform.apply(&mut img);
+        Ok(self.with_pixels(img).with_icc_profile(None))
     }
     
     pub fn from_arc_dynamic(arc_img: Arc<DynamicImage>) -> Result<Self> {
@@ -194,7 +1079,8 @@ impl Image {
         match Arc::try_unwrap(arc_img) {
             Ok(img) => Ok(Self::from_dynamic_unchecked(img)),
             Err(arc_img) => Ok(Self {
-                data: ImageData::Shared(Arc::new(arc_img.to_rgb8())),
+                source_color: Some(arc_img.color()),
+                ..Self::from_buffer_unchecked(arc_img.to_rgb8())
             }),
         }
     }
@@ -204,65 +1090,198 @@ impl Image {
         Self::validate_dimensions(width, height)?;
         
         match Arc::try_unwrap(arc_img) {
-            Ok(img) => Ok(Self::from_rgb_unchecked(img)),
-            Err(arc_img) => Ok(Self {
-                data: ImageData::Shared(arc_img),
-            }),
+            Ok(img) => Ok(Self::from_buffer_unchecked(img)),
+            Err(arc_img) => Ok(Self::from_shared(arc_img)),
         }
     }
     
     pub fn from_dynamic_ref(img: &DynamicImage) -> Result<Self> {
         Self::validate_dimensions(img.width(), img.height())?;
         Ok(Self {
-            data: ImageData::Owned(img.to_rgb8()),
+            source_color: Some(img.color()),
+            ..Self::from_buffer_unchecked(img.to_rgb8())
         })
     }
     
+    /// Returns the pixel format of the source this image was decoded or
+    /// converted from; RGB8 for images created from RGB pixels.
+    /// 
+    /// Pixels are processed as RGB8. [`Image::into_dynamic`] converts back
+    /// to this format, and saving writes it where the output format
+    /// supports it (all formats for PNG; 8/16-bit gray and color for TIFF;
+    /// grayscale for JPEG), so a 16-bit grayscale PNG stays 16-bit
+    /// grayscale, though only with 8 bits of precision. Alpha channels come
+    /// back opaque. [`SharpeningBuilder::apply_with_report`] reports either
+    /// loss as [`Warning::SourceNarrowed`]; [`Image::load_gray16`] and
+    /// [`Image::load_rgb16`] keep full 16-bit precision, and
+    /// `Image<Rgba<u8>>` via [`Image::from_buffer`] keeps alpha.
+    pub fn color_type(&self) -> image::ColorType {
+        self.source_color.unwrap_or(image::ColorType::Rgb8)
+    }
+    
+    /// Sets the pixel format used on output, e.g. `ColorType::Rgb8` to
+    /// write 8-bit color regardless of the source.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    /// use image::ColorType;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let image = Image::load("scan_16bit_gray.png")?;
+    /// assert_eq!(image.color_type(), ColorType::L16);
+    /// image.with_color_type(ColorType::L8).save("scan_8bit_gray.png")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_color_type(mut self, color: image::ColorType) -> Self {
+        self.source_color = Some(color);
+        self
+    }
+    
     pub fn into_arc_dynamic(self) -> Arc<DynamicImage> {
-        match self.data {
-            ImageData::Owned(img) => Arc::new(DynamicImage::ImageRgb8(img)),
-            ImageData::Shared(arc_img) => {
-                // Extract the owned image if possible to avoid cloning
-                match Arc::try_unwrap(arc_img) {
-                    Ok(img) => Arc::new(DynamicImage::ImageRgb8(img)),
-                    Err(arc_img) => Arc::new(DynamicImage::ImageRgb8((*arc_img).clone()))
-                }
-            }
-        }
+        Arc::new(self.into_dynamic())
     }
     
+    /// Converts to a `DynamicImage` in the source pixel format (see
+    /// [`Image::color_type`]).
     pub fn into_dynamic(self) -> DynamicImage {
-        match self.data {
-            ImageData::Owned(img) => DynamicImage::ImageRgb8(img),
-            ImageData::Shared(arc_img) => {
-                DynamicImage::ImageRgb8((*arc_img).clone())
-            }
+        match self.color_type() {
+            image::ColorType::Rgb8 => DynamicImage::ImageRgb8(self.into_buffer()),
+            color => pixel::convert_rgb8(self.as_rgb(), color),
         }
     }
     
     pub fn into_rgb(self) -> RgbImage {
-        match self.data {
-            ImageData::Owned(img) => img,
-            ImageData::Shared(arc_img) => (*arc_img).clone(),
-        }
+        self.into_buffer()
     }
     
     pub fn save<P: AsRef<Path>>(self, path: P) -> Result<()> {
-        self.into_dynamic().save(path)?;
-        Ok(())
+        self.save_with(path, &SaveOptions::default())
     }
     
-    pub fn dimensions(&self) -> (u32, u32) {
-        self.data.get_ref().dimensions()
+    /// Saves the image with explicit encoder settings.
+    /// 
+    /// The format is chosen from the file extension. JPEG output honors
+    /// quality and chroma subsampling, PNG output honors the compression
+    /// level, and WebP output honors quality and lossless mode; see
+    /// [`EncodeOptions`]. Other formats use their defaults. An embedded
+    /// ICC profile is written for JPEG, PNG and WebP.
+    pub fn save_with_options<P: AsRef<Path>>(&self, path: P, options: &EncodeOptions) -> Result<()> {
+        self.save_with(path, &SaveOptions::from(options.clone()))
+    }
+    
+    /// Saves the image with an explicit format and encoder settings.
+    /// 
+    /// The format is taken from `options.format`, falling back to the file
+    /// extension.
+    pub fn save_with<P: AsRef<Path>>(&self, path: P, options: &SaveOptions) -> Result<()> {
+        let path = path.as_ref();
+        let save = || -> Result<()> {
+            let format = match options.format {
+                Some(format) => format,
+                None => image::ImageFormat::from_path(path)?,
+            };
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            self.write_to(&mut file, format, &options.encode)?;
+            file.flush()?;
+            Ok(())
+        };
+        save().map_err(|e| e.in_file(path))
+    }
+    
+    /// Decodes an image from an in-memory buffer, detecting the format
+    /// from its contents.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// use sharpy::{EncodeOptions, Image};
+    /// use image::ImageFormat;
+    /// 
+    /// # fn handle_upload(body: &[u8]) -> sharpy::Result<Vec<u8>> {
+    /// let image = Image::from_bytes(body)?.unsharp_mask(1.0, 1.0, 0)?;
+    /// image.to_bytes(ImageFormat::Jpeg, &EncodeOptions::default().jpeg_quality(90))
+    /// # }
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_limits(bytes, &Limits::default())
+    }
+    
+    /// Decodes an in-memory image, rejecting it if its header exceeds
+    /// `limits`. Use this for untrusted uploads.
+    pub fn from_bytes_with_limits(bytes: &[u8], limits: &Limits) -> Result<Self> {
+        let reader = image::ImageReader::new(std::io::Cursor::new(bytes)).with_guessed_format()?;
+        Self::decode(reader, limits)
+    }
+    
+    /// Decodes an in-memory image in a known format, for input whose
+    /// contents can't be sniffed reliably (e.g. TGA).
+    pub fn from_bytes_with_format(bytes: &[u8], format: image::ImageFormat) -> Result<Self> {
+        Self::from_bytes_with_format_and_limits(bytes, format, &Limits::default())
+    }
+    
+    /// Decodes an in-memory image in a known format, rejecting it if its
+    /// header exceeds `limits`.
+    pub fn from_bytes_with_format_and_limits(bytes: &[u8], format: image::ImageFormat, limits: &Limits) -> Result<Self> {
+        let reader = image::ImageReader::with_format(std::io::Cursor::new(bytes), format);
+        Self::decode(reader, limits)
     }
     
+    /// Encodes the image into an in-memory buffer.
+    pub fn to_bytes(&self, format: image::ImageFormat, options: &EncodeOptions) -> Result<Vec<u8>> {
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        self.write_to(&mut cursor, format, options)?;
+        Ok(cursor.into_inner())
+    }
+    
+    /// Encodes the image in `format` into `writer`.
+    fn write_to<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        format: image::ImageFormat,
+        options: &EncodeOptions,
+    ) -> Result<()> {
+        let rgb = self.data.get_ref();
+        let converted = match encode::output_color(format, self.color_type()) {
+            image::ColorType::Rgb8 => None,
+            color => Some(pixel::convert_rgb8(rgb, color)),
+        };
+        let img = match &converted {
+            Some(img) => encode::Pixels::from(img),
+            None => encode::Pixels::from(rgb),
+        };
+        encode::write(img, writer, format, options, self.icc_profile(), self.metadata())
+    }
+    
+    /// Borrows the pixel buffer without consuming the image.
+    /// 
+    /// # Example
+    /// ```
+    /// # use sharpy::Image;
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let sharpened = image.unsharp_mask_ref(1.0, 1.0, 0).unwrap();
+    /// let clipped = sharpened.pixels().filter(|p| p.0.contains(&255)).count();
+    /// assert_eq!(clipped, 0);
+    /// assert_eq!(sharpened.as_rgb().dimensions(), (64, 64));
+    /// ```
+    pub fn as_rgb(&self) -> &RgbImage {
+        self.data.get_ref()
+    }
+    
+    /// Builds a Gaussian/Laplacian pyramid with `levels` levels, each half
+    /// the size of the previous; see [`Pyramid`].
+    pub fn pyramid(&self, levels: usize) -> Result<Pyramid> {
+        Pyramid::build(self, levels)
+    }
+    
+    /// Counts the pixels at each luminance level, weighted as set by
+    /// [`Image::with_luminance`].
     pub fn histogram(&self) -> [u32; 256] {
         let hist: Vec<AtomicU32> = (0..256).map(|_| AtomicU32::new(0)).collect();
         let img = self.data.get_ref();
         
         img.pixels().par_bridge().for_each(|pixel| {
-            let [r, g, b] = pixel.0;
-            let luminance = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) as usize;
+            let luminance = self.luminance.of(pixel) as usize;
             hist[luminance.min(255)].fetch_add(1, Ordering::Relaxed);
         });
         
@@ -272,89 +1291,269 @@ impl Image {
         }
         result
     }
-    
-    pub fn unsharp_mask(self, radius: f32, amount: f32, threshold: u8) -> Result<Self> {
-        if radius <= 0.0 || radius > 10.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "radius".to_string(),
-                value: radius.to_string(),
-            });
+}
+
+
+/// Registers libheif's HEIC/HEIF decoder with `image`, by extension and by
+/// file signature, before the first decode.
+#[cfg(feature = "heic")]
+fn register_heic() {
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    REGISTER.call_once(|| {
+        libheif_rs::integration::image::register_heic_decoding_hook();
+        libheif_rs::integration::image::register_heif_decoding_hook();
+    });
+}
+
+/// Decodes the pixels, ICC profile and metadata of `reader`, rejecting
+/// headers that exceed `limits` before decoding pixels.
+fn decode<R: std::io::BufRead + std::io::Seek>(
+    mut reader: image::ImageReader<R>,
+    limits: &Limits,
+) -> Result<(DynamicImage, Option<Vec<u8>>, Metadata)> {
+    #[cfg(feature = "heic")]
+    {
+        register_heic();
+        // Hooks match extensions case-sensitively (phones write .HEIC), so
+        // files with extensions image doesn't know are identified by content
+        if reader.format().is_none() {
+            reader = reader.with_guessed_format()?;
         }
-        if amount < 0.0 || amount > 5.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "amount".to_string(),
-                value: amount.to_string(),
-            });
+    }
+    
+    // The decoders don't expose XMP, so JPEG segments are read directly
+    let xmp = match reader.format() {
+        Some(image::ImageFormat::Jpeg) => {
+            let mut inner = reader.into_inner();
+            let xmp = metadata::read_jpeg_xmp(&mut inner)?;
+            reader = image::ImageReader::with_format(inner, image::ImageFormat::Jpeg);
+            xmp
         }
+        _ => None,
+    };
+    
+    // Check our limits first so oversized headers report LimitExceeded
+    reader.no_limits();
+    let mut decoder = reader.into_decoder()?;
+    limits.check_decoder(&decoder)?;
+    decoder.set_limits(limits.to_image_limits())?;
+    
+    let icc_profile = decoder.icc_profile()?;
+    let exif = decoder.exif_metadata()?;
+    let img = DynamicImage::from_decoder(decoder)?;
+    Ok((img, icc_profile, Metadata { exif, xmp }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_image_creation() {
+        let img = RgbImage::new(100, 100);
+        let sharpy_img = Image::from_rgb(img).unwrap();
+        assert_eq!(sharpy_img.dimensions(), (100, 100));
+    }
+    
+    #[test]
+    fn test_bytes_round_trip() {
+        let img = RgbImage::from_fn(16, 8, |x, y| image::Rgb([(x * 16) as u8, (y * 32) as u8, 90]));
+        let image = Image::from_rgb(img.clone()).unwrap();
+        
+        let png = image.to_bytes(image::ImageFormat::Png, &EncodeOptions::default()).unwrap();
+        assert_eq!(Image::from_bytes(&png).unwrap().into_rgb(), img);
+        
+        let jpeg = image.to_bytes(image::ImageFormat::Jpeg, &EncodeOptions::default()).unwrap();
+        assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+        assert_eq!(Image::from_bytes(&jpeg).unwrap().dimensions(), (16, 8));
         
-        sharpening::unsharp_mask(self, radius, amount, threshold)
+        assert!(Image::from_bytes(b"not an image").is_err());
+        
+        assert_eq!(Image::from_bytes_with_format(&png, image::ImageFormat::Png).unwrap().into_rgb(), img);
+        assert!(Image::from_bytes_with_format(&png, image::ImageFormat::Jpeg).is_err());
     }
     
-    pub fn high_pass_sharpen(self, strength: f32) -> Result<Self> {
-        if strength <= 0.0 || strength > 3.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "strength".to_string(),
-                value: strength.to_string(),
-            });
+    #[test]
+    fn test_png_compression() {
+        let img = RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8]));
+        let image = Image::from_rgb(img.clone()).unwrap();
+        let encode = |compression| {
+            let options = EncodeOptions::default().png_compression(compression);
+            image.to_bytes(image::ImageFormat::Png, &options).unwrap()
+        };
+        
+        let fast = encode(PngCompression::Fast);
+        let best = encode(PngCompression::Best);
+        assert!(best.len() < fast.len());
+        assert_eq!(Image::from_bytes(&best).unwrap().into_rgb(), img);
+    }
+
+    #[test]
+    fn test_color_type_preserved() {
+        let gray = image::ImageBuffer::from_fn(32, 32, |x, _| image::Luma([(x * 2048) as u16]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageLuma16(gray).write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        let image = Image::from_bytes(png.get_ref()).unwrap().unsharp_mask(1.0, 1.0, 0).unwrap();
+        assert_eq!(image.color_type(), image::ColorType::L16);
+
+        let options = EncodeOptions::default();
+        let png = image.to_bytes(image::ImageFormat::Png, &options).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().color(), image::ColorType::L16);
+        let jpeg = image.to_bytes(image::ImageFormat::Jpeg, &options).unwrap();
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().color(), image::ColorType::L8);
+        assert_eq!(image.clone().into_dynamic().color(), image::ColorType::L16);
+
+        let rgb = image.with_color_type(image::ColorType::Rgb8);
+        assert_eq!(rgb.into_dynamic().color(), image::ColorType::Rgb8);
+        assert_eq!(Image::from_rgb(RgbImage::new(4, 4)).unwrap().color_type(), image::ColorType::Rgb8);
+    }
+
+    #[test]
+    fn test_icc_profile_preserved() {
+        let profile = std::fs::read("tests/fixtures/display_p3.icc").unwrap();
+        let img = RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 200]));
+        let image = Image::from_rgb(img).unwrap().with_icc_profile(Some(profile.clone()));
+        
+        let sharpened = image.unsharp_mask_ref(1.0, 1.0, 0).unwrap();
+        assert_eq!(sharpened.icc_profile(), Some(&profile[..]));
+        
+        for format in [image::ImageFormat::Jpeg, image::ImageFormat::Png, image::ImageFormat::WebP] {
+            let bytes = sharpened.to_bytes(format, &EncodeOptions::default()).unwrap();
+            let decoded = Image::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.icc_profile(), Some(&profile[..]), "{:?}", format);
         }
+    }
+    
+    #[test]
+    fn test_metadata_preserved() {
+        // Little-endian TIFF header with an empty IFD
+        let exif = b"II*\0\x08\0\0\0\0\0".to_vec();
+        let xmp = b"<x:xmpmeta xmlns:x='adobe:ns:meta/'/>".to_vec();
+        let metadata = Metadata { exif: Some(exif), xmp: Some(xmp) };
+        let img = RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 200]));
+        let image = Image::from_rgb(img).unwrap().with_metadata(metadata.clone());
         
-        sharpening::high_pass_sharpen(self, strength)
+        let sharpened = image.unsharp_mask(1.0, 1.0, 0).unwrap();
+        let bytes = sharpened.to_bytes(image::ImageFormat::Jpeg, &EncodeOptions::default()).unwrap();
+        let decoded = Image::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata(), &metadata);
+        
+        let stripped = decoded.strip_metadata();
+        assert!(stripped.metadata().is_empty());
+        let bytes = stripped.to_bytes(image::ImageFormat::Jpeg, &EncodeOptions::default()).unwrap();
+        assert!(Image::from_bytes(&bytes).unwrap().metadata().is_empty());
     }
     
-    pub fn enhance_edges(self, strength: f32, method: EdgeMethod) -> Result<Self> {
-        if strength <= 0.0 || strength > 3.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "strength".to_string(),
-                value: strength.to_string(),
-            });
-        }
+    #[test]
+    fn test_to_srgb() {
+        let profile = std::fs::read("tests/fixtures/display_p3.icc").unwrap();
+        let img = RgbImage::from_pixel(4, 4, image::Rgb([180, 90, 40]));
+        let image = Image::from_rgb(img).unwrap().with_icc_profile(Some(profile));
         
-        sharpening::enhance_edges(self, strength, method)
+        // The same values are more saturated in P3, so red rises and green falls in sRGB
+        let srgb = image.to_srgb().unwrap();
+        assert!(srgb.icc_profile().is_none());
+        let [r, g, _] = srgb.into_rgb().get_pixel(0, 0).0;
+        assert!(r > 180 && g < 90, "{} {}", r, g);
+        
+        let broken = Image::from_rgb(RgbImage::new(4, 4)).unwrap().with_icc_profile(Some(vec![1, 2, 3]));
+        assert!(matches!(broken.to_srgb(), Err(ImageError::ColorProfile(_))));
     }
     
-    pub fn clarity(self, strength: f32, radius: f32) -> Result<Self> {
-        if strength <= 0.0 || strength > 3.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "strength".to_string(),
-                value: strength.to_string(),
-            });
-        }
-        if radius <= 0.0 || radius > 20.0 {
-            return Err(ImageError::InvalidParameter {
-                param: "radius".to_string(),
-                value: radius.to_string(),
-            });
-        }
+    #[test]
+    fn test_from_raw() {
+        let bytes: Vec<u8> = (0..4 * 2 * 3).map(|i| i as u8).collect();
+        let image = Image::from_raw_ref(4, 2, &bytes).unwrap();
+        assert_eq!(image.clone().into_rgb().get_pixel(1, 0).0, [3, 4, 5]);
+        
+        let pointer = bytes.as_ptr();
+        let owned = Image::from_raw(4, 2, bytes).unwrap().into_rgb();
+        assert_eq!(owned.as_raw().as_ptr(), pointer, "buffer should not be copied");
         
-        sharpening::clarity(self, strength, radius)
+        assert!(Image::from_raw(4, 2, vec![0; 23]).is_err());
+        assert!(matches!(Image::from_raw_ref(70000, 1, &[]), Err(ImageError::LimitExceeded { .. })));
     }
     
-    /// Creates a sharpening builder for fluent configuration.
-    /// 
-    /// # Example
-    /// ```no_run
-    /// # use sharpy::Image;
-    /// # let image = Image::from_rgb(image::RgbImage::new(100, 100)).unwrap();
-    /// let sharpened = image.sharpen()
-    ///     .unsharp_mask(1.0, 1.0, 0)
-    ///     .clarity(0.5, 2.0)
-    ///     .apply()
-    ///     .unwrap();
-    /// ```
-    pub fn sharpen(self) -> SharpeningBuilder {
-        SharpeningBuilder::new(self)
+    #[test]
+    fn test_borrowed_access() {
+        let img = RgbImage::from_fn(4, 3, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let image = Image::from_arc_rgb(Arc::new(img.clone())).unwrap();
+        
+        assert_eq!(image.as_rgb(), &img);
+        assert_eq!(image.get_pixel(3, 2), Some(&image::Rgb([3, 2, 7])));
+        assert_eq!(image.get_pixel(4, 0), None);
+        assert_eq!(image.pixels().count(), 12);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
     
     #[test]
-    fn test_image_creation() {
-        let img = RgbImage::new(100, 100);
-        let sharpy_img = Image::from_rgb(img).unwrap();
-        assert_eq!(sharpy_img.dimensions(), (100, 100));
+    fn test_crop_and_view() {
+        let img = RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let image = Image::from_rgb(img).unwrap();
+        
+        let crop = image.crop(4, 2, 8, 4).unwrap();
+        assert_eq!(crop.dimensions(), (8, 4));
+        assert_eq!(crop.into_rgb().get_pixel(0, 0).0, [4, 2, 0]);
+        
+        use image::GenericImageView;
+        let view = image.view(Rect::new(10, 6, 6, 2)).unwrap();
+        assert_eq!(view.dimensions(), (6, 2));
+        assert_eq!(view.get_pixel(5, 1).0, [15, 7, 0]);
+        
+        assert!(image.crop(12, 0, 8, 8).is_err());
+        assert!(image.view(Rect::new(0, 0, 0, 8)).is_err());
+    }
+    
+    #[test]
+    fn test_decode_limits() {
+        // A header declaring 60000x60000 pixels with no pixel data behind it
+        let bomb = b"P6\n60000 60000\n255\n";
+        assert!(matches!(
+            Image::from_bytes(bomb),
+            Err(ImageError::LimitExceeded { ref limit, .. }) if limit == "pixels"
+        ));
+        
+        let png = Image::from_rgb(RgbImage::new(64, 32)).unwrap()
+            .to_bytes(image::ImageFormat::Png, &EncodeOptions::default()).unwrap();
+        assert!(Image::from_bytes_with_limits(&png, &Limits::default().max_dimensions(64, 32)).is_ok());
+        assert!(matches!(
+            Image::from_bytes_with_limits(&png, &Limits::default().max_decoded_bytes(1024)),
+            Err(ImageError::LimitExceeded { ref limit, value: 6144, max: 1024 }) if limit == "decoded_bytes"
+        ));
+        assert!(matches!(
+            Image::from_bytes_with_format_and_limits(&png, image::ImageFormat::Png, &Limits::default().max_dimensions(32, 32)),
+            Err(ImageError::LimitExceeded { ref limit, .. }) if limit == "width"
+        ));
+    }
+    
+    #[test]
+    fn test_error_context() {
+        struct Fail;
+        impl PipelineOp for Fail {
+            fn name(&self) -> &'static str {
+                "Fail"
+            }
+            fn apply(&self, _: Image) -> Result<Image> {
+                Err(ImageError::InvalidParameter { param: "radius".to_string(), value: "-1".to_string() })
+            }
+        }
+        
+        let image = Image::from_rgb(RgbImage::new(16, 16)).unwrap();
+        let error = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).custom(Fail).apply().err().unwrap();
+        assert_eq!(error.operation(), Some("Fail"));
+        assert!(matches!(error.root(), ImageError::InvalidParameter { param, .. } if param == "radius"));
+        assert_eq!(error.to_string(), "step 1 (Fail) failed: Invalid parameter: radius = -1");
+        assert!(std::error::Error::source(&error).is_some());
+        
+        // Single operations on an image report their errors directly
+        let error = image.clone().clarity(0.5, -1.0).err().unwrap();
+        assert!(matches!(error, ImageError::InvalidParameter { ref param, .. } if param == "radius"));
+        assert_eq!(error.operation(), None);
+        
+        let error = image.save("out.unknown").unwrap_err();
+        assert_eq!(error.path(), Some(Path::new("out.unknown")));
+        assert!(matches!(error.root(), ImageError::Format(_)));
+        assert!(error.to_string().starts_with("out.unknown: "));
     }
     
     #[test]
@@ -379,4 +1578,33 @@ mod tests {
         let sharpy_img4 = Image::from_rgb(img4).unwrap();
         assert!(sharpy_img4.clarity(-1.0, 1.0).is_err());
     }
+    
+    #[cfg(feature = "avif")]
+    #[test]
+    fn test_load_avif() {
+        // 16x16, dark on the left half and light on the right
+        let image = Image::load("tests/fixtures/tiny.avif").unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+        assert!(image.as_rgb().get_pixel(2, 8)[0] < 64);
+        assert!(image.as_rgb().get_pixel(13, 8)[0] > 192);
+        
+        let bytes = std::fs::read("tests/fixtures/tiny.avif").unwrap();
+        assert_eq!(Image::from_bytes(&bytes).unwrap().as_rgb(), image.as_rgb());
+    }
+    
+    #[cfg(feature = "heic")]
+    #[test]
+    fn test_load_heic() {
+        // A 64x64 photo from kamadak-exif's tests
+        let image = Image::load("tests/fixtures/tiny.heic").unwrap();
+        assert_eq!(image.dimensions(), (64, 64));
+        
+        // Phones write upper-case extensions, and uploads have none
+        let bytes = std::fs::read("tests/fixtures/tiny.heic").unwrap();
+        let path = std::env::temp_dir().join(format!("sharpy_{}.HEIC", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(Image::load(&path).unwrap().dimensions(), (64, 64));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Image::from_bytes(&bytes).unwrap().as_rgb(), image.as_rgb());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,134 @@
+//! Size limits enforced when decoding and constructing images.
+
+use crate::{ImageError, Result};
+
+/// Default maximum width or height.
+const MAX_IMAGE_DIMENSION: u32 = 65536;
+/// Default maximum pixel count (~100 megapixels).
+const MAX_IMAGE_PIXELS: u64 = 100_000_000;
+/// Default cap on the decoder's output buffer, matching the `image` crate.
+const DEFAULT_MAX_DECODED_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Limits on image size.
+///
+/// Loading checks them against the file header before any pixels are
+/// decoded: a small compressed file can declare enormous dimensions (a
+/// "decompression bomb"), and checking the header first rejects it before
+/// the pixel buffer is allocated. Constructors such as
+/// [`Image::from_rgb`](crate::Image::from_rgb) check the dimension and pixel
+/// limits too; their `_with_limits` variants accept raised limits for
+/// legitimately huge images.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{Image, ImageError, Limits};
+///
+/// # fn handle_upload(body: &[u8]) -> sharpy::Result<Option<Image>> {
+/// let limits = Limits::default()
+///     .max_dimensions(8192, 8192)
+///     .max_decoded_bytes(64 * 1024 * 1024);
+///
+/// match Image::from_bytes_with_limits(body, &limits) {
+///     Ok(image) => Ok(Some(image)),
+///     // Reject with 413 rather than failing the service
+///     Err(ImageError::LimitExceeded { .. }) => Ok(None),
+///     Err(e) => Err(e),
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum width in pixels
+    pub max_width: u32,
+    /// Maximum height in pixels
+    pub max_height: u32,
+    /// Maximum width * height
+    pub max_pixels: u64,
+    /// Maximum size of the decoded pixel buffer in bytes
+    pub max_decoded_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_width: MAX_IMAGE_DIMENSION,
+            max_height: MAX_IMAGE_DIMENSION,
+            max_pixels: MAX_IMAGE_PIXELS,
+            max_decoded_bytes: DEFAULT_MAX_DECODED_BYTES,
+        }
+    }
+}
+
+impl Limits {
+    /// Sets the maximum width and height.
+    pub fn max_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.max_width = width;
+        self.max_height = height;
+        self
+    }
+
+    /// Sets the maximum number of pixels.
+    pub fn max_pixels(mut self, pixels: u64) -> Self {
+        self.max_pixels = pixels;
+        self
+    }
+
+    /// Sets the maximum decoded buffer size in bytes.
+    pub fn max_decoded_bytes(mut self, bytes: u64) -> Self {
+        self.max_decoded_bytes = bytes;
+        self
+    }
+
+    /// Checks dimensions against the width, height and pixel limits.
+    pub(crate) fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        check("width", width as u64, self.max_width as u64)?;
+        check("height", height as u64, self.max_height as u64)?;
+        check("pixels", width as u64 * height as u64, self.max_pixels)
+    }
+
+    /// Checks a decoder's header before its pixels are read.
+    pub(crate) fn check_decoder(&self, decoder: &impl image::ImageDecoder) -> Result<()> {
+        let (width, height) = decoder.dimensions();
+        self.check_dimensions(width, height)?;
+        check("decoded_bytes", decoder.total_bytes(), self.max_decoded_bytes)
+    }
+
+    /// Equivalent limits for the `image` crate, bounding the decoder's own
+    /// allocations as well.
+    pub(crate) fn to_image_limits(self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_decoded_bytes);
+        limits
+    }
+}
+
+fn check(limit: &str, value: u64, max: u64) -> Result<()> {
+    if value > max {
+        return Err(ImageError::LimitExceeded { limit: limit.to_string(), value, max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_dimensions() {
+        let limits = Limits::default().max_dimensions(100, 50).max_pixels(4000);
+        assert!(limits.check_dimensions(100, 40).is_ok());
+
+        match limits.check_dimensions(101, 10) {
+            Err(ImageError::LimitExceeded { limit, value, max }) => {
+                assert_eq!((limit.as_str(), value, max), ("width", 101, 100));
+            }
+            other => panic!("expected LimitExceeded, got {:?}", other),
+        }
+        assert!(matches!(
+            limits.check_dimensions(100, 41),
+            Err(ImageError::LimitExceeded { ref limit, .. }) if limit == "pixels"
+        ));
+    }
+}
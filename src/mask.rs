@@ -0,0 +1,573 @@
+//! Masks that decide where sharpening applies.
+//!
+//! [`structure`] separates flat areas such as skies, skin and studio
+//! backdrops, whose only detail is noise, from edges and texture such as
+//! foliage, hair and fabric, so adaptive sharpening
+//! ([`SharpeningBuilder::adaptive`](crate::SharpeningBuilder::adaptive) and
+//! [`Graph::structure_mask`](crate::Graph::structure_mask)) can leave the
+//! noise alone.
+//!
+//! [`stars`] finds point sources in astrophotographs, so sharpening
+//! ([`SharpeningBuilder::protect_stars`](crate::SharpeningBuilder::protect_stars))
+//! can bring out nebulosity without ringing around or clipping the stars.
+//!
+//! [`tones`] fades out near black and white, so sharpening
+//! ([`SharpeningBuilder::protect_highlights`](crate::SharpeningBuilder::protect_highlights)
+//! and [`protect_shadows`](crate::SharpeningBuilder::protect_shadows)) doesn't
+//! add crunchy detail to blown highlights and blocked shadows.
+//!
+//! [`radial`] changes from one weight at the center to another in the
+//! corners, so sharpening
+//! ([`SharpeningBuilder::radial_falloff`](crate::SharpeningBuilder::radial_falloff))
+//! can make up for lenses that are softer toward the edges.
+//!
+//! [`skin`] finds smooth, skin-toned areas, so portrait retouching
+//! ([`Image::frequency_separation`](crate::Image::frequency_separation))
+//! can even out skin while sharpening eyes, lashes and hair.
+
+use crate::analysis::noise_level;
+use crate::pixel::{Buffer, SharpenPixel, sample, unit_scale};
+use crate::utils::{blur_plane, Boundary, Luminance};
+use crate::par::*;
+use crate::Image;
+use image::{GrayImage, Luma};
+
+/// Blur applied before taking gradients, so single-pixel noise mostly
+/// cancels out while detail a few pixels across survives.
+const PRESMOOTH_SIGMA: f32 = 1.0;
+/// Size of the neighborhood whose gradients are pooled per pixel.
+const INTEGRATION_SIGMA: f32 = 2.0;
+/// Gradient strength, in 8-bit levels per pixel, below which even a
+/// noise-free image counts as flat.
+const MIN_FLOOR: f32 = 1.0;
+/// How far above the image's noise level the floor sits, in noise
+/// standard deviations.
+const NOISE_FACTOR: f32 = 0.5;
+
+/// Returns a mask of where `image` has structure: white over edges and
+/// texture, black over flat areas, with a soft transition between.
+///
+/// Gradients of the lightly blurred luminance are pooled into a structure
+/// tensor per pixel. Its eigenvalues measure the gradient strength along the
+/// dominant direction and across it: an edge is strong along one direction,
+/// texture in both, and a flat area in neither. Pixels whose combined
+/// strength stays below a floor derived from the image's estimated noise
+/// are flat, so a noisy sky stays black while foliage is white.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{mask, Image};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let image = Image::load("landscape.jpg")?;
+/// let structure = mask::structure(&image);
+/// structure.save("landscape_structure.png").unwrap();
+/// let sharpened = image.sharpen().unsharp_mask(1.2, 1.5, 0).mask(structure).apply()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn structure<P: SharpenPixel>(image: &Image<P>) -> GrayImage {
+    structure_buffer(image.data.get_ref(), image.luminance(), Boundary::Clamp)
+}
+
+pub(crate) fn structure_buffer<P: SharpenPixel>(img: &Buffer<P>, luminance: Luminance, boundary: Boundary) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let scale = unit_scale::<P>();
+    let luma: Vec<f32> = img.pixels().map(|pixel| luminance.of(pixel) / scale).collect();
+    let floor = MIN_FLOOR + NOISE_FACTOR * noise_level(&luma, width, height) as f32;
+    let strength = gradient_strength(&luma, width, height, boundary);
+
+    GrayImage::from_fn(width, height, |x, y| {
+        // Ramp from the floor to twice the floor
+        let weight = smoothstep((strength[(y * width + x) as usize] - floor) / floor);
+        Luma([(weight * 255.0).round() as u8])
+    })
+}
+
+/// Combined gradient strength along and across the dominant direction of
+/// each pixel of the 8-bit luminance `luma`, from its structure tensor.
+fn gradient_strength(luma: &[f32], width: u32, height: u32, boundary: Boundary) -> Vec<f32> {
+    let smooth = blur_plane(luma, width, height, PRESMOOTH_SIGMA, boundary);
+    let at = |x: i32, y: i32| {
+        smooth[boundary.index(y, height) as usize * width as usize + boundary.index(x, width) as usize]
+    };
+    let gradients: Vec<(f32, f32)> = (0..width as usize * height as usize)
+        .map(|index| {
+            let (x, y) = ((index % width as usize) as i32, (index / width as usize) as i32);
+            ((at(x + 1, y) - at(x - 1, y)) / 2.0, (at(x, y + 1) - at(x, y - 1)) / 2.0)
+        })
+        .collect();
+    let pooled = |component: fn(&(f32, f32)) -> f32| {
+        blur_plane(&gradients.iter().map(component).collect::<Vec<_>>(), width, height, INTEGRATION_SIGMA, boundary)
+    };
+    let (xx, xy, yy) = (pooled(|g| g.0 * g.0), pooled(|g| g.0 * g.1), pooled(|g| g.1 * g.1));
+
+    (0..xx.len())
+        .map(|index| {
+            let (major, minor) = eigenvalues(xx[index], xy[index], yy[index]);
+            (major + minor).sqrt()
+        })
+        .collect()
+}
+
+/// Largest half-maximum radius, in pixels, of a point source. Peaks whose
+/// profile falls more slowly are extended objects.
+const MAX_STAR_RADIUS: u32 = 6;
+/// Radius, relative to [`MAX_STAR_RADIUS`], of the ring whose median is the
+/// background under a star; far enough out that nebulosity under the star
+/// is background, not star.
+const BACKGROUND_RING: u32 = 3;
+/// Height above the background, in 8-bit levels, below which even a
+/// noise-free peak is not a star.
+const MIN_STAR_PEAK: f32 = 8.0;
+/// How far above the background a star's peak must be, in noise standard
+/// deviations.
+const STAR_SIGMAS: f32 = 5.0;
+/// Width, in pixels, of the soft edge around each star's disc.
+const STAR_FEATHER: f32 = 2.0;
+
+/// Returns a mask of the stars in `image`: white discs over point sources,
+/// black elsewhere, including over nebulae and galaxies.
+///
+/// A star is a local maximum that rises well above the background (the
+/// median of a wide ring around it) and the image's estimated noise, and
+/// whose profile falls to half its height within a few pixels in every
+/// direction, which rules out extended objects, filaments and noise. Each
+/// disc covers the star out to twice its half-maximum radius plus `margin`
+/// pixels; set `margin` to the reach of the sharpening that follows, so
+/// the disc also covers the background where a halo would form.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{mask, Image};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let image = Image::load("m42.tif")?;
+/// mask::stars(&image, 4).save("m42_stars.png").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+pub fn stars<P: SharpenPixel>(image: &Image<P>, margin: u32) -> GrayImage {
+    stars_buffer(image.data.get_ref(), margin, image.luminance(), Boundary::Clamp)
+}
+
+pub(crate) fn stars_buffer<P: SharpenPixel>(img: &Buffer<P>, margin: u32, luminance: Luminance, boundary: Boundary) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let scale = unit_scale::<P>();
+    let luma: Vec<f32> = img.pixels().map(|pixel| luminance.of(pixel) / scale).collect();
+    let floor = MIN_STAR_PEAK + STAR_SIGMAS * noise_level(&luma, width, height) as f32;
+    let at = |x: i64, y: i64| {
+        luma[boundary.index(y as i32, height) as usize * width as usize + boundary.index(x as i32, width) as usize]
+    };
+    // Samples in eight directions at distance `r` from (x, y)
+    let ring = |x: i64, y: i64, r: i64| {
+        let diagonal = (r as f32 / std::f32::consts::SQRT_2).round() as i64;
+        [(r, 0), (-r, 0), (0, r), (0, -r), (diagonal, diagonal), (-diagonal, diagonal), (diagonal, -diagonal), (-diagonal, -diagonal)]
+            .map(|(dx, dy)| at(x + dx, y + dy))
+    };
+
+    // Returns the half-maximum radius of the star peaking at (x, y), if any
+    let star_radius = |x: i64, y: i64| -> Option<u32> {
+        let peak = at(x, y);
+        // Of a plateau, only its first pixel in scan order counts
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            let neighbor = at(x + dx, y + dy);
+            if neighbor > peak || (neighbor == peak && (dy, dx) < (0, 0)) {
+                return None;
+            }
+        }
+        let mut background = ring(x, y, (BACKGROUND_RING * MAX_STAR_RADIUS) as i64);
+        background.sort_unstable_by(f32::total_cmp);
+        let base = (background[3] + background[4]) / 2.0;
+        if peak - base < floor {
+            return None;
+        }
+        let half = base + (peak - base) / 2.0;
+        (1..=MAX_STAR_RADIUS as i64)
+            .find(|&r| ring(x, y, r).iter().all(|&value| value <= half))
+            .map(|r| r as u32)
+    };
+    let found: Vec<(u32, u32, u32)> = (0..height).into_par_iter()
+        .flat_map(|y| {
+            (0..width)
+                .filter_map(|x| star_radius(x as i64, y as i64).map(|radius| (x, y, radius)))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut mask = GrayImage::new(width, height);
+    for (cx, cy, radius) in found {
+        let disc = (2 * radius + margin) as f32;
+        let extent = (disc + STAR_FEATHER).ceil() as i32;
+        // Discs are cut off at the edges, or continue across them when wrapping
+        let place = |offset: i32, center: u32, len: u32| match boundary {
+            Boundary::Clamp => Some(center as i32 + offset).filter(|&i| (0..len as i32).contains(&i)).map(|i| i as u32),
+            Boundary::Wrap => Some(boundary.index(center as i32 + offset, len)),
+        };
+        for dy in -extent..=extent {
+            let Some(y) = place(dy, cy, height) else { continue };
+            for dx in -extent..=extent {
+                let Some(x) = place(dx, cx, width) else { continue };
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                let weight = (1.0 - (distance - disc) / STAR_FEATHER).clamp(0.0, 1.0);
+                let value = mask.get_pixel_mut(x, y);
+                value[0] = value[0].max((weight * 255.0).round() as u8);
+            }
+        }
+    }
+    mask
+}
+
+/// Returns a mask of the midtones of `image`: white between `shadows` and
+/// `highlights` (luminance in 8-bit levels), fading smoothly to black at
+/// pure black below `shadows` and at pure white above `highlights`. Zero
+/// and 255 leave the shadows and highlights white, respectively.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{mask, Image};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let image = Image::load("snow.jpg")?;
+/// let midtones = mask::tones(&image, 0.0, 220.0);
+/// let sharpened = image.sharpen().unsharp_mask(1.2, 1.5, 0).mask(midtones).apply()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn tones<P: SharpenPixel>(image: &Image<P>, shadows: f32, highlights: f32) -> GrayImage {
+    let img = image.data.get_ref();
+    let luminance = image.luminance();
+    let scale = unit_scale::<P>();
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let luma = luminance.of(img.get_pixel(x, y)) / scale;
+        let mut weight = 1.0;
+        if luma > highlights {
+            weight *= 1.0 - smoothstep((luma - highlights) / (255.0 - highlights));
+        }
+        if luma < shadows {
+            weight *= 1.0 - smoothstep((shadows - luma) / shadows);
+        }
+        Luma([(weight * 255.0).round() as u8])
+    })
+}
+
+/// Returns a `width` x `height` mask that is `center` (0-1) in the middle
+/// and changes smoothly to `edge` (0-1) in the corners, over the outer
+/// `feather` (0-1] share of the distance from the center to a corner.
+///
+/// Distances are relative to the half diagonal, so the mask is round on
+/// square images and stretches to an ellipse on others, reaching `edge`
+/// exactly in the corners.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{mask, Image};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let image = Image::load("wide_angle.jpg")?;
+/// let (width, height) = image.dimensions();
+/// let corners = mask::radial(width, height, 0.4, 1.0, 0.7);
+/// let sharpened = image.sharpen().unsharp_mask(1.2, 2.0, 0).mask(corners).apply()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn radial(width: u32, height: u32, center: f32, edge: f32, feather: f32) -> GrayImage {
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let half_diagonal = cx.hypot(cy).max(f32::EPSILON);
+    let start = 1.0 - feather;
+    GrayImage::from_fn(width, height, |x, y| {
+        let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy) / half_diagonal;
+        let weight = center + (edge - center) * smoothstep((distance - start) / feather);
+        Luma([(weight.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Center of skin tones in the Cb-Cr plane of YCbCr, in 8-bit levels,
+/// across complexions.
+const SKIN_CHROMA: (f32, f32) = (102.0, 153.0);
+/// Distance of Cb and Cr from [`SKIN_CHROMA`] within which a pixel counts
+/// fully as skin-toned; the weight fades out at 1.5 times this.
+const SKIN_SPREAD: (f32, f32) = (25.0, 20.0);
+/// Gradient strength, in 8-bit levels per pixel, above which a skin-toned
+/// area is texture, such as hair, rather than skin; pores and blemishes
+/// stay below it. Texture counts fully at twice this.
+pub(crate) const SKIN_TEXTURE: f32 = 5.0;
+/// How far, in pixels, the skin mask of a pixel depends on the pixels
+/// around it: the structure's presmoothing and pooling, and the gradient.
+pub(crate) const SKIN_REACH: u32 = (3.0 * (PRESMOOTH_SIGMA + INTEGRATION_SIGMA)) as u32 + 1;
+
+/// Returns a mask of the skin in `image`: white over smooth, skin-toned
+/// areas, black over everything else, with a soft transition between.
+///
+/// A pixel is skin-toned when its chroma (Cb and Cr of YCbCr) lies near
+/// the narrow range that skin of any complexion falls in, and smooth: its
+/// gradients, pooled as for [`structure`], stay below those of strands of
+/// hair. Requiring both leaves out blond and brown hair as well as eyes,
+/// brows and lips, which are either not skin-toned or not smooth, while
+/// pores and blemishes still count as skin. Single-channel images carry no
+/// chroma, so every smooth area counts.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{mask, Image};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let image = Image::load("portrait.jpg")?;
+/// let skin = mask::skin(&image);
+/// skin.save("portrait_skin.png").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+pub fn skin<P: SharpenPixel>(image: &Image<P>) -> GrayImage {
+    skin_buffer(image.data.get_ref(), image.luminance(), Boundary::Clamp, SKIN_TEXTURE)
+}
+
+/// [`skin`] with the gradient strength above which skin-toned areas count
+/// as texture, in place of [`SKIN_TEXTURE`].
+pub(crate) fn skin_buffer<P: SharpenPixel>(
+    img: &Buffer<P>,
+    luminance: Luminance,
+    boundary: Boundary,
+    texture: f32,
+) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let scale = unit_scale::<P>();
+    let toned: Vec<f32> = img.pixels()
+        .map(|pixel| {
+            if P::COLOR_CHANNELS < 3 {
+                return 1.0;
+            }
+            let channels = pixel.channels();
+            let [r, g, b] = [0, 1, 2].map(|c| sample::<P>(channels[c]) / scale);
+            let cb = 128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+            let cr = 128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+            let distance = ((cb - SKIN_CHROMA.0) / SKIN_SPREAD.0).hypot((cr - SKIN_CHROMA.1) / SKIN_SPREAD.1);
+            1.0 - smoothstep((distance - 1.0) / 0.5)
+        })
+        .collect();
+    let toned = blur_plane(&toned, width, height, PRESMOOTH_SIGMA, boundary);
+    let luma: Vec<f32> = img.pixels().map(|pixel| luminance.of(pixel) / scale).collect();
+    let strength = gradient_strength(&luma, width, height, boundary);
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let index = (y * width + x) as usize;
+        let smooth = 1.0 - smoothstep((strength[index] - texture) / texture);
+        let weight = toned[index] * smooth;
+        Luma([(weight * 255.0).round() as u8])
+    })
+}
+
+/// Smooth step from 0 to 1 as `t` goes from 0 to 1, flat at both ends.
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Eigenvalues of the symmetric 2x2 matrix [[xx, xy], [xy, yy]], larger
+/// first.
+fn eigenvalues(xx: f32, xy: f32, yy: f32) -> (f32, f32) {
+    let mean = (xx + yy) / 2.0;
+    let spread = (((xx - yy) / 2.0).powi(2) + xy * xy).sqrt();
+    (mean + spread, (mean - spread).max(0.0))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Deterministic noise in -amplitude..=amplitude.
+    fn noise(x: u32, y: u32, amplitude: i32) -> i32 {
+        let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761);
+        (hash >> 16) as i32 % (2 * amplitude + 1) - amplitude
+    }
+
+    /// A noisy sky gradient above foliage-like texture, split by a horizon.
+    fn create_landscape() -> Image {
+        Image::from_rgb(RgbImage::from_fn(96, 96, |x, y| {
+            let value = if y < 48 {
+                120 + y as i32 / 2 + noise(x, y, 6)
+            } else {
+                let leaves = ((x as f32 * 0.9).sin() * (y as f32 * 1.3).cos() * 45.0) as i32;
+                70 + leaves + noise(x, y, 6)
+            };
+            let value = value.clamp(0, 255) as u8;
+            Rgb([value / 2, value, value / 3])
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_structure() {
+        let structure = structure(&create_landscape());
+        let mean = |rows: std::ops::Range<u32>| {
+            let values: Vec<f32> = rows.flat_map(|y| (0..96).map(move |x| (x, y)))
+                .map(|(x, y)| structure.get_pixel(x, y)[0] as f32)
+                .collect();
+            values.iter().sum::<f32>() / values.len() as f32
+        };
+        // Sky, away from the horizon, stays unsharpened
+        assert!(mean(0..40) < 10.0, "{}", mean(0..40));
+        // Foliage and the horizon get full sharpening
+        assert!(mean(56..96) > 245.0, "{}", mean(56..96));
+        assert!(mean(47..49) > 245.0, "{}", mean(47..49));
+
+        // Adaptive sharpening leaves the sky's noise as it was
+        let landscape = create_landscape();
+        let original = landscape.clone().into_rgb();
+        let sharpened = landscape.sharpen().unsharp_mask(1.0, 2.0, 0).adaptive(true).apply().unwrap().into_rgb();
+        for (x, y, pixel) in sharpened.enumerate_pixels() {
+            if y < 40 {
+                assert_eq!(pixel, original.get_pixel(x, y), "({}, {})", x, y);
+            }
+        }
+        assert!((56..96).any(|y| sharpened.get_pixel(40, y) != original.get_pixel(40, y)));
+
+        let flat = Image::from_rgb(RgbImage::from_pixel(16, 16, Rgb([90, 90, 90]))).unwrap();
+        assert!(super::structure(&flat).pixels().all(|pixel| pixel[0] == 0));
+    }
+
+    /// Stars at these (x, y, peak, sigma), some saturating.
+    const STARS: [(f32, f32, f32, f32); 4] = [(20.0, 80.0, 200.0, 1.0), (60.0, 44.0, 180.0, 1.2), (104.0, 78.0, 400.0, 1.5), (2.0, 3.0, 150.0, 0.8)];
+
+    /// A faint nebula with a filament across it, stars and sky noise.
+    fn create_starfield() -> Image {
+        Image::from_rgb(RgbImage::from_fn(128, 96, |x, y| {
+            let (fx, fy) = (x as f32, y as f32);
+            let nebula = 90.0 * (-((fx - 80.0).powi(2) + (fy - 48.0).powi(2)) / (2.0 * 18.0f32.powi(2))).exp();
+            let filament = 40.0 * (-(fy - 20.0 - fx / 4.0).powi(2) / (2.0 * 1.5f32.powi(2))).exp();
+            let stars: f32 = STARS.iter()
+                .map(|&(sx, sy, peak, sigma)| peak * (-((fx - sx).powi(2) + (fy - sy).powi(2)) / (2.0 * sigma * sigma)).exp())
+                .sum();
+            let value = (20.0 + nebula + filament + stars + noise(x, y, 3) as f32).round().clamp(0.0, 255.0) as u8;
+            Rgb([value, value, value])
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_stars() {
+        let sky = create_starfield();
+        let stars = stars(&sky, 3);
+        for &(x, y, _, _) in &STARS {
+            assert_eq!(stars.get_pixel(x as u32, y as u32)[0], 255, "({}, {})", x, y);
+            // Out to the margin beyond the core
+            assert_eq!(stars.get_pixel(x as u32 + 5, y as u32)[0], 255, "({}, {})", x, y);
+        }
+        // Nebula, filament and empty sky aren't stars
+        for (x, y) in [(80, 48), (90, 43), (110, 48), (40, 10), (64, 90)] {
+            assert_eq!(stars.get_pixel(x, y)[0], 0, "({}, {})", x, y);
+        }
+        let covered = stars.pixels().filter(|pixel| pixel[0] > 0).count();
+        assert!(covered < 128 * 96 / 10, "{}", covered);
+
+        // Sharpening leaves the stars and the sky around them as they were,
+        // where it would otherwise dig dark rings
+        let original = sky.clone().into_rgb();
+        let plain = sky.clone().sharpen().unsharp_mask(2.0, 2.0, 0).apply().unwrap().into_rgb();
+        let protected = sky.sharpen().unsharp_mask(2.0, 2.0, 0).protect_stars(true).apply().unwrap().into_rgb();
+        let (x, y) = (20, 80);
+        assert!(plain.get_pixel(x + 3, y)[0] + 10 < original.get_pixel(x + 3, y)[0]);
+        for dy in -6i32..=6 {
+            for dx in -6i32..=6 {
+                let (px, py) = ((x as i32 + dx) as u32, (y as i32 + dy) as u32);
+                assert_eq!(protected.get_pixel(px, py), original.get_pixel(px, py), "({}, {})", px, py);
+            }
+        }
+        // The filament is still sharpened
+        assert_ne!(protected.get_pixel(92, 44), original.get_pixel(92, 44));
+
+        // On tileable images, stars across an edge are found and masked on
+        // both sides of it
+        let (width, height) = (128, 96);
+        let shift = |x: u32, y: u32| ((x + width - 20) % width, (y + height - 8) % height);
+        let wrapped = stars_buffer(&original, 3, Luminance::Bt601, Boundary::Wrap);
+        let shifted = RgbImage::from_fn(width, height, |x, y| *original.get_pixel((x + 20) % width, (y + 8) % height));
+        let shifted = stars_buffer(&shifted, 3, Luminance::Bt601, Boundary::Wrap);
+        for (x, y, pixel) in wrapped.enumerate_pixels() {
+            let (sx, sy) = shift(x, y);
+            assert_eq!(shifted.get_pixel(sx, sy), pixel, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_tones() {
+        let ramp = Image::from_rgb(RgbImage::from_fn(256, 1, |x, _| Rgb([x as u8; 3]))).unwrap();
+        let mask = tones(&ramp, 40.0, 200.0);
+        let weight = |x: u32| mask.get_pixel(x, 0)[0];
+        assert_eq!((weight(0), weight(40), weight(128), weight(200), weight(255)), (0, 255, 255, 255, 0));
+        assert!((1..40).all(|x| weight(x - 1) <= weight(x)) && (201..256).all(|x| weight(x - 1) >= weight(x)));
+        // The full range protects nothing
+        assert!(tones(&ramp, 0.0, 255.0).pixels().all(|pixel| pixel[0] == 255));
+
+        // Sharpening leaves near-white detail nearly as it was
+        let snow = Image::from_rgb(RgbImage::from_fn(32, 32, |x, y| {
+            let value = if (x / 4 + y / 4) % 2 == 0 { 250 } else { 238 };
+            Rgb([value; 3])
+        })).unwrap();
+        let sharpen = |protect: bool| {
+            let builder = snow.clone().sharpen().unsharp_mask(1.0, 2.0, 0);
+            let builder = if protect { builder.protect_highlights(200.0) } else { builder };
+            builder.apply().unwrap().into_rgb()
+        };
+        let original = snow.clone().into_rgb();
+        let change = |result: &RgbImage| -> u32 {
+            result.pixels().zip(original.pixels()).map(|(a, b)| u32::from(a[0].abs_diff(b[0]))).sum()
+        };
+        assert!(change(&sharpen(true)) * 4 < change(&sharpen(false)));
+        assert!(snow.sharpen().unsharp_mask(1.0, 1.0, 0).protect_shadows(300.0).apply().is_err());
+    }
+
+    #[test]
+    fn test_radial() {
+        let mask = radial(101, 61, 0.2, 1.0, 0.5);
+        let weight = |x: u32, y: u32| mask.get_pixel(x, y)[0];
+        // The center share keeps the center weight, the corners reach the edge weight
+        assert_eq!((weight(50, 30), weight(60, 35), weight(0, 0), weight(100, 60)), (51, 51, 255, 255));
+        assert!((50..100).all(|x| weight(x, 30) <= weight(x + 1, 30)));
+        // Symmetric about both axes
+        assert_eq!(weight(10, 5), weight(90, 55));
+
+        // Softer in the middle: the center changes less than the corners
+        let checks = Image::from_rgb(RgbImage::from_fn(64, 64, |x, y| Rgb([if (x / 2 + y / 2) % 2 == 0 { 90 } else { 160 }; 3]))).unwrap();
+        let original = checks.clone().into_rgb();
+        let sharpened = checks.clone().sharpen().unsharp_mask(1.0, 1.0, 0).radial_falloff(0.0, 1.0, 0.6).apply().unwrap().into_rgb();
+        let change = |x: u32, y: u32| sharpened.get_pixel(x, y)[0].abs_diff(original.get_pixel(x, y)[0]);
+        assert_eq!(change(32, 32), 0);
+        assert!(change(1, 1) > 0);
+        assert!(checks.sharpen().unsharp_mask(1.0, 1.0, 0).radial_falloff(0.5, 1.5, 0.5).apply().is_err());
+    }
+
+    /// Blotchy skin on the left, brown hair in the middle and blue fabric
+    /// on the right.
+    pub(crate) fn create_portrait() -> Image {
+        Image::from_rgb(RgbImage::from_fn(96, 48, |x, y| {
+            let (fx, fy) = (x as f32, y as f32);
+            match x {
+                0..=31 => {
+                    let blotch = 10.0 * (fx / 4.0).sin() * (fy / 5.0).cos();
+                    Rgb([224.0 + blotch, 172.0 + blotch, 150.0 + blotch].map(|value| value.round() as u8))
+                }
+                32..=63 if (x / 3 + y / 17) % 2 == 0 => Rgb([110, 75, 50]),
+                32..=63 => Rgb([40, 25, 15]),
+                _ => Rgb([70, 90, 150]),
+            }
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_skin() {
+        let skin = skin(&create_portrait());
+        let mean = |columns: std::ops::Range<u32>| {
+            let values: Vec<f32> = columns.flat_map(|x| (0..48).map(move |y| (x, y)))
+                .map(|(x, y)| skin.get_pixel(x, y)[0] as f32)
+                .collect();
+            values.iter().sum::<f32>() / values.len() as f32
+        };
+        assert!(mean(0..28) > 245.0, "{}", mean(0..28));
+        assert!(mean(36..60) < 10.0, "{}", mean(36..60));
+        assert!(mean(68..96) < 10.0, "{}", mean(68..96));
+
+        // Without chroma, every smooth area counts
+        let gray = Image::from_buffer(GrayImage::from_pixel(16, 16, Luma([90]))).unwrap();
+        assert!(super::skin(&gray).pixels().all(|pixel| pixel[0] == 255));
+    }
+}
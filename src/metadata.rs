@@ -0,0 +1,103 @@
+//! EXIF and XMP metadata carried from loaded images to saved ones.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Prefix of the JPEG APP1 segment holding EXIF data.
+pub(crate) const EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// Prefix of the JPEG APP1 segment holding an XMP packet.
+pub(crate) const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// EXIF and XMP metadata of an image.
+///
+/// Read from JPEG (EXIF and XMP) and WebP (EXIF) files on load, kept through
+/// processing and written back when saving as JPEG. Segments too large for a
+/// single JPEG APP1 segment (64 KB), such as extended XMP, are not written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// EXIF data in TIFF layout, without the JPEG `Exif\0\0` prefix
+    pub exif: Option<Vec<u8>>,
+    /// XMP packet (XML)
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Returns true if there is neither EXIF nor XMP data.
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none()
+    }
+}
+
+/// Reads the XMP packet from a JPEG's APP1 segments, leaving `reader` at
+/// its current position. Malformed files yield `None`; the decoder reports
+/// their errors.
+pub(crate) fn read_jpeg_xmp<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let start = reader.stream_position()?;
+    let xmp = find_jpeg_xmp(reader).unwrap_or(None);
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(xmp)
+}
+
+fn find_jpeg_xmp<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker)?;
+    if marker != [0xFF, 0xD8] {
+        return Ok(None);
+    }
+
+    loop {
+        reader.read_exact(&mut marker)?;
+        match marker {
+            [0xFF, 0x01 | 0xD0..=0xD7] => continue,
+            // Metadata segments precede the image data
+            [0xFF, 0xDA | 0xD9] => return Ok(None),
+            [0xFF, _] => {}
+            _ => return Ok(None),
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u64::from(u16::from_be_bytes(length).saturating_sub(2));
+        if marker[1] != 0xE1 {
+            io::copy(&mut reader.by_ref().take(length), &mut io::sink())?;
+            continue;
+        }
+
+        let mut segment = vec![0; length as usize];
+        reader.read_exact(&mut segment)?;
+        if let Some(xmp) = segment.strip_prefix(XMP_HEADER) {
+            return Ok(Some(xmp.to_vec()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let length = (data.len() as u16 + 2).to_be_bytes();
+        [&[0xFF, marker], &length[..], data].concat()
+    }
+
+    #[test]
+    fn test_read_jpeg_xmp() {
+        let xmp = b"<x:xmpmeta/>";
+        let jpeg = [
+            &[0xFF, 0xD8][..],
+            &segment(0xE0, b"JFIF\0\x01\x01"),
+            &segment(0xE1, &[EXIF_HEADER, b"II*\0"].concat()),
+            &segment(0xE1, &[XMP_HEADER, xmp].concat()),
+            &segment(0xDA, &[0; 4]),
+        ].concat();
+
+        let mut reader = io::Cursor::new(jpeg);
+        assert_eq!(read_jpeg_xmp(&mut reader).unwrap().as_deref(), Some(&xmp[..]));
+        assert_eq!(reader.position(), 0);
+
+        // Not JPEG, truncated, or no XMP before the scan
+        assert_eq!(read_jpeg_xmp(&mut io::Cursor::new(b"\x89PNG")).unwrap(), None);
+        assert_eq!(read_jpeg_xmp(&mut io::Cursor::new([0xFF, 0xD8, 0xFF, 0xE1, 0x01])).unwrap(), None);
+        let plain = [&[0xFF, 0xD8][..], &segment(0xDA, &[0; 4]), &segment(0xE1, &[XMP_HEADER, xmp].concat())].concat();
+        assert_eq!(read_jpeg_xmp(&mut io::Cursor::new(plain)).unwrap(), None);
+    }
+}
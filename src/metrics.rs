@@ -0,0 +1,250 @@
+//! Image comparison metrics for regression testing sharpening recipes.
+
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, unit_scale};
+use crate::utils::Luminance;
+use crate::{ImageError, Result};
+use crate::par::*;
+
+/// Side length of the non-overlapping windows used for SSIM.
+const SSIM_WINDOW: u32 = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Similarity of an image to an approved reference. Levels are in 8-bit
+/// units (0-255) for every pixel format, so 16-bit results compare with
+/// 8-bit ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// Mean structural similarity of luminance (1.0 = identical)
+    pub ssim: f64,
+    /// Mean CIE76 color difference (0.0 = identical, ~2.3 = just noticeable)
+    pub delta_e: f64,
+    /// Mean luminance overshoot, in levels, beyond the reference's local
+    /// 3x3 range; rises when sharpening adds halos the reference lacks
+    pub halo: f64,
+}
+
+pub(crate) fn compare<P: SharpenPixel>(image: &Buffer<P>, reference: &Buffer<P>, luminance: Luminance) -> Result<Comparison> {
+    if image.dimensions() != reference.dimensions() {
+        let (width, height) = reference.dimensions();
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+
+    Ok(Comparison {
+        ssim: ssim(image, reference, luminance),
+        delta_e: delta_e(image, reference),
+        halo: halo(image, reference, luminance),
+    })
+}
+
+/// Luminance of every pixel, in 8-bit units.
+fn luminance_plane<P: SharpenPixel>(img: &Buffer<P>, luminance: Luminance) -> Vec<f64> {
+    let scale = unit_scale::<P>() as f64;
+    img.pixels().map(|p| luminance.of(p) as f64 / scale).collect()
+}
+
+fn ssim<P: SharpenPixel>(image: &Buffer<P>, reference: &Buffer<P>, luminance: Luminance) -> f64 {
+    let (width, height) = image.dimensions();
+    let a = luminance_plane(image, luminance);
+    let b = luminance_plane(reference, luminance);
+
+    let windows: Vec<(u32, u32)> = (0..height.div_ceil(SSIM_WINDOW))
+        .flat_map(|wy| (0..width.div_ceil(SSIM_WINDOW)).map(move |wx| (wx, wy)))
+        .collect();
+
+    let total: f64 = windows.par_iter()
+        .map(|&(wx, wy)| {
+            let x0 = wx * SSIM_WINDOW;
+            let y0 = wy * SSIM_WINDOW;
+            let x1 = (x0 + SSIM_WINDOW).min(width);
+            let y1 = (y0 + SSIM_WINDOW).min(height);
+            let n = ((x1 - x0) * (y1 - y0)) as f64;
+
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let i = (y * width + x) as usize;
+                    sum_a += a[i];
+                    sum_b += b[i];
+                    sum_aa += a[i] * a[i];
+                    sum_bb += b[i] * b[i];
+                    sum_ab += a[i] * b[i];
+                }
+            }
+
+            let mean_a = sum_a / n;
+            let mean_b = sum_b / n;
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+        })
+        .sum();
+
+    total / windows.len() as f64
+}
+
+/// Converts sRGB to CIE L*a*b* (D65 white point). Gray pixels are
+/// treated as equal red, green and blue.
+fn to_lab<P: SharpenPixel>(pixel: &P) -> [f64; 3] {
+    let channels = pixel.channels();
+    let channel = |i: usize| channels[if P::COLOR_CHANNELS >= 3 { i } else { 0 }];
+    let linear = |c: P::Subpixel| {
+        let c = (sample::<P>(c) / max_value::<P>()) as f64;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let [r, g, b] = [0, 1, 2].map(|i| linear(channel(i)));
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e<P: SharpenPixel>(image: &Buffer<P>, reference: &Buffer<P>) -> f64 {
+    let channels = P::CHANNEL_COUNT as usize;
+    let total: f64 = image.as_raw().par_chunks(channels)
+        .zip(reference.as_raw().par_chunks(channels))
+        .map(|(p, q)| {
+            let [l1, a1, b1] = to_lab(P::from_slice(p));
+            let [l2, a2, b2] = to_lab(P::from_slice(q));
+            ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+        })
+        .sum();
+
+    total / (image.width() as f64 * image.height() as f64)
+}
+
+fn halo<P: SharpenPixel>(image: &Buffer<P>, reference: &Buffer<P>, luminance: Luminance) -> f64 {
+    let (width, height) = image.dimensions();
+    let a = luminance_plane(image, luminance);
+    let b = luminance_plane(reference, luminance);
+
+    let total: f64 = (0..height).into_par_iter()
+        .map(|y| {
+            let mut row_total = 0.0;
+            for x in 0..width {
+                let mut low = f64::MAX;
+                let mut high = f64::MIN;
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        let value = b[(ny * width + nx) as usize];
+                        low = low.min(value);
+                        high = high.max(value);
+                    }
+                }
+                let value = a[(y * width + x) as usize];
+                row_total += (value - high).max(0.0) + (low - value).max(0.0);
+            }
+            row_total
+        })
+        .sum();
+
+    total / (width as f64 * height as f64)
+}
+
+/// See [`Image::tiling_seam`](crate::Image::tiling_seam).
+pub(crate) fn tiling_seam<P: SharpenPixel>(img: &Buffer<P>, luminance: Luminance) -> f64 {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if width < 2 || height < 2 {
+        return 0.0;
+    }
+    let luminance: Vec<f64> = img.pixels().map(|pixel| luminance.of(pixel) as f64).collect();
+    let at = |x: usize, y: usize| luminance[y * width + x];
+
+    // Mean step across each column boundary, the last wrapping to column 0,
+    // and likewise for rows
+    let columns: Vec<f64> = (0..width).into_par_iter()
+        .map(|x| (0..height).map(|y| (at((x + 1) % width, y) - at(x, y)).abs()).sum::<f64>() / height as f64)
+        .collect();
+    let rows: Vec<f64> = (0..height).into_par_iter()
+        .map(|y| (0..width).map(|x| (at(x, (y + 1) % height) - at(x, y)).abs()).sum::<f64>() / width as f64)
+        .collect();
+
+    // The wrapping boundary against the strongest one inside, so regular
+    // patterns whose edges fall on the seam still count as seamless
+    let score = |steps: &[f64]| {
+        let (seam, inside) = steps.split_last().expect("at least two boundaries");
+        let strongest = inside.iter().copied().fold(0.0, f64::max);
+        if *seam == 0.0 {
+            0.0
+        } else if strongest == 0.0 {
+            f64::INFINITY
+        } else {
+            seam / strongest
+        }
+    };
+    score(&columns).max(score(&rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma, Rgb, RgbImage};
+
+    fn checkerboard() -> RgbImage {
+        RgbImage::from_fn(64, 64, |x, y| {
+            let value = if (x / 8 + y / 8) % 2 == 0 { 60 } else { 190 };
+            Rgb([value, value, value])
+        })
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let img = checkerboard();
+        let comparison = compare(&img, &img, Luminance::default()).unwrap();
+        assert!((comparison.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(comparison.delta_e, 0.0);
+        assert_eq!(comparison.halo, 0.0);
+    }
+
+    #[test]
+    fn test_sharpened_differs() {
+        let reference = checkerboard();
+        let sharpened = crate::Image::from_rgb(reference.clone()).unwrap()
+            .unsharp_mask(2.0, 2.0, 0).unwrap()
+            .into_rgb();
+
+        let comparison = compare(&sharpened, &reference, Luminance::default()).unwrap();
+        assert!(comparison.ssim < 1.0);
+        assert!(comparison.delta_e > 0.0);
+        assert!(comparison.halo > 0.0);
+    }
+
+    #[test]
+    fn test_16_bit_gray() {
+        let reference = checkerboard();
+        let sharpened = crate::Image::from_rgb(reference.clone()).unwrap()
+            .unsharp_mask(2.0, 2.0, 0).unwrap()
+            .into_rgb();
+        let expected = compare(&sharpened, &reference, Luminance::default()).unwrap();
+
+        let deep = |img: &RgbImage| -> ImageBuffer<Luma<u16>, Vec<u16>> {
+            ImageBuffer::from_fn(64, 64, |x, y| Luma([img.get_pixel(x, y)[0] as u16 * 257]))
+        };
+        let comparison = compare(&deep(&sharpened), &deep(&reference), Luminance::default()).unwrap();
+        assert!((comparison.ssim - expected.ssim).abs() < 1e-3);
+        assert!((comparison.delta_e - expected.delta_e).abs() < 0.05);
+        assert!((comparison.halo - expected.halo).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_luminance_weights() {
+        // Green and red of the same BT.601 luminance, but not BT.709
+        let image = RgbImage::from_pixel(16, 16, Rgb([0, 100, 0]));
+        let reference = RgbImage::from_pixel(16, 16, Rgb([196, 0, 0]));
+        assert!(compare(&image, &reference, Luminance::Bt601).unwrap().halo < 0.5);
+        assert!(compare(&image, &reference, Luminance::Bt709).unwrap().halo > 25.0);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        assert!(compare(&checkerboard(), &RgbImage::new(32, 32), Luminance::default()).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Migration of operations saved by older releases.
+//!
+//! Saved pipelines, preset files and graph scripts keep working when a
+//! release changes the shape of an operation. [`RULES`] records each such
+//! change to a format a release shipped, and loading applies them in order,
+//! reporting every change as a [`Migration`]:
+//!
+//! 1. Renamed operations, in the text syntax and the `op` key of preset
+//!    files, become their current name.
+//! 2. Parameters added to an operation get, when missing, the value that
+//!    keeps the behavior of the release that saved it.
+//!
+//! No released format has changed shape yet: everything 0.1 accepted
+//! (`unsharp:radius:amount:threshold`, `highpass:strength`,
+//! `edges:strength:method` and `clarity:strength:radius`) still parses as
+//! it is, so both tables are empty.
+
+use crate::{ImageError, Result};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Record of a migration rule applied while loading an operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    /// The operation as it was loaded
+    pub from: String,
+    /// The operation after migration
+    pub to: String,
+    /// Why it was migrated
+    pub rule: &'static str,
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} ({})", self.from, self.to, self.rule)
+    }
+}
+
+/// A parameter added to an operation after a release shipped it.
+pub(crate) struct AddedParameter {
+    /// Current name of the operation
+    pub operation: &'static str,
+    /// Position in the text syntax, counting the name as 0
+    pub position: usize,
+    /// Field name in preset files
+    pub field: &'static str,
+    /// Value that keeps the earlier behavior
+    pub default: &'static str,
+    /// Why it was migrated
+    pub rule: &'static str,
+}
+
+/// The changes made to released formats, oldest first.
+pub(crate) struct Rules {
+    /// Renamed operations: (old name, current name, rule)
+    pub renamed: &'static [(&'static str, &'static str, &'static str)],
+    /// Parameters added to operations
+    pub added: &'static [AddedParameter],
+}
+
+/// The changes releases made to saved operations.
+pub(crate) const RULES: Rules = Rules { renamed: &[], added: &[] };
+
+/// Changes a release might make, to exercise the rules with.
+#[cfg(test)]
+pub(crate) const EXAMPLE: Rules = Rules {
+    renamed: &[("sharpen", "unsharp", "operation renamed")],
+    added: &[AddedParameter {
+        operation: "unsharp",
+        position: 3,
+        field: "threshold",
+        default: "0",
+        rule: "threshold parameter added, defaults to 0",
+    }],
+};
+
+/// Migrates an operation in `name:param:...` form to the current syntax.
+pub(crate) fn migrate_operation_text(text: &str) -> (String, Vec<Migration>) {
+    RULES.migrate_text(text)
+}
+
+/// Migrates a structured operation (from TOML/JSON) in place.
+pub(crate) fn migrate_operation_value(value: &mut Value, migrations: &mut Vec<Migration>) -> Result<()> {
+    RULES.migrate_value(value, migrations)
+}
+
+impl Rules {
+    fn rename(&self, name: &str) -> Option<(&'static str, &'static str)> {
+        let name = name.to_lowercase();
+        self.renamed.iter()
+            .find(|&&(old, _, _)| old == name)
+            .map(|&(_, current, rule)| (current, rule))
+    }
+
+    pub(crate) fn canonical_name(&self, name: &str) -> Option<&'static str> {
+        self.rename(name).map(|(current, _)| current)
+    }
+
+    pub(crate) fn migrate_text(&self, text: &str) -> (String, Vec<Migration>) {
+        let mut migrations = Vec::new();
+        let mut parts: Vec<String> = text.trim().split(':').map(|p| p.trim().to_string()).collect();
+
+        if let Some((current, rule)) = self.rename(&parts[0]) {
+            let from = parts.join(":");
+            parts[0] = current.to_string();
+            migrations.push(Migration { from, to: parts.join(":"), rule });
+        }
+
+        let name = parts[0].to_lowercase();
+        for added in self.added {
+            if added.operation == name && parts.len() == added.position {
+                let from = parts.join(":");
+                parts.push(added.default.to_string());
+                migrations.push(Migration { from, to: parts.join(":"), rule: added.rule });
+            }
+        }
+
+        (parts.join(":"), migrations)
+    }
+
+    fn migrate_value(&self, value: &mut Value, migrations: &mut Vec<Migration>) -> Result<()> {
+        let Value::Object(map) = value else {
+            return Err(ImageError::Preset(format!("expected an operation table, found {}", value)));
+        };
+
+        let mut record = |map: &mut Map<String, Value>, rule: &'static str, change: &dyn Fn(&mut Map<String, Value>)| {
+            let from = Value::Object(map.clone()).to_string();
+            change(map);
+            migrations.push(Migration { from, to: Value::Object(map.clone()).to_string(), rule });
+        };
+
+        let Some(name) = map.get("op").and_then(Value::as_str).map(str::to_string) else {
+            return Ok(());
+        };
+        if let Some((current, rule)) = self.rename(&name) {
+            record(map, rule, &|map| {
+                map.insert("op".to_string(), Value::String(current.to_string()));
+            });
+        }
+
+        let name = map.get("op").and_then(Value::as_str).map(str::to_lowercase).unwrap_or_default();
+        for added in self.added {
+            if added.operation == name && !map.contains_key(added.field) {
+                record(map, added.rule, &|map| {
+                    map.insert(added.field.to_string(), parameter_value(added.default));
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A default from [`AddedParameter`] as the JSON value a preset file would
+/// hold.
+fn parameter_value(default: &str) -> Value {
+    if let Ok(integer) = default.parse::<u64>() {
+        Value::from(integer)
+    } else if let Ok(number) = default.parse::<f64>() {
+        Value::from(number)
+    } else {
+        Value::String(default.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+    use serde_json::json;
+
+    #[test]
+    fn test_released_syntax_untouched() {
+        for text in ["unsharp:1:1:0", "highpass:0.5", "Edges:1:prewitt", "clarity:0.3:2"] {
+            let (migrated, migrations) = migrate_operation_text(text);
+            assert_eq!(migrated, text);
+            assert!(migrations.is_empty());
+        }
+        // Everything 0.1 accepted still parses
+        let (pipeline, migrations) = Pipeline::parse_with_migrations("unsharp:1.5:0.8:2,highpass:0.3,edges:1:sobel,clarity:0.5:2").unwrap();
+        assert_eq!(pipeline.len(), 4);
+        assert!(migrations.is_empty());
+    }
+
+    #[test]
+    fn test_text_rules() {
+        let (text, migrations) = EXAMPLE.migrate_text("sharpen:1.5:0.8");
+        assert_eq!(text, "unsharp:1.5:0.8:0");
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].rule, "operation renamed");
+        assert_eq!(migrations[1].from, "unsharp:1.5:0.8");
+
+        let (text, migrations) = EXAMPLE.migrate_text("unsharp:1:1:4");
+        assert_eq!(text, "unsharp:1:1:4");
+        assert!(migrations.is_empty());
+    }
+
+    #[test]
+    fn test_value_rules() {
+        let mut value = json!({ "op": "Sharpen", "radius": 1.5, "amount": 0.8 });
+        let mut migrations = Vec::new();
+        EXAMPLE.migrate_value(&mut value, &mut migrations).unwrap();
+
+        assert_eq!(value, json!({ "op": "unsharp", "radius": 1.5, "amount": 0.8, "threshold": 0 }));
+        assert_eq!(migrations.len(), 2);
+        assert!(EXAMPLE.migrate_value(&mut json!(3), &mut migrations).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Tangent-space normal maps, whose RGB pixels encode unit XYZ vectors
+//! (0 maps to -1, the maximum sample to +1).
+//!
+//! Red and green hold the slopes of the surface's height, so sharpening them
+//! with the linear filters (unsharp mask, high-pass) sharpens the implied
+//! height detail, as filtering commutes with differentiation. Blue is only
+//! the vector's remaining length; sharpening it like a color corrupts
+//! lighting, so [`renormalize`] derives it again from red and green.
+
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample};
+use crate::{ImageError, Result};
+
+/// Fails for pixel types without the three channels of a normal map.
+pub(crate) fn check_channels<P: SharpenPixel>() -> Result<()> {
+    if P::COLOR_CHANNELS < 3 {
+        return Err(ImageError::InvalidParameter {
+            param: "normal_map".to_string(),
+            value: "grayscale image".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Makes every pixel of `processed` that differs from `original` a unit
+/// vector again: X and Y are kept (scaled down if longer than 1 together)
+/// and Z recomputed, facing outward. Unchanged pixels stay as they are.
+pub(crate) fn renormalize<P: SharpenPixel>(original: &Buffer<P>, processed: &mut Buffer<P>) {
+    let max = max_value::<P>();
+    let decode = |value: P::Subpixel| sample::<P>(value) / max * 2.0 - 1.0;
+    let encode = |value: f32| to_sample::<P>((value + 1.0) / 2.0 * max);
+
+    processed.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let channels = pixel.channels_mut();
+                if channels[..3] == original.get_pixel(x, y).channels()[..3] {
+                    continue;
+                }
+                let (mut nx, mut ny) = (decode(channels[0]), decode(channels[1]));
+                let length = (nx * nx + ny * ny).sqrt();
+                if length > 1.0 {
+                    nx /= length;
+                    ny /= length;
+                }
+                let nz = (1.0 - nx * nx - ny * ny).max(0.0).sqrt();
+                channels[0] = encode(nx);
+                channels[1] = encode(ny);
+                channels[2] = encode(nz);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Image;
+    use image::{Rgb, RgbImage};
+
+    /// Normals of bumps on a flat surface, with a flat border.
+    fn create_normal_map() -> RgbImage {
+        let height = |x: f32, y: f32| (x * 0.4).sin() * (y * 0.3).cos() * 3.0;
+        RgbImage::from_fn(48, 48, |x, y| {
+            if !(8..40).contains(&x) || !(8..40).contains(&y) {
+                return Rgb([128, 128, 255]);
+            }
+            let (x, y) = (x as f32, y as f32);
+            let (dx, dy) = (height(x + 0.5, y) - height(x - 0.5, y), height(x, y + 0.5) - height(x, y - 0.5));
+            let length = (dx * dx + dy * dy + 1.0).sqrt();
+            let encode = |value: f32| ((value / length + 1.0) / 2.0 * 255.0).round() as u8;
+            Rgb([encode(-dx), encode(-dy), encode(1.0)])
+        })
+    }
+
+    fn length_error(pixel: &Rgb<u8>) -> f32 {
+        let length = pixel.0.iter().map(|&value| (value as f32 / 255.0 * 2.0 - 1.0).powi(2)).sum::<f32>().sqrt();
+        (length - 1.0).abs()
+    }
+
+    #[test]
+    fn test_renormalize() {
+        let normals = create_normal_map();
+        let image = Image::from_rgb(normals.clone()).unwrap();
+        let plain = image.clone().sharpen().unsharp_mask(1.5, 2.0, 0).apply().unwrap().into_rgb();
+        let safe = image.sharpen().unsharp_mask(1.5, 2.0, 0).normal_map(true).apply().unwrap().into_rgb();
+
+        let worst = |image: &RgbImage| image.pixels().map(length_error).fold(0.0, f32::max);
+        assert!(worst(&plain) > 0.05, "{}", worst(&plain));
+        // Within 8-bit rounding
+        assert!(worst(&safe) < 0.02, "{}", worst(&safe));
+        assert_ne!(safe, normals);
+        // Red and green are sharpened as usual where they stay within the unit circle
+        let decode = |value: u8| value as f32 / 255.0 * 2.0 - 1.0;
+        for (x, y, pixel) in safe.enumerate_pixels() {
+            let expected = plain.get_pixel(x, y);
+            if decode(expected[0]).powi(2) + decode(expected[1]).powi(2) <= 1.0 {
+                assert_eq!(pixel.0[..2], expected.0[..2], "({}, {})", x, y);
+            }
+        }
+        assert_eq!(safe.get_pixel(0, 0), &Rgb([128, 128, 255]));
+
+        let gray = Image::from_buffer(image::GrayImage::new(8, 8)).unwrap();
+        assert!(gray.sharpen().high_pass(0.5).normal_map(true).apply().is_err());
+    }
+}
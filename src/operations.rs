@@ -1,11 +1,45 @@
 //! Common operation types used throughout the library and CLI.
 
-use crate::EdgeMethod;
+use crate::{EdgeMethod, Image, ImageError, Result};
+use crate::{denoise, descreen, mask, sharpening};
+use crate::pixel::SharpenPixel;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 /// Represents a sharpening operation that can be applied to an image.
-#[derive(Debug, Clone, PartialEq)]
+/// 
+/// Operations also have a compact text form, `name:param1:param2:...`, shared
+/// with the CLI:
+/// 
+/// - `unsharp:radius:amount:threshold`, or `unsharp:radius:amount:threshold%`
+///   for a threshold relative to the local contrast
+/// - `highpass:strength`
+/// - `edges:strength:method` (method: `sobel` or `prewitt`)
+/// - `clarity:strength:radius`
+/// - `adaptive_unsharp:min_radius:max_radius:amount:threshold`
+/// - `text:stroke_width:strength`
+/// - `descreen:period` (period in pixels, or `auto` to detect it)
+/// - `median:radius`
+/// - `denoise:strength:patch_radius:search_radius`
+/// - `smart_enhance:level`
+/// - `frequency_separation:radius:smoothing:amount`
+/// 
+/// ```
+/// use sharpy::Operation;
+/// 
+/// let op: Operation = "unsharp:1.5:0.8:2".parse().unwrap();
+/// assert_eq!(op, Operation::UnsharpMask { radius: 1.5, amount: 0.8, threshold: 2 });
+/// assert_eq!(op.to_string(), "unsharp:1.5:0.8:2");
+/// ```
+/// 
+/// Serializes as a table tagged with `op`, using the same operation names as
+/// the CLI (e.g. `{ op = "unsharp", radius = 1.0, amount = 1.0, threshold = 0 }`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op")]
 pub enum Operation {
     /// Unsharp mask operation
+    #[serde(rename = "unsharp")]
     UnsharpMask { 
         /// Blur radius (0.5-10.0)
         radius: f32, 
@@ -14,12 +48,25 @@ pub enum Operation {
         /// Threshold (0-255)
         threshold: u8 
     },
+    /// Unsharp mask whose threshold is a share of the local contrast, so it
+    /// holds back noise alike in dark and bright regions
+    #[serde(rename = "relative_unsharp")]
+    RelativeUnsharp {
+        /// Blur radius (0.5-10.0)
+        radius: f32,
+        /// Strength amount (0.0-5.0)
+        amount: f32,
+        /// Threshold, percent of the local contrast (0-100)
+        threshold: f32,
+    },
     /// High-pass sharpening
+    #[serde(rename = "highpass")]
     HighPassSharpen { 
         /// Strength (0.0-3.0)
         strength: f32 
     },
     /// Edge enhancement
+    #[serde(rename = "edges")]
     EnhanceEdges { 
         /// Strength (0.0-3.0)
         strength: f32, 
@@ -27,12 +74,187 @@ pub enum Operation {
         method: EdgeMethod 
     },
     /// Clarity enhancement
+    #[serde(rename = "clarity")]
     Clarity { 
         /// Strength (0.0-3.0)
         strength: f32, 
         /// Radius (1.0-20.0)
         radius: f32 
     },
+    /// Unsharp mask whose radius follows the local detail scale
+    #[serde(rename = "adaptive_unsharp")]
+    AdaptiveUnsharp {
+        /// Radius for the finest detail (0.5-10.0)
+        min_radius: f32,
+        /// Radius for the softest structures (0.5-10.0, at least `min_radius`)
+        max_radius: f32,
+        /// Strength amount (0.0-5.0)
+        amount: f32,
+        /// Threshold (0-255)
+        threshold: u8,
+    },
+    /// Ringing-free sharpening for text and line art
+    #[serde(rename = "text")]
+    TextSharpen {
+        /// Typical stroke width in pixels (0.5-10.0)
+        stroke_width: f32,
+        /// Strength (0.0-3.0)
+        strength: f32,
+    },
+    /// Halftone screen removal for scans of printed material; place it
+    /// before the sharpening
+    #[serde(rename = "descreen")]
+    Descreen {
+        /// Screen period in pixels (2.0-16.0), the scan's dpi divided by
+        /// the print's lpi; detected from the image if absent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        period: Option<f32>,
+    },
+    /// Median filter removing salt-and-pepper and sensor noise; place it
+    /// before the sharpening
+    #[serde(rename = "median")]
+    Median {
+        /// Window radius in pixels (1-10)
+        radius: u32,
+    },
+    /// Non-local means denoising, averaging pixels whose surroundings look
+    /// alike; place it before the sharpening
+    #[serde(rename = "denoise")]
+    Denoise {
+        /// Filtering level (0.0-3.0); 1.0 suits noise of about 10 levels
+        /// in 8 bits
+        strength: f32,
+        /// Radius of the compared patches in pixels (1-3)
+        patch_radius: u32,
+        /// Radius of the window searched for similar patches in pixels (1-10)
+        search_radius: u32,
+    },
+    /// Denoising as needed, then sharpening as far as the remaining noise
+    /// allows, with parameters chosen from the image
+    #[serde(rename = "smart_enhance")]
+    SmartEnhance {
+        /// How much to enhance (0.0-3.0), 1.0 for the suggested amount
+        level: f32,
+    },
+    /// Portrait retouching that smooths the tones of skin and sharpens the
+    /// detail of everything else
+    #[serde(rename = "frequency_separation")]
+    FrequencySeparation {
+        /// Blur radius separating detail from tones (0.5-10.0)
+        radius: f32,
+        /// How far skin tones are smoothed (0.0-1.0)
+        smoothing: f32,
+        /// Sharpening of the detail outside skin (0.0-5.0)
+        amount: f32,
+    },
+}
+
+/// Upper limits that [`Operation::validate_with`] checks parameters against.
+/// 
+/// The defaults are the strict ranges documented on each operation, which
+/// catch typos in everyday sharpening but also block deliberate extremes
+/// such as creative over-sharpening or local contrast at a huge radius.
+/// [`ValidationConfig::extended`] opens the ranges up, and the fields can be
+/// raised individually, up to [`ValidationConfig::CEILING`]. Larger maxima
+/// are capped at it, and NaN counts as the smallest maximum. Images carry a
+/// configuration, see [`Image::with_validation`](crate::Image::with_validation).
+/// 
+/// # Example
+/// ```
+/// use sharpy::{Operation, ValidationConfig};
+/// 
+/// let op = Operation::UnsharpMask { radius: 30.0, amount: 8.0, threshold: 0 };
+/// assert!(op.validate().is_err());
+/// assert!(op.validate_with(&ValidationConfig::extended()).is_ok());
+/// assert!(op.validate_with(&ValidationConfig::default().max_radius(40.0).max_amount(10.0)).is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValidationConfig {
+    /// Maximum unsharp mask and median radius and text stroke width
+    /// (default 10.0)
+    pub max_radius: f32,
+    /// Maximum clarity radius (default 20.0)
+    pub max_clarity_radius: f32,
+    /// Maximum unsharp mask amount (default 5.0)
+    pub max_amount: f32,
+    /// Maximum strength of high-pass, edge, clarity and text sharpening
+    /// (default 3.0)
+    pub max_strength: f32,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self { max_radius: 10.0, max_clarity_radius: 20.0, max_amount: 5.0, max_strength: 3.0 }
+    }
+}
+
+impl ValidationConfig {
+    /// Hard limits that no configuration can raise the maxima beyond:
+    /// radius 250, clarity radius 500, amount 100 and strength 50.
+    pub const CEILING: Self = Self { max_radius: 250.0, max_clarity_radius: 500.0, max_amount: 100.0, max_strength: 50.0 };
+    
+    /// Ranges wide enough for creative and large-scale uses: radius 50,
+    /// clarity radius 100, amount 20 and strength 10. Processing time grows
+    /// with the radius.
+    pub fn extended() -> Self {
+        Self { max_radius: 50.0, max_clarity_radius: 100.0, max_amount: 20.0, max_strength: 10.0 }
+    }
+    
+    /// Sets the maximum unsharp mask and median radius and text stroke width.
+    pub fn max_radius(mut self, radius: f32) -> Self {
+        self.max_radius = radius;
+        self
+    }
+    
+    /// Sets the maximum clarity radius.
+    pub fn max_clarity_radius(mut self, radius: f32) -> Self {
+        self.max_clarity_radius = radius;
+        self
+    }
+    
+    /// Sets the maximum unsharp mask amount.
+    pub fn max_amount(mut self, amount: f32) -> Self {
+        self.max_amount = amount;
+        self
+    }
+    
+    /// Sets the maximum strength.
+    pub fn max_strength(mut self, strength: f32) -> Self {
+        self.max_strength = strength;
+        self
+    }
+    
+    /// Returns the maxima capped at [`ValidationConfig::CEILING`], with
+    /// NaN moved to the bottom of the range.
+    fn bounded(&self) -> Self {
+        let ceiling = Self::CEILING;
+        Self {
+            max_radius: clamp_to(self.max_radius, CLAMP_FLOOR, ceiling.max_radius),
+            max_clarity_radius: clamp_to(self.max_clarity_radius, CLAMP_FLOOR, ceiling.max_clarity_radius),
+            max_amount: clamp_to(self.max_amount, 0.0, ceiling.max_amount),
+            max_strength: clamp_to(self.max_strength, CLAMP_FLOOR, ceiling.max_strength),
+        }
+    }
+}
+
+/// What a [`SharpeningBuilder`](crate::SharpeningBuilder) does with
+/// out-of-range parameters, see
+/// [`validation_policy`](crate::SharpeningBuilder::validation_policy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    /// Fail with [`ImageError::Validation`] before processing
+    #[default]
+    Reject,
+    /// Move each parameter to the nearest valid value and go on
+    Clamp,
+}
+
+/// Smallest value clamping gives parameters that must be above zero.
+const CLAMP_FLOOR: f32 = 0.01;
+
+/// Like `f32::clamp`, but NaN moves to `min`, and `max` wins over `min`.
+pub(crate) fn clamp_to(value: f32, min: f32, max: f32) -> f32 {
+    value.max(min).min(max)
 }
 
 impl Operation {
@@ -40,9 +262,566 @@ impl Operation {
     pub fn name(&self) -> &'static str {
         match self {
             Operation::UnsharpMask { .. } => "Unsharp Mask",
+            Operation::RelativeUnsharp { .. } => "Relative Unsharp Mask",
             Operation::HighPassSharpen { .. } => "High-Pass Sharpen",
             Operation::EnhanceEdges { .. } => "Edge Enhancement",
             Operation::Clarity { .. } => "Clarity",
+            Operation::AdaptiveUnsharp { .. } => "Adaptive Unsharp Mask",
+            Operation::TextSharpen { .. } => "Text Sharpen",
+            Operation::Descreen { .. } => "Descreen",
+            Operation::Median { .. } => "Median Filter",
+            Operation::Denoise { .. } => "Non-Local Means",
+            Operation::SmartEnhance { .. } => "Smart Enhance",
+            Operation::FrequencySeparation { .. } => "Frequency Separation",
+        }
+    }
+    
+    /// Checks every parameter against its valid range.
+    /// 
+    /// Returns the first out-of-range parameter as
+    /// [`ImageError::InvalidParameter`]; see [`Operation::violations`] for all
+    /// of them.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_with(&ValidationConfig::default())
+    }
+    
+    /// Like [`Operation::validate`], with the ranges of `config`.
+    pub fn validate_with(&self, config: &ValidationConfig) -> Result<()> {
+        match self.violations_with(config).into_iter().next() {
+            Some((param, value)) => Err(ImageError::InvalidParameter {
+                param: param.to_string(),
+                value,
+            }),
+            None => Ok(()),
+        }
+    }
+    
+    /// Returns every out-of-range parameter as (name, value) pairs.
+    pub fn violations(&self) -> Vec<(&'static str, String)> {
+        self.violations_with(&ValidationConfig::default())
+    }
+    
+    /// Like [`Operation::violations`], with the ranges of `config`.
+    pub fn violations_with(&self, config: &ValidationConfig) -> Vec<(&'static str, String)> {
+        let ValidationConfig { max_radius, max_clarity_radius, max_amount, max_strength } = config.bounded();
+        let mut violations = Vec::new();
+        let mut check = |param: &'static str, value: f32, valid: bool| {
+            if !valid {
+                violations.push((param, value.to_string()));
+            }
+        };
+        
+        match *self {
+            Operation::UnsharpMask { radius, amount, .. } => {
+                check("radius", radius, radius > 0.0 && radius <= max_radius);
+                check("amount", amount, (0.0..=max_amount).contains(&amount));
+            }
+            Operation::RelativeUnsharp { radius, amount, threshold } => {
+                check("radius", radius, radius > 0.0 && radius <= max_radius);
+                check("amount", amount, (0.0..=max_amount).contains(&amount));
+                check("threshold", threshold, (0.0..=100.0).contains(&threshold));
+            }
+            Operation::HighPassSharpen { strength } | Operation::EnhanceEdges { strength, .. } => {
+                check("strength", strength, strength > 0.0 && strength <= max_strength);
+            }
+            Operation::Clarity { strength, radius } => {
+                check("strength", strength, strength > 0.0 && strength <= max_strength);
+                check("radius", radius, radius > 0.0 && radius <= max_clarity_radius);
+            }
+            Operation::AdaptiveUnsharp { min_radius, max_radius: largest, amount, .. } => {
+                check("min_radius", min_radius, min_radius > 0.0 && min_radius <= max_radius);
+                check("max_radius", largest, largest >= min_radius && largest <= max_radius);
+                check("amount", amount, (0.0..=max_amount).contains(&amount));
+            }
+            Operation::TextSharpen { stroke_width, strength } => {
+                check("stroke_width", stroke_width, stroke_width > 0.0 && stroke_width <= max_radius);
+                check("strength", strength, strength > 0.0 && strength <= max_strength);
+            }
+            Operation::Descreen { period } => {
+                if let Some(period) = period {
+                    check("period", period, (descreen::MIN_PERIOD..=descreen::MAX_PERIOD).contains(&period));
+                }
+            }
+            Operation::Median { radius } => {
+                check("radius", radius as f32, radius >= 1 && radius as f32 <= max_radius);
+            }
+            Operation::Denoise { strength, patch_radius, search_radius } => {
+                check("strength", strength, strength > 0.0 && strength <= max_strength);
+                check("patch_radius", patch_radius as f32, (1..=denoise::MAX_PATCH_RADIUS).contains(&patch_radius));
+                check("search_radius", search_radius as f32, search_radius >= 1 && search_radius as f32 <= max_radius);
+            }
+            Operation::SmartEnhance { level } => {
+                check("level", level, level > 0.0 && level <= max_strength);
+            }
+            Operation::FrequencySeparation { radius, smoothing, amount } => {
+                check("radius", radius, radius > 0.0 && radius <= max_radius);
+                check("smoothing", smoothing, (0.0..=1.0).contains(&smoothing));
+                check("amount", amount, (0.0..=max_amount).contains(&amount));
+            }
+        }
+        violations
+    }
+    
+    /// Returns a copy with every parameter moved into its range under
+    /// `config`, and the moved parameters as (name, value, clamped value).
+    /// NaN moves to the bottom of the range.
+    pub(crate) fn clamped(&self, config: &ValidationConfig) -> (Operation, Vec<(&'static str, f32, f32)>) {
+        let ValidationConfig { max_radius, max_clarity_radius, max_amount, max_strength } = config.bounded();
+        let mut moved = Vec::new();
+        let mut clamp = |param: &'static str, value: f32, min: f32, max: f32| {
+            let clamped = clamp_to(value, min, max);
+            if clamped != value {
+                moved.push((param, value, clamped));
+            }
+            clamped
+        };
+        
+        let operation = match *self {
+            Operation::UnsharpMask { radius, amount, threshold } => Operation::UnsharpMask {
+                radius: clamp("radius", radius, CLAMP_FLOOR, max_radius),
+                amount: clamp("amount", amount, 0.0, max_amount),
+                threshold,
+            },
+            Operation::RelativeUnsharp { radius, amount, threshold } => Operation::RelativeUnsharp {
+                radius: clamp("radius", radius, CLAMP_FLOOR, max_radius),
+                amount: clamp("amount", amount, 0.0, max_amount),
+                threshold: clamp("threshold", threshold, 0.0, 100.0),
+            },
+            Operation::HighPassSharpen { strength } => Operation::HighPassSharpen {
+                strength: clamp("strength", strength, CLAMP_FLOOR, max_strength),
+            },
+            Operation::EnhanceEdges { strength, method } => Operation::EnhanceEdges {
+                strength: clamp("strength", strength, CLAMP_FLOOR, max_strength),
+                method,
+            },
+            Operation::Clarity { strength, radius } => Operation::Clarity {
+                strength: clamp("strength", strength, CLAMP_FLOOR, max_strength),
+                radius: clamp("radius", radius, CLAMP_FLOOR, max_clarity_radius),
+            },
+            Operation::AdaptiveUnsharp { min_radius, max_radius: largest, amount, threshold } => {
+                let min_radius = clamp("min_radius", min_radius, CLAMP_FLOOR, max_radius);
+                Operation::AdaptiveUnsharp {
+                    min_radius,
+                    max_radius: clamp("max_radius", largest, min_radius, max_radius),
+                    amount: clamp("amount", amount, 0.0, max_amount),
+                    threshold,
+                }
+            }
+            Operation::TextSharpen { stroke_width, strength } => Operation::TextSharpen {
+                stroke_width: clamp("stroke_width", stroke_width, CLAMP_FLOOR, max_radius),
+                strength: clamp("strength", strength, CLAMP_FLOOR, max_strength),
+            },
+            Operation::Descreen { period } => Operation::Descreen {
+                period: period.map(|period| clamp("period", period, descreen::MIN_PERIOD, descreen::MAX_PERIOD)),
+            },
+            Operation::Median { radius } => Operation::Median {
+                radius: clamp("radius", radius as f32, 1.0, max_radius.floor()) as u32,
+            },
+            Operation::Denoise { strength, patch_radius, search_radius } => Operation::Denoise {
+                strength: clamp("strength", strength, CLAMP_FLOOR, max_strength),
+                patch_radius: clamp("patch_radius", patch_radius as f32, 1.0, denoise::MAX_PATCH_RADIUS as f32) as u32,
+                search_radius: clamp("search_radius", search_radius as f32, 1.0, max_radius.floor()) as u32,
+            },
+            Operation::SmartEnhance { level } => Operation::SmartEnhance {
+                level: clamp("level", level, CLAMP_FLOOR, max_strength),
+            },
+            Operation::FrequencySeparation { radius, smoothing, amount } => Operation::FrequencySeparation {
+                radius: clamp("radius", radius, CLAMP_FLOOR, max_radius),
+                smoothing: clamp("smoothing", smoothing, 0.0, 1.0),
+                amount: clamp("amount", amount, 0.0, max_amount),
+            },
+        };
+        (operation, moved)
+    }
+    
+    /// Returns a copy with radius-type parameters multiplied by `factor`.
+    /// 
+    /// Used to approximate an operation on a resized image. High-pass and
+    /// edge enhancement use fixed 3x3 kernels and are returned unchanged.
+    pub(crate) fn scaled(&self, factor: f32) -> Operation {
+        match *self {
+            Operation::UnsharpMask { radius, amount, threshold } => {
+                Operation::UnsharpMask { radius: radius * factor, amount, threshold }
+            }
+            Operation::RelativeUnsharp { radius, amount, threshold } => {
+                Operation::RelativeUnsharp { radius: radius * factor, amount, threshold }
+            }
+            Operation::Clarity { strength, radius } => {
+                Operation::Clarity { strength, radius: radius * factor }
+            }
+            Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold } => {
+                Operation::AdaptiveUnsharp { min_radius: min_radius * factor, max_radius: max_radius * factor, amount, threshold }
+            }
+            Operation::TextSharpen { stroke_width, strength } => {
+                Operation::TextSharpen { stroke_width: stroke_width * factor, strength }
+            }
+            Operation::Descreen { period } => Operation::Descreen { period: period.map(|period| period * factor) },
+            Operation::Median { radius } => Operation::Median { radius: ((radius as f32 * factor).round() as u32).max(1) },
+            Operation::Denoise { strength, patch_radius, search_radius } => Operation::Denoise {
+                strength,
+                patch_radius: ((patch_radius as f32 * factor).round() as u32).max(1),
+                search_radius: ((search_radius as f32 * factor).round() as u32).max(1),
+            },
+            Operation::FrequencySeparation { radius, smoothing, amount } => {
+                Operation::FrequencySeparation { radius: radius * factor, smoothing, amount }
+            }
+            ref other => other.clone(),
+        }
+    }
+    
+    /// Returns how far, in pixels, an output pixel's value depends on input
+    /// pixels around it.
+    pub(crate) fn reach(&self) -> u32 {
+        match *self {
+            // Half the Gaussian kernel, see `utils::gaussian_blur`; it covers
+            // the local range text sharpening clamps to
+            Operation::UnsharpMask { radius, .. } | Operation::RelativeUnsharp { radius, .. } => {
+                ((radius * 6.0).ceil() as u32 | 1) / 2
+            }
+            Operation::TextSharpen { stroke_width, .. } => ((stroke_width * 6.0).ceil() as u32 | 1) / 2,
+            // 3x3 kernels
+            Operation::HighPassSharpen { .. } | Operation::EnhanceEdges { .. } => 1,
+            Operation::Clarity { radius, .. } => (radius * 2.0).round() as u32 / 2,
+            Operation::Descreen { period } => descreen::reach(period),
+            Operation::Median { radius } => radius,
+            Operation::Denoise { patch_radius, search_radius, .. } => patch_radius + search_radius,
+            // The denoising, then the largest unsharp mask it may pick
+            Operation::SmartEnhance { .. } => {
+                sharpening::SMART_PATCH_RADIUS + sharpening::SMART_SEARCH_RADIUS
+                    + ((sharpening::SMART_MAX_RADIUS * 6.0).ceil() as u32 | 1) / 2
+            }
+            // The skin mask, or the tones blurred again for smoothing
+            Operation::FrequencySeparation { radius, .. } => {
+                let blur = |radius: f32| ((radius * 6.0).ceil() as u32 | 1) / 2;
+                mask::SKIN_REACH.max(blur(radius) + blur(radius * sharpening::SKIN_BLUR_FACTOR))
+            }
+            // The largest blur, plus the region choosing the radius
+            Operation::AdaptiveUnsharp { min_radius, max_radius, .. } => {
+                let largest = *sharpening::scale_radii(min_radius, max_radius).last().unwrap_or(&max_radius);
+                let blur = ((largest * 6.0).ceil() as u32 | 1) / 2;
+                if max_radius > min_radius { blur + sharpening::scale_region_reach(max_radius) } else { blur }
+            }
+        }
+    }
+    
+    /// Applies this operation to an image, validating its parameters.
+    pub fn apply<P: SharpenPixel>(&self, image: Image<P>) -> Result<Image<P>> {
+        self.apply_ref(&image)
+    }
+    
+    /// Applies this operation to a borrowed image, returning a new image.
+    pub fn apply_ref<P: SharpenPixel>(&self, image: &Image<P>) -> Result<Image<P>> {
+        match *self {
+            Operation::UnsharpMask { radius, amount, threshold } => {
+                image.unsharp_mask_ref(radius, amount, threshold)
+            }
+            Operation::RelativeUnsharp { radius, amount, threshold } => {
+                image.unsharp_mask_relative_ref(radius, amount, threshold)
+            }
+            Operation::HighPassSharpen { strength } => image.high_pass_sharpen_ref(strength),
+            Operation::EnhanceEdges { strength, method } => image.enhance_edges_ref(strength, method),
+            Operation::Clarity { strength, radius } => image.clarity_ref(strength, radius),
+            Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold } => {
+                image.adaptive_unsharp_mask_ref(min_radius, max_radius, amount, threshold)
+            }
+            Operation::TextSharpen { stroke_width, strength } => image.text_sharpen_ref(stroke_width, strength),
+            Operation::Descreen { period } => image.descreen_ref(period),
+            Operation::Median { radius } => image.median_filter_ref(radius),
+            Operation::Denoise { strength, patch_radius, search_radius } => {
+                image.denoise_ref(strength, patch_radius, search_radius)
+            }
+            Operation::SmartEnhance { level } => image.smart_enhance_ref(level),
+            Operation::FrequencySeparation { radius, smoothing, amount } => {
+                image.frequency_separation_ref(radius, smoothing, amount)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::UnsharpMask { radius, amount, threshold } => {
+                write!(f, "unsharp:{}:{}:{}", radius, amount, threshold)
+            }
+            Operation::RelativeUnsharp { radius, amount, threshold } => {
+                write!(f, "unsharp:{}:{}:{}%", radius, amount, threshold)
+            }
+            Operation::HighPassSharpen { strength } => write!(f, "highpass:{}", strength),
+            Operation::EnhanceEdges { strength, method } => {
+                write!(f, "edges:{}:{}", strength, method)
+            }
+            Operation::Clarity { strength, radius } => write!(f, "clarity:{}:{}", strength, radius),
+            Operation::AdaptiveUnsharp { min_radius, max_radius, amount, threshold } => {
+                write!(f, "adaptive_unsharp:{}:{}:{}:{}", min_radius, max_radius, amount, threshold)
+            }
+            Operation::TextSharpen { stroke_width, strength } => write!(f, "text:{}:{}", stroke_width, strength),
+            Operation::Descreen { period: Some(period) } => write!(f, "descreen:{}", period),
+            Operation::Descreen { period: None } => write!(f, "descreen:auto"),
+            Operation::Median { radius } => write!(f, "median:{}", radius),
+            Operation::Denoise { strength, patch_radius, search_radius } => {
+                write!(f, "denoise:{}:{}:{}", strength, patch_radius, search_radius)
+            }
+            Operation::SmartEnhance { level } => write!(f, "smart_enhance:{}", level),
+            Operation::FrequencySeparation { radius, smoothing, amount } => {
+                write!(f, "frequency_separation:{}:{}:{}", radius, smoothing, amount)
+            }
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = ImageError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.trim().split(':').map(str::trim).collect();
+        let name = parts[0].to_lowercase();
+        
+        let expect_params = |count: usize, usage: &str| {
+            if parts.len() != count + 1 {
+                Err(ImageError::Parse(format!(
+                    "'{}' expects {} parameter(s): {}", name, count, usage
+                )))
+            } else {
+                Ok(())
+            }
+        };
+        
+        match name.as_str() {
+            "unsharp" => {
+                expect_params(3, "unsharp:radius:amount:threshold")?;
+                let radius = parse_param(parts[1], "radius")?;
+                let amount = parse_param(parts[2], "amount")?;
+                match parts[3].strip_suffix('%') {
+                    Some(percent) => Ok(Operation::RelativeUnsharp {
+                        radius,
+                        amount,
+                        threshold: parse_param(percent.trim_end(), "threshold")?,
+                    }),
+                    None => Ok(Operation::UnsharpMask { radius, amount, threshold: parse_param(parts[3], "threshold")? }),
+                }
+            }
+            "highpass" => {
+                expect_params(1, "highpass:strength")?;
+                Ok(Operation::HighPassSharpen {
+                    strength: parse_param(parts[1], "strength")?,
+                })
+            }
+            "edges" => {
+                expect_params(2, "edges:strength:method")?;
+                Ok(Operation::EnhanceEdges {
+                    strength: parse_param(parts[1], "strength")?,
+                    method: parts[2].parse()?,
+                })
+            }
+            "clarity" => {
+                expect_params(2, "clarity:strength:radius")?;
+                Ok(Operation::Clarity {
+                    strength: parse_param(parts[1], "strength")?,
+                    radius: parse_param(parts[2], "radius")?,
+                })
+            }
+            "adaptive_unsharp" => {
+                expect_params(4, "adaptive_unsharp:min_radius:max_radius:amount:threshold")?;
+                Ok(Operation::AdaptiveUnsharp {
+                    min_radius: parse_param(parts[1], "min_radius")?,
+                    max_radius: parse_param(parts[2], "max_radius")?,
+                    amount: parse_param(parts[3], "amount")?,
+                    threshold: parse_param(parts[4], "threshold")?,
+                })
+            }
+            "text" => {
+                expect_params(2, "text:stroke_width:strength")?;
+                Ok(Operation::TextSharpen {
+                    stroke_width: parse_param(parts[1], "stroke_width")?,
+                    strength: parse_param(parts[2], "strength")?,
+                })
+            }
+            "descreen" => {
+                expect_params(1, "descreen:period (or descreen:auto)")?;
+                Ok(Operation::Descreen {
+                    period: match parts[1].to_lowercase().as_str() {
+                        "auto" => None,
+                        period => Some(parse_param(period, "period")?),
+                    },
+                })
+            }
+            "median" => {
+                expect_params(1, "median:radius")?;
+                Ok(Operation::Median {
+                    radius: parse_param(parts[1], "radius")?,
+                })
+            }
+            "denoise" => {
+                expect_params(3, "denoise:strength:patch_radius:search_radius")?;
+                Ok(Operation::Denoise {
+                    strength: parse_param(parts[1], "strength")?,
+                    patch_radius: parse_param(parts[2], "patch_radius")?,
+                    search_radius: parse_param(parts[3], "search_radius")?,
+                })
+            }
+            "smart_enhance" => {
+                expect_params(1, "smart_enhance:level")?;
+                Ok(Operation::SmartEnhance {
+                    level: parse_param(parts[1], "level")?,
+                })
+            }
+            "frequency_separation" => {
+                expect_params(3, "frequency_separation:radius:smoothing:amount")?;
+                Ok(Operation::FrequencySeparation {
+                    radius: parse_param(parts[1], "radius")?,
+                    smoothing: parse_param(parts[2], "smoothing")?,
+                    amount: parse_param(parts[3], "amount")?,
+                })
+            }
+            "" => Err(ImageError::Parse("empty operation".to_string())),
+            _ => Err(ImageError::Parse(format!("unknown operation '{}'", parts[0]))),
+        }
+    }
+}
+
+fn parse_param<T: FromStr>(value: &str, param: &str) -> Result<T> {
+    value.parse().map_err(|_| ImageError::Parse(format!("invalid {} '{}'", param, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_validate() {
+        assert!(Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 }.validate().is_ok());
+        assert!(Operation::HighPassSharpen { strength: 0.0 }.validate().is_err());
+        
+        let op = Operation::Clarity { strength: 4.0, radius: 25.0 };
+        assert_eq!(op.violations(), vec![("strength", "4".to_string()), ("radius", "25".to_string())]);
+        assert!(matches!(
+            op.validate(),
+            Err(ImageError::InvalidParameter { ref param, .. }) if param == "strength"
+        ));
+        let op = Operation::AdaptiveUnsharp { min_radius: 2.0, max_radius: 1.0, amount: 1.0, threshold: 0 };
+        assert_eq!(op.violations(), vec![("max_radius", "1".to_string())]);
+        assert!(Operation::TextSharpen { stroke_width: 0.0, strength: 1.0 }.validate().is_err());
+        let op = Operation::RelativeUnsharp { radius: 1.0, amount: 1.0, threshold: 120.0 };
+        assert_eq!(op.violations(), vec![("threshold", "120".to_string())]);
+        assert!(Operation::Descreen { period: None }.validate().is_ok());
+        assert!(Operation::Descreen { period: Some(1.0) }.validate().is_err());
+        assert!(Operation::Median { radius: 1 }.validate().is_ok());
+        assert_eq!(Operation::Median { radius: 0 }.violations(), vec![("radius", "0".to_string())]);
+        assert!(Operation::Denoise { strength: 1.0, patch_radius: 1, search_radius: 7 }.validate().is_ok());
+        let op = Operation::Denoise { strength: 1.0, patch_radius: 4, search_radius: 0 };
+        assert_eq!(op.violations(), vec![("patch_radius", "4".to_string()), ("search_radius", "0".to_string())]);
+        assert!(Operation::SmartEnhance { level: 1.0 }.validate().is_ok());
+        assert!(Operation::SmartEnhance { level: 0.0 }.validate().is_err());
+        assert!(Operation::FrequencySeparation { radius: 2.0, smoothing: 0.4, amount: 0.8 }.validate().is_ok());
+        let op = Operation::FrequencySeparation { radius: 2.0, smoothing: 1.5, amount: 0.8 };
+        assert_eq!(op.violations(), vec![("smoothing", "1.5".to_string())]);
+    }
+    
+    #[test]
+    fn test_validation_config() {
+        let extended = ValidationConfig::extended();
+        let op = Operation::Clarity { strength: 6.0, radius: 60.0 };
+        assert_eq!(op.violations().len(), 2);
+        assert!(op.validate_with(&extended).is_ok());
+        assert_eq!(
+            op.violations_with(&ValidationConfig::default().max_strength(8.0)),
+            vec![("radius", "60".to_string())]
+        );
+        
+        let op = Operation::AdaptiveUnsharp { min_radius: 12.0, max_radius: 40.0, amount: 12.0, threshold: 0 };
+        assert_eq!(op.violations().len(), 3);
+        assert!(op.validate_with(&extended).is_ok());
+        // Ranges that aren't configurable stay fixed
+        assert!(Operation::RelativeUnsharp { radius: 1.0, amount: 1.0, threshold: 150.0 }.validate_with(&extended).is_err());
+        assert!(Operation::HighPassSharpen { strength: 0.0 }.validate_with(&extended).is_err());
+        
+        // Maxima stop at the ceiling; NaN allows next to nothing
+        let unbounded = ValidationConfig::default().max_radius(f32::INFINITY).max_amount(f32::NAN);
+        let op = Operation::UnsharpMask { radius: 1e30, amount: 1.0, threshold: 0 };
+        assert_eq!(op.violations_with(&unbounded), vec![("radius", "1000000000000000000000000000000".to_string()), ("amount", "1".to_string())]);
+        assert_eq!(op.clamped(&unbounded).0, Operation::UnsharpMask { radius: 250.0, amount: 0.0, threshold: 0 });
+    }
+    
+    #[test]
+    fn test_clamped() {
+        let config = ValidationConfig::default();
+        let op = Operation::AdaptiveUnsharp { min_radius: 14.0, max_radius: 2.0, amount: f32::NAN, threshold: 3 };
+        let (clamped, moved) = op.clamped(&config);
+        assert_eq!(clamped, Operation::AdaptiveUnsharp { min_radius: 10.0, max_radius: 10.0, amount: 0.0, threshold: 3 });
+        assert_eq!(moved.iter().map(|&(param, ..)| param).collect::<Vec<_>>(), vec!["min_radius", "max_radius", "amount"]);
+        assert!(clamped.validate().is_ok());
+        
+        let op = Operation::Clarity { strength: 0.0, radius: 5.0 };
+        assert_eq!(op.clamped(&config).1, vec![("strength", 0.0, CLAMP_FLOOR)]);
+        let valid = Operation::TextSharpen { stroke_width: 1.5, strength: 1.0 };
+        assert_eq!(valid.clamped(&config), (valid.clone(), Vec::new()));
+        assert_eq!(Operation::Median { radius: 0 }.clamped(&config).0, Operation::Median { radius: 1 });
+        let op = Operation::Denoise { strength: 1.0, patch_radius: 5, search_radius: 7 };
+        assert_eq!(op.clamped(&config).1, vec![("patch_radius", 5.0, 3.0)]);
+    }
+    
+    #[test]
+    fn test_parse_operations() {
+        assert_eq!(
+            "unsharp:1.0:1.0:0".parse::<Operation>().unwrap(),
+            Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 }
+        );
+        assert_eq!(
+            "HighPass:0.5".parse::<Operation>().unwrap(),
+            Operation::HighPassSharpen { strength: 0.5 }
+        );
+        assert_eq!(
+            "edges:0.5:prewitt".parse::<Operation>().unwrap(),
+            Operation::EnhanceEdges { strength: 0.5, method: EdgeMethod::Prewitt }
+        );
+        assert_eq!(
+            " clarity:0.3:2 ".parse::<Operation>().unwrap(),
+            Operation::Clarity { strength: 0.3, radius: 2.0 }
+        );
+        assert_eq!(
+            "unsharp:1.5:1.0:10 %".parse::<Operation>().unwrap(),
+            Operation::RelativeUnsharp { radius: 1.5, amount: 1.0, threshold: 10.0 }
+        );
+        assert_eq!("descreen:Auto".parse::<Operation>().unwrap(), Operation::Descreen { period: None });
+        assert_eq!("median:2".parse::<Operation>().unwrap(), Operation::Median { radius: 2 });
+        assert_eq!(
+            "denoise:1.5:1:7".parse::<Operation>().unwrap(),
+            Operation::Denoise { strength: 1.5, patch_radius: 1, search_radius: 7 }
+        );
+    }
+    
+    #[test]
+    fn test_parse_errors() {
+        assert!("unsharp:1.0:1.0".parse::<Operation>().is_err());
+        assert!("unsharp:a:1.0:0".parse::<Operation>().is_err());
+        assert!("unsharp:1.0:1.0:x%".parse::<Operation>().is_err());
+        assert!("edges:0.5:canny".parse::<Operation>().is_err());
+        assert!("blur:1.0".parse::<Operation>().is_err());
+        assert!("median:1.5".parse::<Operation>().is_err());
+        assert!("".parse::<Operation>().is_err());
+    }
+    
+    #[test]
+    fn test_display_round_trip() {
+        let ops = [
+            Operation::UnsharpMask { radius: 1.25, amount: 0.8, threshold: 3 },
+            Operation::RelativeUnsharp { radius: 1.25, amount: 0.8, threshold: 12.5 },
+            Operation::HighPassSharpen { strength: 0.4 },
+            Operation::EnhanceEdges { strength: 1.0, method: EdgeMethod::Sobel },
+            Operation::Clarity { strength: 0.5, radius: 2.0 },
+            Operation::AdaptiveUnsharp { min_radius: 0.8, max_radius: 3.0, amount: 1.2, threshold: 2 },
+            Operation::TextSharpen { stroke_width: 1.5, strength: 1.0 },
+            Operation::Descreen { period: Some(4.5) },
+            Operation::Descreen { period: None },
+            Operation::Median { radius: 3 },
+            Operation::Denoise { strength: 0.8, patch_radius: 2, search_radius: 5 },
+            Operation::SmartEnhance { level: 1.5 },
+            Operation::FrequencySeparation { radius: 2.5, smoothing: 0.4, amount: 0.8 },
+        ];
+        
+        for op in ops {
+            assert_eq!(op.to_string().parse::<Operation>().unwrap(), op);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,434 @@
+//! Ordered operation lists with a compact text syntax.
+
+use crate::migrate::{self, Migration};
+use crate::par::*;
+use crate::{Image, ImageError, Operation, PipelineOp, Result, SharpenPixel};
+use serde::{Deserialize, Serialize, Serializer};
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An ordered list of operations, independent of any image.
+///
+/// Pipelines use the CLI's comma-separated syntax, so frontends such as
+/// servers or GUIs can accept the same strings users pass to `sharpy batch`.
+///
+/// # Example
+/// ```
+/// use sharpy::Pipeline;
+///
+/// let pipeline = Pipeline::parse("unsharp:1:1:0, clarity:0.5:2").unwrap();
+/// assert_eq!(pipeline.len(), 2);
+/// assert_eq!(pipeline.to_string(), "unsharp:1:1:0,clarity:0.5:2");
+/// ```
+///
+/// Applications can add their own steps with [`Pipeline::push_custom`].
+/// These only live in memory: they have no text form, and serializing a
+/// pipeline holding one fails.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(from = "Vec<Operation>")]
+pub struct Pipeline {
+    operations: Vec<Operation>,
+    /// Custom steps, each with the number of operations before it
+    custom: Vec<(usize, CustomStep)>,
+}
+
+/// A step from [`Pipeline::push_custom`].
+#[derive(Clone)]
+struct CustomStep {
+    name: &'static str,
+    /// The step as an `Arc<dyn PipelineOp<P>>` for the pixel format it takes
+    op: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomStep {
+    /// The step for images of pixel format `P`.
+    fn get<P: SharpenPixel>(&self) -> Result<&dyn PipelineOp<P>> {
+        match self.op.downcast_ref::<Arc<dyn PipelineOp<P>>>() {
+            Some(op) => Ok(op.as_ref()),
+            None => Err(ImageError::InvalidParameter {
+                param: "pixel format".to_string(),
+                value: format!("custom step {} takes another pixel format than the image", self.name),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for CustomStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CustomStep").field(&self.name).finish()
+    }
+}
+
+impl PartialEq for CustomStep {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.op, &other.op)
+    }
+}
+
+/// A step of a pipeline, in order.
+enum Stage<'a> {
+    Operation(&'a Operation),
+    Custom(&'a CustomStep),
+}
+
+impl Pipeline {
+    /// Bytes of working memory [`Pipeline::run_all`] allows in flight.
+    pub const DEFAULT_MEMORY_BUDGET: u64 = 1 << 30;
+
+    /// Creates an empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma-separated list of operations.
+    ///
+    /// Operations saved by older releases are migrated to the current
+    /// syntax; use [`Pipeline::parse_with_migrations`] to see what changed.
+    pub fn parse(s: &str) -> Result<Self> {
+        Self::parse_with_migrations(s).map(|(pipeline, _)| pipeline)
+    }
+
+    /// Parses a comma-separated list of operations, reporting each
+    /// migration applied to operations saved by older releases.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::Pipeline;
+    ///
+    /// let (pipeline, migrations) = Pipeline::parse_with_migrations("unsharp:1:1:0,clarity:0.4:2").unwrap();
+    /// assert_eq!(pipeline.len(), 2);
+    /// for migration in &migrations {
+    ///     eprintln!("Migrated: {}", migration);
+    /// }
+    /// ```
+    pub fn parse_with_migrations(s: &str) -> Result<(Self, Vec<Migration>)> {
+        let mut migrations = Vec::new();
+        let operations = s.split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(|part| {
+                let (text, applied) = migrate::migrate_operation_text(part);
+                migrations.extend(applied);
+                text.parse()
+            })
+            .collect::<Result<Vec<Operation>>>()?;
+
+        if operations.is_empty() {
+            return Err(ImageError::Parse("pipeline contains no operations".to_string()));
+        }
+
+        Ok((Self::from(operations), migrations))
+    }
+
+    /// Appends an operation.
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    /// Appends a step the application defines, run in order with the
+    /// operations on images of the pixel format it takes.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Pipeline, PipelineOp, Result};
+    ///
+    /// struct Invert;
+    ///
+    /// impl PipelineOp for Invert {
+    ///     fn name(&self) -> &'static str {
+    ///         "Invert"
+    ///     }
+    ///
+    ///     fn apply(&self, image: Image) -> Result<Image> {
+    ///         let mut rgb = image.into_rgb();
+    ///         image::imageops::invert(&mut rgb);
+    ///         Image::from_rgb(rgb)
+    ///     }
+    /// }
+    ///
+    /// let mut pipeline = Pipeline::parse("unsharp:1:1:0").unwrap();
+    /// pipeline.push_custom(Invert);
+    /// assert_eq!(pipeline.len(), 2);
+    /// assert_eq!(pipeline.operations().len(), 1);
+    ///
+    /// # let image = Image::from_rgb(image::RgbImage::new(64, 64)).unwrap();
+    /// let result = pipeline.apply(image).unwrap();
+    /// ```
+    pub fn push_custom<P: SharpenPixel, O: PipelineOp<P> + 'static>(&mut self, op: O) {
+        let name = op.name();
+        let op: Arc<dyn PipelineOp<P>> = Arc::new(op);
+        self.custom.push((self.operations.len(), CustomStep { name, op: Arc::new(op) }));
+    }
+
+    /// Returns the built-in operations in order, without
+    /// [custom steps](Pipeline::push_custom).
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Returns the number of steps, counting custom ones.
+    pub fn len(&self) -> usize {
+        self.operations.len() + self.custom.len()
+    }
+
+    /// Returns true if the pipeline has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns every step in order.
+    fn stages(&self) -> impl Iterator<Item = Stage<'_>> + '_ {
+        let mut custom = self.custom.iter().peekable();
+        let mut operations = self.operations.iter().enumerate().peekable();
+        std::iter::from_fn(move || {
+            let next = operations.peek().map(|&(index, _)| index);
+            match custom.next_if(|&&(position, _)| next.is_none_or(|index| position <= index)) {
+                Some((_, step)) => Some(Stage::Custom(step)),
+                None => operations.next().map(|(_, operation)| Stage::Operation(operation)),
+            }
+        })
+    }
+
+    /// Applies every step in order, validating parameters as it goes. A
+    /// failing step's error is wrapped in [`ImageError::Operation`] with its
+    /// name and index.
+    pub fn apply<P: SharpenPixel>(&self, image: Image<P>) -> Result<Image<P>> {
+        self.stages().enumerate().try_fold(image, |image, (index, stage)| {
+            let (name, result) = match stage {
+                Stage::Operation(operation) => (operation.name(), operation.apply(image)),
+                Stage::Custom(step) => (step.name, step.get().and_then(|op| op.apply(image))),
+            };
+            result.map_err(|e| e.in_operation(name, Some(index)))
+        })
+    }
+
+    /// Checks the parameters of every step, as [`Pipeline::apply`] does.
+    pub(crate) fn validate<P: SharpenPixel>(&self) -> Result<()> {
+        self.stages().try_for_each(|stage| match stage {
+            Stage::Operation(operation) => operation.validate(),
+            Stage::Custom(step) => step.get::<P>()?.validate(),
+        })
+    }
+
+    /// How far an output pixel depends on input pixels around it, for
+    /// images of pixel format `P`.
+    pub(crate) fn reach<P: SharpenPixel>(&self) -> u32 {
+        self.stages().map(|stage| match stage {
+            Stage::Operation(operation) => operation.reach(),
+            Stage::Custom(step) => step.get::<P>().map_or(0, |op| op.reach()),
+        }).sum()
+    }
+
+    /// Applies the pipeline to each image, processing several images at
+    /// once within [`Pipeline::DEFAULT_MEMORY_BUDGET`]. Results are in input order.
+    ///
+    /// # Example
+    /// ```
+    /// use sharpy::{Image, Pipeline};
+    ///
+    /// let thumbnails: Vec<Image> = (0..8)
+    ///     .map(|_| Image::from_rgb(image::RgbImage::new(64, 64)).unwrap())
+    ///     .collect();
+    /// let pipeline = Pipeline::parse("unsharp:0.8:0.6:2").unwrap();
+    /// let results = pipeline.run_all(thumbnails);
+    /// assert!(results.iter().all(|result| result.is_ok()));
+    /// ```
+    pub fn run_all<P: SharpenPixel>(&self, images: Vec<Image<P>>) -> Vec<Result<Image<P>>> {
+        self.run_all_within(images, Self::DEFAULT_MEMORY_BUDGET)
+    }
+
+    /// Like [`Pipeline::run_all`], keeping the estimated peak memory (see
+    /// [`Pipeline::estimate`]) of the images processed at once within
+    /// `max_bytes`.
+    ///
+    /// Consecutive images are processed together while their estimates fit;
+    /// an image larger than the budget is processed on its own.
+    pub fn run_all_within<P: SharpenPixel>(&self, images: Vec<Image<P>>, max_bytes: u64) -> Vec<Result<Image<P>>> {
+        let mut results = Vec::with_capacity(images.len());
+        let mut group = Vec::new();
+        let mut group_bytes = 0;
+        for image in images {
+            let (width, height) = image.dimensions();
+            let bytes = self.estimate_for::<P>(width, height).peak_bytes;
+            if !group.is_empty() && group_bytes + bytes > max_bytes {
+                results.extend(self.run_group(std::mem::take(&mut group)));
+                group_bytes = 0;
+            }
+            group_bytes += bytes;
+            group.push(image);
+        }
+        results.extend(self.run_group(group));
+        results
+    }
+
+    fn run_group<P: SharpenPixel>(&self, images: Vec<Image<P>>) -> Vec<Result<Image<P>>> {
+        images.into_par_iter().map(|image| self.apply(image)).collect()
+    }
+}
+
+impl From<Vec<Operation>> for Pipeline {
+    fn from(operations: Vec<Operation>) -> Self {
+        Self { operations, custom: Vec::new() }
+    }
+}
+
+impl FromIterator<Operation> for Pipeline {
+    fn from_iter<I: IntoIterator<Item = Operation>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+/// Yields the built-in operations, dropping custom steps.
+impl IntoIterator for Pipeline {
+    type Item = Operation;
+    type IntoIter = std::vec::IntoIter<Operation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl Serialize for Pipeline {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if let Some((_, step)) = self.custom.first() {
+            return Err(serde::ser::Error::custom(format!("custom step {} can't be saved", step.name)));
+        }
+        self.operations.serialize(serializer)
+    }
+}
+
+/// Writes the text syntax. Custom steps have none and are written as their
+/// name in angle brackets, which doesn't parse back.
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match stage {
+                Stage::Operation(operation) => write!(f, "{}", operation)?,
+                Stage::Custom(step) => write!(f, "<{}>", step.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeMethod;
+    use image::RgbImage;
+
+    #[test]
+    fn test_parse_pipeline() {
+        let pipeline = Pipeline::parse("unsharp:1.0:1.0:0,edges:0.5:sobel,clarity:0.3:2.0").unwrap();
+        assert_eq!(pipeline.operations(), &[
+            Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 },
+            Operation::EnhanceEdges { strength: 0.5, method: EdgeMethod::Sobel },
+            Operation::Clarity { strength: 0.3, radius: 2.0 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Pipeline::parse("").is_err());
+        assert!(Pipeline::parse("unsharp:1:1:0,bogus:1").is_err());
+    }
+
+    #[test]
+    fn test_released_pipeline_unmigrated() {
+        let (pipeline, migrations) = Pipeline::parse_with_migrations("highpass:0.3,edges:0.5:sobel,clarity:0.4:1.5").unwrap();
+        assert_eq!(pipeline.to_string(), "highpass:0.3,edges:0.5:sobel,clarity:0.4:1.5");
+        assert!(migrations.is_empty());
+
+        // Names and shorthands no release accepted aren't guessed at
+        assert!(Pipeline::parse("high_pass:0.3").is_err());
+        assert!(Pipeline::parse("edges:0.5").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "unsharp:1.5:0.8:2,highpass:0.3,edges:1:prewitt";
+        let pipeline: Pipeline = text.parse().unwrap();
+        assert_eq!(pipeline.to_string(), text);
+        assert_eq!(pipeline.to_string().parse::<Pipeline>().unwrap(), pipeline);
+    }
+
+    #[test]
+    fn test_run_all() {
+        let pipeline = Pipeline::parse("unsharp:1:1:0,clarity:0.5:2").unwrap();
+        let images: Vec<Image> = (0..5u8)
+            .map(|i| Image::from_rgb(RgbImage::from_fn(16, 16, |x, y| image::Rgb([i * 40, (x * 16) as u8, (y * 16) as u8]))).unwrap())
+            .collect();
+        let expected: Vec<Image> = images.iter().map(|image| pipeline.apply(image.clone()).unwrap()).collect();
+
+        // A budget below one image's estimate processes them one at a time
+        for budget in [Pipeline::DEFAULT_MEMORY_BUDGET, 1] {
+            let results = pipeline.run_all_within(images.clone(), budget);
+            assert_eq!(results.len(), expected.len());
+            for (result, expected) in results.into_iter().zip(&expected) {
+                assert_eq!(result.unwrap().as_rgb(), expected.as_rgb());
+            }
+        }
+
+        let results = Pipeline::parse("unsharp:50:1:0").unwrap().run_all(images);
+        assert!(results.iter().all(|result| result.is_err()));
+    }
+
+    #[test]
+    fn test_custom_steps() {
+        struct Fill(u8);
+        impl PipelineOp for Fill {
+            fn name(&self) -> &'static str {
+                "Fill"
+            }
+            fn apply(&self, image: Image) -> Result<Image> {
+                let (width, height) = image.dimensions();
+                Image::from_rgb(RgbImage::from_pixel(width, height, image::Rgb([self.0; 3])))
+            }
+        }
+
+        let mut pipeline = Pipeline::new();
+        pipeline.push_custom(Fill(10));
+        pipeline.push(Operation::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0 });
+        pipeline.push_custom(Fill(200));
+        pipeline.push_custom(Fill(50));
+        assert_eq!(pipeline.len(), 4);
+        assert_eq!(pipeline.to_string(), "<Fill>,unsharp:1:1:0,<Fill>,<Fill>");
+        assert!(serde_json::to_string(&pipeline).is_err());
+        assert_eq!(pipeline.clone(), pipeline);
+
+        // Steps run in the order they were added
+        let image = Image::from_rgb(RgbImage::new(16, 16)).unwrap();
+        let result = pipeline.apply(image).unwrap();
+        assert!(result.as_rgb().pixels().all(|pixel| pixel.0 == [50; 3]));
+
+        let deep = Image::from_buffer(image::ImageBuffer::<image::Rgb<u16>, _>::new(16, 16)).unwrap();
+        assert_eq!(pipeline.apply(deep).err().unwrap().operation(), Some("Fill"));
+    }
+
+    #[test]
+    fn test_apply_validates() {
+        let image = Image::from_rgb(RgbImage::new(32, 32)).unwrap();
+        assert!(Pipeline::parse("unsharp:1:1:0,clarity:0.5:2").unwrap().apply(image).is_ok());
+
+        let image = Image::from_rgb(RgbImage::new(32, 32)).unwrap();
+        match Pipeline::parse("clarity:0.5:2,unsharp:50:1:0").unwrap().apply(image) {
+            Err(ImageError::Operation { operation, index, ref source }) => {
+                assert_eq!((operation, index), ("Unsharp Mask", Some(1)));
+                assert!(matches!(**source, ImageError::InvalidParameter { .. }));
+            }
+            other => panic!("expected operation error, got {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Pixel formats an [`Image`](crate::Image) can hold.
+//!
+//! The sharpening algorithms are written once against [`SharpenPixel`],
+//! which describes how many color channels a pixel has and how its samples
+//! map to floating point. Parameters such as unsharp `threshold` stay in
+//! 8-bit units (0-255) for every format and are scaled to the sample range.
+
+use image::buffer::ConvertBuffer;
+use image::{ColorType, DynamicImage, ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba, RgbImage};
+
+/// Pixel buffer of an image holding `P` pixels.
+pub(crate) type Buffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// Channel sample types: 8- and 16-bit integers.
+pub trait Sample: image::Primitive + Send + Sync + 'static {
+    /// Largest sample value
+    const MAX: f32;
+
+    fn to_f32(self) -> f32;
+
+    /// Rounds and clamps `value` to the sample range.
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for u8 {
+    const MAX: f32 = u8::MAX as f32;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0.0, <Self as Sample>::MAX) as u8
+    }
+}
+
+impl Sample for u16 {
+    const MAX: f32 = u16::MAX as f32;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0.0, <Self as Sample>::MAX) as u16
+    }
+}
+
+/// Pixel types supported by [`Image`](crate::Image): RGB, RGBA, grayscale
+/// and grayscale with alpha, at 8 or 16 bits per channel.
+///
+/// Color channels are sharpened; alpha is carried through unchanged.
+pub trait SharpenPixel: Pixel<Subpixel: Sample> + Send + Sync + 'static + private::Sealed {
+    /// Number of color channels; an alpha channel, if any, follows them
+    const COLOR_CHANNELS: usize;
+
+    /// The same layout at 16 bits, for intermediates that need the precision
+    type Wide: SharpenPixel<Subpixel = u16>;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_sharpen_pixel {
+    ($($pixel:ident => $channels:expr),*) => {
+        $(
+            impl<T: Sample> private::Sealed for $pixel<T> {}
+            impl<T: Sample> SharpenPixel for $pixel<T> where $pixel<T>: Pixel<Subpixel = T> {
+                const COLOR_CHANNELS: usize = $channels;
+                type Wide = $pixel<u16>;
+            }
+        )*
+    };
+}
+
+impl_sharpen_pixel!(Rgb => 3, Rgba => 3, Luma => 1, LumaA => 1);
+
+/// Converts a sample to f32.
+pub(crate) fn sample<P: SharpenPixel>(value: P::Subpixel) -> f32 {
+    value.to_f32()
+}
+
+/// Rounds and clamps `value` into a sample.
+pub(crate) fn to_sample<P: SharpenPixel>(value: f32) -> P::Subpixel {
+    <P::Subpixel as Sample>::from_f32(value)
+}
+
+/// Largest sample value of `P`.
+pub(crate) fn max_value<P: SharpenPixel>() -> f32 {
+    <P::Subpixel as Sample>::MAX
+}
+
+/// Factor converting 8-bit parameter units to `P`'s sample range.
+pub(crate) fn unit_scale<P: SharpenPixel>() -> f32 {
+    max_value::<P>() / 255.0
+}
+
+/// Converts RGB8 pixels to `color`. Alpha channels come out opaque.
+pub(crate) fn convert_rgb8(img: &RgbImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(img.convert()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(img.convert()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(img.convert()),
+        ColorType::L16 => DynamicImage::ImageLuma16(img.convert()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(img.convert()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(img.convert()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(img.convert()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(img.convert()),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(img.convert()),
+        _ => DynamicImage::ImageRgb8(img.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_conversion() {
+        assert_eq!(u8::from_f32(300.0), 255);
+        assert_eq!(u8::from_f32(-4.0), 0);
+        assert_eq!(u16::from_f32(1000.4), 1000);
+        assert_eq!(unit_scale::<Rgb<u16>>(), 257.0);
+        assert_eq!(Rgba::<u8>::COLOR_CHANNELS, 3);
+        assert_eq!(Luma::<u16>::COLOR_CHANNELS, 1);
+    }
+
+    #[test]
+    fn test_convert_rgb8() {
+        let img = RgbImage::from_pixel(2, 2, Rgb([255, 255, 255]));
+        let gray = convert_rgb8(&img, ColorType::L16);
+        assert_eq!(gray.color(), ColorType::L16);
+        assert_eq!(gray.as_luma16().unwrap().get_pixel(1, 1).0, [65535]);
+        assert_eq!(convert_rgb8(&img, ColorType::Rgba8).as_rgba8().unwrap().get_pixel(0, 0).0[3], 255);
+    }
+}
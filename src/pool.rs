@@ -0,0 +1,78 @@
+//! Scratch buffers reused across the operations of one pipeline run.
+//!
+//! A multi-step pipeline allocates several full-size temporaries per step
+//! (blur passes, edge maps, outputs that become the next step's input).
+//! Returning them to a pool once consumed lets later steps reuse the
+//! allocations instead of going back to the allocator for each one.
+
+use crate::pixel::{Buffer, SharpenPixel};
+use image::Primitive;
+use std::any::Any;
+use std::sync::{Mutex, PoisonError};
+
+/// Largest number of idle buffers kept; enough for the temporaries of one
+/// operation plus the previous step's output.
+const MAX_IDLE: usize = 4;
+
+/// Pool of sample vectors of any pixel type.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    idle: Mutex<Vec<Box<dyn Any + Send>>>,
+}
+
+impl BufferPool {
+    /// Returns a zeroed buffer, reusing an idle allocation large enough.
+    pub(crate) fn take<P: SharpenPixel>(&self, width: u32, height: u32) -> Buffer<P> {
+        let len = width as usize * height as usize * P::CHANNEL_COUNT as usize;
+        let reused = {
+            let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+            idle.iter()
+                .position(|samples| {
+                    samples.downcast_ref::<Vec<P::Subpixel>>().is_some_and(|samples| samples.capacity() >= len)
+                })
+                .map(|index| idle.swap_remove(index))
+        };
+
+        match reused.and_then(|samples| samples.downcast::<Vec<P::Subpixel>>().ok()) {
+            Some(mut samples) => {
+                samples.clear();
+                samples.resize(len, P::Subpixel::DEFAULT_MIN_VALUE);
+                Buffer::<P>::from_raw(width, height, *samples).expect("buffer matches dimensions")
+            }
+            None => Buffer::<P>::new(width, height),
+        }
+    }
+
+    /// Returns a buffer that is no longer needed to the pool.
+    pub(crate) fn recycle<P: SharpenPixel>(&self, buffer: Buffer<P>) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE {
+            idle.push(Box::new(buffer.into_raw()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb};
+
+    #[test]
+    fn test_reuses_allocations() {
+        let pool = BufferPool::default();
+        let mut buffer = pool.take::<Rgb<u8>>(16, 16);
+        buffer.put_pixel(3, 3, Rgb([9, 9, 9]));
+        let address = buffer.as_ptr();
+        pool.recycle(buffer);
+
+        // Smaller buffers of the same sample type reuse the allocation, zeroed
+        let reused = pool.take::<Luma<u8>>(8, 8);
+        assert_eq!(reused.as_ptr(), address);
+        assert!(reused.pixels().all(|p| p.0 == [0]));
+
+        // Other sample types and larger sizes allocate afresh
+        pool.recycle(reused);
+        assert_ne!(pool.take::<Rgb<u16>>(4, 4).as_ptr() as *const u8, address);
+        assert_ne!(pool.take::<Rgb<u8>>(32, 32).as_ptr(), address);
+    }
+}
@@ -0,0 +1,378 @@
+//! Named presets, both built-in and loaded from definition files.
+//!
+//! Preset files list presets as a name plus an ordered list of operations,
+//! optionally with `protect_stars = true` to leave stars unsharpened (see
+//! [`SharpeningBuilder::protect_stars`]) and `chroma_clamp = <tolerance>` to
+//! suppress color fringes after each operation (see
+//! [`SharpeningBuilder::chroma_clamp`]). TOML:
+//!
+//! ```toml
+//! [[presets]]
+//! name = "warm_portrait"
+//! description = "Gentle portrait sharpening"
+//! chroma_clamp = 2.0
+//! operations = [
+//!     { op = "unsharp", radius = 1.2, amount = 0.7, threshold = 8 },
+//!     { op = "clarity", strength = 0.2, radius = 5.0 },
+//! ]
+//! ```
+//!
+//! JSON uses the same structure:
+//! `{ "presets": [ { "name": "...", "operations": [ { "op": "highpass", "strength": 0.4 } ] } ] }`
+//!
+//! Operations written by older releases are migrated on load; see
+//! [`PresetRegistry::migrations`].
+
+use crate::builder::BUILTIN_PRESETS;
+use crate::migrate::{self, Migration};
+use crate::{Image, ImageError, Operation, Result, SharpenPixel, SharpeningBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A named, ordered list of operations, whether they leave stars alone and
+/// how far their colors may stray from the original's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    /// Preset name used for lookup
+    pub name: String,
+    /// Optional human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Operations applied in order
+    pub operations: Vec<Operation>,
+    /// Whether stars are left unsharpened, see
+    /// [`SharpeningBuilder::protect_stars`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub protect_stars: bool,
+    /// Tolerance every operation's chroma is clamped to, see
+    /// [`SharpeningBuilder::chroma_clamp`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chroma_clamp: Option<f32>,
+}
+
+impl Preset {
+    /// Creates a preset from a name and its operations.
+    pub fn new(name: impl Into<String>, operations: Vec<Operation>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            operations,
+            protect_stars: false,
+            chroma_clamp: None,
+        }
+    }
+
+    /// Creates a builder that applies this preset to `image`.
+    pub fn builder<P: SharpenPixel>(&self, image: Image<P>) -> SharpeningBuilder<P> {
+        let builder = self.operations.iter().fold(image.sharpen(), |builder, operation| {
+            let builder = builder.operation(operation.clone());
+            match self.chroma_clamp {
+                Some(tolerance) => builder.chroma_clamp(tolerance),
+                None => builder,
+            }
+        });
+        builder.protect_stars(self.protect_stars)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PresetFile {
+    #[serde(default)]
+    presets: Vec<Preset>,
+}
+
+/// A collection of presets looked up by name.
+///
+/// Names are matched case-insensitively, treating `-` and `_` as equal, so
+/// `edge-aware` and `Edge_Aware` refer to the same preset.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{Image, PresetRegistry};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut registry = PresetRegistry::builtin();
+/// registry.extend(PresetRegistry::load_file("mypresets.toml")?);
+///
+/// let image = Image::load("photo.jpg")?;
+/// let result = registry.builder("warm_portrait", image)?.apply()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PresetRegistry {
+    presets: BTreeMap<String, Preset>,
+    migrations: Vec<Migration>,
+}
+
+impl PresetRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing the built-in presets.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for &(name, description, operations, protect_stars) in BUILTIN_PRESETS {
+            registry.register(Preset {
+                name: name.to_string(),
+                description: Some(description.to_string()),
+                operations: operations.to_vec(),
+                protect_stars,
+                chroma_clamp: None,
+            });
+        }
+        registry
+    }
+
+    /// Loads presets from a `.toml` or `.json` file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| ImageError::from(e).in_file(path))?;
+
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        let result = match extension.as_deref() {
+            Some("toml") => Self::from_toml_str(&contents),
+            Some("json") => Self::from_json_str(&contents),
+            _ => Err(ImageError::Preset(
+                "unsupported preset file extension (expected .toml or .json)".to_string(),
+            )),
+        };
+
+        result.map_err(|e| match e {
+            ImageError::Preset(msg) => ImageError::Preset(format!("{}: {}", path.display(), msg)),
+            other => other,
+        })
+    }
+
+    /// Parses presets from TOML text.
+    pub fn from_toml_str(contents: &str) -> Result<Self> {
+        let value: serde_json::Value = toml::from_str(contents)
+            .map_err(|e| ImageError::Preset(e.to_string()))?;
+        Self::from_value(value)
+    }
+
+    /// Parses presets from JSON text.
+    pub fn from_json_str(contents: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents)
+            .map_err(|e| ImageError::Preset(e.to_string()))?;
+        Self::from_value(value)
+    }
+
+    fn from_value(mut value: serde_json::Value) -> Result<Self> {
+        let mut migrations = Vec::new();
+        let presets = value.get_mut("presets").and_then(|p| p.as_array_mut());
+        for preset in presets.into_iter().flatten() {
+            let operations = preset.get_mut("operations").and_then(|o| o.as_array_mut());
+            for operation in operations.into_iter().flatten() {
+                migrate::migrate_operation_value(operation, &mut migrations)?;
+            }
+        }
+
+        let file: PresetFile = serde_json::from_value(value)
+            .map_err(|e| ImageError::Preset(e.to_string()))?;
+
+        let mut registry = Self::new();
+        for preset in file.presets {
+            registry.register(preset);
+        }
+        registry.migrations = migrations;
+        Ok(registry)
+    }
+
+    /// Adds a preset, replacing any existing preset with the same name.
+    pub fn register(&mut self, preset: Preset) {
+        self.presets.insert(normalize_name(&preset.name), preset);
+    }
+
+    /// Adds all presets from `other`, replacing presets with the same name.
+    pub fn extend(&mut self, other: PresetRegistry) {
+        self.presets.extend(other.presets);
+        self.migrations.extend(other.migrations);
+    }
+
+    /// Returns the migrations applied to operations written by older releases
+    /// when this registry was loaded.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Looks up a preset by name.
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.get(&normalize_name(name))
+    }
+
+    /// Iterates over all presets, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Preset> {
+        self.presets.values()
+    }
+
+    /// Returns the number of presets.
+    pub fn len(&self) -> usize {
+        self.presets.len()
+    }
+
+    /// Returns true if the registry contains no presets.
+    pub fn is_empty(&self) -> bool {
+        self.presets.is_empty()
+    }
+
+    /// Creates a builder that applies the named preset to `image`.
+    pub fn builder<P: SharpenPixel>(&self, name: &str, image: Image<P>) -> Result<SharpeningBuilder<P>> {
+        let preset = self.get(name).ok_or_else(|| {
+            let available: Vec<_> = self.iter().map(|p| p.name.as_str()).collect();
+            ImageError::Preset(format!(
+                "unknown preset '{}' (available: {})",
+                name,
+                available.join(", ")
+            ))
+        })?;
+        Ok(preset.builder(image))
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase().replace('-', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeMethod;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_builtin_lookup() {
+        let registry = PresetRegistry::builtin();
+        assert_eq!(registry.len(), 10);
+        assert!(registry.get("edge-aware").is_some());
+        assert!(registry.get("Edge_Aware").is_some());
+        assert!(registry.get("nonexistent").is_none());
+        assert!(registry.get("astro").unwrap().protect_stars);
+        assert!(!registry.get("moderate").unwrap().protect_stars);
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let registry = PresetRegistry::from_toml_str(r#"
+            [[presets]]
+            name = "warm_portrait"
+            operations = [
+                { op = "unsharp", radius = 1.2, amount = 0.7, threshold = 8 },
+                { op = "edges", strength = 0.3, method = "prewitt" },
+            ]
+
+            [[presets]]
+            name = "deep_sky"
+            protect_stars = true
+            operations = [ { op = "unsharp", radius = 3.0, amount = 2.0, threshold = 2 } ]
+        "#).unwrap();
+
+        let preset = registry.get("warm_portrait").unwrap();
+        assert_eq!(preset.operations, vec![
+            Operation::UnsharpMask { radius: 1.2, amount: 0.7, threshold: 8 },
+            Operation::EnhanceEdges { strength: 0.3, method: EdgeMethod::Prewitt },
+        ]);
+        assert!(!preset.protect_stars);
+        assert!(registry.get("deep_sky").unwrap().protect_stars);
+        assert_eq!(preset.chroma_clamp, None);
+    }
+
+    #[test]
+    fn test_chroma_clamp() {
+        let registry = PresetRegistry::from_toml_str(r#"
+            [[presets]]
+            name = "fringe_free"
+            chroma_clamp = 2.0
+            operations = [
+                { op = "unsharp", radius = 2.0, amount = 1.0, threshold = 0 },
+                { op = "clarity", strength = 0.5, radius = 3.0 },
+            ]
+        "#).unwrap();
+        let preset = registry.get("fringe_free").unwrap();
+        assert_eq!(preset.chroma_clamp, Some(2.0));
+
+        // Every operation is clamped
+        let image = Image::from_rgb(RgbImage::from_fn(48, 32, |x, _| if x < 24 { Rgb([180, 150, 120]) } else { Rgb([70, 80, 110]) })).unwrap();
+        let expected = image.clone().sharpen()
+            .unsharp_mask(2.0, 1.0, 0).chroma_clamp(2.0)
+            .clarity(0.5, 3.0).chroma_clamp(2.0)
+            .apply().unwrap();
+        let unclamped = image.clone().sharpen().unsharp_mask(2.0, 1.0, 0).clarity(0.5, 3.0).apply().unwrap();
+        let result = preset.builder(image).apply().unwrap().into_rgb();
+        assert!(result == expected.into_rgb());
+        assert!(result != unclamped.into_rgb());
+
+        // Written back only when set
+        let json = serde_json::to_string(preset).unwrap();
+        assert!(json.contains(r#""chroma_clamp":2.0"#), "{}", json);
+        assert!(!serde_json::to_string(&Preset::new("plain", Vec::new())).unwrap().contains("chroma_clamp"));
+    }
+
+    #[test]
+    fn test_load_json() {
+        let registry = PresetRegistry::from_json_str(r#"
+            { "presets": [
+                { "name": "crisp", "description": "Crisp web output",
+                  "operations": [ { "op": "highpass", "strength": 0.4 } ] }
+            ] }
+        "#).unwrap();
+
+        let preset = registry.get("crisp").unwrap();
+        assert_eq!(preset.description.as_deref(), Some("Crisp web output"));
+        assert_eq!(preset.operations, vec![Operation::HighPassSharpen { strength: 0.4 }]);
+    }
+
+    #[test]
+    fn test_no_migrations() {
+        let registry = PresetRegistry::from_toml_str(r#"
+            [[presets]]
+            name = "current"
+            operations = [
+                { op = "unsharp", radius = 1.0, amount = 0.5, threshold = 0 },
+                { op = "edges", strength = 0.3, method = "sobel" },
+            ]
+        "#).unwrap();
+
+        assert_eq!(registry.get("current").unwrap().operations, vec![
+            Operation::UnsharpMask { radius: 1.0, amount: 0.5, threshold: 0 },
+            Operation::EnhanceEdges { strength: 0.3, method: EdgeMethod::Sobel },
+        ]);
+        assert!(registry.migrations().is_empty());
+        assert!(PresetRegistry::builtin().migrations().is_empty());
+
+        // Field names no release wrote aren't guessed at
+        assert!(PresetRegistry::from_toml_str(r#"
+            [[presets]]
+            name = "guessed"
+            operations = [{ type = "unsharp_mask", sigma = 1.0, amount = 0.5 }]
+        "#).is_err());
+    }
+
+    #[test]
+    fn test_unknown_operation_rejected() {
+        let result = PresetRegistry::from_toml_str(r#"
+            [[presets]]
+            name = "bad"
+            operations = [ { op = "blur", radius = 1.0 } ]
+        "#);
+        assert!(matches!(result, Err(ImageError::Preset(_))));
+    }
+
+    #[test]
+    fn test_builder_applies_preset() {
+        let registry = PresetRegistry::builtin();
+        let image = Image::from_rgb(RgbImage::new(32, 32)).unwrap();
+        let builder = registry.builder("moderate", image).unwrap();
+        assert_eq!(builder.operation_count(), 2);
+        assert!(builder.apply().is_ok());
+
+        let image = Image::from_rgb(RgbImage::new(32, 32)).unwrap();
+        assert!(registry.builder("missing", image).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Gaussian and Laplacian image pyramids.
+//!
+//! Each Gaussian level is the previous one blurred and halved in size. Each
+//! Laplacian level holds the detail lost between a Gaussian level and the
+//! upsampled next one, so the original image is rebuilt exactly by
+//! upsampling the smallest level and adding the details back. Scaling
+//! Laplacian levels before reconstructing boosts detail at that scale, the
+//! basis of multi-scale sharpening and halo-free local contrast.
+
+use crate::context::Context;
+use crate::utils::gaussian_blur;
+use crate::{Image, ImageError, Result};
+use image::imageops::{self, FilterType};
+use image::{Rgb32FImage, RgbImage};
+use crate::par::*;
+
+/// Blur applied before each downsample, in pixels.
+const DOWNSAMPLE_SIGMA: f32 = 1.0;
+
+/// A Gaussian/Laplacian pyramid built by [`Image::pyramid`].
+///
+/// # Example
+/// ```
+/// # use sharpy::Image;
+/// # let image = Image::from_rgb(image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 128]))).unwrap();
+/// let mut pyramid = image.pyramid(4).unwrap();
+///
+/// // Boost fine and medium detail, leaving coarse tones alone
+/// pyramid.scale_detail(0, 1.5);
+/// pyramid.scale_detail(1, 1.2);
+/// let sharpened = pyramid.reconstruct();
+///
+/// // Small levels make fast previews
+/// let thumbnail = pyramid.gaussian(3);
+/// assert_eq!(thumbnail.dimensions(), (8, 8));
+/// ```
+#[derive(Clone)]
+pub struct Pyramid {
+    gaussian: Vec<Image>,
+    laplacian: Vec<Rgb32FImage>,
+}
+
+impl Pyramid {
+    pub(crate) fn build(image: &Image, levels: usize) -> Result<Self> {
+        let (width, height) = image.dimensions();
+        let max_levels = (width.max(height) as f32).log2().floor() as usize + 1;
+        if levels == 0 || levels > max_levels {
+            return Err(ImageError::InvalidParameter {
+                param: "levels".to_string(),
+                value: format!("{} (1-{} for {}x{})", levels, max_levels, width, height),
+            });
+        }
+
+        let mut gaussian = vec![image.clone()];
+        for _ in 1..levels {
+            let previous = gaussian.last().expect("at least one level").data.get_ref();
+            gaussian.push(image.with_pixels(downsample(previous)));
+        }
+
+        let laplacian = gaussian.windows(2)
+            .map(|pair| {
+                let fine = pair[0].data.get_ref();
+                let coarse = upsample(&to_f32(pair[1].data.get_ref()), fine.width(), fine.height());
+                difference(fine, &coarse)
+            })
+            .collect();
+
+        Ok(Self { gaussian, laplacian })
+    }
+
+    /// Number of levels, including the full-size image.
+    pub fn levels(&self) -> usize {
+        self.gaussian.len()
+    }
+
+    /// Gaussian level `level`; level 0 is the original image.
+    ///
+    /// # Panics
+    /// Panics if `level >= self.levels()`.
+    pub fn gaussian(&self, level: usize) -> &Image {
+        &self.gaussian[level]
+    }
+
+    /// Detail (signed, in 0-255 units) between Gaussian levels `level` and
+    /// `level + 1`.
+    ///
+    /// # Panics
+    /// Panics if `level >= self.levels() - 1`.
+    pub fn laplacian(&self, level: usize) -> &Rgb32FImage {
+        &self.laplacian[level]
+    }
+
+    /// Mutable access to a Laplacian level for custom detail processing.
+    ///
+    /// # Panics
+    /// Panics if `level >= self.levels() - 1`.
+    pub fn laplacian_mut(&mut self, level: usize) -> &mut Rgb32FImage {
+        &mut self.laplacian[level]
+    }
+
+    /// Multiplies the detail at Laplacian level `level` by `factor`.
+    ///
+    /// # Panics
+    /// Panics if `level >= self.levels() - 1`.
+    pub fn scale_detail(&mut self, level: usize, factor: f32) {
+        self.laplacian[level].par_iter_mut().for_each(|value| *value *= factor);
+    }
+
+    /// Rebuilds the full-size image from the smallest Gaussian level and the
+    /// Laplacian levels. Without modifications this reproduces the original.
+    pub fn reconstruct(&self) -> Image {
+        let coarsest = self.gaussian.last().expect("at least one level");
+        let mut current = to_f32(coarsest.data.get_ref());
+        for detail in self.laplacian.iter().rev() {
+            current = upsample(&current, detail.width(), detail.height());
+            current.par_iter_mut()
+                .zip(detail.par_iter())
+                .for_each(|(value, detail)| *value += detail);
+        }
+
+        let pixels: Vec<u8> = current.par_iter()
+            .map(|value| value.round().clamp(0.0, 255.0) as u8)
+            .collect();
+        let (width, height) = current.dimensions();
+        let img = RgbImage::from_raw(width, height, pixels).expect("buffer matches dimensions");
+        self.gaussian[0].with_pixels(img)
+    }
+}
+
+fn downsample(img: &RgbImage) -> RgbImage {
+    let blurred = gaussian_blur(img, DOWNSAMPLE_SIGMA, &Context::default());
+    let width = img.width().div_ceil(2);
+    let height = img.height().div_ceil(2);
+    RgbImage::from_fn(width, height, |x, y| *blurred.get_pixel(x * 2, y * 2))
+}
+
+fn upsample(img: &Rgb32FImage, width: u32, height: u32) -> Rgb32FImage {
+    imageops::resize(img, width, height, FilterType::Triangle)
+}
+
+fn to_f32(img: &RgbImage) -> Rgb32FImage {
+    let pixels = img.as_raw().iter().map(|&value| value as f32).collect();
+    Rgb32FImage::from_raw(img.width(), img.height(), pixels).expect("buffer matches dimensions")
+}
+
+fn difference(fine: &RgbImage, coarse: &Rgb32FImage) -> Rgb32FImage {
+    let pixels = fine.as_raw().par_iter()
+        .zip(coarse.par_iter())
+        .map(|(&value, coarse)| value as f32 - coarse)
+        .collect();
+    Rgb32FImage::from_raw(fine.width(), fine.height(), pixels).expect("buffer matches dimensions")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn create_test_image() -> Image {
+        let img = RgbImage::from_fn(50, 37, |x, y| {
+            let value = if (x / 5 + y / 5) % 2 == 0 { 40 } else { 210 };
+            Rgb([value, (x * 5) as u8, (y * 6) as u8])
+        });
+        Image::from_rgb(img).unwrap()
+    }
+
+    #[test]
+    fn test_level_sizes() {
+        let pyramid = create_test_image().pyramid(4).unwrap();
+        assert_eq!(pyramid.levels(), 4);
+        assert_eq!(pyramid.gaussian(1).dimensions(), (25, 19));
+        assert_eq!(pyramid.gaussian(3).dimensions(), (7, 5));
+        assert_eq!(pyramid.laplacian(2).dimensions(), (13, 10));
+    }
+
+    #[test]
+    fn test_reconstruct_is_exact() {
+        let image = create_test_image();
+        let original = image.clone().into_rgb();
+        let reconstructed = image.pyramid(5).unwrap().reconstruct().into_rgb();
+        assert_eq!(reconstructed, original);
+    }
+
+    #[test]
+    fn test_scale_detail_sharpens() {
+        let image = create_test_image();
+        let mut pyramid = image.pyramid(3).unwrap();
+        pyramid.scale_detail(0, 2.0);
+        let sharpened = pyramid.reconstruct();
+        let comparison = sharpened.compare(&image).unwrap();
+        assert!(comparison.halo > 0.0);
+    }
+
+    #[test]
+    fn test_invalid_levels() {
+        let image = create_test_image();
+        assert!(image.pyramid(0).is_err());
+        assert!(image.pyramid(6).is_ok());
+        assert!(image.pyramid(7).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Camera RAW files, decoded with [rawler](https://docs.rs/rawler) (the
+//! `raw` feature).
+//!
+//! RAW sensor data is demosaiced, white balanced and converted to sRGB at
+//! 16 bits per channel, so capture sharpening runs before any 8-bit
+//! rounding and the result can go on to a regular editor as a 16-bit TIFF.
+
+use crate::{Image, ImageError, Limits, Result};
+use image::Rgb;
+use rawler::imgop::develop::RawDevelop;
+use std::path::Path;
+
+/// File extensions of the RAW formats rawler reads, in lowercase.
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "ari", "arw", "cr2", "cr3", "crm", "crw", "dcr", "dcs", "dng", "erf", "fff", "iiq", "kdc",
+    "mdc", "mef", "mos", "mrw", "nef", "nrw", "orf", "ori", "pef", "raf", "raw", "rw2", "rwl", "srw", "x3f",
+];
+
+impl Image<Rgb<u16>> {
+    /// Decodes a camera RAW file (CR2, NEF, ARW, DNG, ...) to a demosaiced
+    /// 16-bit sRGB image, failing with [`ImageError::Raw`] for files rawler
+    /// can't read.
+    ///
+    /// Images exceeding the default [`Limits`] are rejected with
+    /// [`ImageError::LimitExceeded`] after decoding. Orientation, lens
+    /// corrections and tone curves are left to the editor that opens the
+    /// result.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sharpy::Image;
+    ///
+    /// # fn main() -> sharpy::Result<()> {
+    /// let image = Image::load_raw("IMG_0042.CR2")?;
+    /// image.unsharp_mask(0.6, 0.8, 1)?.save("IMG_0042.tif")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_raw<Q: AsRef<Path>>(path: Q) -> Result<Self> {
+        let path = path.as_ref();
+        let load = || {
+            // Opening first reports a missing file as ImageError::Io
+            std::fs::File::open(path)?;
+            let raw = rawler::decode_file(path).map_err(|e| ImageError::Raw(e.to_string()))?;
+            // rawler panics on some sensor layouts it doesn't support
+            let developed = std::panic::catch_unwind(|| RawDevelop::default().develop_intermediate(&raw))
+                .map_err(|_| ImageError::Raw("unsupported sensor layout".to_string()))?
+                .map_err(|e| ImageError::Raw(e.to_string()))?;
+            let img = developed.to_dynamic_image()
+                .ok_or_else(|| ImageError::Raw("no image data".to_string()))?;
+            Self::from_buffer_with_limits(img.into_rgb16(), &Limits::default())
+        };
+        load().map_err(|e| e.in_file(path))
+    }
+}
+
+/// Whether `path` has the extension of a RAW format.
+pub fn is_raw_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| RAW_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_raw() {
+        assert!(is_raw_path(Path::new("shoot/IMG_0042.CR2")));
+        assert!(!is_raw_path(Path::new("shoot/IMG_0042.jpg")));
+
+        let missing = Image::load_raw("missing.nef").err().unwrap();
+        assert!(matches!(missing.root(), ImageError::Io(_)));
+        assert_eq!(missing.path(), Some(Path::new("missing.nef")));
+        let path = std::env::temp_dir().join(format!("sharpy_raw_{}.dng", std::process::id()));
+        std::fs::write(&path, b"not a raw file").unwrap();
+        let result = Image::load_raw(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result.as_ref().map_err(ImageError::root), Err(ImageError::Raw(_))), "{:?}", result.err());
+    }
+}
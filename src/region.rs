@@ -0,0 +1,68 @@
+//! Rectangular regions of an image.
+
+use crate::{ImageError, Result};
+use std::fmt;
+
+/// A rectangle in pixel coordinates, `x`/`y` being the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Grows the rectangle by `margin` on every side, clipped to an image of
+    /// the given size.
+    pub(crate) fn expand(&self, margin: u32, width: u32, height: u32) -> Rect {
+        let (x, y) = (self.x.saturating_sub(margin), self.y.saturating_sub(margin));
+        let right = self.x.saturating_add(self.width).saturating_add(margin).min(width);
+        let bottom = self.y.saturating_add(self.height).saturating_add(margin).min(height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+    
+    /// Fails unless the rectangle is non-empty and lies within an image of
+    /// the given size.
+    pub(crate) fn check_within(&self, width: u32, height: u32) -> Result<()> {
+        let fits = |start: u32, len: u32, max: u32| len > 0 && start.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(self.x, self.width, width) || !fits(self.y, self.height, height) {
+            return Err(ImageError::InvalidParameter {
+                param: "rect".to_string(),
+                value: format!("{} outside {}x{} image", self, width, height),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_within() {
+        assert!(Rect::new(0, 0, 8, 8).check_within(8, 8).is_ok());
+        assert!(Rect::new(4, 2, 4, 6).check_within(8, 8).is_ok());
+        assert!(Rect::new(5, 0, 4, 4).check_within(8, 8).is_err());
+        assert!(Rect::new(0, 0, 0, 4).check_within(8, 8).is_err());
+        assert!(Rect::new(u32::MAX, 0, 2, 2).check_within(8, 8).is_err());
+        assert_eq!(Rect::new(1, 2, 3, 4).to_string(), "3x4+1+2");
+    }
+    
+    #[test]
+    fn test_expand() {
+        assert_eq!(Rect::new(10, 10, 4, 4).expand(3, 100, 100), Rect::new(7, 7, 10, 10));
+        assert_eq!(Rect::new(1, 2, 4, 4).expand(3, 6, 7), Rect::new(0, 0, 6, 7));
+    }
+}
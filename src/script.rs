@@ -0,0 +1,235 @@
+//! Parser for graph scripts of named intermediate buffers.
+//!
+//! See [`Graph::parse`](crate::Graph::parse) for the syntax.
+
+use crate::migrate::{self, Migration, Rules};
+use crate::{EdgeMethod, Graph, ImageError, NodeId, Operation, Result};
+
+/// Name of the buffer holding the input image.
+const SOURCE: &str = "src";
+/// Name of the buffer used as the graph output when present.
+const OUTPUT: &str = "out";
+
+pub(crate) fn parse(script: &str) -> Result<(Graph, Vec<Migration>)> {
+    parse_with_rules(script, &migrate::RULES)
+}
+
+/// Parses `script`, migrating its operations with `rules`.
+fn parse_with_rules(script: &str, rules: &Rules) -> Result<(Graph, Vec<Migration>)> {
+    let mut graph = Graph::new();
+    let mut migrations = Vec::new();
+    let mut last = None;
+
+    for (line_index, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        for statement in line.split(';') {
+            if statement.trim().is_empty() {
+                continue;
+            }
+            let node = parse_statement(&mut graph, statement, rules, &mut migrations)
+                .map_err(|msg| ImageError::Parse(format!("line {}: {}", line_index + 1, msg)))?;
+            last = Some(node);
+        }
+    }
+
+    let output = graph.node(OUTPUT)
+        .or(last)
+        .ok_or_else(|| ImageError::Parse("script defines no buffers".to_string()))?;
+    graph.set_output(output);
+
+    Ok((graph, migrations))
+}
+
+fn parse_statement(graph: &mut Graph, statement: &str, rules: &Rules, migrations: &mut Vec<Migration>) -> std::result::Result<NodeId, String> {
+    let (name, call) = statement.split_once('=')
+        .ok_or_else(|| format!("expected 'name = call(...)', found '{}'", statement.trim()))?;
+    let name = name.trim();
+
+    if !is_identifier(name) {
+        return Err(format!("invalid buffer name '{}'", name));
+    }
+    if name == SOURCE || graph.node(name).is_some() {
+        return Err(format!("buffer '{}' is already defined", name));
+    }
+
+    let call = call.trim();
+    let (function, args) = call.split_once('(')
+        .and_then(|(function, rest)| Some((function.trim(), rest.strip_suffix(')')?)))
+        .ok_or_else(|| format!("expected 'function(args)', found '{}'", call))?;
+
+    let args: Vec<&str> = if args.trim().is_empty() {
+        Vec::new()
+    } else {
+        args.split(',').map(str::trim).collect()
+    };
+
+    let node = build_node(graph, &function.to_lowercase(), &args, rules, migrations)?;
+    graph.set_name(name, node);
+    Ok(node)
+}
+
+fn build_node(graph: &mut Graph, function: &str, args: &[&str], rules: &Rules, migrations: &mut Vec<Migration>) -> std::result::Result<NodeId, String> {
+    // Renamed operations keep the name they were called by, so migrating
+    // them reports the rename
+    match rules.canonical_name(function).unwrap_or(function) {
+        "unsharp" | "highpass" | "edges" | "clarity" | "adaptive_unsharp" | "text" | "descreen" | "median" | "denoise" | "smart_enhance" | "frequency_separation" => {
+            let (input, params) = split_input(graph, args);
+            let (text, applied) = rules.migrate_text(&format!("{}:{}", function, params.join(":")));
+            migrations.extend(applied);
+            let operation: Operation = text.parse().map_err(|e: ImageError| e.to_string())?;
+            Ok(graph.operation(input, operation))
+        }
+        "gaussian" => {
+            let (input, params) = split_input(graph, args);
+            expect_count(function, params, 1, "gaussian([input], radius)")?;
+            Ok(graph.blur(input, parse_number(params[0])?))
+        }
+        "edge_mask" => {
+            let (input, params) = split_input(graph, args);
+            expect_count(function, params, 1, "edge_mask([input], method)")?;
+            let method: EdgeMethod = params[0].parse().map_err(|e: ImageError| e.to_string())?;
+            Ok(graph.edge_mask(input, method))
+        }
+        "structure_mask" => {
+            let (input, params) = split_input(graph, args);
+            expect_count(function, params, 0, "structure_mask([input])")?;
+            Ok(graph.structure_mask(input))
+        }
+        "blend" => {
+            expect_count(function, args, 3, "blend(base, overlay, opacity)")?;
+            let base = buffer(graph, args[0])?;
+            let overlay = buffer(graph, args[1])?;
+            Ok(graph.blend(base, overlay, parse_number(args[2])?))
+        }
+        "mask_blend" => {
+            expect_count(function, args, 3, "mask_blend(base, overlay, mask)")?;
+            let base = buffer(graph, args[0])?;
+            let overlay = buffer(graph, args[1])?;
+            let mask = buffer(graph, args[2])?;
+            Ok(graph.mask_blend(base, overlay, mask))
+        }
+        "max" | "average" => {
+            if args.is_empty() {
+                return Err(format!("'{}' expects at least one buffer", function));
+            }
+            let inputs = args.iter()
+                .map(|arg| buffer(graph, arg))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(if function == "max" { graph.max(&inputs) } else { graph.average(&inputs) })
+        }
+        _ => Err(format!("unknown function '{}'", function)),
+    }
+}
+
+/// Splits off a leading buffer argument, defaulting the input to `src`.
+fn split_input<'a>(graph: &Graph, args: &'a [&'a str]) -> (NodeId, &'a [&'a str]) {
+    match args.first().and_then(|arg| graph.node(arg)) {
+        Some(node) => (node, &args[1..]),
+        None => (graph.source(), args),
+    }
+}
+
+fn buffer(graph: &Graph, name: &str) -> std::result::Result<NodeId, String> {
+    graph.node(name).ok_or_else(|| format!("unknown buffer '{}'", name))
+}
+
+fn parse_number(value: &str) -> std::result::Result<f32, String> {
+    value.parse().map_err(|_| format!("invalid number '{}'", value))
+}
+
+fn expect_count(function: &str, args: &[&str], count: usize, usage: &str) -> std::result::Result<(), String> {
+    if args.len() != count {
+        return Err(format!("'{}' expects {} argument(s): {}", function, count, usage));
+    }
+    Ok(())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Graph, Image, Pipeline};
+    use image::{Rgb, RgbImage};
+
+    fn create_test_image() -> Image {
+        let img = RgbImage::from_fn(48, 48, |x, y| {
+            let value = if (x / 6 + y / 6) % 2 == 0 { 70 } else { 180 };
+            Rgb([value, value, value])
+        });
+        Image::from_rgb(img).unwrap()
+    }
+
+    #[test]
+    fn test_named_buffers() {
+        let graph = Graph::parse("
+            # soften, then bring detail back
+            blur1 = gaussian(2.0)
+            sharp = unsharp(src, 1.0, 1.0, 0); mask = edge_mask(sobel)
+            out = mask_blend(blur1, sharp, mask)
+        ").unwrap();
+
+        assert_eq!(graph.output(), graph.node("out"));
+        assert!(graph.node("blur1").is_some());
+        assert!(graph.apply(create_test_image()).is_ok());
+    }
+
+    #[test]
+    fn test_operation_chain_matches_pipeline() {
+        let graph = Graph::parse("a = unsharp(1, 1, 0); b = clarity(a, 0.5, 2)").unwrap();
+        let pipeline = Pipeline::parse("unsharp:1:1:0,clarity:0.5:2").unwrap();
+
+        let expected = pipeline.apply(create_test_image()).unwrap().into_rgb();
+        assert_eq!(graph.apply(create_test_image()).unwrap().into_rgb(), expected);
+    }
+
+    #[test]
+    fn test_migrations_reported() {
+        let (graph, migrations) = Graph::parse_with_migrations("a = unsharp(1, 1, 0); b = edges(a, 0.5, sobel)").unwrap();
+        assert_eq!(graph.output(), graph.node("b"));
+        assert!(migrations.is_empty());
+
+        // A renamed operation missing a later parameter reports both changes
+        let (graph, migrations) = super::parse_with_rules("a = sharpen(1, 1); b = edges(a, 0.5, sobel)", &crate::migrate::EXAMPLE).unwrap();
+        assert_eq!(graph.output(), graph.node("b"));
+        let changes: Vec<_> = migrations.iter().map(|m| (m.from.as_str(), m.to.as_str())).collect();
+        assert_eq!(changes, [("sharpen:1:1", "unsharp:1:1"), ("unsharp:1:1", "unsharp:1:1:0")]);
+        let expected = Graph::parse("a = unsharp(1, 1, 0); b = edges(a, 0.5, sobel)").unwrap();
+        assert_eq!(graph.apply(create_test_image()).unwrap().into_rgb(), expected.apply(create_test_image()).unwrap().into_rgb());
+    }
+
+    #[test]
+    fn test_structure_mask() {
+        let graph = Graph::parse("sharp = unsharp(1, 1.5, 0); mask = structure_mask(); out = mask_blend(src, sharp, mask)").unwrap();
+        let original = create_test_image().into_rgb();
+        let result = graph.apply(create_test_image()).unwrap().into_rgb();
+        // Flat interior pixels keep the original, edges are sharpened
+        assert_eq!(result.get_pixel(3, 3), original.get_pixel(3, 3));
+        assert_ne!(result, original);
+        assert!(Graph::parse("mask = structure_mask(src, 2)").is_err());
+    }
+
+    #[test]
+    fn test_blend_endpoints() {
+        let graph = Graph::parse("blur1 = gaussian(2.0); out = blend(src, blur1, 0.0)").unwrap();
+        let original = create_test_image().into_rgb();
+        assert_eq!(graph.apply(create_test_image()).unwrap().into_rgb(), original);
+    }
+
+    #[test]
+    fn test_script_errors() {
+        assert!(Graph::parse("").is_err());
+        assert!(Graph::parse("out = blend(src, missing, 0.5)").is_err());
+        assert!(Graph::parse("src = gaussian(1.0)").is_err());
+        assert!(Graph::parse("a = gaussian(1.0); a = gaussian(2.0)").is_err());
+        assert!(Graph::parse("out = sharpen(1.0)").is_err());
+        assert!(Graph::parse("out gaussian(1.0)").is_err());
+        assert!(Graph::parse("out = unsharp(1.0, 1.0)").is_err());
+
+        let err = Graph::parse("a = gaussian(1.0)\nb = nope(a)").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+}
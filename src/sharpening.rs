@@ -1,60 +1,594 @@
-use crate::{Image, Result};
+use crate::analysis;
+use crate::context::Context;
+use crate::mask;
+use crate::{Image, ImageError, Operation, Result, ValidationConfig};
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample, unit_scale};
 use crate::utils::{
-    EdgeMethod, gaussian_blur, apply_convolution, get_high_pass_kernel,
-    apply_edge_detection, blend_images, calculate_luminance,
+    Boundary, EdgeMethod, Luminance, apply_convolution, get_high_pass_kernel,
+    apply_edge_detection, blur_plane, gaussian_blur,
 };
-use rayon::prelude::*;
+use crate::blend::{BlendMode, blend_images};
+use image::GrayImage;
 use std::sync::Arc;
+use crate::par::*;
+
+/// Name of unsharp masking with an amount map in progress events, errors
+/// and validation reports.
+pub(crate) const AMOUNT_MAP_NAME: &str = "Amount Map Unsharp";
+/// Amount-map level that sharpens with amount 1, so white is the default
+/// maximum amount of 5.
+pub(crate) const AMOUNT_MAP_UNIT: f32 = 51.0;
 
 /// Applies unsharp masking to sharpen an image.
 /// 
 /// # Parameters
 /// - `radius`: Blur radius for the mask (0.5-10.0)
 /// - `amount`: Strength of sharpening (0.0-5.0)
-/// - `threshold`: Minimum difference to apply sharpening (0-255)
-pub fn unsharp_mask(mut image: Image, radius: f32, amount: f32, threshold: u8) -> Result<Image> {
-    // Keep original reference before mutating
-    let original = Arc::new(image.data.get_ref().clone());
-    let blurred = Arc::new(gaussian_blur(&original, radius));
+/// - `threshold`: Minimum difference to apply sharpening (0-255, in 8-bit units)
+pub fn unsharp_mask<P: SharpenPixel>(
+    image: &Image<P>,
+    radius: f32,
+    amount: f32,
+    threshold: u8,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let threshold = threshold as f32 * unit_scale::<P>();
     
-    let buffer = image.data.get_mut();
+    // Two blur passes plus the combine pass
+    ctx.start(height as usize * 3);
+    let blurred = ctx.gaussian_blur(original, radius);
+    ctx.advance(height as usize * 2);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
     
     buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
         for (x, _, pixel) in row {
-            let orig_pixel = original.get_pixel(x, y);
-            let blur_pixel = blurred.get_pixel(x, y);
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let blur_pixel = blurred.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
             
-            for i in 0..3 {
-                let orig_val = orig_pixel[i] as f32;
-                let blur_val = blur_pixel[i] as f32;
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let blur_val = sample::<P>(blur_pixel[i]);
                 let diff = orig_val - blur_val;
                 
-                if diff.abs() > threshold as f32 {
+                if diff.abs() > threshold {
                     let sharpened = orig_val + (diff * amount);
-                    pixel[i] = sharpened.round().clamp(0.0, 255.0) as u8;
+                    pixel[i] = ctx.store::<P>(orig_val, sharpened);
+                } else {
+                    pixel[i] = orig_pixel[i];
+                }
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    ctx.release(blurred);
+    Ok(image.with_pixels(buffer))
+}
+
+/// Applies unsharp masking with the amount of each pixel read from
+/// `amount_map`, e.g. a saliency map or a subject matte computed elsewhere.
+/// 
+/// Each map level of [`AMOUNT_MAP_UNIT`] is an amount of 1, so black leaves
+/// a pixel unsharpened and white sharpens it with amount 5. Fails with
+/// [`ImageError::InvalidDimensions`](crate::ImageError::InvalidDimensions)
+/// unless the map has the image's dimensions.
+/// 
+/// # Parameters
+/// - `radius`: Blur radius for the mask (0.5-10.0)
+/// - `amount_map`: Amount of each pixel, in levels of [`AMOUNT_MAP_UNIT`]
+/// - `threshold`: Minimum difference to apply sharpening (0-255, in 8-bit units)
+pub fn unsharp_mask_with_amount_map<P: SharpenPixel>(
+    image: &Image<P>,
+    radius: f32,
+    amount_map: &GrayImage,
+    threshold: u8,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    if amount_map.dimensions() != (width, height) {
+        let (width, height) = amount_map.dimensions();
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+    let threshold = threshold as f32 * unit_scale::<P>();
+    
+    // Two blur passes plus the combine pass
+    ctx.start(height as usize * 3);
+    let blurred = ctx.gaussian_blur(original, radius);
+    ctx.advance(height as usize * 2);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let blur_pixel = blurred.get_pixel(x, y).channels();
+            let amount = amount_map.get_pixel(x, y)[0] as f32 / AMOUNT_MAP_UNIT;
+            let pixel = pixel.channels_mut();
+    
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let diff = orig_val - sample::<P>(blur_pixel[i]);
+    
+                if amount > 0.0 && diff.abs() > threshold {
+                    pixel[i] = ctx.store::<P>(orig_val, orig_val + diff * amount);
                 } else {
                     pixel[i] = orig_pixel[i];
                 }
             }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    ctx.release(blurred);
+    Ok(image.with_pixels(buffer))
+}
+
+/// Local contrast, in 8-bit levels, that [`relative_unsharp_mask`] assumes
+/// at least, so a flat region's quantization steps aren't measured against
+/// a contrast of nearly zero.
+const MIN_CONTRAST: f32 = 4.0;
+
+/// Applies unsharp masking with a threshold relative to the local contrast.
+/// 
+/// A channel is sharpened where its difference from the blur exceeds
+/// `threshold` percent of the local contrast: the channel's range (maximum
+/// minus minimum) within `radius` pixels, at least `MIN_CONTRAST`. An
+/// absolute threshold that holds back noise in the shadows lets it through
+/// in the highlights, and one that holds it back in the highlights swallows
+/// real detail in the shadows; a relative one treats both alike.
+/// 
+/// # Parameters
+/// - `radius`: Blur radius for the mask (0.5-10.0)
+/// - `amount`: Strength of sharpening (0.0-5.0)
+/// - `threshold`: Minimum local contrast to apply sharpening, in percent
+///   (0-100)
+pub fn relative_unsharp_mask<P: SharpenPixel>(
+    image: &Image<P>,
+    radius: f32,
+    amount: f32,
+    threshold: f32,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let share = threshold / 100.0;
+    let floor = MIN_CONTRAST * unit_scale::<P>();
+    
+    // Two blur passes, the local ranges, then the combine pass
+    ctx.start(height as usize * 4);
+    let blurred = ctx.gaussian_blur(original, radius);
+    ctx.advance(height as usize * 2);
+    let contrast: Vec<Vec<f32>> = (0..P::COLOR_CHANNELS).map(|i| {
+        let plane: Vec<f32> = original.pixels().map(|pixel| sample::<P>(pixel.channels()[i])).collect();
+        let (low, high) = local_range(&plane, width, height, radius.ceil() as u32, ctx.boundary());
+        low.iter().zip(&high).map(|(low, high)| (high - low).max(floor)).collect()
+    }).collect();
+    ctx.advance(height as usize);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let index = (y * width + x) as usize;
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let blur_pixel = blurred.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let blur_val = sample::<P>(blur_pixel[i]);
+                let diff = orig_val - blur_val;
+                pixel[i] = if diff.abs() > share * contrast[i][index] {
+                    ctx.store::<P>(orig_val, orig_val + diff * amount)
+                } else {
+                    orig_pixel[i]
+                };
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    ctx.release(blurred);
+    Ok(image.with_pixels(buffer))
+}
+
+/// Number of radii, from the smallest to the largest, that
+/// [`adaptive_unsharp_mask`] chooses between.
+const SCALES: usize = 4;
+/// Size, relative to the largest radius, of the region whose detail scale
+/// decides a pixel's radius.
+const SCALE_REGION: f32 = 2.0;
+
+/// Radii [`adaptive_unsharp_mask`] blurs at: `SCALES` geometrically spaced
+/// from `min_radius` to `max_radius` (one if they're equal), then one more
+/// step up that bounds the coarsest detail band.
+pub(crate) fn scale_radii(min_radius: f32, max_radius: f32) -> Vec<f32> {
+    if max_radius <= min_radius {
+        return vec![min_radius];
+    }
+    let step = (max_radius / min_radius).powf(1.0 / (SCALES - 1) as f32);
+    (0..=SCALES).map(|k| min_radius * step.powi(k as i32)).collect()
+}
+
+/// How far the region deciding a pixel's radius extends, in pixels.
+pub(crate) fn scale_region_reach(max_radius: f32) -> u32 {
+    (SCALE_REGION * max_radius * 3.0).ceil() as u32
+}
+
+/// Applies unsharp masking with a radius that follows the detail around
+/// each pixel: fine texture is sharpened with a small radius and soft,
+/// large structures with a larger one.
+/// 
+/// Blurs at radii from `min_radius` to `max_radius` split the luminance
+/// into detail bands (differences of Gaussians, so each band responds to
+/// structures of its own size). Each pixel's mask blends the blurs,
+/// weighted by the energy of their bands in the surrounding region.
+/// 
+/// # Parameters
+/// - `min_radius`, `max_radius`: Range of blur radii (0.5-10.0)
+/// - `amount`: Strength of sharpening (0.0-5.0)
+/// - `threshold`: Minimum difference to apply sharpening (0-255, in 8-bit units)
+pub fn adaptive_unsharp_mask<P: SharpenPixel>(
+    image: &Image<P>,
+    min_radius: f32,
+    max_radius: f32,
+    amount: f32,
+    threshold: u8,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let radii = scale_radii(min_radius, max_radius);
+    if radii.len() == 1 {
+        return unsharp_mask(image, min_radius, amount, threshold, ctx);
+    }
+    let original = image.data.get_ref();
+    let luminance = ctx.luminance();
+    let (width, height) = original.dimensions();
+    let threshold = threshold as f32 * unit_scale::<P>();
+    
+    // Two passes per blur, the band energies, then the combine pass
+    ctx.start(height as usize * (2 * radii.len() + 2));
+    let blurs: Vec<Arc<Buffer<P>>> = radii.iter()
+        .map(|&radius| {
+            let blurred = ctx.gaussian_blur(original, radius);
+            ctx.advance(height as usize * 2);
+            blurred
+        })
+        .collect();
+    let luma: Vec<Vec<f32>> = blurs.iter()
+        .map(|blurred| blurred.pixels().map(|pixel| luminance.of(pixel)).collect())
+        .collect();
+    let energies: Vec<Vec<f32>> = luma.windows(2)
+        .map(|pair| {
+            let band: Vec<f32> = pair[0].iter().zip(&pair[1]).map(|(fine, coarse)| (fine - coarse).powi(2)).collect();
+            blur_plane(&band, width, height, SCALE_REGION * max_radius, ctx.boundary())
+        })
+        .collect();
+    ctx.advance(height as usize);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let index = (y * width + x) as usize;
+            // Squaring favors the dominant band; flat areas weigh all alike
+            let weights: Vec<f32> = energies.iter().map(|energy| energy[index].powi(2)).collect();
+            let total: f32 = weights.iter().sum();
+            let weight = |scale: usize| if total > 0.0 { weights[scale] / total } else { 1.0 / weights.len() as f32 };
+            
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let blur_val: f32 = (0..energies.len())
+                    .map(|scale| weight(scale) * sample::<P>(blurs[scale].get_pixel(x, y).channels()[i]))
+                    .sum();
+                let diff = orig_val - blur_val;
+                
+                if diff.abs() > threshold {
+                    pixel[i] = ctx.store::<P>(orig_val, orig_val + diff * amount);
+                } else {
+                    pixel[i] = orig_pixel[i];
+                }
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    for blurred in blurs {
+        ctx.release(blurred);
+    }
+    Ok(image.with_pixels(buffer))
+}
+
+/// Noise, as a standard deviation in 8-bit levels, below which
+/// [`smart_enhance`] sharpens without denoising first.
+const SMART_NOISE_FLOOR: f64 = 1.5;
+/// Patch and search radii of the non-local means run by [`smart_enhance`].
+pub(crate) const SMART_PATCH_RADIUS: u32 = 1;
+pub(crate) const SMART_SEARCH_RADIUS: u32 = 5;
+/// Largest unsharp mask radius [`smart_operations`] picks.
+pub(crate) const SMART_MAX_RADIUS: f32 = 3.0;
+/// Share of the noise left by non-local means of the strength
+/// [`smart_operations`] matches to it, measured on photos under noise of
+/// 2 to 24 levels.
+const SMART_DENOISED_NOISE: f32 = 0.2;
+
+/// The operations a smart enhancement of `img` runs: sharpening as far as
+/// the image's noise allows, denoising it first if needed.
+/// 
+/// The noise is estimated from the luminance and, above
+/// `SMART_NOISE_FLOOR`, removed with non-local means of a matching
+/// strength, which keeps edges. The unsharp mask that follows takes its
+/// radius from the blur of the original and its amount and threshold from
+/// the noise expected after denoising, as [`Analysis::suggest`](crate::Analysis::suggest)
+/// would, with the amount scaled by `level`. Derived parameters are kept
+/// within `config`.
+/// 
+/// The builder resolves smart enhancements on the whole image before any
+/// region crop or preview downscale, which would estimate differently.
+pub(crate) fn smart_operations<P: SharpenPixel>(
+    img: &Buffer<P>,
+    level: f32,
+    luminance: Luminance,
+    config: &ValidationConfig,
+) -> Vec<Operation> {
+    let estimate = analysis::analyze(img, luminance);
+    let mut operations = Vec::new();
+    let mut noise = estimate.noise as f32;
+    if estimate.noise > SMART_NOISE_FLOOR {
+        // Strength 1 suits noise of about 10 levels
+        let strength = (noise / 10.0).clamp(0.1, 3.0).min(config.max_strength);
+        operations.push(Operation::Denoise {
+            strength,
+            patch_radius: SMART_PATCH_RADIUS,
+            search_radius: SMART_SEARCH_RADIUS,
+        });
+        noise *= SMART_DENOISED_NOISE;
+    }
+
+    let blur = estimate.blur as f32;
+    let radius = (0.6 + 2.4 * blur).clamp(0.5, SMART_MAX_RADIUS).min(config.max_radius);
+    let amount = (level * (0.5 + 1.5 * blur) / (1.0 + noise / 4.0)).min(config.max_amount);
+    let threshold = (noise * 2.0).round().clamp(0.0, 20.0) as u8;
+    operations.push(Operation::UnsharpMask { radius, amount, threshold });
+    operations
+}
+
+/// Local contrast, in 8-bit levels, below which [`text_sharpen`] treats a
+/// region as background, such as paper grain or compression noise, and
+/// leaves it unchanged. Full sharpening starts at twice this.
+const BACKGROUND_CONTRAST: f32 = 24.0;
+
+/// Sharpens text and line art in scans and screenshots without ringing
+/// around glyphs.
+/// 
+/// The detail is the luminance minus a blur as wide as the strokes, so thin
+/// strokes respond fully while paper shading doesn't. The sharpened
+/// luminance is clamped to the darkest and lightest values within a stroke
+/// width of each pixel, so edges steepen without the dark and light halos
+/// of an unsharp mask. Regions whose contrast stays below
+/// `BACKGROUND_CONTRAST` are background and are left alone, so paper
+/// texture and noise aren't amplified.
+/// 
+/// # Parameters
+/// - `stroke_width`: Typical stroke width in pixels (0.5-10.0)
+/// - `strength`: Sharpening strength (0.0-3.0)
+pub fn text_sharpen<P: SharpenPixel>(image: &Image<P>, stroke_width: f32, strength: f32, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let luminance = ctx.luminance();
+    let (width, height) = original.dimensions();
+    let floor = BACKGROUND_CONTRAST * unit_scale::<P>();
+    
+    // Two blur passes, the local range, then the combine pass
+    ctx.start(height as usize * 4);
+    let blurred = ctx.gaussian_blur(original, stroke_width);
+    ctx.advance(height as usize * 2);
+    let luma: Vec<f32> = original.pixels().map(|pixel| luminance.of(pixel)).collect();
+    let (low, high) = local_range(&luma, width, height, stroke_width.ceil() as u32, ctx.boundary());
+    ctx.advance(height as usize);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let index = (y * width + x) as usize;
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            
+            // Ramp from the background floor to twice the floor
+            let weight = ((high[index] - low[index] - floor) / floor).clamp(0.0, 1.0);
+            let weight = weight * weight * (3.0 - 2.0 * weight);
+            let detail = luma[index] - luminance.of(blurred.get_pixel(x, y));
+            let target = (luma[index] + detail * strength).clamp(low[index], high[index]);
+            let shift = weight * (target - luma[index]);
+            
+            for i in 0..P::COLOR_CHANNELS {
+                pixel[i] = if shift == 0.0 {
+                    orig_pixel[i]
+                } else {
+                    let orig_val = sample::<P>(orig_pixel[i]);
+                    ctx.store::<P>(orig_val, orig_val + shift)
+                };
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
         }
+        ctx.advance(1);
     });
     
-    Ok(image)
+    ctx.release(blurred);
+    Ok(image.with_pixels(buffer))
+}
+
+/// Minimum and maximum of `plane` within `radius` pixels (a square window)
+/// of each pixel.
+fn local_range(plane: &[f32], width: u32, height: u32, radius: u32, boundary: Boundary) -> (Vec<f32>, Vec<f32>) {
+    let radius = radius as i32;
+    let pass = |low: &[f32], high: &[f32], horizontal: bool| -> (Vec<f32>, Vec<f32>) {
+        (0..height).into_par_iter()
+            .flat_map(|y| {
+                (0..width).map(|x| {
+                    (-radius..=radius).fold((f32::MAX, f32::MIN), |(min, max), k| {
+                        let (sx, sy) = if horizontal {
+                            (boundary.index(x as i32 + k, width), y)
+                        } else {
+                            (x, boundary.index(y as i32 + k, height))
+                        };
+                        let index = (sy * width + sx) as usize;
+                        (min.min(low[index]), max.max(high[index]))
+                    })
+                }).collect::<Vec<_>>()
+            })
+            .unzip()
+    };
+    let (low, high) = pass(plane, plane, true);
+    pass(&low, &high, false)
 }
 
 /// Applies high-pass sharpening using a convolution kernel.
 /// 
 /// # Parameters
 /// - `strength`: Blend strength with original image (0.0-3.0)
-pub fn high_pass_sharpen(mut image: Image, strength: f32) -> Result<Image> {
-    let original = image.data.get_ref().clone();
-    let (kernel, kernel_size) = get_high_pass_kernel();
-    let sharpened = apply_convolution(&original, &kernel, kernel_size);
+pub fn high_pass_sharpen<P: SharpenPixel>(image: &Image<P>, strength: f32, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let height = original.height() as usize;
     
-    let buffer = image.data.get_mut();
-    *buffer = blend_images(&original, &sharpened, strength);
+    ctx.start(height * 2);
+    let mut sharpened = apply_convolution(original, &get_high_pass_kernel(), ctx);
+    ctx.advance(height);
     
-    Ok(image)
+    blend_images(original, &mut sharpened, BlendMode::Normal, strength);
+    ctx.advance(height);
+    
+    Ok(image.with_pixels(sharpened))
+}
+
+/// Extracts the detail layer of frequency separation: each color sample's
+/// difference from its Gaussian blur of `radius`, added to mid-gray; alpha
+/// is kept.
+/// 
+/// This is what an unsharp mask of the same radius amplifies, so the layer
+/// shows where sharpening will act. Differences beyond half the sample
+/// range clip.
+/// 
+/// # Parameters
+/// - `radius`: Blur radius separating detail from tones (0.5-10.0)
+pub fn high_pass_layer<P: SharpenPixel>(image: &Image<P>, radius: f32, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    let mid = max_value::<P>() / 2.0;
+    
+    // Two blur passes plus the difference pass
+    ctx.start(height as usize * 3);
+    let blurred = ctx.gaussian_blur(original, radius);
+    ctx.advance(height as usize * 2);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let blur_pixel = blurred.get_pixel(x, y).channels();
+            let pixel = pixel.channels_mut();
+            for i in 0..P::COLOR_CHANNELS {
+                pixel[i] = to_sample::<P>(mid + sample::<P>(orig_pixel[i]) - sample::<P>(blur_pixel[i]));
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    ctx.release(blurred);
+    Ok(image.with_pixels(buffer))
+}
+
+/// Blur of the tones layer, relative to the separation radius, that
+/// [`frequency_separation`] smooths skin towards.
+pub(crate) const SKIN_BLUR_FACTOR: f32 = 2.0;
+
+/// Separation radius at which [`frequency_separation`] tells skin from
+/// texture by [`mask::SKIN_TEXTURE`]. Other radii scale the threshold
+/// inversely: a larger radius suits a larger image, where the same skin
+/// has shallower gradients, and a preview's shrunken radius a downscaled
+/// one, where they are steeper.
+const SKIN_TEXTURE_RADIUS: f32 = 2.0;
+
+/// The skin mask [`frequency_separation`] uses at `radius`.
+fn skin_mask<P: SharpenPixel>(img: &Buffer<P>, radius: f32, ctx: &Context) -> GrayImage {
+    let texture = mask::SKIN_TEXTURE * SKIN_TEXTURE_RADIUS / radius;
+    mask::skin_buffer(img, ctx.luminance(), ctx.boundary(), texture)
+}
+
+/// Retouches portraits by frequency separation: evens out the tones of skin
+/// and sharpens the detail everywhere else.
+/// 
+/// The image splits at a Gaussian blur of `radius` into a tones layer, the
+/// blur, and a detail layer, what [`high_pass_layer`] shows. Where
+/// [`mask::skin`](crate::mask::skin) finds skin, the tones move by
+/// `smoothing` towards a blur twice as wide, softening
+/// blotches and uneven shading while pores and fine texture, which live in
+/// the detail layer, stay. Elsewhere, over eyes, lashes, lips and hair, the
+/// detail layer is amplified by `amount` as by an unsharp mask of `radius`.
+/// 
+/// This is the split [`high_pass_layer`] and a linear light
+/// [`blend`](crate::blend::blend) make, but done in one pass on unclipped
+/// samples: a detail layer stored as pixels clips differences beyond half
+/// the range and rounds them to the sample depth, and the skin mask varies
+/// smoothing and gain per pixel where blends and
+/// [`Layers`](crate::Layers) apply one opacity.
+/// 
+/// # Parameters
+/// - `radius`: Blur radius separating detail from tones (0.5-10.0)
+/// - `smoothing`: How far skin tones move towards the wider blur (0.0-1.0)
+/// - `amount`: Sharpening of the detail outside skin (0.0-5.0)
+pub fn frequency_separation<P: SharpenPixel>(
+    image: &Image<P>,
+    radius: f32,
+    smoothing: f32,
+    amount: f32,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let (width, height) = original.dimensions();
+    
+    // The skin mask, two passes for each blur, then the combine pass
+    ctx.start(height as usize * 6);
+    let skin = skin_mask(original, radius, ctx);
+    ctx.advance(height as usize);
+    let tones = ctx.gaussian_blur(original, radius);
+    ctx.advance(height as usize * 2);
+    let smoothed = gaussian_blur(&tones, radius * SKIN_BLUR_FACTOR, ctx);
+    ctx.advance(height as usize * 2);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
+    buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
+        for (x, _, pixel) in row {
+            let orig_pixel = original.get_pixel(x, y).channels();
+            let tone_pixel = tones.get_pixel(x, y).channels();
+            let smooth_pixel = smoothed.get_pixel(x, y).channels();
+            let skin = skin.get_pixel(x, y)[0] as f32 / 255.0;
+            let (smoothing, gain) = (smoothing * skin, 1.0 + amount * (1.0 - skin));
+            let pixel = pixel.channels_mut();
+            
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let tone = sample::<P>(tone_pixel[i]);
+                let tone = tone + (sample::<P>(smooth_pixel[i]) - tone) * smoothing;
+                let detail = orig_val - sample::<P>(tone_pixel[i]);
+                pixel[i] = ctx.store::<P>(orig_val, tone + detail * gain);
+            }
+            copy_alpha::<P>(orig_pixel, pixel);
+        }
+        ctx.advance(1);
+    });
+    
+    ctx.recycle(smoothed);
+    ctx.release(tones);
+    Ok(image.with_pixels(buffer))
 }
 
 /// Enhances edges in an image using edge detection.
@@ -62,30 +596,46 @@ pub fn high_pass_sharpen(mut image: Image, strength: f32) -> Result<Image> {
 /// # Parameters
 /// - `strength`: Edge enhancement strength (0.0-3.0)
 /// - `method`: Edge detection method (Sobel or Prewitt)
-pub fn enhance_edges(mut image: Image, strength: f32, method: EdgeMethod) -> Result<Image> {
-    let original = Arc::new(image.data.get_ref().clone());
-    let edges = Arc::new(apply_edge_detection(&original, method));
+pub fn enhance_edges<P: SharpenPixel>(
+    image: &Image<P>,
+    strength: f32,
+    method: EdgeMethod,
+    ctx: &Context,
+) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let luminance = ctx.luminance();
+    let (width, height) = original.dimensions();
+    let max = 255.0 * unit_scale::<P>();
     
-    let buffer = image.data.get_mut();
+    // Edge detection plus the enhancement pass
+    ctx.start(height as usize * 2);
+    let edges = apply_edge_detection(original, method, ctx);
+    ctx.advance(height as usize);
+    
+    let mut buffer = ctx.buffer::<P>(width, height);
     
     // Process rows in parallel, avoiding collecting all pixels into memory
     buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
         for (x, _, pixel) in row {
-            let orig_pixel = original.get_pixel(x, y);
+            let orig_pixel = original.get_pixel(x, y).channels();
             let edge_pixel = edges.get_pixel(x, y);
+            let pixel = pixel.channels_mut();
             
-            let edge_strength = calculate_luminance(edge_pixel) / 255.0;
+            let edge_strength = luminance.of(edge_pixel) / max;
             let enhancement = edge_strength * strength;
             
-            for i in 0..3 {
-                let orig_val = orig_pixel[i] as f32;
-                let enhanced = orig_val + (edge_strength * 255.0 * enhancement);
-                pixel[i] = enhanced.round().clamp(0.0, 255.0) as u8;
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                let enhanced = orig_val + (edge_strength * max * enhancement);
+                pixel[i] = ctx.store::<P>(orig_val, enhanced);
             }
+            copy_alpha::<P>(orig_pixel, pixel);
         }
+        ctx.advance(1);
     });
     
-    Ok(image)
+    ctx.recycle(edges);
+    Ok(image.with_pixels(buffer))
 }
 
 /// Applies clarity enhancement to improve local contrast.
@@ -93,20 +643,26 @@ pub fn enhance_edges(mut image: Image, strength: f32, method: EdgeMethod) -> Res
 /// # Parameters
 /// - `strength`: Enhancement strength (0.0-3.0)
 /// - `radius`: Local area radius (1.0-20.0)
-pub fn clarity(mut image: Image, strength: f32, radius: f32) -> Result<Image> {
-    let original = Arc::new(image.data.get_ref().clone());
+pub fn clarity<P: SharpenPixel>(image: &Image<P>, strength: f32, radius: f32, ctx: &Context) -> Result<Image<P>> {
+    let original = image.data.get_ref();
+    let luminance = ctx.luminance();
     let (width, height) = original.dimensions();
+    let scale = unit_scale::<P>();
+    ctx.start(height as usize);
     
-    let buffer = image.data.get_mut();
+    let mut buffer = ctx.buffer::<P>(width, height);
     
     let window_size = (radius * 2.0).round() as usize;
     let half_window = window_size / 2;
+    let boundary = ctx.boundary();
     
-    // Process rows in parallel, calculating and applying enhancements in-place
+    // Process rows in parallel, writing enhanced pixels into the output
     buffer.enumerate_rows_mut().par_bridge().for_each(|(y, row)| {
         for (x, _, pixel) in row {
-            let orig_pixel = original.get_pixel(x, y);
-            let orig_luminance = calculate_luminance(orig_pixel);
+            let orig = original.get_pixel(x, y);
+            let orig_pixel = orig.channels();
+            let orig_luminance = luminance.of(orig);
+            let pixel = pixel.channels_mut();
             
             let mut local_sum = 0.0;
             let mut count = 0;
@@ -114,11 +670,11 @@ pub fn clarity(mut image: Image, strength: f32, radius: f32) -> Result<Image> {
             // Calculate local average luminance
             for dy in -(half_window as i32)..=(half_window as i32) {
                 for dx in -(half_window as i32)..=(half_window as i32) {
-                    let nx = (x as i32 + dx).max(0).min(width as i32 - 1) as u32;
-                    let ny = (y as i32 + dy).max(0).min(height as i32 - 1) as u32;
+                    let nx = boundary.index(x as i32 + dx, width);
+                    let ny = boundary.index(y as i32 + dy, height);
                     
                     let neighbor_pixel = original.get_pixel(nx, ny);
-                    local_sum += calculate_luminance(neighbor_pixel);
+                    local_sum += luminance.of(neighbor_pixel);
                     count += 1;
                 }
             }
@@ -127,7 +683,7 @@ pub fn clarity(mut image: Image, strength: f32, radius: f32) -> Result<Image> {
             let contrast_diff = orig_luminance - local_avg;
             
             // Apply stronger enhancement to midtones
-            let midtone_factor = if orig_luminance > 64.0 && orig_luminance < 192.0 {
+            let midtone_factor = if orig_luminance > 64.0 * scale && orig_luminance < 192.0 * scale {
                 1.0
             } else {
                 0.5
@@ -136,14 +692,21 @@ pub fn clarity(mut image: Image, strength: f32, radius: f32) -> Result<Image> {
             let enhancement = contrast_diff * strength * midtone_factor * 0.5;
             
             // Apply enhancement directly to pixel
-            for i in 0..3 {
-                let enhanced = orig_pixel[i] as f32 + enhancement;
-                pixel[i] = enhanced.round().clamp(0.0, 255.0) as u8;
+            for i in 0..P::COLOR_CHANNELS {
+                let orig_val = sample::<P>(orig_pixel[i]);
+                pixel[i] = ctx.store::<P>(orig_val, orig_val + enhancement);
             }
+            copy_alpha::<P>(orig_pixel, pixel);
         }
+        ctx.advance(1);
     });
     
-    Ok(image)
+    Ok(image.with_pixels(buffer))
+}
+
+/// Copies the channels after the color channels (alpha) unchanged.
+fn copy_alpha<P: SharpenPixel>(original: &[P::Subpixel], pixel: &mut [P::Subpixel]) {
+    pixel[P::COLOR_CHANNELS..].copy_from_slice(&original[P::COLOR_CHANNELS..]);
 }
 
 #[cfg(test)]
@@ -169,37 +732,292 @@ mod tests {
     #[test]
     fn test_unsharp_mask() {
         let img = create_test_image();
-        let result = unsharp_mask(img, 1.0, 1.0, 0);
+        let result = unsharp_mask(&img, 1.0, 1.0, 0, &Context::default());
         assert!(result.is_ok());
     }
     
+    #[test]
+    fn test_unsharp_mask_with_amount_map() {
+        let img = create_test_image();
+        let ctx = Context::default();
+        let twice = unsharp_mask(&img, 1.5, 2.0, 0, &ctx).unwrap().into_rgb();
+        
+        // Level 102 is amount 2; black leaves the left half as it was
+        let map = GrayImage::from_fn(100, 100, |x, _| image::Luma([if x < 50 { 0 } else { 102 }]));
+        let result = unsharp_mask_with_amount_map(&img, 1.5, &map, 0, &ctx).unwrap().into_rgb();
+        for (x, y, pixel) in result.enumerate_pixels() {
+            let expected = if x < 50 { img.data.get_ref().get_pixel(x, y) } else { twice.get_pixel(x, y) };
+            assert_eq!(pixel, expected, "({}, {})", x, y);
+        }
+        
+        let small = GrayImage::new(50, 100);
+        assert!(unsharp_mask_with_amount_map(&img, 1.5, &small, 0, &ctx).is_err());
+    }
+    
+    #[test]
+    fn test_relative_unsharp_mask() {
+        // The same fine texture, at the same relative contrast, on a dark
+        // and a bright background
+        let img = Image::from_rgb(RgbImage::from_fn(64, 16, |x, y| {
+            let base = if x < 32 { 40.0 } else { 200.0 };
+            let value = base * if (x + y) % 2 == 0 { 1.1 } else { 0.9 };
+            Rgb([value as u8, value as u8, value as u8])
+        })).unwrap();
+        let ctx = Context::default();
+        let changed = |img: &Image, result: &RgbImage, columns: std::ops::Range<u32>| {
+            columns.filter(|&x| result.get_pixel(x, 8) != img.data.get_ref().get_pixel(x, 8)).count()
+        };
+        
+        // An absolute threshold sharpens only the bright half
+        let absolute = unsharp_mask(&img, 1.0, 1.0, 4, &ctx).unwrap().into_rgb();
+        assert_eq!(changed(&img, &absolute, 8..24), 0);
+        assert!(changed(&img, &absolute, 40..56) > 0);
+        
+        // A relative one treats both halves alike
+        let below = relative_unsharp_mask(&img, 1.0, 1.0, 20.0, &ctx).unwrap().into_rgb();
+        assert_eq!((changed(&img, &below, 8..24), changed(&img, &below, 40..56)), (16, 16));
+        let above = relative_unsharp_mask(&img, 1.0, 1.0, 60.0, &ctx).unwrap().into_rgb();
+        assert_eq!((changed(&img, &above, 8..24), changed(&img, &above, 40..56)), (0, 0));
+        
+        // Quantization steps in a flat region stay below the contrast floor
+        let img = Image::from_rgb(RgbImage::from_fn(32, 16, |x, y| Rgb([((x + y) % 2) as u8 + 3; 3]))).unwrap();
+        let result = relative_unsharp_mask(&img, 1.0, 1.0, 30.0, &ctx).unwrap().into_rgb();
+        assert_eq!(changed(&img, &result, 0..32), 0);
+    }
+    
     #[test]
     fn test_high_pass_sharpen() {
         let img = create_test_image();
-        let result = high_pass_sharpen(img, 0.5);
+        let result = high_pass_sharpen(&img, 0.5, &Context::default());
         assert!(result.is_ok());
     }
     
+    #[test]
+    fn test_high_pass_layer() {
+        let img = create_test_image();
+        let ctx = Context::default();
+        let layer = high_pass_layer(&img, 2.0, &ctx).unwrap().into_rgb();
+        // The layer is what unsharp masking adds, on mid-gray
+        let sharpened = unsharp_mask(&img, 2.0, 1.0, 0, &ctx).unwrap().into_rgb();
+        for ((pixel, detail), sharp) in img.data.get_ref().pixels().zip(layer.pixels()).zip(sharpened.pixels()) {
+            let rebuilt = (pixel[0] as i32 + detail[0] as i32 - 128).clamp(0, 255);
+            assert!((rebuilt - sharp[0] as i32).abs() <= 1);
+        }
+        // Flat areas are mid-gray
+        let flat = Image::from_rgb(RgbImage::from_pixel(16, 16, Rgb([30, 90, 250]))).unwrap();
+        assert!(high_pass_layer(&flat, 2.0, &ctx).unwrap().into_rgb().pixels().all(|pixel| pixel.0 == [128; 3]));
+        assert!(img.high_pass_layer(0.0).is_err());
+    }
+    
     #[test]
     fn test_enhance_edges() {
         let img = create_test_image();
-        let result = enhance_edges(img, 1.0, EdgeMethod::Sobel);
+        let result = enhance_edges(&img, 1.0, EdgeMethod::Sobel, &Context::default());
         assert!(result.is_ok());
     }
     
     #[test]
     fn test_clarity() {
         let img = create_test_image();
-        let result = clarity(img, 1.0, 2.0);
+        let result = clarity(&img, 1.0, 2.0, &Context::default());
         assert!(result.is_ok());
     }
     
+    #[test]
+    fn test_adaptive_unsharp_mask() {
+        // Fine stripes on the left, a soft wide ramp on the right
+        let img = Image::from_rgb(RgbImage::from_fn(128, 64, |x, _| {
+            let value = if x < 64 {
+                if x % 3 == 0 { 170 } else { 110 }
+            } else {
+                (140.0 + 60.0 * ((x as f32 - 96.0) / 8.0).tanh()) as u8
+            };
+            Rgb([value, value, value])
+        })).unwrap();
+        let ctx = Context::default();
+        let adaptive = adaptive_unsharp_mask(&img, 0.5, 4.0, 1.0, 0, &ctx).unwrap().into_rgb();
+        let fine = unsharp_mask(&img, 0.5, 1.0, 0, &ctx).unwrap().into_rgb();
+        let coarse = unsharp_mask(&img, 4.0, 1.0, 0, &ctx).unwrap().into_rgb();
+        
+        let distance = |a: &RgbImage, b: &RgbImage, columns: std::ops::Range<u32>| -> f32 {
+            columns.map(|x| (a.get_pixel(x, 32)[0] as f32 - b.get_pixel(x, 32)[0] as f32).abs()).sum()
+        };
+        // The stripes take the small radius, the ramp the large one
+        assert!(distance(&adaptive, &fine, 16..40) < distance(&adaptive, &coarse, 16..40) / 4.0);
+        assert!(distance(&adaptive, &coarse, 84..108) < distance(&adaptive, &fine, 84..108) / 4.0);
+        
+        // A single radius is a plain unsharp mask
+        let single = adaptive_unsharp_mask(&img, 1.5, 1.5, 1.0, 2, &ctx).unwrap().into_rgb();
+        assert_eq!(single, unsharp_mask(&img, 1.5, 1.0, 2, &ctx).unwrap().into_rgb());
+    }
+    
+    #[test]
+    fn test_smart_enhance() {
+        // A soft vertical edge, clean and under noise of about ±12 levels
+        let noise = |x: u32, y: u32| ((x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761) >> 27) as f32 - 16.0;
+        let edge = |x: u32| 125.0 + 55.0 * ((x as f32 - 32.0) / 3.0).tanh();
+        let image = |amplitude: f32| Image::from_rgb(RgbImage::from_fn(64, 48, |x, y| {
+            let value = (edge(x) + noise(x, y) * amplitude).round() as u8;
+            Rgb([value, value, value])
+        })).unwrap();
+        let ctx = Context::default();
+        // Spread of the flat area left of the edge, and the step across it
+        let spread = |img: &RgbImage| {
+            let values: Vec<f32> = (4..20).flat_map(|x| (0..48).map(move |y| (x, y))).map(|(x, y)| img.get_pixel(x, y)[0] as f32).collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+        };
+        let step = |img: &RgbImage| (0..48).map(|y| img.get_pixel(33, y)[0] as f32 - img.get_pixel(31, y)[0] as f32).sum::<f32>() / 48.0;
+
+        let noisy = image(0.75);
+        let enhanced = noisy.smart_enhance_ref(1.0).unwrap().into_rgb();
+        let sharpened = unsharp_mask(&noisy, 1.0, 1.0, 0, &ctx).unwrap().into_rgb();
+        let noisy = noisy.into_rgb();
+        assert!(spread(&enhanced) < spread(&noisy) / 2.0, "{} vs {}", spread(&enhanced), spread(&noisy));
+        assert!(spread(&sharpened) > spread(&noisy));
+        assert!(step(&enhanced) > step(&noisy), "{} vs {}", step(&enhanced), step(&noisy));
+
+        // Clean images are only sharpened, more so with a higher level
+        let clean = image(0.0);
+        let gentle = clean.smart_enhance_ref(0.5).unwrap().into_rgb();
+        let strong = clean.smart_enhance_ref(2.0).unwrap().into_rgb();
+        assert!(step(&clean.clone().into_rgb()) < step(&gentle) && step(&gentle) < step(&strong));
+
+        // Derived settings stay within the ranges operations accept
+        let config = ValidationConfig::default();
+        for level in [1.0, 3.0] {
+            for operation in smart_operations(clean.data.get_ref(), level, Luminance::default(), &config) {
+                assert!(operation.validate_with(&config).is_ok(), "{}", operation);
+            }
+        }
+        let tight = ValidationConfig::default().max_amount(0.5);
+        assert!(smart_operations(clean.data.get_ref(), 3.0, Luminance::default(), &tight).iter().all(|operation| operation.validate_with(&tight).is_ok()));
+
+        // Regions use the settings of the whole image: a clean
+        // corner of a noisy image is denoised too
+        let mixed = Image::from_rgb(RgbImage::from_fn(128, 48, |x, y| {
+            let amplitude = if x < 64 { 0.75 } else { 0.0 };
+            let value = (edge(x.saturating_sub(72)) + noise(x, y) * amplitude).round() as u8;
+            Rgb([value, value, value])
+        })).unwrap();
+        let rect = crate::Rect::new(96, 8, 16, 32);
+        let full = mixed.clone().sharpen().smart_enhance(1.0).apply().unwrap().into_rgb();
+        let spot = mixed.clone().sharpen().smart_enhance(1.0).region(rect).apply().unwrap().into_rgb();
+        for (x, y) in (rect.y..rect.y + rect.height).flat_map(|y| (rect.x..rect.x + rect.width).map(move |x| (x, y))) {
+            assert_eq!(spot.get_pixel(x, y), full.get_pixel(x, y), "({}, {})", x, y);
+        }
+    }
+    
+    #[test]
+    fn test_frequency_separation() {
+        let img = crate::mask::tests::create_portrait();
+        let ctx = Context::default();
+        let spread = |result: &RgbImage, columns: std::ops::Range<u32>| {
+            let values: Vec<f32> = columns.flat_map(|x| (4..44).map(move |y| (x, y))).map(|(x, y)| result.get_pixel(x, y)[1] as f32).collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+        };
+        let original = img.clone().into_rgb();
+        
+        // Tones plus detail rebuild the image
+        let unchanged = frequency_separation(&img, 2.0, 0.0, 0.0, &ctx).unwrap().into_rgb();
+        assert!(unchanged.pixels().zip(original.pixels()).all(|(a, b)| a[1].abs_diff(b[1]) <= 1));
+        
+        // Skin evens out, hair gains contrast
+        let retouched = frequency_separation(&img, 2.0, 1.0, 1.0, &ctx).unwrap().into_rgb();
+        assert!(spread(&retouched, 0..16) < spread(&original, 0..16) * 0.7, "{} vs {}", spread(&retouched, 0..16), spread(&original, 0..16));
+        assert!(spread(&retouched, 40..56) > spread(&original, 40..56) * 1.2, "{} vs {}", spread(&retouched, 40..56), spread(&original, 40..56));
+        // An unsharp mask would have roughened the skin instead
+        let sharpened = unsharp_mask(&img, 2.0, 1.0, 0, &ctx).unwrap().into_rgb();
+        assert!(spread(&sharpened, 0..16) > spread(&original, 0..16));
+        
+        // Downscaling steepens the shading of skin, but a preview's shrunken
+        // radius still finds it
+        let shaded = RgbImage::from_fn(160, 160, |x, y| {
+            let shading = 40.0 * (x as f32 / 12.0).sin() * (y as f32 / 15.0).cos();
+            Rgb([200.0 + shading, 150.0 + shading, 130.0 + shading].map(|value| value.round() as u8))
+        });
+        let small = image::imageops::resize(&shaded, 40, 40, image::imageops::FilterType::Triangle);
+        let mean = |mask: GrayImage| mask.pixels().map(|pixel| pixel[0] as f32).sum::<f32>() / mask.len() as f32;
+        assert!(mean(skin_mask(&shaded, 2.0, &ctx)) > 250.0);
+        assert!(mean(skin_mask(&small, 0.5, &ctx)) > 250.0, "{}", mean(skin_mask(&small, 0.5, &ctx)));
+    }
+    
+    #[test]
+    fn test_text_sharpen() {
+        // Soft two-pixel strokes on slightly grainy paper
+        let paper = |x: u32, y: u32| 225.0 + ((x * 7 + y * 13) % 5) as f32 * 2.0;
+        let img = Image::from_rgb(RgbImage::from_fn(64, 32, |x, y| {
+            let ink = [12.0f32, 13.0, 32.0, 33.0].iter()
+                .map(|&stroke| (-(x as f32 - stroke).powi(2) / 2.0).exp())
+                .sum::<f32>()
+                .min(1.0);
+            let value = (paper(x, y) * (1.0 - ink) + 30.0 * ink).round() as u8;
+            Rgb([value, value, value])
+        })).unwrap();
+        let ctx = Context::default();
+        let original = img.clone().into_rgb();
+        let text = text_sharpen(&img, 1.5, 1.5, &ctx).unwrap().into_rgb();
+        let unsharp = unsharp_mask(&img, 1.5, 1.5, 0, &ctx).unwrap().into_rgb();
+        
+        // Largest excursion beyond the original's range within two pixels
+        let overshoot = |result: &RgbImage| -> f32 {
+            result.enumerate_pixels().map(|(x, y, pixel)| {
+                let (low, high) = (x.saturating_sub(2)..=(x + 2).min(63))
+                    .flat_map(|nx| (y.saturating_sub(2)..=(y + 2).min(31)).map(move |ny| (nx, ny)))
+                    .map(|(nx, ny)| original.get_pixel(nx, ny)[0] as f32)
+                    .fold((f32::MAX, f32::MIN), |(low, high), value| (low.min(value), high.max(value)));
+                let value = pixel[0] as f32;
+                (low - value).max(value - high).max(0.0)
+            }).fold(0.0, f32::max)
+        };
+        assert!(overshoot(&unsharp) > 20.0, "{}", overshoot(&unsharp));
+        assert_eq!(overshoot(&text), 0.0);
+        
+        // Stroke edges steepen
+        let step = |img: &RgbImage| img.get_pixel(11, 16)[0] as i32 - img.get_pixel(10, 16)[0] as i32;
+        assert!(step(&text).abs() > step(&original).abs() + 10, "{} vs {}", step(&text), step(&original));
+        // Paper away from the strokes keeps its grain as it was
+        for y in 0..32 {
+            for x in (0..4).chain(44..64) {
+                assert_eq!(text.get_pixel(x, y), original.get_pixel(x, y), "({}, {})", x, y);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_alpha_and_16_bit() {
+        let rgba = image::RgbaImage::from_fn(32, 32, |x, y| {
+            let value = if (x / 4 + y / 4) % 2 == 0 { 60 } else { 190 };
+            image::Rgba([value, value, value, (x * 8) as u8])
+        });
+        let image = Image::from_buffer(rgba.clone()).unwrap();
+        let result = unsharp_mask(&image, 1.0, 1.0, 0, &Context::default()).unwrap().into_buffer();
+        assert!(result.pixels().zip(rgba.pixels()).all(|(a, b)| a[3] == b[3]));
+        assert_ne!(result, rgba);
+        let result = high_pass_sharpen(&image, 0.5, &Context::default()).unwrap().into_buffer();
+        assert!(result.pixels().zip(rgba.pixels()).all(|(a, b)| a[3] == b[3]));
+        
+        // 16-bit results match 8-bit ones at 16-bit precision
+        let rgb8 = create_test_image();
+        let rgb16 = Image::from_buffer(image::DynamicImage::ImageRgb8(rgb8.clone().into_rgb()).to_rgb16()).unwrap();
+        let sharp8 = clarity(&rgb8, 1.0, 2.0, &Context::default()).unwrap().into_rgb();
+        let sharp16 = clarity(&rgb16, 1.0, 2.0, &Context::default()).unwrap().into_buffer();
+        for (a, b) in sharp8.pixels().zip(sharp16.pixels()) {
+            assert!((a[0] as f32 - b[0] as f32 / 257.0).abs() <= 1.0);
+        }
+        
+        let gray = image::GrayImage::from_fn(16, 16, |x, _| image::Luma([if x < 8 { 50 } else { 200 }]));
+        let edges = enhance_edges(&Image::from_buffer(gray).unwrap(), 1.0, EdgeMethod::Sobel, &Context::default());
+        assert!(edges.is_ok());
+    }
+    
     #[test]
     fn test_chain_operations() {
         let img = create_test_image();
-        let result = unsharp_mask(img, 0.5, 0.5, 0)
-            .and_then(|img| high_pass_sharpen(img, 0.3))
-            .and_then(|img| clarity(img, 0.5, 1.0));
+        let result = unsharp_mask(&img, 0.5, 0.5, 0, &Context::default())
+            .and_then(|img| high_pass_sharpen(&img, 0.3, &Context::default()))
+            .and_then(|img| clarity(&img, 0.5, 1.0, &Context::default()));
         assert!(result.is_ok());
     }
 }
\ No newline at end of file
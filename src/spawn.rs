@@ -0,0 +1,95 @@
+//! Async wrappers that run loading, processing and saving on tokio's
+//! blocking pool, so async servers don't stall their executor threads.
+//!
+//! The work still uses rayon (with `parallel`) inside the blocking task.
+//! A panic in the task resumes in the awaiting caller.
+
+use crate::pixel::SharpenPixel;
+use crate::{Image, ImageError, Result, SharpeningBuilder};
+use std::path::Path;
+
+/// Runs `work` on the blocking pool of the current tokio runtime.
+///
+/// Panics if called outside a runtime, like [`tokio::task::spawn_blocking`].
+async fn run_blocking<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        // Only happens when the runtime shuts down first
+        Err(error) => Err(ImageError::Io(std::io::Error::other(error))),
+    }
+}
+
+impl Image {
+    /// [`Image::load`] on the blocking pool.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn handler() -> sharpy::Result<()> {
+    /// use sharpy::Image;
+    ///
+    /// let image = Image::load_async("upload.jpg").await?;
+    /// let sharpened = image.sharpen().unsharp_mask(1.0, 1.0, 0).apply_async().await?;
+    /// sharpened.save_async("upload_sharp.jpg").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn load_async<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        run_blocking(move || Image::load(path)).await
+    }
+
+    /// [`Image::save`] on the blocking pool. Like `save`, it consumes the
+    /// image; clone it first to keep using it.
+    pub async fn save_async<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        run_blocking(move || self.save(path)).await
+    }
+}
+
+impl<P: SharpenPixel> SharpeningBuilder<P> {
+    /// [`SharpeningBuilder::apply`] on the blocking pool, with the same
+    /// validation, region, mask and thread settings.
+    pub async fn apply_async(self) -> Result<Image<P>> {
+        run_blocking(move || self.apply()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Image, ImageError};
+    use image::{Rgb, RgbImage};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join(format!("sharpy_async_{}.png", std::process::id()));
+        let image = Image::from_rgb(RgbImage::from_fn(32, 32, |x, _| Rgb([(x * 8) as u8, 0, 0]))).unwrap();
+        let expected = image.clone().sharpen().unsharp_mask(1.0, 1.0, 0).apply().unwrap();
+
+        let loaded = runtime().block_on(async {
+            let sharpened = image.sharpen().unsharp_mask(1.0, 1.0, 0).apply_async().await?;
+            sharpened.save_async(&path).await?;
+            Image::load_async(&path).await
+        });
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.unwrap().as_rgb(), expected.as_rgb());
+    }
+
+    #[test]
+    fn test_errors_pass_through() {
+        let image = Image::from_rgb(RgbImage::new(8, 8)).unwrap();
+        let result = runtime().block_on(image.sharpen().unsharp_mask(-1.0, 1.0, 0).apply_async());
+        assert!(matches!(result, Err(ImageError::Validation(_))));
+
+        let result = runtime().block_on(Image::load_async("does/not/exist.png"));
+        assert!(matches!(result.as_ref().map_err(ImageError::root), Err(ImageError::Io(_))));
+    }
+}
@@ -0,0 +1,106 @@
+//! `tracing` spans around operations, blur passes and regions, when the
+//! `tracing` feature is enabled.
+//!
+//! Spans carry the image size and, once closed, an `elapsed_us` field with
+//! the time spent inside. Without the feature, [`timed!`] expands to an
+//! empty guard and its field expressions aren't evaluated.
+
+/// Enters a span at `$level` (`DEBUG`, `TRACE`, ...) until the returned
+/// guard drops, recording `elapsed_us` on it then.
+///
+/// Field values are only evaluated with the feature, so compute them inline
+/// rather than binding locals that only the span uses.
+macro_rules! timed {
+    ($level:ident, $name:literal, $($fields:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let guard = $crate::trace::Timed::enter(tracing::span!(
+            tracing::Level::$level,
+            $name,
+            $($fields)+,
+            elapsed_us = tracing::field::Empty
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::Timed;
+        guard
+    }};
+}
+
+pub(crate) use timed;
+
+#[cfg(feature = "tracing")]
+pub(crate) struct Timed {
+    span: tracing::span::EnteredSpan,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Timed {
+    pub(crate) fn enter(span: tracing::Span) -> Self {
+        Self { span: span.entered(), start: std::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Timed {
+    fn drop(&mut self) {
+        let elapsed = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        self.span.record("elapsed_us", elapsed);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Timed;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::Image;
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Span names in creation order, and the names of spans that recorded
+    /// their elapsed time.
+    #[derive(Default)]
+    struct Spans {
+        created: Vec<&'static str>,
+        timed: Vec<&'static str>,
+    }
+
+    struct Recorder(Arc<Mutex<Spans>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            spans.created.push(span.metadata().name());
+            Id::from_u64(spans.created.len() as u64)
+        }
+
+        fn record(&self, span: &Id, _: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            let name = spans.created[span.into_u64() as usize - 1];
+            spans.timed.push(name);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_spans() {
+        let spans = Arc::new(Mutex::new(Spans::default()));
+        let image = Image::from_rgb(image::RgbImage::new(32, 32)).unwrap();
+        tracing::subscriber::with_default(Recorder(Arc::clone(&spans)), || {
+            image.sharpen().unsharp_mask(1.0, 1.0, 0).apply().unwrap();
+        });
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.created, ["operation", "blur", "blur_pass", "blur_pass"]);
+        assert_eq!(spans.timed, ["blur_pass", "blur_pass", "blur", "operation"]);
+    }
+}
@@ -0,0 +1,232 @@
+//! Transfer functions for sharpening in linear light.
+//!
+//! Pixel values are usually gamma- or log-encoded, so an overshoot of the
+//! same size is much brighter on the dark side of an edge than on the light
+//! side, and log footage rings far more than it looks. Decoding to linear
+//! light first makes the operations act on light intensities. Linear values
+//! are held at 16 bits, since 8 bits would band in the shadows.
+
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample};
+use crate::{Image, ImageError, Result};
+
+/// S-Log3 code value (of 1023) where its curve turns linear.
+const SLOG3_CUT: f32 = 171.210_3;
+/// LogC3 (EI 800) curve parameters.
+const LOGC3: [f32; 7] = [0.010_591, 5.555_556, 0.052_272, 0.247_190, 0.385_537, 5.367_655, 0.092_809];
+
+/// How an image's values encode light, for
+/// [`SharpeningBuilder::linearize`](crate::SharpeningBuilder::linearize).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    /// The sRGB curve of most photos and web images
+    Srgb,
+    /// The BT.709 camera curve of HD video
+    Rec709,
+    /// A pure power law, e.g. 2.2 for many displays or 1.8 for older Macs
+    Gamma(f32),
+    /// Sony S-Log3
+    SLog3,
+    /// ARRI LogC3 at EI 800
+    LogC3,
+}
+
+impl Transfer {
+    /// Converts an encoded value (0-1) to linear light.
+    pub fn decode(self, value: f32) -> f32 {
+        match self {
+            Transfer::Srgb if value <= 0.040_45 => value / 12.92,
+            Transfer::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Transfer::Rec709 if value < 0.081 => value / 4.5,
+            Transfer::Rec709 => ((value + 0.099) / 1.099).powf(1.0 / 0.45),
+            Transfer::Gamma(gamma) => value.max(0.0).powf(gamma),
+            Transfer::SLog3 if value * 1023.0 >= SLOG3_CUT => {
+                10f32.powf((value * 1023.0 - 420.0) / 261.5) * 0.19 - 0.01
+            }
+            Transfer::SLog3 => (value * 1023.0 - 95.0) * 0.011_25 / (SLOG3_CUT - 95.0),
+            Transfer::LogC3 => {
+                let [cut, a, b, c, d, e, f] = LOGC3;
+                if value > e * cut + f { (10f32.powf((value - d) / c) - b) / a } else { (value - f) / e }
+            }
+        }
+    }
+
+    /// Converts linear light to an encoded value; the inverse of
+    /// [`decode`](Self::decode).
+    pub fn encode(self, linear: f32) -> f32 {
+        match self {
+            Transfer::Srgb if linear <= 0.003_130_8 => linear * 12.92,
+            Transfer::Srgb => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+            Transfer::Rec709 if linear < 0.018 => linear * 4.5,
+            Transfer::Rec709 => 1.099 * linear.powf(0.45) - 0.099,
+            Transfer::Gamma(gamma) => linear.max(0.0).powf(1.0 / gamma),
+            Transfer::SLog3 if linear >= 0.011_25 => (420.0 + ((linear + 0.01) / 0.19).log10() * 261.5) / 1023.0,
+            Transfer::SLog3 => (linear * (SLOG3_CUT - 95.0) / 0.011_25 + 95.0) / 1023.0,
+            Transfer::LogC3 => {
+                let [cut, a, b, c, d, e, f] = LOGC3;
+                if linear > cut { c * (a * linear + b).log10() + d } else { e * linear + f }
+            }
+        }
+    }
+
+    /// Fails unless a [`Gamma`](Transfer::Gamma) exponent is within 0.1-10.
+    pub(crate) fn check(self) -> Result<()> {
+        match self {
+            Transfer::Gamma(gamma) if !(0.1..=10.0).contains(&gamma) => Err(ImageError::InvalidParameter {
+                param: "gamma".to_string(),
+                value: gamma.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Linear light at encoded 0 and 1. Log curves reach below 0 and far
+    /// above 1, so linear values are stored relative to this range.
+    fn range(self) -> (f32, f32) {
+        (self.decode(0.0), self.decode(1.0))
+    }
+}
+
+impl std::str::FromStr for Transfer {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        let gamma = s.strip_prefix("gamma").map(|rest| rest.trim_start_matches([':', ' '])).unwrap_or(&s);
+        match s.as_str() {
+            "srgb" => Ok(Transfer::Srgb),
+            "rec709" | "bt709" => Ok(Transfer::Rec709),
+            "slog3" | "s-log3" => Ok(Transfer::SLog3),
+            "logc3" | "logc" => Ok(Transfer::LogC3),
+            _ => {
+                let transfer = gamma.parse().map(Transfer::Gamma).map_err(|_| ImageError::Parse(format!(
+                    "unknown transfer function '{}' (use 'srgb', 'rec709', 'slog3', 'logc3' or a gamma such as 'gamma:2.2')", s
+                )))?;
+                transfer.check()?;
+                Ok(transfer)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transfer::Srgb => write!(f, "srgb"),
+            Transfer::Rec709 => write!(f, "rec709"),
+            Transfer::Gamma(gamma) => write!(f, "gamma:{}", gamma),
+            Transfer::SLog3 => write!(f, "slog3"),
+            Transfer::LogC3 => write!(f, "logc3"),
+        }
+    }
+}
+
+/// Decodes the color channels of `image` to 16-bit linear light, relative
+/// to the transfer's range. Alpha is widened unchanged.
+pub(crate) fn to_linear<P: SharpenPixel>(image: &Image<P>, transfer: Transfer) -> Image<P::Wide> {
+    let (low, high) = transfer.range();
+    let max = max_value::<P>();
+    let table: Vec<u16> = (0..=max as u32)
+        .map(|value| ((transfer.decode(value as f32 / max) - low) / (high - low) * 65535.0).round().clamp(0.0, 65535.0) as u16)
+        .collect();
+    let alpha = 65535.0 / max;
+    let buffer = convert::<P, P::Wide>(image.data.get_ref(), |i, value| {
+        if i < P::COLOR_CHANNELS { table[sample::<P>(value) as usize] } else { (sample::<P>(value) * alpha).round() as u16 }
+    });
+    Image::from_buffer_unchecked(buffer).with_luminance(image.luminance())
+}
+
+/// Encodes `linear`, from [`to_linear`], back to `image`'s format.
+pub(crate) fn from_linear<P: SharpenPixel>(linear: &Image<P::Wide>, image: &Image<P>, transfer: Transfer) -> Image<P> {
+    let (low, high) = transfer.range();
+    let max = max_value::<P>();
+    let table: Vec<P::Subpixel> = (0..=u16::MAX)
+        .map(|value| to_sample::<P>(transfer.encode(low + value as f32 / 65535.0 * (high - low)) * max))
+        .collect();
+    let alpha = max / 65535.0;
+    image.with_pixels(convert::<P::Wide, P>(linear.data.get_ref(), |i, value| {
+        if i < P::COLOR_CHANNELS { table[value as usize] } else { to_sample::<P>(value as f32 * alpha) }
+    }))
+}
+
+/// Maps every sample of `img`, given its channel index, into a buffer of
+/// another sample type with the same layout.
+fn convert<P: SharpenPixel, Q: SharpenPixel>(img: &Buffer<P>, map: impl Fn(usize, P::Subpixel) -> Q::Subpixel + Sync) -> Buffer<Q> {
+    let (width, height) = img.dimensions();
+    let mut output = Buffer::<Q>::new(width, height);
+    output.enumerate_rows_mut().par_bridge().for_each(|(_, row)| {
+        for (x, y, pixel) in row {
+            for (i, (value, &source)) in pixel.channels_mut().iter_mut().zip(img.get_pixel(x, y).channels()).enumerate() {
+                *value = map(i, source);
+            }
+        }
+    });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    const TRANSFERS: [Transfer; 5] = [Transfer::Srgb, Transfer::Rec709, Transfer::Gamma(2.2), Transfer::SLog3, Transfer::LogC3];
+
+    #[test]
+    fn test_curves() {
+        for transfer in TRANSFERS {
+            for step in 0..=20 {
+                let value = step as f32 / 20.0;
+                let round_trip = transfer.encode(transfer.decode(value));
+                assert!((round_trip - value).abs() < 1e-4, "{} at {}: {}", transfer, value, round_trip);
+            }
+        }
+        // Mid gray lands where each curve puts it
+        assert!((Transfer::Srgb.decode(0.5) - 0.214).abs() < 1e-3);
+        assert!((Transfer::SLog3.encode(0.18) - 420.0 / 1023.0).abs() < 1e-4);
+        assert!((Transfer::LogC3.encode(0.18) - 0.391).abs() < 1e-3);
+
+        assert_eq!("gamma:2.2".parse::<Transfer>().unwrap(), Transfer::Gamma(2.2));
+        assert_eq!("1.8".parse::<Transfer>().unwrap(), Transfer::Gamma(1.8));
+        for transfer in TRANSFERS {
+            assert_eq!(transfer.to_string().parse::<Transfer>().unwrap(), transfer);
+        }
+        assert!("gamma:0".parse::<Transfer>().is_err());
+        assert!("pq".parse::<Transfer>().is_err());
+    }
+
+    #[test]
+    fn test_linear_round_trip() {
+        let img = RgbaImage::from_fn(256, 2, |x, y| Rgba([x as u8, 255 - x as u8, (x * 7) as u8, (y * 200) as u8]));
+        let image = Image::from_buffer(img.clone()).unwrap();
+        for transfer in [Transfer::Srgb, Transfer::Rec709] {
+            let back = from_linear(&to_linear(&image, transfer), &image, transfer);
+            assert!(back.into_buffer() == img, "{}", transfer);
+        }
+        // Curves without a linear toe, or spanning far more than 1.0 of
+        // linear light, share 16-bit linear values among the darkest levels
+        for transfer in [Transfer::Gamma(2.2), Transfer::SLog3, Transfer::LogC3] {
+            let back = from_linear(&to_linear(&image, transfer), &image, transfer).into_buffer();
+            let worst = back.as_raw().iter().zip(img.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+            assert!(worst <= 1, "{}: {}", transfer, worst);
+        }
+    }
+
+    #[test]
+    fn test_linearize() {
+        let edge = Image::from_rgb(RgbImage::from_fn(32, 8, |x, _| if x < 16 { Rgb([40, 40, 40]) } else { Rgb([200, 200, 200]) })).unwrap();
+        let plain = edge.clone().sharpen().unsharp_mask(1.5, 1.0, 0).apply().unwrap().into_rgb();
+        // An identity curve only adds 16-bit rounding
+        let identity = edge.clone().sharpen().linearize(Transfer::Gamma(1.0)).unsharp_mask(1.5, 1.0, 0).apply().unwrap().into_rgb();
+        for (a, b) in plain.pixels().zip(identity.pixels()) {
+            assert!((a[0] as i32 - b[0] as i32).abs() <= 1);
+        }
+        // In linear light the dark side's halo is the deeper one
+        let linear = edge.clone().sharpen().linearize(Transfer::Srgb).unsharp_mask(1.5, 1.0, 0).apply().unwrap().into_rgb();
+        let halos = |img: &RgbImage| (40 - img.get_pixel(15, 4)[0] as i32, img.get_pixel(16, 4)[0] as i32 - 200);
+        let (plain_dark, plain_light) = halos(&plain);
+        let (dark, light) = halos(&linear);
+        assert!(dark - light > plain_dark - plain_light, "{:?} {:?}", halos(&plain), halos(&linear));
+
+        assert!(edge.sharpen().linearize(Transfer::Gamma(-1.0)).high_pass(0.5).apply().is_err());
+    }
+}
@@ -1,116 +1,261 @@
-use image::{RgbImage, Rgb};
-use rayon::prelude::*;
-use std::sync::Arc;
+use crate::context::Context;
+use crate::kernel::Kernel;
+use crate::par::*;
+use crate::pixel::{Buffer, SharpenPixel, max_value, sample, to_sample};
+use crate::trace::timed;
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum EdgeMethod {
     Sobel,
     Prewitt,
 }
 
+impl std::str::FromStr for EdgeMethod {
+    type Err = crate::ImageError;
+    
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "sobel" => Ok(EdgeMethod::Sobel),
+            "prewitt" => Ok(EdgeMethod::Prewitt),
+            _ => Err(crate::ImageError::Parse(format!(
+                "unknown edge method '{}' (use 'sobel' or 'prewitt')", s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for EdgeMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdgeMethod::Sobel => write!(f, "sobel"),
+            EdgeMethod::Prewitt => write!(f, "prewitt"),
+        }
+    }
+}
+
+/// Weights of the red, green and blue channels in an image's luminance,
+/// used wherever Sharpy works on luminance: edge detection, clarity, text
+/// sharpening, masks, analysis and [`Image::histogram`](crate::Image::histogram).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Luminance {
+    /// SD video and JPEG (0.299, 0.587, 0.114)
+    #[default]
+    Bt601,
+    /// HD video and sRGB (0.2126, 0.7152, 0.0722)
+    Bt709,
+    /// UHD and HDR video (0.2627, 0.6780, 0.0593)
+    Bt2020,
+    /// The plain average of the channels
+    Equal,
+}
+
+impl Luminance {
+    /// Red, green and blue weights, summing to 1.
+    pub fn weights(self) -> [f32; 3] {
+        match self {
+            Luminance::Bt601 => [0.299, 0.587, 0.114],
+            Luminance::Bt709 => [0.2126, 0.7152, 0.0722],
+            Luminance::Bt2020 => [0.2627, 0.6780, 0.0593],
+            Luminance::Equal => [1.0 / 3.0; 3],
+        }
+    }
+    
+    /// Returns the luminance of a pixel in its sample range (0-255 for
+    /// 8-bit). Single-channel pixels are their own luminance.
+    pub fn of<P: SharpenPixel>(self, pixel: &P) -> f32 {
+        let channels = pixel.channels();
+        if P::COLOR_CHANNELS >= 3 {
+            let [r, g, b] = self.weights();
+            r * sample::<P>(channels[0]) + g * sample::<P>(channels[1]) + b * sample::<P>(channels[2])
+        } else {
+            sample::<P>(channels[0])
+        }
+    }
+}
+
+impl std::str::FromStr for Luminance {
+    type Err = crate::ImageError;
+    
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bt601" | "601" => Ok(Luminance::Bt601),
+            "bt709" | "709" => Ok(Luminance::Bt709),
+            "bt2020" | "2020" => Ok(Luminance::Bt2020),
+            "equal" => Ok(Luminance::Equal),
+            _ => Err(crate::ImageError::Parse(format!(
+                "unknown luminance '{}' (use 'bt601', 'bt709', 'bt2020' or 'equal')", s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Luminance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Luminance::Bt601 => write!(f, "bt601"),
+            Luminance::Bt709 => write!(f, "bt709"),
+            Luminance::Bt2020 => write!(f, "bt2020"),
+            Luminance::Equal => write!(f, "equal"),
+        }
+    }
+}
+
+/// How kernels read pixels beyond the image's edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Boundary {
+    /// Repeat the edge pixels
+    #[default]
+    Clamp,
+    /// Continue from the opposite edge, so tileable textures stay tileable
+    Wrap,
+}
+
+impl Boundary {
+    /// Maps a coordinate that may lie outside `0..len` to a pixel inside.
+    pub(crate) fn index(self, i: i32, len: u32) -> u32 {
+        match self {
+            Boundary::Clamp => i.clamp(0, len as i32 - 1) as u32,
+            Boundary::Wrap => i.rem_euclid(len as i32) as u32,
+        }
+    }
+}
+
+/// Rolls off the change from `original` to `value` as it nears black or
+/// white, instead of clipping it: within the last `knee` share (0-1] of the
+/// headroom between `original` and 0 or `max`, the change follows the
+/// curve `u / (1 + u)`, which meets the straight line with the same slope
+/// and approaches the limit without reaching it, slowly enough to keep
+/// large overshoots apart. Smaller changes pass unchanged.
+pub(crate) fn soft_clip(original: f32, value: f32, knee: f32, max: f32) -> f32 {
+    let change = value - original;
+    let headroom = if change > 0.0 { max - original } else { original };
+    if headroom <= 0.0 {
+        return original;
+    }
+    let share = change.abs() / headroom;
+    let start = 1.0 - knee;
+    if share <= start {
+        return value;
+    }
+    let excess = (share - start) / knee;
+    let rolled = start + knee * excess / (1.0 + excess);
+    original + change.signum() * rolled * headroom
+}
+
 /// Applies Gaussian blur to an image with the specified radius.
 /// 
 /// Uses separable convolution for better performance on larger kernels.
-pub fn gaussian_blur(img: &RgbImage, radius: f32) -> RgbImage {
+/// Buffers and edge handling come from `ctx`.
+pub(crate) fn gaussian_blur<P: SharpenPixel>(img: &Buffer<P>, radius: f32, ctx: &Context) -> Buffer<P> {
     let (width, height) = img.dimensions();
+    let channels = P::CHANNEL_COUNT as usize;
     
     let kernel_size = (radius * 6.0).ceil() as usize | 1;
-    let kernel = Arc::new(generate_gaussian_kernel(kernel_size, radius));
+    let kernel = generate_gaussian_kernel(kernel_size, radius);
     let half_kernel = kernel_size / 2;
+    let boundary = ctx.boundary();
+    let _span = timed!(DEBUG, "blur", radius, kernel_size, width, height);
     
     // First pass: horizontal blur
-    let mut temp = RgbImage::new(width, height);
+    let mut temp = ctx.buffer::<P>(width, height);
     
     // Process in parallel chunks for better cache locality
-    temp.enumerate_rows_mut()
-        .par_bridge()
-        .for_each(|(y, row)| {
-            for (x, _, pixel) in row {
-                let mut r_sum = 0.0;
-                let mut g_sum = 0.0;
-                let mut b_sum = 0.0;
-                let mut weight_sum = 0.0;
-                
-                for k in 0..kernel_size {
-                    let img_x = (x as i32 + k as i32 - half_kernel as i32)
-                        .max(0)
-                        .min(width as i32 - 1) as u32;
+    {
+        let _pass = timed!(TRACE, "blur_pass", direction = "horizontal");
+        temp.enumerate_rows_mut()
+            .par_bridge()
+            .for_each(|(y, row)| {
+                for (x, _, pixel) in row {
+                    let mut sums = [0.0; 4];
+                    let mut weight_sum = 0.0;
                     
-                    let source_pixel = img.get_pixel(img_x, y);
-                    let weight = kernel[k];
+                    for (k, &weight) in kernel.iter().enumerate() {
+                        let img_x = boundary.index(x as i32 + k as i32 - half_kernel as i32, width);
                     
-                    r_sum += source_pixel[0] as f32 * weight;
-                    g_sum += source_pixel[1] as f32 * weight;
-                    b_sum += source_pixel[2] as f32 * weight;
-                    weight_sum += weight;
+                        let source_pixel = img.get_pixel(img_x, y).channels();
+                        for c in 0..channels {
+                            sums[c] += sample::<P>(source_pixel[c]) * weight;
+                        }
+                        weight_sum += weight;
+                    }
+                    
+                    for (c, value) in pixel.channels_mut().iter_mut().enumerate() {
+                        *value = to_sample::<P>(sums[c] / weight_sum);
+                    }
                 }
-                
-                *pixel = Rgb([
-                    (r_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                    (g_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                    (b_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                ]);
-            }
-        });
+            });
+    }
     
     // Second pass: vertical blur
-    let mut result = RgbImage::new(width, height);
-    let temp = Arc::new(temp);
-    let kernel = Arc::clone(&kernel);
+    let mut result = ctx.buffer::<P>(width, height);
     
-    // Process all pixels and collect results
-    let pixel_values: Vec<_> = (0..width).into_par_iter()
-        .flat_map(|x| {
-            let temp = Arc::clone(&temp);
-            let kernel = Arc::clone(&kernel);
-            (0..height).into_par_iter().map(move |y| {
-                let mut r_sum = 0.0;
-                let mut g_sum = 0.0;
-                let mut b_sum = 0.0;
-                let mut weight_sum = 0.0;
-                
-                for k in 0..kernel_size {
-                    let img_y = (y as i32 + k as i32 - half_kernel as i32)
-                        .max(0)
-                        .min(height as i32 - 1) as u32;
+    {
+        let _pass = timed!(TRACE, "blur_pass", direction = "vertical");
+        result.enumerate_rows_mut()
+            .par_bridge()
+            .for_each(|(y, row)| {
+                for (x, _, pixel) in row {
+                    let mut sums = [0.0; 4];
+                    let mut weight_sum = 0.0;
                     
-                    let source_pixel = temp.get_pixel(x, img_y);
-                    let weight = kernel[k];
+                    for (k, &weight) in kernel.iter().enumerate() {
+                        let img_y = boundary.index(y as i32 + k as i32 - half_kernel as i32, height);
                     
-                    r_sum += source_pixel[0] as f32 * weight;
-                    g_sum += source_pixel[1] as f32 * weight;
-                    b_sum += source_pixel[2] as f32 * weight;
-                    weight_sum += weight;
+                        let source_pixel = temp.get_pixel(x, img_y).channels();
+                        for c in 0..channels {
+                            sums[c] += sample::<P>(source_pixel[c]) * weight;
+                        }
+                        weight_sum += weight;
+                    }
+                    
+                    for (c, value) in pixel.channels_mut().iter_mut().enumerate() {
+                        *value = to_sample::<P>(sums[c] / weight_sum);
+                    }
                 }
-                
-                let pixel = Rgb([
-                    (r_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                    (g_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                    (b_sum / weight_sum).round().clamp(0.0, 255.0) as u8,
-                ]);
-                
-                (x, y, pixel)
-            })
-        })
-        .collect();
-    
-    // Apply all pixel values
-    for (x, y, pixel) in pixel_values {
-        result.put_pixel(x, y, pixel);
+            });
     }
     
+    ctx.recycle(temp);
     result
 }
 
+/// Gaussian blur of a plane, reading beyond its edges as `boundary` says.
+pub(crate) fn blur_plane(plane: &[f32], width: u32, height: u32, sigma: f32, boundary: Boundary) -> Vec<f32> {
+    let half = (sigma * 3.0).ceil() as i32;
+    let kernel: Vec<f32> = (-half..=half).map(|k| (-(k * k) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = kernel.iter().sum();
+    let pass = |source: &[f32], horizontal: bool| -> Vec<f32> {
+        (0..height).into_par_iter()
+            .flat_map(|y| {
+                (0..width).map(|x| {
+                    kernel.iter().zip(-half..=half).map(|(weight, k)| {
+                        let (sx, sy) = if horizontal {
+                            (boundary.index(x as i32 + k, width), y)
+                        } else {
+                            (x, boundary.index(y as i32 + k, height))
+                        };
+                        weight * source[sy as usize * width as usize + sx as usize]
+                    }).sum::<f32>() / total
+                }).collect::<Vec<_>>()
+            })
+            .collect()
+    };
+    pass(&pass(plane, true), false)
+}
+
 fn generate_gaussian_kernel(size: usize, sigma: f32) -> Vec<f32> {
     let mut kernel = vec![0.0; size];
     let half_size = size / 2;
     let two_sigma_sq = 2.0 * sigma * sigma;
     
     // 1D Gaussian kernel
-    for i in 0..size {
+    for (i, value) in kernel.iter_mut().enumerate() {
         let x = i as f32 - half_size as f32;
-        kernel[i] = (-x * x / two_sigma_sq).exp();
+        *value = (-x * x / two_sigma_sq).exp();
     }
     
     let sum: f32 = kernel.iter().sum();
@@ -121,205 +266,211 @@ fn generate_gaussian_kernel(size: usize, sigma: f32) -> Vec<f32> {
     kernel
 }
 
-/// Applies a convolution kernel to an image.
+/// Applies a convolution kernel to the color channels of an image; alpha
+/// is kept.
 /// 
-/// Optimized for small kernels (3x3, 5x5) commonly used in sharpening.
-pub fn apply_convolution(
-    img: &RgbImage,
-    kernel: &[f32],
-    kernel_size: usize,
-) -> RgbImage {
+/// Separable kernels large enough to benefit run as a horizontal and a
+/// vertical pass; others, like the 3x3 kernels used in sharpening, as one
+/// 2D pass.
+pub(crate) fn apply_convolution<P: SharpenPixel>(img: &Buffer<P>, kernel: &Kernel, ctx: &Context) -> Buffer<P> {
+    match kernel.factors() {
+        Some((column, row)) if kernel.runs_separately() => convolve_separable(img, column, row, ctx),
+        _ => convolve_2d(img, kernel, ctx),
+    }
+}
+
+fn convolve_2d<P: SharpenPixel>(img: &Buffer<P>, kernel: &Kernel, ctx: &Context) -> Buffer<P> {
     let (width, height) = img.dimensions();
-    let mut result = RgbImage::new(width, height);
-    let half_kernel = kernel_size / 2;
+    let mut result = ctx.buffer::<P>(width, height);
+    let (kernel_width, kernel_height) = (kernel.width(), kernel.height());
+    let weights = kernel.weights();
+    let (half_width, half_height) = (kernel_width / 2, kernel_height / 2);
+    let boundary = ctx.boundary();
     
-    // Calculate all convolved pixels in parallel
-    let pixel_values: Vec<_> = (0..height).into_par_iter()
-        .flat_map(|y| {
-            (0..width).into_par_iter().map(move |x| {
-                let mut r_sum = 0.0;
-                let mut g_sum = 0.0;
-                let mut b_sum = 0.0;
+    // Calculate convolved rows in parallel
+    result.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let mut sums = [0.0; 4];
                 
-                for ky in 0..kernel_size {
-                    for kx in 0..kernel_size {
-                        let img_x = (x as i32 + kx as i32 - half_kernel as i32)
-                            .max(0)
-                            .min(width as i32 - 1) as u32;
-                        let img_y = (y as i32 + ky as i32 - half_kernel as i32)
-                            .max(0)
-                            .min(height as i32 - 1) as u32;
+                for ky in 0..kernel_height {
+                    let img_y = boundary.index(y as i32 + ky as i32 - half_height as i32, height);
+                    for kx in 0..kernel_width {
+                        let img_x = boundary.index(x as i32 + kx as i32 - half_width as i32, width);
                         
-                        let source_pixel = img.get_pixel(img_x, img_y);
-                        let weight = kernel[ky * kernel_size + kx];
+                        let source_pixel = img.get_pixel(img_x, img_y).channels();
+                        let weight = weights[ky * kernel_width + kx];
                         
-                        r_sum += source_pixel[0] as f32 * weight;
-                        g_sum += source_pixel[1] as f32 * weight;
-                        b_sum += source_pixel[2] as f32 * weight;
+                        for c in 0..P::COLOR_CHANNELS {
+                            sums[c] += sample::<P>(source_pixel[c]) * weight;
+                        }
                     }
                 }
                 
-                let pixel = Rgb([
-                    r_sum.round().clamp(0.0, 255.0) as u8,
-                    g_sum.round().clamp(0.0, 255.0) as u8,
-                    b_sum.round().clamp(0.0, 255.0) as u8,
-                ]);
-                
-                (x, y, pixel)
-            })
+                let channels = pixel.channels_mut();
+                for c in 0..P::COLOR_CHANNELS {
+                    channels[c] = to_sample::<P>(sums[c]);
+                }
+                channels[P::COLOR_CHANNELS..].copy_from_slice(&img.get_pixel(x, y).channels()[P::COLOR_CHANNELS..]);
+            }
+        });
+    
+    result
+}
+
+/// Convolves with the outer product of `column` and `row`: horizontally
+/// into floats, so samples are rounded once, then vertically.
+fn convolve_separable<P: SharpenPixel>(img: &Buffer<P>, column: &[f32], row: &[f32], ctx: &Context) -> Buffer<P> {
+    let (width, height) = img.dimensions();
+    let channels = P::COLOR_CHANNELS;
+    let boundary = ctx.boundary();
+    let _span = timed!(DEBUG, "separable_convolution", width, height, kernel_width = row.len(), kernel_height = column.len());
+    
+    let half = row.len() / 2;
+    let horizontal: Vec<f32> = (0..height).into_par_iter()
+        .flat_map(|y| {
+            let mut sums = vec![0.0; width as usize * channels];
+            for (x, pixel_sums) in sums.chunks_mut(channels).enumerate() {
+                for (k, &weight) in row.iter().enumerate() {
+                    let img_x = boundary.index(x as i32 + k as i32 - half as i32, width);
+                    let source_pixel = img.get_pixel(img_x, y).channels();
+                    for c in 0..channels {
+                        pixel_sums[c] += sample::<P>(source_pixel[c]) * weight;
+                    }
+                }
+            }
+            sums
         })
         .collect();
     
-    // Apply all pixel values
-    for (x, y, pixel) in pixel_values {
-        result.put_pixel(x, y, pixel);
-    }
+    let half = column.len() / 2;
+    let mut result = ctx.buffer::<P>(width, height);
+    result.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, pixels)| {
+            for (x, _, pixel) in pixels {
+                let mut sums = [0.0; 4];
+                for (k, &weight) in column.iter().enumerate() {
+                    let img_y = boundary.index(y as i32 + k as i32 - half as i32, height);
+                    let offset = (img_y as usize * width as usize + x as usize) * channels;
+                    for c in 0..channels {
+                        sums[c] += horizontal[offset + c] * weight;
+                    }
+                }
+                
+                let pixel_channels = pixel.channels_mut();
+                for c in 0..channels {
+                    pixel_channels[c] = to_sample::<P>(sums[c]);
+                }
+                pixel_channels[channels..].copy_from_slice(&img.get_pixel(x, y).channels()[channels..]);
+            }
+        });
     
     result
 }
 
-pub fn get_high_pass_kernel() -> ([f32; 9], usize) {
-    (
-        [
-            0.0, -1.0, 0.0,
-            -1.0, 5.0, -1.0,
-            0.0, -1.0, 0.0,
-        ],
-        3,
-    )
+pub(crate) fn get_high_pass_kernel() -> Kernel {
+    Kernel::new(3, 3, vec![
+        0.0, -1.0, 0.0,
+        -1.0, 5.0, -1.0,
+        0.0, -1.0, 0.0,
+    ]).expect("3x3 kernel")
 }
 
-pub fn get_sobel_kernels() -> (([f32; 9], usize), ([f32; 9], usize)) {
-    let x_kernel = (
-        [
-            -1.0, 0.0, 1.0,
-            -2.0, 0.0, 2.0,
-            -1.0, 0.0, 1.0,
-        ],
-        3,
-    );
+pub(crate) fn get_sobel_kernels() -> (Kernel, Kernel) {
+    let x_kernel = Kernel::new(3, 3, vec![
+        -1.0, 0.0, 1.0,
+        -2.0, 0.0, 2.0,
+        -1.0, 0.0, 1.0,
+    ]).expect("3x3 kernel");
     
-    let y_kernel = (
-        [
-            -1.0, -2.0, -1.0,
-            0.0, 0.0, 0.0,
-            1.0, 2.0, 1.0,
-        ],
-        3,
-    );
+    let y_kernel = Kernel::new(3, 3, vec![
+        -1.0, -2.0, -1.0,
+        0.0, 0.0, 0.0,
+        1.0, 2.0, 1.0,
+    ]).expect("3x3 kernel");
     
     (x_kernel, y_kernel)
 }
 
-pub fn get_prewitt_kernels() -> (([f32; 9], usize), ([f32; 9], usize)) {
-    let x_kernel = (
-        [
-            -1.0, 0.0, 1.0,
-            -1.0, 0.0, 1.0,
-            -1.0, 0.0, 1.0,
-        ],
-        3,
-    );
+pub(crate) fn get_prewitt_kernels() -> (Kernel, Kernel) {
+    let x_kernel = Kernel::new(3, 3, vec![
+        -1.0, 0.0, 1.0,
+        -1.0, 0.0, 1.0,
+        -1.0, 0.0, 1.0,
+    ]).expect("3x3 kernel");
     
-    let y_kernel = (
-        [
-            -1.0, -1.0, -1.0,
-            0.0, 0.0, 0.0,
-            1.0, 1.0, 1.0,
-        ],
-        3,
-    );
+    let y_kernel = Kernel::new(3, 3, vec![
+        -1.0, -1.0, -1.0,
+        0.0, 0.0, 0.0,
+        1.0, 1.0, 1.0,
+    ]).expect("3x3 kernel");
     
     (x_kernel, y_kernel)
 }
 
-/// Blends two images with the specified strength.
+/// Blends `processed` with `original` per pixel, weighted by `mask`: white
+/// keeps `processed`, black restores `original`. The result is written into
+/// `processed`; `mask` must have the same dimensions.
 /// 
-/// Optimized version that operates directly on pixel data.
-pub fn blend_images(original: &RgbImage, processed: &RgbImage, strength: f32) -> RgbImage {
-    let (width, height) = original.dimensions();
-    let mut result = RgbImage::new(width, height);
-    
-    let blend_factor = strength.clamp(0.0, 1.0);
-    let inv_blend = 1.0 - blend_factor;
-    
-    // Process all pixels in parallel
-    let pixel_values: Vec<_> = (0..height).into_par_iter()
-        .flat_map(|y| {
-            (0..width).into_par_iter().map(move |x| {
-                let orig_pixel = original.get_pixel(x, y);
-                let proc_pixel = processed.get_pixel(x, y);
-                
-                let pixel = Rgb([
-                    (orig_pixel[0] as f32 * inv_blend + proc_pixel[0] as f32 * blend_factor)
-                        .round()
-                        .clamp(0.0, 255.0) as u8,
-                    (orig_pixel[1] as f32 * inv_blend + proc_pixel[1] as f32 * blend_factor)
-                        .round()
-                        .clamp(0.0, 255.0) as u8,
-                    (orig_pixel[2] as f32 * inv_blend + proc_pixel[2] as f32 * blend_factor)
-                        .round()
-                        .clamp(0.0, 255.0) as u8,
-                ]);
+/// Color channels are blended; alpha is taken from `original`.
+pub(crate) fn blend_masked<P: SharpenPixel>(original: &Buffer<P>, processed: &mut Buffer<P>, mask: &GrayImage) {
+    processed.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let orig_pixel = original.get_pixel(x, y).channels();
+                let weight = f32::from(mask.get_pixel(x, y)[0]) / 255.0;
+                let channels = pixel.channels_mut();
                 
-                (x, y, pixel)
-            })
-        })
-        .collect();
-    
-    // Apply all pixel values
-    for (x, y, pixel) in pixel_values {
-        result.put_pixel(x, y, pixel);
-    }
-    
-    result
-}
-
-pub fn calculate_luminance(pixel: &Rgb<u8>) -> f32 {
-    0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+                for c in 0..P::COLOR_CHANNELS {
+                    let blended = sample::<P>(orig_pixel[c]) * (1.0 - weight) + sample::<P>(channels[c]) * weight;
+                    channels[c] = to_sample::<P>(blended);
+                }
+                channels[P::COLOR_CHANNELS..].copy_from_slice(&orig_pixel[P::COLOR_CHANNELS..]);
+            }
+        });
 }
 
 /// Applies edge detection using the specified method.
 /// 
-/// Combines horizontal and vertical edge detection kernels.
-pub fn apply_edge_detection(
-    img: &RgbImage,
+/// Combines horizontal and vertical edge detection kernels. The magnitude
+/// is written to every color channel; alpha is opaque.
+pub(crate) fn apply_edge_detection<P: SharpenPixel>(
+    img: &Buffer<P>,
     method: EdgeMethod,
-) -> RgbImage {
+    ctx: &Context,
+) -> Buffer<P> {
     let (x_kernel, y_kernel) = match method {
         EdgeMethod::Sobel => get_sobel_kernels(),
         EdgeMethod::Prewitt => get_prewitt_kernels(),
     };
     
-    let x_edges = Arc::new(apply_convolution(img, &x_kernel.0, x_kernel.1));
-    let y_edges = Arc::new(apply_convolution(img, &y_kernel.0, y_kernel.1));
+    let x_edges = apply_convolution(img, &x_kernel, ctx);
+    let y_edges = apply_convolution(img, &y_kernel, ctx);
     
     let (width, height) = img.dimensions();
-    let mut result = RgbImage::new(width, height);
+    let max = max_value::<P>();
+    let mut result = ctx.buffer::<P>(width, height);
     
     // Calculate edge magnitudes in parallel
-    let pixel_values: Vec<_> = (0..height).into_par_iter()
-        .flat_map(|y| {
-            let x_edges = Arc::clone(&x_edges);
-            let y_edges = Arc::clone(&y_edges);
-            (0..width).into_par_iter().map(move |x| {
-                let x_pixel = x_edges.get_pixel(x, y);
-                let y_pixel = y_edges.get_pixel(x, y);
-                
-                let x_mag = calculate_luminance(x_pixel);
-                let y_mag = calculate_luminance(y_pixel);
-                let magnitude = (x_mag * x_mag + y_mag * y_mag).sqrt().clamp(0.0, 255.0) as u8;
+    result.enumerate_rows_mut()
+        .par_bridge()
+        .for_each(|(y, row)| {
+            for (x, _, pixel) in row {
+                let x_mag = ctx.luminance().of(x_edges.get_pixel(x, y));
+                let y_mag = ctx.luminance().of(y_edges.get_pixel(x, y));
+                let magnitude = to_sample::<P>((x_mag * x_mag + y_mag * y_mag).sqrt().clamp(0.0, max).floor());
                 
-                let pixel = Rgb([magnitude, magnitude, magnitude]);
-                (x, y, pixel)
-            })
-        })
-        .collect();
-    
-    // Apply all pixel values
-    for (x, y, pixel) in pixel_values {
-        result.put_pixel(x, y, pixel);
-    }
+                let channels = pixel.channels_mut();
+                for (c, value) in channels.iter_mut().enumerate() {
+                    *value = if c < P::COLOR_CHANNELS { magnitude } else { to_sample::<P>(max) };
+                }
+            }
+        });
     
+    ctx.recycle(x_edges);
+    ctx.recycle(y_edges);
     result
-}
\ No newline at end of file
+}
@@ -0,0 +1,214 @@
+//! Sharpening ordered frame sequences (video stills) without flicker.
+//!
+//! Sharpening each frame on its own lets parameters chosen from analysis,
+//! and masks computed per frame, jump between neighbouring frames, which
+//! shows as flicker on playback. [`TemporalSharpener`] carries an
+//! exponential moving average of both from frame to frame.
+
+use crate::{Analysis, Image, ImageError, OutputTarget, Pipeline, Result};
+use image::{GrayImage, Luma};
+
+/// Weight of the previous frames in the moving averages by default.
+const DEFAULT_SMOOTHING: f32 = 0.8;
+
+/// Where the parameters for each frame come from.
+#[derive(Debug, Clone)]
+enum Parameters {
+    /// Suggested from the smoothed analysis of the frames so far
+    Auto(OutputTarget),
+    /// The same operations for every frame
+    Fixed(Pipeline),
+}
+
+/// Sharpens the frames of a sequence in order, smoothing parameters and
+/// masks over time.
+///
+/// # Example
+/// ```no_run
+/// use sharpy::{Image, OutputTarget, TemporalSharpener};
+///
+/// # fn main() -> sharpy::Result<()> {
+/// let mut sharpener = TemporalSharpener::auto(OutputTarget::Screen).smoothing(0.9);
+/// for number in 1..=240 {
+///     let frame = Image::load(format!("frames/{:06}.png", number))?;
+///     sharpener.process(frame, None)?.save(format!("sharp/{:06}.png", number))?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TemporalSharpener {
+    parameters: Parameters,
+    smoothing: f32,
+    analysis: Option<Analysis>,
+    /// Smoothed mask values (0-255) and the mask's size
+    mask: Option<(Vec<f32>, (u32, u32))>,
+    pipeline: Option<Pipeline>,
+}
+
+impl TemporalSharpener {
+    /// Sharpens with [`Analysis::suggest_for`] parameters, suggested from
+    /// the blur and noise averaged over recent frames.
+    pub fn auto(target: OutputTarget) -> Self {
+        Self::new(Parameters::Auto(target))
+    }
+
+    /// Applies `pipeline` to every frame; only masks are smoothed.
+    pub fn fixed(pipeline: Pipeline) -> Self {
+        Self::new(Parameters::Fixed(pipeline))
+    }
+
+    fn new(parameters: Parameters) -> Self {
+        Self { parameters, smoothing: DEFAULT_SMOOTHING, analysis: None, mask: None, pipeline: None }
+    }
+
+    /// Sets the weight of earlier frames in the moving averages, from 0.0
+    /// (each frame on its own) to below 1.0 (slower to follow changes).
+    /// Defaults to 0.8.
+    pub fn smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Forgets earlier frames, e.g. at a scene cut, so the next frame's
+    /// parameters and mask aren't blended with the previous shot's.
+    pub fn reset(&mut self) {
+        self.analysis = None;
+        self.mask = None;
+    }
+
+    /// Returns the operations applied to the last processed frame.
+    pub fn pipeline(&self) -> Option<&Pipeline> {
+        self.pipeline.as_ref()
+    }
+
+    /// Sharpens the next frame. With a `mask`, the result is applied where
+    /// the smoothed mask is bright (see
+    /// [`SharpeningBuilder::mask`](crate::SharpeningBuilder::mask)); masks
+    /// must have the frame's dimensions.
+    pub fn process(&mut self, frame: Image, mask: Option<&GrayImage>) -> Result<Image> {
+        if !(0.0..1.0).contains(&self.smoothing) {
+            return Err(ImageError::InvalidParameter {
+                param: "smoothing".to_string(),
+                value: self.smoothing.to_string(),
+            });
+        }
+
+        let pipeline = match &self.parameters {
+            Parameters::Fixed(pipeline) => pipeline.clone(),
+            Parameters::Auto(target) => {
+                let current = frame.analyze();
+                let analysis = match self.analysis {
+                    Some(previous) => {
+                        let mix = |old: f64, new: f64| ema(old as f32, new as f32, self.smoothing) as f64;
+                        Analysis {
+                            blur: mix(previous.blur, current.blur),
+                            sharpness: mix(previous.sharpness, current.sharpness),
+                            noise: mix(previous.noise, current.noise),
+                        }
+                    }
+                    None => current,
+                };
+                self.analysis = Some(analysis);
+                analysis.suggest_for(*target)
+            }
+        };
+
+        let mut builder = frame.sharpen().with_operations(pipeline.operations().iter().cloned());
+        if let Some(mask) = mask {
+            builder = builder.mask(self.smooth_mask(mask));
+        }
+        let result = builder.apply()?;
+        self.pipeline = Some(pipeline);
+        Ok(result)
+    }
+
+    /// Blends `mask` into the running average and returns the average. A
+    /// mask of a new size starts a new average.
+    fn smooth_mask(&mut self, mask: &GrayImage) -> GrayImage {
+        let size = mask.dimensions();
+        let values = match &mut self.mask {
+            Some((values, previous_size)) if *previous_size == size => {
+                for (value, &Luma([new])) in values.iter_mut().zip(mask.pixels()) {
+                    *value = ema(*value, f32::from(new), self.smoothing);
+                }
+                values
+            }
+            state => &mut state.insert((mask.pixels().map(|&Luma([value])| f32::from(value)).collect(), size)).0,
+        };
+        let (width, height) = size;
+        GrayImage::from_fn(width, height, |x, y| {
+            Luma([values[(y * width + x) as usize].round() as u8])
+        })
+    }
+}
+
+fn ema(previous: f32, current: f32, smoothing: f32) -> f32 {
+    previous * smoothing + current * (1.0 - smoothing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Operation;
+    use image::{Rgb, RgbImage};
+
+    /// Alternates crisp and soft frames, as with focus hunting.
+    fn create_test_frame(index: u32) -> Image {
+        let crisp = RgbImage::from_fn(64, 64, |x, y| Rgb([if (x / 2 + y / 2) % 2 == 0 { 60 } else { 190 }; 3]));
+        let frame = if index.is_multiple_of(2) { crisp } else { image::imageops::blur(&crisp, 2.0) };
+        Image::from_rgb(frame).unwrap()
+    }
+
+    fn radii(smoothing: f32) -> Vec<f32> {
+        let mut sharpener = TemporalSharpener::auto(OutputTarget::Screen).smoothing(smoothing);
+        (0..8).map(|index| {
+            sharpener.process(create_test_frame(index), None).unwrap();
+            match sharpener.pipeline().unwrap().operations()[0] {
+                Operation::UnsharpMask { radius, .. } => radius,
+                ref other => panic!("unexpected {}", other),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_smoothing_steadies_parameters() {
+        let jumps = |radii: Vec<f32>| radii.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f32::max);
+        let unsmoothed = jumps(radii(0.0));
+        let smoothed = jumps(radii(0.8));
+        assert!(unsmoothed > 0.0);
+        assert!(smoothed < unsmoothed / 2.0, "{} vs {}", smoothed, unsmoothed);
+    }
+
+    #[test]
+    fn test_smooth_mask() {
+        let mut sharpener = TemporalSharpener::fixed(Pipeline::parse("unsharp:1:1:0").unwrap()).smoothing(0.5);
+        let on = GrayImage::from_pixel(4, 4, Luma([255]));
+        let off = GrayImage::new(4, 4);
+        assert_eq!(sharpener.smooth_mask(&on).get_pixel(0, 0)[0], 255);
+        assert_eq!(sharpener.smooth_mask(&off).get_pixel(0, 0)[0], 128);
+        assert_eq!(sharpener.smooth_mask(&off).get_pixel(0, 0)[0], 64);
+
+        sharpener.reset();
+        assert_eq!(sharpener.smooth_mask(&on).get_pixel(0, 0)[0], 255);
+        // A new size starts over
+        assert_eq!(sharpener.smooth_mask(&GrayImage::new(2, 2)).get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_process() {
+        let pipeline = Pipeline::parse("unsharp:1:1:0").unwrap();
+        let mut sharpener = TemporalSharpener::fixed(pipeline.clone());
+        let frame = create_test_frame(1);
+        let result = sharpener.process(frame.clone(), None).unwrap();
+        assert_eq!(result.as_rgb(), pipeline.apply(frame.clone()).unwrap().as_rgb());
+
+        // An all-dark mask leaves the frame unchanged
+        let result = sharpener.process(frame.clone(), Some(&GrayImage::new(64, 64))).unwrap();
+        assert_eq!(result.as_rgb(), frame.as_rgb());
+
+        assert!(sharpener.process(frame.clone(), Some(&GrayImage::new(8, 8))).is_err());
+        let mut sharpener = TemporalSharpener::fixed(pipeline).smoothing(1.0);
+        assert!(sharpener.process(frame, None).is_err());
+    }
+}
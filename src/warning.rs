@@ -0,0 +1,140 @@
+//! Conditions that don't stop a pipeline but may spoil its result.
+//!
+//! [`SharpeningBuilder::apply_with_report`](crate::SharpeningBuilder::apply_with_report)
+//! returns them alongside the image, so applications can pass them on to
+//! users instead of failing: heavy clipping, a step reaching beyond the
+//! image, a resized mask, clamped parameters and a source decoded without
+//! all of its precision or alpha.
+
+use crate::Clamp;
+use crate::pixel::{SharpenPixel, max_value, sample};
+use image::{ColorType, ImageBuffer};
+
+/// Share of color samples a pipeline may newly push to black or white
+/// before [`Warning::Clipping`] is reported.
+pub(crate) const CLIPPING_THRESHOLD: f32 = 0.01;
+
+/// A condition that doesn't stop processing but may spoil the result,
+/// reported by [`SharpeningBuilder::apply_with_report`](crate::SharpeningBuilder::apply_with_report).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// This share of the color samples was clipped to black or white by
+    /// the pipeline, usually from too much amount or strength
+    Clipping {
+        /// Newly clipped samples as a fraction of all color samples
+        fraction: f32,
+    },
+    /// The mask didn't match the image and was scaled to fit
+    MaskResized {
+        /// The mask's own dimensions
+        from: (u32, u32),
+        /// The image's dimensions, which the mask was scaled to
+        to: (u32, u32),
+    },
+    /// A step reads further than the image's shorter side, so edge
+    /// handling decides much of its result
+    ReachExceedsImage {
+        /// Position of the step in the pipeline
+        index: usize,
+        /// Name of the step's operation
+        operation: &'static str,
+        /// How far the step reads around each pixel, in pixels
+        reach: u32,
+        /// The image's shorter side
+        size: u32,
+    },
+    /// A parameter was clamped under [`ValidationPolicy::Clamp`](crate::ValidationPolicy::Clamp)
+    Clamped(Clamp),
+    /// The image was decoded from a file with more precision or an alpha
+    /// channel than its pixel type holds, which were lost on loading
+    SourceNarrowed {
+        /// The file's pixel format
+        source: ColorType,
+    },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::Clipping { fraction } => {
+                write!(f, "{:.1}% of samples clipped to black or white", fraction * 100.0)
+            }
+            Warning::MaskResized { from, to } => {
+                write!(f, "mask resized from {}x{} to {}x{}", from.0, from.1, to.0, to.1)
+            }
+            Warning::ReachExceedsImage { index, operation, reach, size } => {
+                write!(f, "step {} ({}): reaches {} pixels, beyond the image's {}-pixel side", index, operation, reach, size)
+            }
+            Warning::Clamped(clamp) => clamp.fmt(f),
+            Warning::SourceNarrowed { source } => {
+                let lost = match (source.bytes_per_pixel() > source.channel_count(), source.has_alpha()) {
+                    (true, true) => "its precision and alpha",
+                    (true, false) => "its precision",
+                    _ => "its alpha",
+                };
+                write!(f, "decoded from {:?} pixels, losing {}", source, lost)
+            }
+        }
+    }
+}
+
+/// Whether pixels of type `P` lose the precision or alpha of a `source`
+/// decoded into them.
+pub(crate) fn narrows<P: SharpenPixel>(source: ColorType) -> bool {
+    let bytes = usize::from(source.bytes_per_pixel() / source.channel_count());
+    let alpha = usize::from(P::CHANNEL_COUNT) > P::COLOR_CHANNELS;
+    bytes > std::mem::size_of::<P::Subpixel>() || (source.has_alpha() && !alpha)
+}
+
+/// Share of the color samples at 0 or the maximum in `after` that weren't
+/// in `before`. Both buffers have the same dimensions.
+pub(crate) fn newly_clipped<P: SharpenPixel>(
+    before: &ImageBuffer<P, Vec<P::Subpixel>>,
+    after: &ImageBuffer<P, Vec<P::Subpixel>>,
+) -> f32 {
+    let max = max_value::<P>();
+    let clipped = |value: P::Subpixel| {
+        let value = sample::<P>(value);
+        value <= 0.0 || value >= max
+    };
+    let mut count = 0usize;
+    for (old, new) in before.pixels().zip(after.pixels()) {
+        let channels = old.channels().iter().zip(new.channels()).take(P::COLOR_CHANNELS);
+        count += channels.filter(|&(&old, &new)| clipped(new) && !clipped(old)).count();
+    }
+    let total = before.width() as usize * before.height() as usize * P::COLOR_CHANNELS;
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 / total as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_newly_clipped() {
+        let before = RgbImage::from_pixel(4, 4, Rgb([128, 0, 255]));
+        let mut after = before.clone();
+        after.put_pixel(0, 0, Rgb([255, 0, 255]));
+        after.put_pixel(1, 0, Rgb([0, 10, 200]));
+        // Two of 48 samples are newly clipped; the ones clipped before don't count
+        assert!((newly_clipped(&before, &after) - 2.0 / 48.0).abs() < 1e-6);
+        assert_eq!(newly_clipped(&before, &before), 0.0);
+    }
+
+    #[test]
+    fn test_narrows() {
+        assert!(narrows::<Rgb<u8>>(ColorType::Rgb16));
+        assert!(narrows::<Rgb<u8>>(ColorType::La8));
+        assert!(!narrows::<Rgb<u8>>(ColorType::L8));
+        assert!(!narrows::<image::Rgba<u8>>(ColorType::Rgba8));
+        assert!(narrows::<image::Rgba<u8>>(ColorType::Rgba16));
+        assert!(!narrows::<Rgb<u16>>(ColorType::Rgb16));
+        assert!(narrows::<Rgb<u16>>(ColorType::Rgb32F));
+    }
+}
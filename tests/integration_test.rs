@@ -1,6 +1,8 @@
-use sharpy::{Image, EdgeMethod};
+use sharpy::{Image, EdgeMethod, ImageError, Limits};
 use image::{RgbImage, Rgb};
 
+type ImageOp = Box<dyn Fn(Image) -> sharpy::Result<Image>>;
+
 /// Create a test image with patterns to test sharpening
 fn create_test_image() -> RgbImage {
     let mut img = RgbImage::new(256, 256);
@@ -188,7 +190,7 @@ fn test_image_dimensions_preserved() {
         let image = Image::from_rgb(img).unwrap();
         
         // Test all operations preserve dimensions
-        let operations: Vec<(&str, Box<dyn Fn(Image) -> sharpy::Result<Image>>)> = vec![
+        let operations: Vec<(&str, ImageOp)> = vec![
             ("unsharp_mask", Box::new(|img| img.unsharp_mask(1.0, 1.0, 0))),
             ("high_pass", Box::new(|img| img.high_pass_sharpen(0.5))),
             ("edge_enhance", Box::new(|img| img.enhance_edges(1.0, EdgeMethod::Sobel))),
@@ -240,6 +242,10 @@ fn test_preset_integration() {
         ("edge_aware", SharpeningPresets::edge_aware(image.clone())),
         ("portrait", SharpeningPresets::portrait(image.clone())),
         ("landscape", SharpeningPresets::landscape(image.clone())),
+        ("document", SharpeningPresets::document(image.clone())),
+        ("astro", SharpeningPresets::astro(image.clone())),
+        ("smart", SharpeningPresets::smart(image.clone())),
+        ("portrait_fs", SharpeningPresets::portrait_fs(image.clone())),
     ];
     
     for (name, builder) in presets {
@@ -250,18 +256,53 @@ fn test_preset_integration() {
 
 #[test]
 fn test_memory_bounds_checking() {
-    // Test that extremely large images are rejected
-    let huge_image = RgbImage::new(100000, 100000); // 10 billion pixels
-    let result = Image::from_rgb(huge_image);
-    assert!(result.is_err(), "Should reject images exceeding memory limits");
+    // Test that extremely large images are rejected (checked before the
+    // 30 GB buffer would be allocated)
+    let result = Image::from_rgba_bytes(100000, 100000, &[]); // 10 billion pixels
+    assert!(matches!(result, Err(ImageError::LimitExceeded { .. })), "Should reject images exceeding memory limits");
+    
+    // Test pixel limits on an allocated image, against a small limit
+    let small_limits = Limits::default().max_pixels(1000);
+    let big_image = RgbImage::new(40, 30); // 1200 pixels
+    let result = Image::from_rgb_with_limits(big_image.clone(), &small_limits);
+    assert!(matches!(result, Err(ImageError::LimitExceeded { .. })), "Should reject images exceeding pixel limits");
     
     // Test dimension limits
     let tall_image = RgbImage::new(100, 70000); // Exceeds max dimension
-    let result = Image::from_rgb(tall_image);
+    let result = Image::from_rgb(tall_image.clone());
     assert!(result.is_err(), "Should reject images exceeding dimension limits");
     
+    // Test that limits can be raised for legitimate giant images
+    let limits = Limits::default().max_dimensions(100000, 100000);
+    assert!(Image::from_rgb_with_limits(tall_image, &limits).is_ok());
+    assert!(Image::from_rgb_with_limits(big_image, &small_limits.max_pixels(2000)).is_ok());
+    
     // Test that reasonable images are accepted
     let normal_image = RgbImage::new(4096, 4096); // 16 megapixels
     let result = Image::from_rgb(normal_image);
     assert!(result.is_ok(), "Should accept reasonably sized images");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_luminance_weights() {
+    use sharpy::Luminance;
+    
+    // Green beside blue: BT.709 weighs green more and blue less than BT.601
+    let img = RgbImage::from_fn(64, 64, |x, _| if x < 32 { Rgb([0, 200, 0]) } else { Rgb([0, 0, 255]) });
+    let bt601 = Image::from_rgb(img.clone()).unwrap();
+    let bt709 = Image::from_rgb(img).unwrap().with_luminance(Luminance::Bt709);
+    
+    assert_eq!(bt601.luminance(), Luminance::Bt601);
+    assert_eq!(bt601.histogram()[117], 64 * 32);
+    assert_eq!(bt709.histogram()[143], 64 * 32);
+    
+    // Edge detection follows the weights, and results keep them
+    let edges601 = bt601.clone().enhance_edges(1.0, EdgeMethod::Sobel).unwrap();
+    let edges709 = bt709.clone().sharpen().edge_enhance(1.0, EdgeMethod::Sobel).apply().unwrap();
+    assert_eq!(edges709.luminance(), Luminance::Bt709);
+    assert_ne!(edges601.into_rgb(), edges709.into_rgb());
+    
+    assert_eq!("709".parse::<Luminance>().unwrap(), Luminance::Bt709);
+    assert_eq!(Luminance::Equal.to_string().parse::<Luminance>().unwrap(), Luminance::Equal);
+    assert!("srgb".parse::<Luminance>().is_err());
+}